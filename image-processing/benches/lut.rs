@@ -0,0 +1,72 @@
+// The histogram and lookup table kernels against plain loops, on the samples of
+// a 4000x3000 RGB image, and on as many 16 bit ones.
+#[macro_use]
+extern crate criterion;
+extern crate image_processing;
+
+use criterion::Criterion;
+use image_processing::image::{
+  apply_lut_u8,
+  apply_lut_u8_scalar,
+  apply_lut_u16,
+  apply_lut_u16_scalar,
+  histogram_u8,
+  histogram_u16,
+  levels_lut_u8,
+  levels_lut_u16
+};
+
+const SAMPLES: usize = 4000 * 3000 * 3;
+
+fn samples_u8() -> Vec<u8> {
+  (0..SAMPLES).map(|i| ((i * 7 + i / 12000 * 13) % 200 + 20) as u8).collect()
+}
+
+fn samples_u16() -> Vec<u16> {
+  (0..SAMPLES).map(|i| ((i * 7 + i / 12000 * 13) % 60000 + 1000) as u16).collect()
+}
+
+fn plain_histogram(samples: &[u8]) -> [usize; 256] {
+  let mut histogram = [0; 256];
+  for &sample in samples {
+    histogram[sample as usize] += 1;
+  }
+  histogram
+}
+
+fn histograms(c: &mut Criterion) {
+  let samples = samples_u8();
+  // flat regions, where successive samples have the same level
+  let flat = vec![128u8; SAMPLES];
+  let samples_16 = samples_u16();
+
+  let plain = samples.clone();
+  c.bench_function("histogram 8 bit plain", move |b| b.iter(|| plain_histogram(&plain)));
+  c.bench_function("histogram 8 bit", move |b| b.iter(|| histogram_u8(&samples, 1, 0)));
+  let plain = flat.clone();
+  c.bench_function("histogram flat plain", move |b| b.iter(|| plain_histogram(&plain)));
+  c.bench_function("histogram flat", move |b| b.iter(|| histogram_u8(&flat, 1, 0)));
+  c.bench_function("histogram 16 bit", move |b| b.iter(|| histogram_u16(&samples_16, 1, 0)));
+}
+
+fn lookups(c: &mut Criterion) {
+  let lut = levels_lut_u8(20, 220, 1.8);
+  let mut samples = samples_u8();
+  let mut scalar = samples.clone();
+  c.bench_function("lookup 8 bit scalar", move |b| b.iter(|| apply_lut_u8_scalar(&mut scalar, &lut)));
+  c.bench_function("lookup 8 bit", move |b| b.iter(|| apply_lut_u8(&mut samples, &lut)));
+
+  let lut = levels_lut_u16(1000, 61000, 1.8);
+  let mut samples = samples_u16();
+  let mut scalar = samples.clone();
+  let scalar_lut = lut.clone();
+  c.bench_function("lookup 16 bit scalar", move |b| b.iter(|| apply_lut_u16_scalar(&mut scalar, &scalar_lut)));
+  c.bench_function("lookup 16 bit", move |b| b.iter(|| apply_lut_u16(&mut samples, &lut)));
+}
+
+criterion_group! {
+  name = benches;
+  config = Criterion::default().sample_size(10);
+  targets = histograms, lookups
+}
+criterion_main!(benches);
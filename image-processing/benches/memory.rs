@@ -0,0 +1,84 @@
+// Peak memory allocated on top of a 4000x3000 RGB image while handling it,
+// next to what the whole image copies it used to take would add.
+extern crate image_processing;
+
+use image_processing::image::Image;
+use image_processing::image::image::{DynamicImage, GenericImage, GenericImageView, Rgb, Rgba, RgbImage};
+use image_processing::pipeline::{Operation, Pipeline};
+use image_processing::operations::Value;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+const WIDTH: u32 = 4000;
+const HEIGHT: u32 = 3000;
+
+struct Counting;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+  unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+    let allocated = ALLOCATED.fetch_add(layout.size(), Ordering::SeqCst) + layout.size();
+    PEAK.fetch_max(allocated, Ordering::SeqCst);
+    System.alloc(layout)
+  }
+
+  unsafe fn dealloc(&self, pointer: *mut u8, layout: Layout) {
+    ALLOCATED.fetch_sub(layout.size(), Ordering::SeqCst);
+    System.dealloc(pointer, layout)
+  }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+// the most memory allocated at once while running `f`, on top of what was
+// allocated before
+fn peak<T, F: FnOnce() -> T>(f: F) -> usize {
+  let before = ALLOCATED.load(Ordering::SeqCst);
+  PEAK.store(before, Ordering::SeqCst);
+  drop(f());
+  PEAK.load(Ordering::SeqCst) - before
+}
+
+fn scan() -> DynamicImage {
+  DynamicImage::ImageRgb8(RgbImage::from_fn(WIDTH, HEIGHT, |x, y| {
+    let level = ((x * 7 + y * 13) % 200 + 20) as u8;
+    Rgb([level, level / 2, 255 - level])
+  }))
+}
+
+fn report(name: &str, bytes: usize) {
+  let image_bytes = (WIDTH * HEIGHT * 3) as f64;
+  println!("{:<40} {:>8.1} MB  {:>5.2} images", name, bytes as f64 / 1e6, bytes as f64 / image_bytes);
+}
+
+fn main() {
+  let path = PathBuf::from("scan");
+
+  let scan = scan();
+  report("new, from a copy as it used to", peak(|| Image::new(&path, scan.clone())));
+  report("new", peak(move || Image::new(&path, scan)));
+
+  let path = PathBuf::from("scan");
+  let image = Image::new(&path, self::scan());
+  report("accessor, copying as it used to", peak(|| image.as_dynamic_image().clone()));
+  report("accessor", peak(|| image.as_dynamic_image().width()));
+  report("clone", peak(|| image.clone()));
+
+  let mut unique = Image::new(&path, self::scan());
+  report("in place, unique", peak(|| unique.dynamic_image_mut().put_pixel(0, 0, Rgba([0, 0, 0, 255]))));
+  report("in place, shared", peak(|| {
+    let mut shared = image.clone();
+    shared.dynamic_image_mut().put_pixel(0, 0, Rgba([0, 0, 0, 255]));
+    shared
+  }));
+
+  let mut pipeline = Pipeline::new();
+  pipeline.record(Operation::registered("equalize_histogram", &[]), None);
+  pipeline.record(Operation::registered("threshold", &[("level", Value::Integer(100))]), None);
+  pipeline.record(Operation::registered("denoise", &[("radius", Value::Integer(1))]), None);
+  report("pipeline of 3 steps", peak(|| pipeline.apply(&image).unwrap()));
+}
@@ -0,0 +1,34 @@
+/*
+ * An example plugin, inverting the colors of the image.
+ *
+ *   cc -shared -fPIC -o libinvert.so invert.c
+ *
+ * then copy libinvert.so to the plugins folder next to the executable, or to
+ * the folder named by IMAGE_PROCESSING_PLUGINS.
+ */
+#include <stdint.h>
+#include <string.h>
+
+const char *operation_name(void) {
+	return "invert";
+}
+
+const char *operation_label(void) {
+	return "invert colors";
+}
+
+const char *operation_parameters(void) {
+	return "[{\"name\": \"alpha\", \"label\": \"invert alpha\", \"type\": \"boolean\", \"default\": false}]";
+}
+
+int apply_operation(uint8_t *rgba, uint32_t width, uint32_t height, const char *arguments) {
+	/* a real plugin would parse the arguments with a JSON library */
+	int alpha = strstr(arguments, "\"alpha\":true") != NULL;
+
+	for (uint64_t i = 0; i < (uint64_t)width * height; i++) {
+		for (int channel = 0; channel < (alpha ? 4 : 3); channel++) {
+			rgba[4 * i + channel] = 255 - rgba[4 * i + channel];
+		}
+	}
+	return 0;
+}
@@ -0,0 +1,665 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Cursor};
+use std::path::{Path, PathBuf};
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+use failure::{err_msg, format_err, Error};
+use gif;
+use png::{self, HasParameters};
+
+use image::Image;
+use image::image::{DynamicImage, GenericImageView, ImageBuffer, Rgba, RgbaImage};
+use depth::{AnyImage, TypedImage};
+use pipeline::Pipeline;
+use tiff::{self, Layout, SampleFormat};
+use tiles::{LARGE_IMAGE_PIXELS, TILE_SIZE};
+
+const PNG_SIGNATURE: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+// how long a frame shows when the file doesn't say, in milliseconds
+pub const DEFAULT_DELAY: u32 = 100;
+
+// One frame of an animation, whole rather than the part that changed, and how
+// long it shows in milliseconds.
+#[derive(Clone)]
+pub struct Frame {
+  pub image: DynamicImage,
+  pub delay: u32
+}
+
+// The frames of an animated GIF or PNG, or the pages of a TIFF file. `plays`
+// is how many times the animation runs, 0 for ever.
+#[derive(Clone)]
+pub struct Animation {
+  pub path: PathBuf,
+  pub frames: Vec<Frame>,
+  pub plays: u32
+}
+
+fn extension_of(path: &Path) -> String {
+  path.extension().and_then(|extension| extension.to_str()).unwrap_or("").to_lowercase()
+}
+
+// GIF and PNG images with more than a frame and TIFF files with more than a page
+pub fn is_animated(path: &PathBuf) -> bool {
+  let frames = match extension_of(path).as_str() {
+    "gif" => File::open(path).ok().and_then(|file| {
+      let mut reader = gif::Decoder::new(BufReader::new(file)).read_info().ok()?;
+      let mut frames = 0;
+      while frames < 2 {
+        match reader.read_next_frame() {
+          Ok(Some(_)) => frames += 1,
+          _ => break
+        }
+      }
+      Some(frames)
+    }),
+    "png" => File::open(path).ok()
+      .and_then(|file| png::Decoder::new(file).read_info().ok())
+      .and_then(|(_, reader)| reader.info().animation_control.map(|control| control.num_frames as usize)),
+    "tif" | "tiff" => File::open(path).ok().and_then(|mut file| tiff::page_count(&mut file).ok()),
+    _ => None
+  };
+  frames.unwrap_or(0) > 1
+}
+
+// Which frames "1-3,5" means, counting from 1 as people do. Empty or "all"
+// means every frame.
+pub fn parse_frames(text: &str, count: usize) -> Result<Vec<usize>, Error> {
+  let text = text.trim();
+  if text.is_empty() || text == "all" {
+    return Ok((0..count).collect());
+  }
+
+  let number = |text: &str| match text.trim().parse::<usize>() {
+    Ok(number) if number >= 1 && number <= count => Ok(number - 1),
+    _ => Err(format_err!("{} isn't a frame from 1 to {}", text.trim(), count))
+  };
+  let mut frames = Vec::new();
+  for part in text.split(',') {
+    let mut ends = part.splitn(2, '-');
+    let first = number(ends.next().unwrap_or(""))?;
+    let last = match ends.next() {
+      Some(last) => number(last)?,
+      None => first
+    };
+    if last < first {
+      return Err(format_err!("{} runs backwards", part.trim()));
+    }
+    frames.extend(first..last + 1);
+  }
+  frames.sort();
+  frames.dedup();
+  Ok(frames)
+}
+
+// Replays the recipe on the chosen frames of an animation, or pages of a TIFF
+// file, as `parse_frames` reads them, and saves the result. Pages deeper than
+// 8 bits keep their depth when saved as TIFF. Pages too large to load at once
+// are refused rather than loaded whole.
+pub fn apply_to_frames(pipeline: &Pipeline, input: &PathBuf, output: &PathBuf, frames: &str) -> Result<PathBuf, Error> {
+  if let "tif" | "tiff" = extension_of(input).as_str() {
+    let layouts = File::open(input).map_err(Error::from)
+      .and_then(|mut file| Layout::read_pages(&mut file))
+      .map_err(|error| format_err!("{}: {}", input.display(), error))?;
+    if layouts.len() > 1 && layouts.iter().any(|layout| layout.width as u64 * layout.height as u64 >= LARGE_IMAGE_PIXELS) {
+      return Err(format_err!("{}: pages this large can't be replayed without loading them whole", input.display()));
+    }
+    if layouts.iter().any(|layout| layout.format != SampleFormat::U8) {
+      let frames = parse_frames(frames, layouts.len())?;
+      return apply_to_pages(pipeline, input, output, &layouts, &frames);
+    }
+  }
+
+  let animation = Animation::open(input)?;
+  let frames = parse_frames(frames, animation.frames.len())?;
+  animation.apply(pipeline, &frames)?.save(output)
+}
+
+// the chosen pages at their own depth, read one after the other
+fn apply_to_pages(pipeline: &Pipeline, input: &PathBuf, output: &PathBuf, layouts: &[Layout],
+                  frames: &[usize]) -> Result<PathBuf, Error> {
+  let mut file = File::open(input)?;
+  let mut pages = Vec::with_capacity(layouts.len());
+  for (index, layout) in layouts.iter().enumerate() {
+    let page = AnyImage::from_tiff(layout, tiff::read_samples(&mut file, layout)?)?;
+    pages.push(if frames.contains(&index) { pipeline.apply_precise(&page)? } else { page });
+  }
+
+  match extension_of(output).as_str() {
+    "tif" | "tiff" => AnyImage::save_pages(&pages, output),
+    // the other formats take 8 bit frames
+    _ => {
+      let frames = pages.iter().map(|page| Frame { image: page.to_dynamic_image(), delay: DEFAULT_DELAY }).collect();
+      Animation { path: input.to_path_buf(), frames, plays: 0 }.save(output)
+    }
+  }
+}
+
+impl Animation {
+  // Other formats give a single frame.
+  pub fn open(path: &PathBuf) -> Result<Self, Error> {
+    let animation = match extension_of(path).as_str() {
+      "gif" => open_gif(path),
+      "png" => open_apng(path),
+      "tif" | "tiff" => open_pages(path),
+      _ => match Image::open(path) {
+        Ok(image) => Ok((vec![Frame { image: image.as_dynamic_image().clone(), delay: DEFAULT_DELAY }], 0)),
+        Err(error) => Err(err_msg(error))
+      }
+    };
+    match animation {
+      Ok((frames, plays)) => Ok(Animation { path: path.to_path_buf(), frames, plays }),
+      Err(error) => Err(format_err!("{}: {}", path.display(), error))
+    }
+  }
+
+  pub fn frame(&self, index: usize) -> Option<Image> {
+    self.frames.get(index).map(|frame| Image::new(&self.path, frame.image.clone()))
+  }
+
+  // applies the recipe to the chosen frames, leaving the others and the timing
+  // as they are
+  pub fn apply(&self, pipeline: &Pipeline, frames: &[usize]) -> Result<Self, Error> {
+    let mut animation = self.clone();
+    for &index in frames {
+      let image = self.frame(index).ok_or_else(|| format_err!("there is no frame {}", index + 1))?;
+      animation.frames[index].image = pipeline.apply(&image)?.as_dynamic_image().clone();
+    }
+    Ok(animation)
+  }
+
+  // As an animated GIF or PNG with the same timing, or as the pages of a TIFF
+  // file.
+  pub fn save(&self, path: &PathBuf) -> Result<PathBuf, Error> {
+    let (width, height) = match self.frames.first() {
+      Some(frame) => frame.image.dimensions(),
+      None => return Err(err_msg("there are no frames to save"))
+    };
+    if self.frames.iter().any(|frame| frame.image.dimensions() != (width, height)) {
+      return Err(err_msg("the frames aren't all the same size"));
+    }
+
+    match extension_of(path).as_str() {
+      "gif" => save_gif(self, width, height, path),
+      "png" => save_apng(self, width, height, path),
+      "tif" | "tiff" => save_pages(self, width, height, path),
+      _ => Err(format_err!("{} can't hold several frames, save as .gif, .png or .tif", path.display()))
+    }?;
+    Ok(path.to_path_buf())
+  }
+}
+
+// Draws a frame over the canvas, replacing what is under it or blending with
+// it, GIF frames having only transparent pixels and opaque ones.
+fn draw(canvas: &mut RgbaImage, frame: &RgbaImage, left: u32, top: u32, blend: bool) {
+  for (x, y, pixel) in frame.enumerate_pixels() {
+    let (x, y) = (left + x, top + y);
+    if x >= canvas.width() || y >= canvas.height() {
+      continue;
+    }
+    if !blend || pixel[3] == 255 {
+      canvas.put_pixel(x, y, *pixel);
+    }
+    else if pixel[3] > 0 {
+      let under = *canvas.get_pixel(x, y);
+      let alpha = pixel[3] as f32 / 255.0;
+      let under_alpha = under[3] as f32 / 255.0 * (1.0 - alpha);
+      let total = alpha + under_alpha;
+      let mix = |c: usize| ((pixel[c] as f32 * alpha + under[c] as f32 * under_alpha) / total).round() as u8;
+      canvas.put_pixel(x, y, Rgba([mix(0), mix(1), mix(2), (total * 255.0).round() as u8]));
+    }
+  }
+}
+
+fn clear(canvas: &mut RgbaImage, left: u32, top: u32, width: u32, height: u32) {
+  for y in top..(top + height).min(canvas.height()) {
+    for x in left..(left + width).min(canvas.width()) {
+      canvas.put_pixel(x, y, Rgba([0, 0, 0, 0]));
+    }
+  }
+}
+
+fn open_gif(path: &PathBuf) -> Result<(Vec<Frame>, u32), Error> {
+  let bytes = ::std::fs::read(path)?;
+  let mut decoder = gif::Decoder::new(Cursor::new(&bytes));
+  // both crates' builders have a `set`
+  gif::SetParameter::set(&mut decoder, gif::ColorOutput::RGBA);
+  let mut reader = decoder.read_info()?;
+  let mut canvas = RgbaImage::new(reader.width() as u32, reader.height() as u32);
+
+  let mut frames = Vec::new();
+  while let Some(frame) = reader.read_next_frame()? {
+    let (left, top) = (frame.left as u32, frame.top as u32);
+    let (width, height) = (frame.width as u32, frame.height as u32);
+    let pixels = ImageBuffer::from_raw(width, height, frame.buffer.to_vec())
+    .ok_or_else(|| err_msg("a frame of the GIF image is truncated"))?;
+
+    let previous = if frame.dispose == gif::DisposalMethod::Previous { Some(canvas.clone()) } else { None };
+    draw(&mut canvas, &pixels, left, top, true);
+    let delay = if frame.delay == 0 { DEFAULT_DELAY } else { frame.delay as u32 * 10 };
+    frames.push(Frame { image: DynamicImage::ImageRgba8(canvas.clone()), delay });
+
+    match frame.dispose {
+      gif::DisposalMethod::Background => clear(&mut canvas, left, top, width, height),
+      gif::DisposalMethod::Previous => canvas = previous.unwrap(),
+      _ => ()
+    }
+  }
+  if frames.is_empty() {
+    return Err(err_msg("the GIF image has no frames"));
+  }
+  Ok((frames, gif_plays(&bytes)))
+}
+
+// The NETSCAPE2.0 application extension tells how many times the animation
+// repeats after it first runs, 0 for ever. Without it, it runs once.
+fn gif_plays(bytes: &[u8]) -> u32 {
+  const LOOP_EXTENSION: &[u8] = b"\x21\xff\x0bNETSCAPE2.0\x03\x01";
+  match bytes.windows(LOOP_EXTENSION.len() + 2).find(|window| window.starts_with(LOOP_EXTENSION)) {
+    Some(window) => match LittleEndian::read_u16(&window[LOOP_EXTENSION.len()..]) {
+      0 => 0,
+      repeats => repeats as u32 + 1
+    },
+    None => 1
+  }
+}
+
+fn save_gif(animation: &Animation, width: u32, height: u32, path: &PathBuf) -> Result<(), Error> {
+  if width > u16::MAX as u32 || height > u16::MAX as u32 {
+    return Err(err_msg("GIF images are at most 65535 pixels wide and high"));
+  }
+  let mut encoder = gif::Encoder::new(BufWriter::new(File::create(path)?), width as u16, height as u16, &[])?;
+  // animations running once go without the loop extension
+  let repeat = match animation.plays {
+    0 => Some(gif::Repeat::Infinite),
+    1 => None,
+    plays => Some(gif::Repeat::Finite((plays - 1).min(u16::MAX as u32) as u16))
+  };
+  if let Some(repeat) = repeat {
+    gif::SetParameter::set(&mut encoder, repeat)?;
+  }
+  for frame in animation.frames.iter() {
+    let mut pixels = frame.image.to_rgba().into_raw();
+    let mut gif_frame = gif::Frame::from_rgba_speed(width as u16, height as u16, &mut pixels, 10);
+    // every frame is whole, so none shows through the next
+    gif_frame.dispose = gif::DisposalMethod::Background;
+    gif_frame.delay = ((frame.delay + 5) / 10).min(u16::MAX as u32) as u16;
+    encoder.write_frame(&gif_frame)?;
+  }
+  Ok(())
+}
+
+// the type and data of a PNG chunk
+type Chunk<'a> = ([u8; 4], &'a [u8]);
+
+fn png_chunks<'a>(bytes: &'a [u8]) -> Result<Vec<Chunk<'a>>, Error> {
+  if bytes.len() < 8 || bytes[..8] != PNG_SIGNATURE {
+    return Err(err_msg("not a PNG file"));
+  }
+  let mut chunks = Vec::new();
+  let mut position = 8;
+  while position + 8 <= bytes.len() {
+    let length = BigEndian::read_u32(&bytes[position..]) as usize;
+    let mut kind = [0; 4];
+    kind.copy_from_slice(&bytes[position + 4..position + 8]);
+    let end = position + 8 + length;
+    if end + 4 > bytes.len() {
+      return Err(format_err!("the {} chunk is truncated", String::from_utf8_lossy(&kind)));
+    }
+    chunks.push((kind, &bytes[position + 8..end]));
+    if kind == png::chunk::IEND {
+      break;
+    }
+    position = end + 4;
+  }
+  Ok(chunks)
+}
+
+// where a frame goes, how long it shows and what happens to it after
+struct FrameControl {
+  width: u32,
+  height: u32,
+  left: u32,
+  top: u32,
+  delay: u32,
+  dispose: u8,
+  blend: bool
+}
+
+impl FrameControl {
+  // refuses frames reaching past the canvas, before anything is allocated for
+  // them
+  fn read(data: &[u8], canvas_width: u32, canvas_height: u32) -> Result<Self, Error> {
+    if data.len() < 26 {
+      return Err(err_msg("an fcTL chunk is truncated"));
+    }
+    let (numerator, denominator) = (BigEndian::read_u16(&data[20..]) as u32, BigEndian::read_u16(&data[22..]) as u32);
+    let control = FrameControl {
+      width: BigEndian::read_u32(&data[4..]),
+      height: BigEndian::read_u32(&data[8..]),
+      left: BigEndian::read_u32(&data[12..]),
+      top: BigEndian::read_u32(&data[16..]),
+      delay: numerator * 1000 / if denominator == 0 { 100 } else { denominator },
+      dispose: data[24],
+      blend: data[25] == 1
+    };
+
+    let right = control.left.checked_add(control.width);
+    let bottom = control.top.checked_add(control.height);
+    match (right, bottom) {
+      (Some(right), Some(bottom)) if control.width > 0 && control.height > 0 &&
+                                     right <= canvas_width && bottom <= canvas_height => Ok(control),
+      _ => Err(format_err!("a {}x{} frame at {}, {} doesn't fit the {}x{} image",
+                           control.width, control.height, control.left, control.top, canvas_width, canvas_height))
+    }
+  }
+}
+
+// Animated PNG frames are stored as the image data of a PNG file of their
+// own, which is put back together for the png crate to decode.
+fn open_apng(path: &PathBuf) -> Result<(Vec<Frame>, u32), Error> {
+  let bytes = ::std::fs::read(path)?;
+  let chunks = png_chunks(&bytes)?;
+  let header = match chunks.first() {
+    Some(&(kind, data)) if kind == png::chunk::IHDR && data.len() == 13 => data,
+    _ => return Err(err_msg("the PNG file has no header"))
+  };
+  let plays = match chunks.iter().find(|&&(kind, _)| kind == png::chunk::acTL) {
+    Some(&(_, data)) if data.len() >= 8 => BigEndian::read_u32(&data[4..]),
+    _ => match Image::open(path) {
+      Ok(image) => return Ok((vec![Frame { image: image.as_dynamic_image().clone(), delay: DEFAULT_DELAY }], 0)),
+      Err(error) => return Err(err_msg(error))
+    }
+  };
+  if header[12] != 0 {
+    return Err(err_msg("interlaced animated PNG images aren't supported"));
+  }
+  let (color_type, bit_depth) = match (png::ColorType::from_u8(header[9]), png::BitDepth::from_u8(header[8])) {
+    (Some(color_type), Some(bit_depth)) => (color_type, bit_depth),
+    _ => return Err(err_msg("the PNG file has an unknown color type or bit depth"))
+  };
+  let palette: Vec<Chunk> = chunks.iter()
+  .filter(|&&(kind, _)| kind == png::chunk::PLTE || kind == png::chunk::tRNS)
+  .cloned()
+  .collect();
+
+  // the image data before the first frame control isn't part of the animation
+  let (width, height) = (BigEndian::read_u32(&header[0..]), BigEndian::read_u32(&header[4..]));
+  let mut parts: Vec<(FrameControl, Vec<u8>)> = Vec::new();
+  for &(kind, data) in chunks.iter() {
+    if kind == png::chunk::fcTL {
+      parts.push((FrameControl::read(data, width, height)?, Vec::new()));
+    }
+    else if kind == png::chunk::IDAT || kind == png::chunk::fdAT {
+      let data = if kind == png::chunk::fdAT { &data[4.min(data.len())..] } else { data };
+      if let Some(&mut (_, ref mut frame_data)) = parts.last_mut() {
+        frame_data.extend_from_slice(data);
+      }
+    }
+  }
+  if parts.is_empty() {
+    return Err(err_msg("the animated PNG file has no frames"));
+  }
+
+  let mut canvas = RgbaImage::new(width, height);
+  let mut frames = Vec::with_capacity(parts.len());
+  for (index, (control, data)) in parts.iter().enumerate() {
+    let pixels = decode_apng_frame(control.width, control.height, color_type, bit_depth, &palette, data)?;
+    let previous = if control.dispose == 2 { Some(canvas.clone()) } else { None };
+    draw(&mut canvas, &pixels, control.left, control.top, control.blend);
+    let delay = if control.delay == 0 { DEFAULT_DELAY } else { control.delay };
+    frames.push(Frame { image: DynamicImage::ImageRgba8(canvas.clone()), delay });
+
+    match (control.dispose, previous) {
+      (1, _) => clear(&mut canvas, control.left, control.top, control.width, control.height),
+      // there is nothing to go back to before the first frame
+      (2, Some(_)) if index == 0 => clear(&mut canvas, control.left, control.top, control.width, control.height),
+      (2, Some(previous)) => canvas = previous,
+      _ => ()
+    }
+  }
+  Ok((frames, plays))
+}
+
+fn decode_apng_frame(width: u32, height: u32, color_type: png::ColorType, bit_depth: png::BitDepth,
+                     palette: &[Chunk], data: &[u8]) -> Result<RgbaImage, Error> {
+  let mut bytes = Vec::new();
+  {
+    let mut encoder = png::Encoder::new(&mut bytes, width, height);
+    encoder.set(color_type).set(bit_depth);
+    let mut writer = encoder.write_header()?;
+    for &(kind, chunk) in palette {
+      writer.write_chunk(kind, chunk)?;
+    }
+    writer.write_chunk(png::chunk::IDAT, data)?;
+  }
+
+  let mut decoder = png::Decoder::new(Cursor::new(bytes));
+  decoder.set(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+  let (info, mut reader) = decoder.read_info()?;
+  let mut samples = vec![0; info.buffer_size()];
+  reader.next_frame(&mut samples)?;
+  let image = match info.color_type {
+    png::ColorType::Grayscale => ImageBuffer::from_raw(width, height, samples).map(DynamicImage::ImageLuma8),
+    png::ColorType::GrayscaleAlpha => ImageBuffer::from_raw(width, height, samples).map(DynamicImage::ImageLumaA8),
+    png::ColorType::RGB => ImageBuffer::from_raw(width, height, samples).map(DynamicImage::ImageRgb8),
+    _ => ImageBuffer::from_raw(width, height, samples).map(DynamicImage::ImageRgba8)
+  };
+  image.map(|image| image.to_rgba()).ok_or_else(|| err_msg("a frame of the PNG image is truncated"))
+}
+
+// the compressed image data of a whole RGBA frame
+fn encode_apng_frame(width: u32, height: u32, pixels: &[u8]) -> Result<Vec<u8>, Error> {
+  let mut bytes = Vec::new();
+  {
+    let mut encoder = png::Encoder::new(&mut bytes, width, height);
+    encoder.set(png::ColorType::RGBA).set(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(pixels)?;
+  }
+  Ok(png_chunks(&bytes)?.into_iter()
+  .filter(|&(kind, _)| kind == png::chunk::IDAT)
+  .flat_map(|(_, data)| data.to_vec())
+  .collect())
+}
+
+fn save_apng(animation: &Animation, width: u32, height: u32, path: &PathBuf) -> Result<(), Error> {
+  let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), width, height);
+  encoder.set(png::ColorType::RGBA).set(png::BitDepth::Eight);
+  let mut writer = encoder.write_header()?;
+
+  let mut control = [0; 8];
+  BigEndian::write_u32(&mut control[0..], animation.frames.len() as u32);
+  BigEndian::write_u32(&mut control[4..], animation.plays);
+  writer.write_chunk(png::chunk::acTL, &control)?;
+
+  // frame controls and frame data share the sequence numbers
+  let mut sequence = 0;
+  for (index, frame) in animation.frames.iter().enumerate() {
+    let mut control = [0; 26];
+    BigEndian::write_u32(&mut control[0..], sequence);
+    BigEndian::write_u32(&mut control[4..], width);
+    BigEndian::write_u32(&mut control[8..], height);
+    BigEndian::write_u16(&mut control[20..], frame.delay.min(u16::MAX as u32) as u16);
+    BigEndian::write_u16(&mut control[22..], 1000);
+    writer.write_chunk(png::chunk::fcTL, &control)?;
+    sequence += 1;
+
+    let pixels = frame.image.to_rgba().into_raw();
+    if index == 0 {
+      writer.write_image_data(&pixels)?;
+    }
+    else {
+      let mut data = vec![0; 4];
+      BigEndian::write_u32(&mut data, sequence);
+      data.extend(encode_apng_frame(width, height, &pixels)?);
+      writer.write_chunk(png::chunk::fdAT, &data)?;
+      sequence += 1;
+    }
+  }
+  Ok(())
+}
+
+fn open_pages(path: &PathBuf) -> Result<(Vec<Frame>, u32), Error> {
+  let pages = tiff::read_pages(&mut File::open(path)?)?;
+  let frames = pages.into_iter().map(|(layout, bytes)| {
+    Ok(Frame { image: AnyImage::from_tiff(&layout, bytes)?.to_dynamic_image(), delay: DEFAULT_DELAY })
+  }).collect::<Result<Vec<Frame>, Error>>()?;
+  Ok((frames, 0))
+}
+
+// The pages keep the channels of the frames when they all have the same, and
+// are RGBA otherwise.
+fn save_pages(animation: &Animation, width: u32, height: u32, path: &PathBuf) -> Result<(), Error> {
+  let mut pages: Vec<TypedImage<u8>> = animation.frames.iter().map(|frame| TypedImage::from_dynamic_image(&frame.image)).collect();
+  let channels = pages[0].channels;
+  if pages.iter().any(|page| page.channels != channels) {
+    pages = animation.frames.iter()
+    .map(|frame| TypedImage::from_dynamic_image(&DynamicImage::ImageRgba8(frame.image.to_rgba())))
+    .collect();
+  }
+  let samples: Vec<&[u8]> = pages.iter().map(|page| page.samples.as_slice()).collect();
+  tiff::write_pages(path, width, height, pages[0].channels, SampleFormat::U8, &samples, TILE_SIZE)
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::env;
+  use std::fs;
+  use std::process;
+  use depth::Sample;
+  use pipeline::Operation;
+  use operations::Value;
+
+  fn temporary_path(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("image-processing-{}-{}", process::id(), name))
+  }
+
+  // a light square moving over a dark background, frame after frame
+  fn moving_square(frames: u32) -> Animation {
+    let frames = (0..frames).map(|index| {
+      let image = ImageBuffer::from_fn(16, 8, |x, y| {
+        if x >= 4 * index && x < 4 * index + 4 && y < 4 { Rgba([250, 250, 250, 255]) } else { Rgba([10, 10, 10, 255]) }
+      });
+      Frame { image: DynamicImage::ImageRgba8(image), delay: 40 + 20 * index }
+    }).collect();
+    Animation { path: PathBuf::from("moving.gif"), frames, plays: 0 }
+  }
+
+  fn round_trip(name: &str) -> (Animation, Animation) {
+    let animation = moving_square(3);
+    let path = temporary_path(name);
+    animation.save(&path).unwrap();
+    assert!(is_animated(&path));
+    let read = Animation::open(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    (animation, read)
+  }
+
+  #[test]
+  fn saves_and_opens_animated_png_with_its_timing() {
+    let (animation, read) = round_trip("animation.png");
+    assert_eq!(read.frames.len(), 3);
+    for (frame, original) in read.frames.iter().zip(animation.frames.iter()) {
+      assert_eq!(frame.delay, original.delay);
+      assert_eq!(frame.image.to_rgba().into_raw(), original.image.to_rgba().into_raw());
+    }
+  }
+
+  #[test]
+  fn saves_and_opens_animated_gif_with_its_timing() {
+    let (animation, read) = round_trip("animation.gif");
+    assert_eq!(read.frames.len(), 3);
+    for (frame, original) in read.frames.iter().zip(animation.frames.iter()) {
+      assert_eq!(frame.delay, original.delay);
+      // the palette is quantized, but two colors come through close
+      let (a, b) = (frame.image.to_rgba(), original.image.to_rgba());
+      for (x, y) in [(1, 1), (12, 6)].iter().cloned().chain((0..3).map(|i| (4 * i + 1, 1))) {
+        let (a, b) = (a.get_pixel(x, y), b.get_pixel(x, y));
+        assert!((0..3).all(|c| (a[c] as i32 - b[c] as i32).abs() <= 8), "{:?} isn't {:?}", a, b);
+      }
+    }
+  }
+
+  #[test]
+  fn refuses_animated_png_frames_past_the_canvas() {
+    let path = temporary_path("outside.png");
+    moving_square(2).save(&path).unwrap();
+
+    // moves the second frame so far right that its edge wraps around
+    let mut bytes = fs::read(&path).unwrap();
+    let control = (0..bytes.len() - 4).filter(|&i| &bytes[i..i + 4] == b"fcTL").nth(1).unwrap() + 4;
+    BigEndian::write_u32(&mut bytes[control + 12..], u32::MAX - 4);
+    fs::write(&path, &bytes).unwrap();
+
+    let error = Animation::open(&path).err().unwrap();
+    assert!(error.to_string().contains("doesn't fit the 16x8 image"), "{}", error);
+    fs::remove_file(&path).unwrap();
+  }
+
+  #[test]
+  fn keeps_how_many_times_gif_animations_play() {
+    let path = temporary_path("plays.gif");
+    for &plays in [0, 1, 3].iter() {
+      let mut animation = moving_square(2);
+      animation.plays = plays;
+      animation.save(&path).unwrap();
+      assert_eq!(Animation::open(&path).unwrap().plays, plays);
+    }
+    fs::remove_file(&path).unwrap();
+  }
+
+  #[test]
+  fn saves_and_opens_tiff_pages() {
+    let (animation, read) = round_trip("animation.tif");
+    assert_eq!(read.frames.len(), 3);
+    for (frame, original) in read.frames.iter().zip(animation.frames.iter()) {
+      assert_eq!(frame.image.to_rgba().into_raw(), original.image.to_rgba().into_raw());
+    }
+  }
+
+  #[test]
+  fn applies_recipes_to_chosen_frames() {
+    let animation = moving_square(4);
+    let mut pipeline = Pipeline::new();
+    pipeline.record(Operation::registered("threshold", &[("level", Value::Integer(128))]), None);
+    let applied = animation.apply(&pipeline, &parse_frames("2-3", 4).unwrap()).unwrap();
+
+    assert_eq!(applied.frames[0].image.to_rgba().into_raw(), animation.frames[0].image.to_rgba().into_raw());
+    assert_eq!(applied.frames[1].image.to_rgba().get_pixel(0, 0)[0], 0);
+    assert_eq!(applied.frames[2].image.to_rgba().get_pixel(8, 0)[0], 255);
+    assert_eq!(applied.frames[2].delay, animation.frames[2].delay);
+  }
+
+  #[test]
+  fn keeps_the_depth_of_tiff_pages() {
+    // 32800 and 32950 both come to 128 at 8 bits
+    let pages = [u16::to_bytes(&[1000, 32800, 32950, 65000]), u16::to_bytes(&[32800; 4])];
+    let input = temporary_path("deep-pages.tif");
+    let pages: Vec<&[u8]> = pages.iter().map(|page| page.as_slice()).collect();
+    tiff::write_pages(&input, 2, 2, 1, SampleFormat::U16, &pages, TILE_SIZE).unwrap();
+    assert!(is_animated(&input));
+
+    let mut pipeline = Pipeline::new();
+    pipeline.record(Operation::registered("threshold", &[("level", Value::Integer(128))]), None);
+    let output = temporary_path("deep-pages-out.tif");
+    apply_to_frames(&pipeline, &input, &output, "1").unwrap();
+    let pages = tiff::read_pages(&mut File::open(&output).unwrap()).unwrap();
+    fs::remove_file(&input).unwrap();
+    fs::remove_file(&output).unwrap();
+
+    assert_eq!(pages.len(), 2);
+    assert_eq!(pages[0].0.format, SampleFormat::U16);
+    assert_eq!(u16::from_bytes(&pages[0].1), vec![0, 0, 65535, 65535]);
+    assert_eq!(u16::from_bytes(&pages[1].1), vec![32800; 4]);
+  }
+
+  #[test]
+  fn parses_frame_selections() {
+    assert_eq!(parse_frames("all", 3).unwrap(), vec![0, 1, 2]);
+    assert_eq!(parse_frames(" 3, 1-2 ,2", 5).unwrap(), vec![0, 1, 2]);
+    assert!(parse_frames("0", 3).is_err());
+    assert!(parse_frames("2-1", 3).is_err());
+    assert!(parse_frames("4", 3).is_err());
+  }
+}
@@ -0,0 +1,342 @@
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::process;
+use failure::{err_msg, format_err, Error};
+use serde_json;
+
+// Marks drawn over an image, e.g. to point out defects in a report. They are
+// kept apart from the pixels, in a sidecar file next to the image.
+
+// the sidecar of image.png is image.png.annotations.json
+const SIDECAR_SUFFIX: &str = ".annotations.json";
+// how wide a character of text is taken to be, relative to the text size
+const CHARACTER_WIDTH: f64 = 0.6;
+// how many segments stand for an ellipse when measuring how far it is
+const ELLIPSE_SEGMENTS: usize = 64;
+
+// in image pixel coordinates
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "shape", rename_all = "snake_case")]
+pub enum Shape {
+  Arrow { from: (f64, f64), to: (f64, f64) },
+  Rectangle { x: f64, y: f64, width: f64, height: f64 },
+  Ellipse { center_x: f64, center_y: f64, radius_x: f64, radius_y: f64 },
+  Freehand { points: Vec<(f64, f64)> },
+  // starting on the baseline at `x` and `y`
+  Text { x: f64, y: f64, text: String }
+}
+
+fn distance_to_segment(point: (f64, f64), from: (f64, f64), to: (f64, f64)) -> f64 {
+  let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+  let length_squared = dx * dx + dy * dy;
+  let t = if length_squared > 0.0 {
+    (((point.0 - from.0) * dx + (point.1 - from.1) * dy) / length_squared).clamp(0.0, 1.0)
+  } else {
+    0.0
+  };
+  ((point.0 - from.0 - t * dx).powi(2) + (point.1 - from.1 - t * dy).powi(2)).sqrt()
+}
+
+impl Shape {
+  // the rectangle spanned by two opposite corners, in any order
+  pub fn rectangle(from: (f64, f64), to: (f64, f64)) -> Self {
+    Shape::Rectangle {
+      x: from.0.min(to.0),
+      y: from.1.min(to.1),
+      width: (to.0 - from.0).abs(),
+      height: (to.1 - from.1).abs()
+    }
+  }
+
+  // the ellipse inscribed in the rectangle spanned by two opposite corners
+  pub fn ellipse(from: (f64, f64), to: (f64, f64)) -> Self {
+    Shape::Ellipse {
+      center_x: (from.0 + to.0) / 2.0,
+      center_y: (from.1 + to.1) / 2.0,
+      radius_x: (to.0 - from.0).abs() / 2.0,
+      radius_y: (to.1 - from.1).abs() / 2.0
+    }
+  }
+
+  pub fn translate(&mut self, dx: f64, dy: f64) {
+    match *self {
+      Shape::Arrow { ref mut from, ref mut to } => {
+        *from = (from.0 + dx, from.1 + dy);
+        *to = (to.0 + dx, to.1 + dy);
+      },
+      Shape::Rectangle { ref mut x, ref mut y, .. } | Shape::Text { ref mut x, ref mut y, .. } => {
+        *x += dx;
+        *y += dy;
+      },
+      Shape::Ellipse { ref mut center_x, ref mut center_y, .. } => {
+        *center_x += dx;
+        *center_y += dy;
+      },
+      Shape::Freehand { ref mut points } => for point in points.iter_mut() {
+        *point = (point.0 + dx, point.1 + dy);
+      }
+    }
+  }
+
+  // Mirrored as the image `image_width` by `image_height` pixels under it is
+  // flipped. Text still reads left to right, only its box is mirrored.
+  pub fn flip(&mut self, horizontally: bool, image_width: f64, image_height: f64, text_size: f64) {
+    let mirror = |point: (f64, f64)| if horizontally { (image_width - point.0, point.1) } else { (point.0, image_height - point.1) };
+    let text_width = self.bounds(text_size).2;
+    match *self {
+      Shape::Arrow { ref mut from, ref mut to } => {
+        *from = mirror(*from);
+        *to = mirror(*to);
+      },
+      Shape::Rectangle { ref mut x, ref mut y, width, height } => if horizontally {
+        *x = image_width - *x - width;
+      } else {
+        *y = image_height - *y - height;
+      },
+      Shape::Ellipse { ref mut center_x, ref mut center_y, .. } => {
+        let center = mirror((*center_x, *center_y));
+        *center_x = center.0;
+        *center_y = center.1;
+      },
+      Shape::Freehand { ref mut points } => for point in points.iter_mut() {
+        *point = mirror(*point);
+      },
+      // the baseline is at the bottom of the box
+      Shape::Text { ref mut x, ref mut y, .. } => if horizontally {
+        *x = image_width - *x - text_width;
+      } else {
+        *y = image_height - *y + text_size;
+      }
+    }
+  }
+
+  // too small to see, as left by a click without a drag
+  pub fn is_degenerate(&self) -> bool {
+    match *self {
+      Shape::Arrow { from, to } => distance_to_segment(from, to, to) < 1.0,
+      Shape::Rectangle { width, height, .. } => width < 1.0 || height < 1.0,
+      Shape::Ellipse { radius_x, radius_y, .. } => radius_x < 0.5 || radius_y < 0.5,
+      Shape::Freehand { ref points } => points.len() < 2,
+      Shape::Text { ref text, .. } => text.trim().is_empty()
+    }
+  }
+
+  // The smallest rectangle holding the shape, as x, y, width and height. Text
+  // is taken to be `size` tall.
+  pub fn bounds(&self, size: f64) -> (f64, f64, f64, f64) {
+    let points = match *self {
+      Shape::Rectangle { x, y, width, height } => return (x, y, width, height),
+      Shape::Ellipse { center_x, center_y, radius_x, radius_y } =>
+        return (center_x - radius_x, center_y - radius_y, 2.0 * radius_x, 2.0 * radius_y),
+      Shape::Text { x, y, ref text } => return (x, y - size, CHARACTER_WIDTH * size * text.chars().count() as f64, size),
+      Shape::Arrow { from, to } => vec![from, to],
+      Shape::Freehand { ref points } if !points.is_empty() => points.clone(),
+      Shape::Freehand { .. } => return (0.0, 0.0, 0.0, 0.0)
+    };
+    let left = points.iter().map(|p| p.0).fold(f64::INFINITY, f64::min);
+    let top = points.iter().map(|p| p.1).fold(f64::INFINITY, f64::min);
+    let right = points.iter().map(|p| p.0).fold(f64::NEG_INFINITY, f64::max);
+    let bottom = points.iter().map(|p| p.1).fold(f64::NEG_INFINITY, f64::max);
+    (left, top, right - left, bottom - top)
+  }
+
+  // how far `point` is from the outline, or 0 inside text
+  fn distance(&self, point: (f64, f64), size: f64) -> f64 {
+    match *self {
+      Shape::Arrow { from, to } => distance_to_segment(point, from, to),
+      Shape::Rectangle { x, y, width, height } => {
+        let corners = [(x, y), (x + width, y), (x + width, y + height), (x, y + height)];
+        (0..4).map(|i| distance_to_segment(point, corners[i], corners[(i + 1) % 4])).fold(f64::INFINITY, f64::min)
+      },
+      // scaling by the radii would bring points far off a thin ellipse close
+      Shape::Ellipse { center_x, center_y, radius_x, radius_y } => {
+        let outline: Vec<(f64, f64)> = (0..ELLIPSE_SEGMENTS + 1).map(|i| {
+          let angle = 2.0 * ::std::f64::consts::PI * i as f64 / ELLIPSE_SEGMENTS as f64;
+          (center_x + radius_x * angle.cos(), center_y + radius_y * angle.sin())
+        }).collect();
+        outline.windows(2).map(|pair| distance_to_segment(point, pair[0], pair[1])).fold(f64::INFINITY, f64::min)
+      },
+      Shape::Freehand { ref points } => match points.len() {
+        0 => f64::INFINITY,
+        1 => distance_to_segment(point, points[0], points[0]),
+        _ => points.windows(2).map(|pair| distance_to_segment(point, pair[0], pair[1])).fold(f64::INFINITY, f64::min)
+      },
+      Shape::Text { .. } => {
+        let (x, y, width, height) = self.bounds(size);
+        let dx = (x - point.0).max(point.0 - x - width).max(0.0);
+        let dy = (y - point.1).max(point.1 - y - height).max(0.0);
+        (dx * dx + dy * dy).sqrt()
+      }
+    }
+  }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Annotation {
+  #[serde(flatten)]
+  pub shape: Shape,
+  // red, green and blue, from 0 to 1
+  pub color: (f64, f64, f64),
+  // the width of the lines, or the height of the text, in image pixels
+  pub size: f64
+}
+
+impl Annotation {
+  // whether `point` is within `tolerance` of what's drawn
+  pub fn hit(&self, point: (f64, f64), tolerance: f64) -> bool {
+    self.shape.distance(point, self.size) <= tolerance + self.size / 2.0
+  }
+}
+
+pub fn sidecar_path(image_path: &Path) -> PathBuf {
+  let mut name = image_path.file_name().map(|name| name.to_os_string()).unwrap_or_default();
+  name.push(SIDECAR_SUFFIX);
+  image_path.with_file_name(name)
+}
+
+// The annotations of an image, drawn in order.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Annotations {
+  pub annotations: Vec<Annotation>
+}
+
+impl Annotations {
+  pub fn new() -> Self {
+    Self { annotations: Vec::new() }
+  }
+
+  // those in the sidecar of the image, none when it has none
+  pub fn open(image_path: &Path) -> Result<Self, Error> {
+    let path = sidecar_path(image_path);
+    if !path.is_file() {
+      return Ok(Self::new());
+    }
+    serde_json::from_reader(File::open(&path)?).map_err(|error| format_err!("{}: {}", path.display(), error))
+  }
+
+  // Into the sidecar of the image, which goes away once there's nothing in it.
+  // It's written under another name and then renamed, so that a failed save
+  // leaves the previous annotations as they were.
+  pub fn save(&self, image_path: &Path) -> Result<(), Error> {
+    let path = sidecar_path(image_path);
+    if self.annotations.is_empty() {
+      if path.is_file() {
+        fs::remove_file(&path)?;
+      }
+      return Ok(());
+    }
+    let partial_path = path.with_extension(format!("json.{}.partial", process::id()));
+    let saved = File::create(&partial_path).map_err(Error::from)
+      .and_then(|file| serde_json::to_writer_pretty(file, self).map_err(err_msg))
+      .and_then(|_| fs::rename(&partial_path, &path).map_err(Error::from));
+    if saved.is_err() {
+      let _ = fs::remove_file(&partial_path);
+    }
+    saved
+  }
+
+  // follows the image as it's flipped
+  pub fn flip(&mut self, horizontally: bool, image_width: u32, image_height: u32) {
+    for annotation in self.annotations.iter_mut() {
+      let size = annotation.size;
+      annotation.shape.flip(horizontally, image_width as f64, image_height as f64, size);
+    }
+  }
+
+  // the topmost annotation at `point`
+  pub fn find(&self, point: (f64, f64), tolerance: f64) -> Option<usize> {
+    self.annotations.iter().rposition(|annotation| annotation.hit(point, tolerance))
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::env;
+  use std::process;
+
+  fn annotation(shape: Shape) -> Annotation {
+    Annotation { shape, color: (1.0, 0.0, 0.0), size: 2.0 }
+  }
+
+  #[test]
+  fn keeps_the_annotations_in_a_sidecar() {
+    let image_path = env::temp_dir().join(format!("image-processing-{}-annotated.png", process::id()));
+    assert_eq!(sidecar_path(&image_path).file_name().unwrap().to_str().unwrap(),
+               format!("image-processing-{}-annotated.png.annotations.json", process::id()));
+    assert_eq!(Annotations::open(&image_path).unwrap(), Annotations::new());
+
+    let annotations = Annotations { annotations: vec![
+      annotation(Shape::Arrow { from: (1.0, 2.0), to: (30.0, 40.0) }),
+      annotation(Shape::rectangle((10.0, 10.0), (5.0, 20.0))),
+      annotation(Shape::ellipse((0.0, 0.0), (8.0, 4.0))),
+      annotation(Shape::Freehand { points: vec![(0.0, 0.0), (3.0, 1.0), (4.0, 5.0)] }),
+      annotation(Shape::Text { x: 5.0, y: 50.0, text: "scratch".to_string() })
+    ]};
+    annotations.save(&image_path).unwrap();
+    assert_eq!(Annotations::open(&image_path).unwrap(), annotations);
+    // nothing is left under the name it was written with
+    assert!(!sidecar_path(&image_path).with_extension(format!("json.{}.partial", process::id())).exists());
+
+    Annotations::new().save(&image_path).unwrap();
+    assert!(!sidecar_path(&image_path).exists());
+  }
+
+  #[test]
+  fn finds_the_topmost_annotation_at_a_point() {
+    let mut annotations = Annotations { annotations: vec![
+      annotation(Shape::rectangle((0.0, 0.0), (100.0, 100.0))),
+      annotation(Shape::ellipse((40.0, 40.0), (60.0, 60.0))),
+      annotation(Shape::Text { x: 10.0, y: 90.0, text: "dent".to_string() })
+    ]};
+    // only the outlines count
+    assert_eq!(annotations.find((100.5, 50.0), 1.0), Some(0));
+    assert_eq!(annotations.find((20.0, 20.0), 1.0), None);
+    assert_eq!(annotations.find((60.0, 50.0), 1.0), Some(1));
+    assert_eq!(annotations.find((15.0, 89.0), 1.0), Some(2));
+
+    annotations.annotations[1].shape.translate(30.0, 0.0);
+    assert_eq!(annotations.find((60.0, 50.0), 1.0), None);
+    assert_eq!(annotations.find((90.0, 50.0), 1.0), Some(1));
+    assert!(Shape::rectangle((3.0, 3.0), (3.0, 9.0)).is_degenerate());
+    assert_eq!(Shape::Freehand { points: vec![(4.0, 1.0), (2.0, 6.0), (9.0, 3.0)] }.bounds(2.0), (2.0, 1.0, 7.0, 5.0));
+  }
+
+  #[test]
+  fn measures_thin_ellipses_along_their_outline() {
+    let thin = annotation(Shape::ellipse((0.0, 48.0), (200.0, 52.0)));
+    assert!(thin.hit((100.0, 46.5), 1.0));
+    assert!(thin.hit((1.0, 50.0), 1.0));
+    // past the end of the long axis, and off the middle of it
+    assert!(!thin.hit((230.0, 50.0), 1.0));
+    assert!(!thin.hit((100.0, 40.0), 1.0));
+    assert!((thin.shape.distance((230.0, 50.0), 2.0) - 30.0).abs() < 0.5);
+  }
+
+  #[test]
+  fn flips_along_with_the_image() {
+    let mut annotations = Annotations { annotations: vec![
+      annotation(Shape::Arrow { from: (1.0, 2.0), to: (30.0, 40.0) }),
+      annotation(Shape::rectangle((10.0, 10.0), (20.0, 30.0))),
+      annotation(Shape::ellipse((0.0, 0.0), (8.0, 4.0))),
+      annotation(Shape::Text { x: 5.0, y: 50.0, text: "dent".to_string() })
+    ]};
+    let original = annotations.clone();
+    let close = |a: (f64, f64, f64, f64), b: (f64, f64, f64, f64)| (a.0 - b.0).abs() + (a.1 - b.1).abs() < 1e-9;
+
+    annotations.flip(true, 100, 60);
+    assert_eq!(annotations.annotations[0].shape, Shape::Arrow { from: (99.0, 2.0), to: (70.0, 40.0) });
+    assert_eq!(annotations.annotations[1].shape, Shape::rectangle((80.0, 10.0), (90.0, 30.0)));
+    assert_eq!(annotations.annotations[2].shape.bounds(2.0), (92.0, 0.0, 8.0, 4.0));
+    assert!(close(annotations.annotations[3].shape.bounds(2.0), (90.2, 48.0, 4.8, 2.0)));
+
+    annotations.flip(true, 100, 60);
+    annotations.flip(false, 100, 60);
+    assert_eq!(annotations.annotations[1].shape, Shape::rectangle((10.0, 30.0), (20.0, 50.0)));
+    assert!(close(annotations.annotations[3].shape.bounds(2.0), (5.0, 10.0, 4.8, 2.0)));
+    annotations.flip(false, 100, 60);
+    assert_eq!(annotations.annotations[..3], original.annotations[..3]);
+    assert!(close(annotations.annotations[3].shape.bounds(2.0), original.annotations[3].shape.bounds(2.0)));
+  }
+}
@@ -0,0 +1,252 @@
+use std::io::{self, Write};
+
+use image::Image;
+use image::image::{GenericImageView, ImageBuffer, Pixel, Rgba, RgbaImage};
+
+// pixels at least this bright are foreground
+const FOREGROUND_LEVEL: u8 = 128;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Connectivity {
+  Four,
+  Eight
+}
+
+impl Connectivity {
+  fn offsets(&self) -> &'static [(i64, i64)] {
+    match *self {
+      Connectivity::Four => &[(1, 0), (-1, 0), (0, 1), (0, -1)],
+      Connectivity::Eight => &[(1, 0), (-1, 0), (0, 1), (0, -1), (1, 1), (1, -1), (-1, 1), (-1, -1)]
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Blob {
+  pub label: u32,
+  // in pixels
+  pub area: usize,
+  // x, y, width, height
+  pub bounding_box: (u32, u32, u32, u32),
+  pub centroid: (f64, f64),
+  // number of pixel edges bordering the background or the image's border
+  pub perimeter: usize
+}
+
+// Component label of every pixel, 0 being the background.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Labels {
+  width: u32,
+  height: u32,
+  labels: Vec<u32>,
+  count: u32
+}
+
+impl Labels {
+  pub fn get(&self, x: u32, y: u32) -> u32 {
+    if x < self.width && y < self.height {
+      self.labels[(y as usize) * (self.width as usize) + x as usize]
+    }
+    else {
+      0
+    }
+  }
+
+  pub fn count(&self) -> u32 {
+    self.count
+  }
+
+  // sorted by label
+  pub fn blobs(&self) -> Vec<Blob> {
+    let mut blobs: Vec<Blob> = (1..self.count + 1).map(|label| Blob {
+      label,
+      area: 0,
+      bounding_box: (u32::MAX, u32::MAX, 0, 0),
+      centroid: (0.0, 0.0),
+      perimeter: 0
+    }).collect();
+
+    // the bounding box holds min x, min y, max x, max y until the end
+    for y in 0..self.height {
+      for x in 0..self.width {
+        let label = self.get(x, y);
+        if label == 0 {
+          continue;
+        }
+
+        let blob = &mut blobs[(label - 1) as usize];
+        blob.area += 1;
+        blob.centroid.0 += x as f64;
+        blob.centroid.1 += y as f64;
+        blob.bounding_box.0 = blob.bounding_box.0.min(x);
+        blob.bounding_box.1 = blob.bounding_box.1.min(y);
+        blob.bounding_box.2 = blob.bounding_box.2.max(x);
+        blob.bounding_box.3 = blob.bounding_box.3.max(y);
+        blob.perimeter += Connectivity::Four.offsets().iter()
+        .filter(|&&(dx, dy)| self.neighbour(x, y, dx, dy) != Some(label))
+        .count();
+      }
+    }
+
+    for blob in blobs.iter_mut() {
+      let (min_x, min_y, max_x, max_y) = blob.bounding_box;
+      blob.bounding_box = (min_x, min_y, max_x - min_x + 1, max_y - min_y + 1);
+      blob.centroid = (blob.centroid.0 / (blob.area as f64), blob.centroid.1 / (blob.area as f64));
+    }
+    blobs
+  }
+
+  fn neighbour(&self, x: u32, y: u32, dx: i64, dy: i64) -> Option<u32> {
+    let (x, y) = (x as i64 + dx, y as i64 + dy);
+    if x < 0 || y < 0 || x >= self.width as i64 || y >= self.height as i64 {
+      None
+    }
+    else {
+      Some(self.get(x as u32, y as u32))
+    }
+  }
+
+  // every component gets its own color, the background is transparent
+  pub fn colorize(&self) -> RgbaImage {
+    ImageBuffer::from_fn(self.width, self.height, |x, y| {
+      match self.get(x, y) {
+        0 => Rgba([0, 0, 0, 0]),
+        label => label_color(label)
+      }
+    })
+  }
+}
+
+// spreads the hues with the golden angle so that neighbouring labels differ
+fn label_color(label: u32) -> Rgba<u8> {
+  let hue = ((label as f64) * 137.508) % 360.0;
+  let sector = hue / 60.0;
+  let fraction = sector - sector.floor();
+  let (rising, falling) = ((255.0 * fraction) as u8, (255.0 * (1.0 - fraction)) as u8);
+
+  let (r, g, b) = match sector as u32 {
+    0 => (255, rising, 0),
+    1 => (falling, 255, 0),
+    2 => (0, 255, rising),
+    3 => (0, falling, 255),
+    4 => (rising, 0, 255),
+    _ => (255, 0, falling)
+  };
+  Rgba([r, g, b, 255])
+}
+
+impl Image {
+  // Labels the connected foreground regions of a binary image, e.g. the
+  // result of `threshold`.
+  pub fn label_components(&self, connectivity: Connectivity) -> Labels {
+    let dynamic_image = self.as_dynamic_image();
+    let (width, height) = dynamic_image.dimensions();
+
+    let foreground: Vec<bool> = dynamic_image.to_luma().pixels()
+    .map(|luma| luma.channels()[0] >= FOREGROUND_LEVEL)
+    .collect();
+
+    let mut labels = Labels { width, height, labels: vec![0; foreground.len()], count: 0 };
+    let mut stack = Vec::new();
+
+    for start in 0..foreground.len() {
+      if !foreground[start] || labels.labels[start] != 0 {
+        continue;
+      }
+
+      labels.count += 1;
+      labels.labels[start] = labels.count;
+      stack.push(start);
+
+      while let Some(index) = stack.pop() {
+        let (x, y) = ((index % width as usize) as i64, (index / width as usize) as i64);
+        for &(dx, dy) in connectivity.offsets() {
+          let (nx, ny) = (x + dx, y + dy);
+          if nx < 0 || ny < 0 || nx >= width as i64 || ny >= height as i64 {
+            continue;
+          }
+          let neighbour = (ny as usize) * (width as usize) + nx as usize;
+          if foreground[neighbour] && labels.labels[neighbour] == 0 {
+            labels.labels[neighbour] = labels.count;
+            stack.push(neighbour);
+          }
+        }
+      }
+    }
+    labels
+  }
+}
+
+pub fn write_csv<W: Write>(blobs: &[Blob], mut writer: W) -> io::Result<()> {
+  writeln!(writer, "label,area,x,y,width,height,centroid_x,centroid_y,perimeter")?;
+  for blob in blobs {
+    let (x, y, width, height) = blob.bounding_box;
+    writeln!(writer, "{},{},{},{},{},{},{:.3},{:.3},{}",
+             blob.label, blob.area, x, y, width, height, blob.centroid.0, blob.centroid.1, blob.perimeter)?;
+  }
+  Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::path::PathBuf;
+  use image::image::{DynamicImage, Luma};
+
+  // two squares touching at a corner, and a lone pixel
+  fn binary_image() -> Image {
+    let rows = ["##...",
+                "##...",
+                "..##.",
+                "..##.",
+                "....#"];
+    let buffer = ImageBuffer::from_fn(5, 5, |x, y| {
+      let set = rows[y as usize].as_bytes()[x as usize] == b'#';
+      Luma([if set { 255 } else { 0 }])
+    });
+    Image::new(&PathBuf::from("nobody cares"), DynamicImage::ImageLuma8(buffer))
+  }
+
+  #[test]
+  fn labels_depend_on_the_connectivity() {
+    assert_eq!(binary_image().label_components(Connectivity::Four).count(), 3);
+    assert_eq!(binary_image().label_components(Connectivity::Eight).count(), 1);
+  }
+
+  #[test]
+  fn measures_the_blobs() {
+    let blobs = binary_image().label_components(Connectivity::Four).blobs();
+
+    assert_eq!(blobs.len(), 3);
+    assert_eq!(blobs[1], Blob {
+      label: 2,
+      area: 4,
+      bounding_box: (2, 2, 2, 2),
+      centroid: (2.5, 2.5),
+      perimeter: 8
+    });
+    assert_eq!(blobs[2].area, 1);
+    assert_eq!(blobs[2].perimeter, 4);
+  }
+
+  #[test]
+  fn writes_the_blob_table_as_csv() {
+    let blobs = binary_image().label_components(Connectivity::Eight).blobs();
+    let mut csv = Vec::new();
+    write_csv(&blobs, &mut csv).unwrap();
+
+    assert_eq!(String::from_utf8(csv).unwrap(),
+               "label,area,x,y,width,height,centroid_x,centroid_y,perimeter\n\
+                1,9,0,0,5,5,1.778,1.778,20\n");
+  }
+
+  #[test]
+  fn colorizes_only_the_foreground() {
+    let colors = binary_image().label_components(Connectivity::Four).colorize();
+
+    assert_eq!(colors.get_pixel(4, 0)[3], 0);
+    assert_eq!(colors.get_pixel(0, 0)[3], 255);
+    assert!(colors.get_pixel(0, 0) != colors.get_pixel(2, 2));
+  }
+}
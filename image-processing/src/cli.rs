@@ -0,0 +1,294 @@
+use std::path::PathBuf;
+use failure::{format_err, Error};
+
+use image::Image;
+use pipeline::Pipeline;
+use stitching::stitch;
+use operations::{registry, Arguments, ParameterKind};
+use tiles::{apply_tiled, TiledImage, OVERVIEW_SIZE};
+use depth::{is_high_depth, AnyImage};
+use hdr::{merge_bracket, parse_exposure, ToneMapping};
+use animation::{apply_to_frames, is_animated, parse_frames};
+use pnm::{is_netpbm, Encoding};
+
+const USAGE: &str = "usage:
+  image-processing                                       start the graphical interface
+  image-processing replay <recipe.json> <input> <output> [<frames>]
+                                                         replay a recipe on an image, or on every image in a folder,
+                                                         keeping the depth of 16 bit and floating point images
+                                                         and a tile at a time for large TIFF images, and on every
+                                                         frame of animated GIF and PNG images and page of TIFF
+                                                         files, or only the frames given like 1-3,5
+  image-processing overview <input> <output> [<size>]    save a copy of an image shrunk to fit in <size> pixels, 2048 by default
+  image-processing stitch <output> <input> <input>...    stitch overlapping images, each overlapping the one before
+  image-processing hdr <tone mapping> <output> <input>=<seconds> <input>=<seconds>...
+                                                         merge exposures of a scene, taken for the given times, and
+                                                         tone map them with reinhard, drago or local, or with none
+                                                         to save the radiance itself as .hdr or .tif
+  image-processing tonemap <tone mapping> <input> <output> [<parameter>=<value>...]
+                                                         tone map a radiance image: reinhard takes key and white,
+                                                         drago bias, local contrast and saturation
+  image-processing netpbm <plain|raw> <input> <output>   convert an image to NetPBM, as ASCII or binary: .pbm, .pgm and .ppm
+                                                         make a bitmap, graymap or pixmap, .pnm whichever fits
+  image-processing operations                            list the operations, built in or from plugins, and their parameters
+  image-processing apply <operation> <input> <output> [<parameter>=<value>...]
+                                                         apply a single operation";
+
+pub fn run(args: &[String]) -> Result<(), Error> {
+  match args.first().map(|command| command.as_str()) {
+    Some("replay") => replay(&args[1..]),
+    Some("overview") => save_overview(&args[1..]),
+    Some("stitch") => stitch_images(&args[1..]),
+    Some("hdr") => merge_exposures(&args[1..]),
+    Some("tonemap") => tone_map(&args[1..]),
+    Some("netpbm") => convert_to_netpbm(&args[1..]),
+    Some("operations") => list_operations(),
+    Some("apply") => apply_operation(&args[1..]),
+    Some("help") | Some("--help") | Some("-h") => {
+      println!("{}", USAGE);
+      Ok(())
+    },
+    _ => Err(format_err!("{}", USAGE))
+  }
+}
+
+fn replay(args: &[String]) -> Result<(), Error> {
+  if args.len() != 3 && args.len() != 4 {
+    return Err(format_err!("{}", USAGE));
+  }
+
+  let recipe_path = PathBuf::from(&args[0]);
+  let input_path = PathBuf::from(&args[1]);
+  let output_path = PathBuf::from(&args[2]);
+
+  let pipeline = Pipeline::open(&recipe_path)?;
+  if args.len() == 4 {
+    if input_path.is_dir() {
+      return Err(format_err!("{}: frames can only be chosen in a single file", input_path.display()));
+    }
+    if !is_animated(&input_path) {
+      // a single image is its only frame
+      parse_frames(&args[3], 1)?;
+    }
+  }
+
+  if input_path.is_dir() {
+    let batch = pipeline.apply_to_dir(&input_path, &output_path)?;
+    for saved in &batch.saved {
+      println!("{}", saved.display());
+    }
+    for (failed, error) in &batch.failed {
+      eprintln!("{}: {}", failed.display(), error);
+    }
+    if !batch.failed.is_empty() {
+      return Err(format_err!("{} of {} images failed", batch.failed.len(), batch.saved.len() + batch.failed.len()));
+    }
+  }
+  else if is_animated(&input_path) {
+    let frames = args.get(3).map(|frames| frames.as_str()).unwrap_or("all");
+    println!("{}", apply_to_frames(&pipeline, &input_path, &output_path, frames)?.display());
+  }
+  else if is_high_depth(&input_path) {
+    let image = AnyImage::open(&input_path)?;
+    println!("{}", pipeline.apply_precise(&image)?.save(&output_path)?.display());
+  }
+  else if TiledImage::should_tile(&input_path) {
+    println!("{}", apply_tiled(&pipeline, &input_path, &output_path)?.display());
+  }
+  else {
+    let image = match Image::open(&input_path) {
+      Ok(image) => image,
+      Err(error) => return Err(format_err!("{}: {}", input_path.display(), error))
+    };
+    let saved = pipeline.apply(&image)?.save_image(Some(&output_path))?;
+    println!("{}", saved.display());
+  }
+  Ok(())
+}
+
+// Large TIFF images are read a block at a time, never whole.
+fn save_overview(args: &[String]) -> Result<(), Error> {
+  if args.len() != 2 && args.len() != 3 {
+    return Err(format_err!("{}", USAGE));
+  }
+
+  let input_path = PathBuf::from(&args[0]);
+  let output_path = PathBuf::from(&args[1]);
+  let size = match args.get(2) {
+    Some(size) => size.parse::<u32>().map_err(|_| format_err!("{} isn't a size in pixels", size))?,
+    None => OVERVIEW_SIZE
+  };
+
+  let overview = if TiledImage::should_tile(&input_path) {
+    TiledImage::open(&input_path)?.overview(size)?
+  }
+  else {
+    match Image::open(&input_path) {
+      Ok(image) => image.as_dynamic_image().thumbnail(size, size),
+      Err(error) => return Err(format_err!("{}: {}", input_path.display(), error))
+    }
+  };
+  println!("{}", Image::new(&output_path, overview).save_image(None)?.display());
+  Ok(())
+}
+
+fn stitch_images(args: &[String]) -> Result<(), Error> {
+  if args.len() < 3 {
+    return Err(format_err!("{}", USAGE));
+  }
+
+  let output_path = PathBuf::from(&args[0]);
+  let mut images = Vec::with_capacity(args.len() - 1);
+  for input in &args[1..] {
+    let input_path = PathBuf::from(input);
+    match Image::open(&input_path) {
+      Ok(image) => images.push(image),
+      Err(error) => return Err(format_err!("{}: {}", input_path.display(), error))
+    }
+  }
+
+  let stitched = Image::new(&output_path, stitch(&images)?);
+  println!("{}", stitched.save_image(None)?.display());
+  Ok(())
+}
+
+// The exposure times follow the last = of every input, so 1/60 works too.
+fn merge_exposures(args: &[String]) -> Result<(), Error> {
+  if args.len() < 4 {
+    return Err(format_err!("{}", USAGE));
+  }
+
+  let tone_mapping = match args[0].as_str() {
+    "none" => None,
+    name => Some(ToneMapping::new(name)?)
+  };
+  let output_path = PathBuf::from(&args[1]);
+  let mut images = Vec::with_capacity(args.len() - 2);
+  let mut exposures = Vec::with_capacity(args.len() - 2);
+  for argument in &args[2..] {
+    let mut parts = argument.rsplitn(2, '=');
+    let (exposure, input) = match (parts.next(), parts.next()) {
+      (Some(exposure), Some(input)) => (exposure, input),
+      _ => return Err(format_err!("{} isn't of the form <input>=<seconds>", argument))
+    };
+    let input_path = PathBuf::from(input);
+    match Image::open(&input_path) {
+      Ok(image) => images.push(image),
+      Err(error) => return Err(format_err!("{}: {}", input_path.display(), error))
+    }
+    exposures.push(parse_exposure(exposure)?);
+  }
+
+  let radiance = merge_bracket(&images, &exposures)?;
+  let saved = match tone_mapping {
+    Some(tone_mapping) => Image::new(&output_path, tone_mapping.apply(&radiance)?).save_image(None)?,
+    None => AnyImage::F32(radiance).save(&output_path)?
+  };
+  println!("{}", saved.display());
+  Ok(())
+}
+
+fn tone_map(args: &[String]) -> Result<(), Error> {
+  if args.len() < 3 {
+    return Err(format_err!("{}", USAGE));
+  }
+
+  let mut tone_mapping = ToneMapping::new(&args[0])?;
+  for argument in &args[3..] {
+    let mut parts = argument.splitn(2, '=');
+    match (parts.next(), parts.next()) {
+      (Some(key), Some(value)) => {
+        let value = value.parse::<f64>().map_err(|_| format_err!("{} isn't a number", value))?;
+        tone_mapping.set(key, value)?
+      },
+      _ => return Err(format_err!("{} isn't of the form <parameter>=<value>", argument))
+    }
+  }
+
+  let input_path = PathBuf::from(&args[1]);
+  let output_path = PathBuf::from(&args[2]);
+  let radiance = match AnyImage::open(&input_path)? {
+    AnyImage::U8(image) => image.convert::<f32>(),
+    AnyImage::U16(image) => image.convert::<f32>(),
+    AnyImage::F32(image) => image
+  };
+  let saved = Image::new(&output_path, tone_mapping.apply(&radiance)?).save_image(None)?;
+  println!("{}", saved.display());
+  Ok(())
+}
+
+fn convert_to_netpbm(args: &[String]) -> Result<(), Error> {
+  if args.len() != 3 {
+    return Err(format_err!("{}", USAGE));
+  }
+
+  let encoding = Encoding::new(&args[0])?;
+  let input_path = PathBuf::from(&args[1]);
+  let output_path = PathBuf::from(&args[2]);
+  if !is_netpbm(&output_path) {
+    return Err(format_err!("{} isn't a .pbm, .pgm, .ppm or .pnm file", output_path.display()));
+  }
+
+  println!("{}", AnyImage::open(&input_path)?.save_netpbm(&output_path, encoding)?.display());
+  Ok(())
+}
+
+fn list_operations() -> Result<(), Error> {
+  let registry = registry().read().map_err(|error| format_err!("{}", error))?;
+  for operation in registry.operations() {
+    println!("{}", operation.name());
+    for parameter in operation.parameters() {
+      let description = match parameter.kind {
+        ParameterKind::Integer { min, max, default } => format!("integer from {} to {}, {} by default", min, max, default),
+        ParameterKind::Number { min, max, default } => format!("number from {} to {}, {} by default", min, max, default),
+        ParameterKind::Choice { ref options, ref default } => {
+          let values: Vec<&str> = options.iter().map(|option| option.0.as_str()).collect();
+          format!("one of {}, {} by default", values.join(", "), default)
+        },
+        ParameterKind::Boolean { default } => format!("true or false, {} by default", default)
+      };
+      println!("  {}: {}", parameter.name, description);
+    }
+  }
+  Ok(())
+}
+
+fn apply_operation(args: &[String]) -> Result<(), Error> {
+  if args.len() < 3 {
+    return Err(format_err!("{}", USAGE));
+  }
+
+  let name = &args[0];
+  let input_path = PathBuf::from(&args[1]);
+  let output_path = PathBuf::from(&args[2]);
+
+  let registry = registry().read().map_err(|error| format_err!("{}", error))?;
+  let operation = registry.get(name).ok_or_else(|| format_err!("there's no operation called {}", name))?;
+  let parameters = operation.parameters();
+
+  let mut arguments = Arguments::new();
+  for argument in &args[3..] {
+    let mut parts = argument.splitn(2, '=');
+    let (key, value) = match (parts.next(), parts.next()) {
+      (Some(key), Some(value)) => (key, value),
+      _ => return Err(format_err!("{} isn't of the form <parameter>=<value>", argument))
+    };
+    let parameter = parameters.iter().find(|parameter| parameter.name == key)
+    .ok_or_else(|| format_err!("{} has no parameter called {}", name, key))?;
+    arguments.insert(key.to_string(), parameter.parse(value)?);
+  }
+
+  if is_high_depth(&input_path) {
+    let result = registry.apply_precise(name, &AnyImage::open(&input_path)?, &arguments)?;
+    println!("{}", result.save(&output_path)?.display());
+    return Ok(());
+  }
+
+  let image = match Image::open(&input_path) {
+    Ok(image) => image,
+    Err(error) => return Err(format_err!("{}: {}", input_path.display(), error))
+  };
+  let result = Image::new(&output_path, registry.apply(name, &image, &arguments, None)?);
+  println!("{}", result.save_image(None)?.display());
+  Ok(())
+}
@@ -0,0 +1,831 @@
+use std::cmp::Ordering;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+use failure::{err_msg, format_err, Error};
+use png::{self, HasParameters};
+use rayon::prelude::*;
+
+use image::{apply_lut_u16, apply_lut_u8, channel_histogram, equalized_levels, lut_u16, lut_u8, CHUNK_SIZE};
+use image::image::{self, DynamicImage, GenericImageView, ImageBuffer, Rgb};
+use image::image::hdr::{HDRDecoder, HDREncoder};
+use filters::median;
+use tiff::{self, Layout, SampleFormat};
+use pnm::{self, Encoding, Header, Kind};
+use tiles::TILE_SIZE;
+
+// how finely the histograms of floating point samples are binned
+pub const FLOAT_BINS: usize = 4096;
+
+// A sample of any depth. Its level goes from 0 to 1 over the displayable range,
+// which floating point samples may go beyond.
+pub trait Sample: Copy + PartialOrd + Default + Send + Sync + 'static {
+  // how many levels the samples can take, None for floating point ones
+  const LEVELS: Option<usize>;
+  const FORMAT: SampleFormat;
+
+  fn to_level(self) -> f32;
+  fn from_level(level: f32) -> Self;
+
+  // Which of `bins` equal parts of the range from 0 to 1 the sample falls in,
+  // and how far into it. Integer samples stand for their whole bin.
+  fn bin(self, bins: usize) -> (usize, f32);
+
+  // a total order, NaN coming after every number
+  fn compare(self, other: Self) -> Ordering;
+
+  // in little endian order
+  fn from_bytes(bytes: &[u8]) -> Vec<Self>;
+  fn to_bytes(samples: &[Self]) -> Vec<u8>;
+
+  // maps the levels of the color channels, leaving the alpha alone
+  fn map_levels<F: Fn(f32) -> f32 + Sync>(samples: &mut [Self], channels: usize, f: F) {
+    let colors = color_channels(channels);
+    samples.par_chunks_mut(CHUNK_SIZE * channels).for_each(|chunk| {
+      for pixel in chunk.chunks_mut(channels) {
+        for sample in pixel[..colors].iter_mut() {
+          *sample = Self::from_level(f(sample.to_level()));
+        }
+      }
+    });
+  }
+}
+
+impl Sample for u8 {
+  const LEVELS: Option<usize> = Some(256);
+  const FORMAT: SampleFormat = SampleFormat::U8;
+
+  fn to_level(self) -> f32 { self as f32 / 255.0 }
+  fn from_level(level: f32) -> Self { (level.clamp(0.0, 1.0) * 255.0).round() as u8 }
+  fn bin(self, bins: usize) -> (usize, f32) { (self as usize * bins / 256, 1.0) }
+  fn compare(self, other: Self) -> Ordering { self.cmp(&other) }
+
+  fn from_bytes(bytes: &[u8]) -> Vec<Self> { bytes.to_vec() }
+  fn to_bytes(samples: &[Self]) -> Vec<u8> { samples.to_vec() }
+
+  fn map_levels<F: Fn(f32) -> f32 + Sync>(samples: &mut [Self], channels: usize, f: F) {
+    let lut = lut_u8(|level| Self::from_level(f(level.to_level())));
+    let colors = color_channels(channels);
+    samples.par_chunks_mut(CHUNK_SIZE * channels).for_each(|chunk| {
+      if colors == channels {
+        apply_lut_u8(chunk, &lut);
+        return;
+      }
+      for pixel in chunk.chunks_mut(channels) {
+        for sample in pixel[..colors].iter_mut() {
+          *sample = lut[*sample as usize];
+        }
+      }
+    });
+  }
+}
+
+impl Sample for u16 {
+  const LEVELS: Option<usize> = Some(1 << 16);
+  const FORMAT: SampleFormat = SampleFormat::U16;
+
+  fn to_level(self) -> f32 { self as f32 / 65535.0 }
+  fn from_level(level: f32) -> Self { (level.clamp(0.0, 1.0) * 65535.0).round() as u16 }
+  fn bin(self, bins: usize) -> (usize, f32) { (self as usize * bins / (1 << 16), 1.0) }
+  fn compare(self, other: Self) -> Ordering { self.cmp(&other) }
+
+  fn from_bytes(bytes: &[u8]) -> Vec<Self> {
+    let mut samples = vec![0; bytes.len() / 2];
+    LittleEndian::read_u16_into(bytes, &mut samples);
+    samples
+  }
+
+  fn to_bytes(samples: &[Self]) -> Vec<u8> {
+    let mut bytes = vec![0; samples.len() * 2];
+    LittleEndian::write_u16_into(samples, &mut bytes);
+    bytes
+  }
+
+  fn map_levels<F: Fn(f32) -> f32 + Sync>(samples: &mut [Self], channels: usize, f: F) {
+    let lut = lut_u16(|level| Self::from_level(f(level.to_level())));
+    let colors = color_channels(channels);
+    samples.par_chunks_mut(CHUNK_SIZE * channels).for_each(|chunk| {
+      if colors == channels {
+        apply_lut_u16(chunk, &lut);
+        return;
+      }
+      for pixel in chunk.chunks_mut(channels) {
+        for sample in pixel[..colors].iter_mut() {
+          *sample = lut[*sample as usize];
+        }
+      }
+    });
+  }
+}
+
+impl Sample for f32 {
+  const LEVELS: Option<usize> = None;
+  const FORMAT: SampleFormat = SampleFormat::F32;
+
+  fn to_level(self) -> f32 { self }
+  fn from_level(level: f32) -> Self { level }
+
+  fn bin(self, bins: usize) -> (usize, f32) {
+    let position = self.clamp(0.0, 1.0) * bins as f32;
+    let bin = (position as usize).min(bins - 1);
+    (bin, position - bin as f32)
+  }
+
+  fn compare(self, other: Self) -> Ordering {
+    self.partial_cmp(&other).unwrap_or_else(|| self.is_nan().cmp(&other.is_nan()))
+  }
+
+  fn from_bytes(bytes: &[u8]) -> Vec<Self> {
+    let mut samples = vec![0.0; bytes.len() / 4];
+    LittleEndian::read_f32_into(bytes, &mut samples);
+    samples
+  }
+
+  fn to_bytes(samples: &[Self]) -> Vec<u8> {
+    let mut bytes = vec![0; samples.len() * 4];
+    LittleEndian::write_f32_into(samples, &mut bytes);
+    bytes
+  }
+}
+
+// the channels before the alpha, if any
+fn color_channels(channels: usize) -> usize {
+  if channels == 2 || channels == 4 { channels - 1 } else { channels }
+}
+
+// with the weights of `DynamicImage::to_luma`
+fn luminance<T: Sample>(pixel: &[T], colors: usize) -> f32 {
+  if colors == 3 {
+    0.2126 * pixel[0].to_level() + 0.7152 * pixel[1].to_level() + 0.0722 * pixel[2].to_level()
+  }
+  else {
+    pixel[0].to_level()
+  }
+}
+
+// Interleaved samples of any depth, gray, gray and alpha, RGB or RGBA, for
+// processing without going through 8 bits.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TypedImage<T: Sample> {
+  pub width: u32,
+  pub height: u32,
+  pub channels: usize,
+  pub samples: Vec<T>
+}
+
+impl<T: Sample> TypedImage<T> {
+  pub fn new(width: u32, height: u32, channels: usize, samples: Vec<T>) -> Result<Self, Error> {
+    if !(1..=4).contains(&channels) || samples.len() != width as usize * height as usize * channels {
+      return Err(format_err!("{} samples don't make a {}x{} image of {} channels", samples.len(), width, height, channels));
+    }
+    Ok(Self { width, height, channels, samples })
+  }
+
+  pub fn from_dynamic_image(image: &DynamicImage) -> Self {
+    let (channels, samples) = match *image {
+      DynamicImage::ImageLuma8(ref buffer) => (1, buffer.to_vec()),
+      DynamicImage::ImageLumaA8(ref buffer) => (2, buffer.to_vec()),
+      DynamicImage::ImageRgb8(ref buffer) => (3, buffer.to_vec()),
+      _ => (4, image.to_rgba().into_raw())
+    };
+    let (width, height) = image.dimensions();
+    Self { width, height, channels, samples: samples.into_iter().map(|sample| T::from_level(sample.to_level())).collect() }
+  }
+
+  // the samples of one pixel, None outside of the image
+  pub fn pixel(&self, x: u32, y: u32) -> Option<&[T]> {
+    if x >= self.width || y >= self.height {
+      return None;
+    }
+    let start = (y as usize * self.width as usize + x as usize) * self.channels;
+    Some(&self.samples[start..start + self.channels])
+  }
+
+  // for display, at 8 bits
+  pub fn to_dynamic_image(&self) -> DynamicImage {
+    let samples: Vec<u8> = self.samples.iter().map(|&sample| u8::from_level(sample.to_level())).collect();
+    let (width, height) = (self.width, self.height);
+    match self.channels {
+      1 => DynamicImage::ImageLuma8(ImageBuffer::from_raw(width, height, samples).unwrap()),
+      2 => DynamicImage::ImageLumaA8(ImageBuffer::from_raw(width, height, samples).unwrap()),
+      3 => DynamicImage::ImageRgb8(ImageBuffer::from_raw(width, height, samples).unwrap()),
+      _ => DynamicImage::ImageRgba8(ImageBuffer::from_raw(width, height, samples).unwrap())
+    }
+  }
+
+  pub fn convert<U: Sample>(&self) -> TypedImage<U> {
+    TypedImage {
+      width: self.width,
+      height: self.height,
+      channels: self.channels,
+      samples: self.samples.par_iter().map(|&sample| U::from_level(sample.to_level())).collect()
+    }
+  }
+
+  // the histogram of a channel over `bins` equal parts of the range from 0 to 1
+  pub fn histogram(&self, channel: usize, bins: usize) -> Vec<usize> {
+    channel_histogram(&self.samples, self.channels, channel, bins, |sample: T| sample.bin(bins).0)
+  }
+
+  // Equalizes the first channel over `bins` levels into the color channels, as
+  // `Image::equalize_histogram` does at 8 bits, keeping the alpha. Floating
+  // point samples are placed within their bin, so that they keep their order.
+  pub fn equalize_histogram(&self, bins: usize) -> Self {
+    let levels = equalized_levels(&self.histogram(0, bins), self.width as usize * self.height as usize);
+    let (channels, colors) = (self.channels, color_channels(self.channels));
+
+    let mut equalized = self.clone();
+    equalized.samples.par_chunks_mut(CHUNK_SIZE * channels).for_each(|chunk| {
+      for pixel in chunk.chunks_mut(channels) {
+        let (bin, fraction) = pixel[0].bin(bins);
+        let below = if bin == 0 { 0.0 } else { levels[bin - 1] };
+        let level = T::from_level(below + (levels[bin] - below) * fraction);
+        for sample in pixel[..colors].iter_mut() {
+          *sample = level;
+        }
+      }
+    });
+    equalized
+  }
+
+  // the pixels whose luminance is above `level` become white, the others black
+  pub fn threshold(&self, level: f32) -> Self {
+    let (channels, colors) = (self.channels, color_channels(self.channels));
+    let (black, white) = (T::from_level(0.0), T::from_level(1.0));
+
+    let mut thresholded = self.clone();
+    thresholded.samples.par_chunks_mut(CHUNK_SIZE * channels).for_each(|chunk| {
+      for pixel in chunk.chunks_mut(channels) {
+        let value = if luminance(pixel, colors) > level { white } else { black };
+        for sample in pixel[..colors].iter_mut() {
+          *sample = value;
+        }
+      }
+    });
+    thresholded
+  }
+
+  // Stretches the levels from `black` to `white` over the whole range, then
+  // applies the gamma, as `Image::adjust_levels`.
+  pub fn adjust_levels(&self, black: f32, white: f32, gamma: f32) -> Self {
+    let range = (white - black).max(1e-6);
+    let mut adjusted = self.clone();
+    T::map_levels(&mut adjusted.samples, self.channels, |level| {
+      ((level - black) / range).clamp(0.0, 1.0).powf(1.0 / gamma)
+    });
+    adjusted
+  }
+
+  pub fn adjust_gamma(&self, gamma: f32) -> Self {
+    self.adjust_levels(0.0, 1.0, gamma)
+  }
+
+  // the median of every channel, as `Image::denoise`
+  pub fn denoise(&self, radius: u32) -> Self {
+    TypedImage {
+      width: self.width,
+      height: self.height,
+      channels: self.channels,
+      samples: median(&self.samples, self.width, self.height, self.channels, radius, T::compare)
+    }
+  }
+
+  // White where the Sobel gradient of the luminance is above `level`, from 0
+  // to 1, and black elsewhere, as RGB like `Image::detect_edges`.
+  pub fn detect_edges(&self, level: f32) -> Self {
+    let (width, height) = (self.width as i64, self.height as i64);
+    let colors = color_channels(self.channels);
+    let luma: Vec<f32> = self.samples.par_chunks(self.channels).map(|pixel| luminance(pixel, colors)).collect();
+    let at = |x: i64, y: i64| luma[(y.max(0).min(height - 1) * width + x.max(0).min(width - 1)) as usize];
+
+    let mut samples = vec![T::from_level(0.0); luma.len() * 3];
+    if width > 0 {
+      samples.par_chunks_mut(width as usize * 3).enumerate().for_each(|(y, row)| {
+        let y = y as i64;
+        for (x, rgb) in row.chunks_mut(3).enumerate() {
+          let x = x as i64;
+          let gx = at(x + 1, y - 1) + 2.0 * at(x + 1, y) + at(x + 1, y + 1)
+                 - at(x - 1, y - 1) - 2.0 * at(x - 1, y) - at(x - 1, y + 1);
+          let gy = at(x - 1, y + 1) + 2.0 * at(x, y + 1) + at(x + 1, y + 1)
+                 - at(x - 1, y - 1) - 2.0 * at(x, y - 1) - at(x + 1, y - 1);
+          // the kernels weigh 4 times the difference
+          if (gx * gx + gy * gy).sqrt() / 4.0 > level {
+            for sample in rgb.iter_mut() {
+              *sample = T::from_level(1.0);
+            }
+          }
+        }
+      });
+    }
+    TypedImage { width: self.width, height: self.height, channels: 3, samples }
+  }
+
+  pub fn flip(&self, horizontally: bool) -> Self {
+    let (width, channels) = (self.width as usize, self.channels);
+    let mut flipped = self.clone();
+    if width == 0 {
+      return flipped;
+    }
+    let rows: Vec<&[T]> = self.samples.chunks(width * channels).collect();
+    flipped.samples.par_chunks_mut(width * channels).enumerate().for_each(|(y, row)| {
+      if horizontally {
+        for (x, pixel) in row.chunks_mut(channels).enumerate() {
+          let from = (width - 1 - x) * channels;
+          pixel.copy_from_slice(&rows[y][from..from + channels]);
+        }
+      }
+      else {
+        row.copy_from_slice(rows[rows.len() - 1 - y]);
+      }
+    });
+    flipped
+  }
+}
+
+// An image at the depth it was stored at.
+#[derive(Clone, Debug, PartialEq)]
+pub enum AnyImage {
+  U8(TypedImage<u8>),
+  U16(TypedImage<u16>),
+  F32(TypedImage<f32>)
+}
+
+fn extension_of(path: &Path) -> String {
+  path.extension().and_then(|extension| extension.to_str()).unwrap_or("").to_lowercase()
+}
+
+// 16 bit PNG and TIFF images, floating point TIFF images, Radiance HDR ones
+// and NetPBM ones with a maxval above 255
+pub fn is_high_depth(path: &PathBuf) -> bool {
+  match extension_of(path).as_str() {
+    "png" => File::open(path).ok()
+      .and_then(|file| {
+        let mut decoder = png::Decoder::new(file);
+        decoder.set(png::Transformations::IDENTITY);
+        decoder.read_info().ok()
+      })
+      .map(|(info, _)| info.bit_depth == png::BitDepth::Sixteen)
+      .unwrap_or(false),
+    "tif" | "tiff" => File::open(path).map_err(Error::from)
+      .and_then(|mut file| Layout::read(&mut file))
+      .map(|layout| layout.format != SampleFormat::U8)
+      .unwrap_or(false),
+    "hdr" => true,
+    "pbm" | "pgm" | "ppm" | "pnm" => pnm::read_header(path).map(|header| header.maxval > 255).unwrap_or(false),
+    _ => false
+  }
+}
+
+impl AnyImage {
+  // PNG, TIFF, HDR and NetPBM images at their own depth, the others at 8 bits
+  pub fn open(path: &PathBuf) -> Result<Self, Error> {
+    match AnyImage::open_at_full_depth(path) {
+      Some(image) => image,
+      None => match image::open(path) {
+        Ok(dynamic_image) => Ok(AnyImage::U8(TypedImage::from_dynamic_image(&dynamic_image))),
+        Err(error) => Err(format_err!("{}: {}", path.display(), error))
+      }
+    }
+  }
+
+  // None for the formats left to the image crate
+  pub fn open_at_full_depth(path: &PathBuf) -> Option<Result<Self, Error>> {
+    let image = match extension_of(path).as_str() {
+      "png" => open_png(path),
+      "tif" | "tiff" => open_tiff(path),
+      "hdr" => open_hdr(path),
+      "pbm" | "pgm" | "ppm" | "pnm" => open_pnm(path),
+      _ => return None
+    };
+    Some(image.map_err(|error| format_err!("{}: {}", path.display(), error)))
+  }
+
+  // Keeps the depth where the format allows: 16 bit PNG and NetPBM, any depth
+  // of TIFF and floating point HDR. Other formats get 8 bits.
+  pub fn save(&self, path: &PathBuf) -> Result<PathBuf, Error> {
+    match extension_of(path).as_str() {
+      "png" => match *self {
+        AnyImage::U8(ref image) => save_png(image, png::BitDepth::Eight, image.samples.clone(), path),
+        AnyImage::U16(ref image) => save_png(image, png::BitDepth::Sixteen, to_be_bytes(&image.samples), path),
+        AnyImage::F32(ref image) =>
+          save_png(image, png::BitDepth::Sixteen, to_be_bytes(&image.convert::<u16>().samples), path)
+      },
+      "tif" | "tiff" => match *self {
+        AnyImage::U8(ref image) => save_tiff(image, path),
+        AnyImage::U16(ref image) => save_tiff(image, path),
+        AnyImage::F32(ref image) => save_tiff(image, path)
+      },
+      "hdr" => match *self {
+        AnyImage::U8(ref image) => save_hdr(&image.convert(), path),
+        AnyImage::U16(ref image) => save_hdr(&image.convert(), path),
+        AnyImage::F32(ref image) => save_hdr(image, path)
+      },
+      "pbm" | "pgm" | "ppm" | "pnm" => self.save_netpbm(path, Encoding::Raw).map(|_| ()),
+      _ => self.to_dynamic_image().save(path).map_err(err_msg)
+    }?;
+    Ok(path.to_path_buf())
+  }
+
+  // as plain or raw NetPBM, with a maxval of 255 for 8 bit images and of 65535
+  // for the others
+  pub fn save_netpbm(&self, path: &PathBuf, encoding: Encoding) -> Result<PathBuf, Error> {
+    match *self {
+      AnyImage::U8(ref image) => save_pnm(image, 255, encoding, path),
+      AnyImage::U16(ref image) => save_pnm(image, 65535, encoding, path),
+      AnyImage::F32(ref image) => save_pnm(image, 65535, encoding, path)
+    }?;
+    Ok(path.to_path_buf())
+  }
+
+  // from the samples of a TIFF image, as `tiff::read_image` gives them
+  pub fn from_tiff(layout: &Layout, bytes: Vec<u8>) -> Result<Self, Error> {
+    let (width, height, channels) = (layout.width, layout.height, layout.channels);
+    Ok(match layout.format {
+      SampleFormat::U8 => AnyImage::U8(TypedImage::new(width, height, channels, bytes)?),
+      SampleFormat::U16 => AnyImage::U16(TypedImage::new(width, height, channels, u16::from_bytes(&bytes))?),
+      SampleFormat::F32 => AnyImage::F32(TypedImage::new(width, height, channels, f32::from_bytes(&bytes))?)
+    })
+  }
+
+  // As the pages of a TIFF file, at the depth of the deepest page. The pages
+  // have to be the same size, with the same channels.
+  pub fn save_pages(pages: &[AnyImage], path: &PathBuf) -> Result<PathBuf, Error> {
+    let (size, channels) = match pages.first() {
+      Some(page) => (page.dimensions(), page.channels()),
+      None => return Err(err_msg("there are no pages to save"))
+    };
+    if pages.iter().any(|page| page.dimensions() != size || page.channels() != channels) {
+      return Err(err_msg("the pages aren't all the same size with the same channels"));
+    }
+
+    let format = pages.iter().map(AnyImage::format).max_by_key(SampleFormat::bytes).unwrap_or(SampleFormat::U8);
+    let samples: Vec<Vec<u8>> = pages.iter().map(|page| match format {
+      SampleFormat::U8 => u8::to_bytes(&page.convert::<u8>().samples),
+      SampleFormat::U16 => u16::to_bytes(&page.convert::<u16>().samples),
+      SampleFormat::F32 => f32::to_bytes(&page.convert::<f32>().samples)
+    }).collect();
+    let samples: Vec<&[u8]> = samples.iter().map(|page| page.as_slice()).collect();
+    tiff::write_pages(path, size.0, size.1, channels, format, &samples, TILE_SIZE)?;
+    Ok(path.to_path_buf())
+  }
+
+  pub fn dimensions(&self) -> (u32, u32) {
+    match *self {
+      AnyImage::U8(ref image) => (image.width, image.height),
+      AnyImage::U16(ref image) => (image.width, image.height),
+      AnyImage::F32(ref image) => (image.width, image.height)
+    }
+  }
+
+  pub fn channels(&self) -> usize {
+    match *self {
+      AnyImage::U8(ref image) => image.channels,
+      AnyImage::U16(ref image) => image.channels,
+      AnyImage::F32(ref image) => image.channels
+    }
+  }
+
+  pub fn format(&self) -> SampleFormat {
+    match *self {
+      AnyImage::U8(_) => SampleFormat::U8,
+      AnyImage::U16(_) => SampleFormat::U16,
+      AnyImage::F32(_) => SampleFormat::F32
+    }
+  }
+
+  // the samples of one pixel as the pixel inspector lists them, floating point
+  // ones with four decimals
+  pub fn describe_pixel(&self, x: u32, y: u32) -> Option<String> {
+    let samples: Vec<String> = match *self {
+      AnyImage::U8(ref image) => image.pixel(x, y)?.iter().map(|sample| sample.to_string()).collect(),
+      AnyImage::U16(ref image) => image.pixel(x, y)?.iter().map(|sample| sample.to_string()).collect(),
+      AnyImage::F32(ref image) => image.pixel(x, y)?.iter().map(|sample| format!("{:.4}", sample)).collect()
+    };
+    let names: &[&str] = match samples.len() {
+      1 => &["gray"],
+      2 => &["gray", "A"],
+      3 => &["R", "G", "B"],
+      _ => &["R", "G", "B", "A"]
+    };
+    let described: Vec<String> = names.iter().zip(samples).map(|(name, sample)| format!("{} {}", name, sample)).collect();
+    Some(described.join("  "))
+  }
+
+  pub fn convert<U: Sample>(&self) -> TypedImage<U> {
+    match *self {
+      AnyImage::U8(ref image) => image.convert(),
+      AnyImage::U16(ref image) => image.convert(),
+      AnyImage::F32(ref image) => image.convert()
+    }
+  }
+
+  pub fn to_dynamic_image(&self) -> DynamicImage {
+    match *self {
+      AnyImage::U8(ref image) => image.to_dynamic_image(),
+      AnyImage::U16(ref image) => image.to_dynamic_image(),
+      AnyImage::F32(ref image) => image.to_dynamic_image()
+    }
+  }
+
+  // over every level of integer samples, `FLOAT_BINS` of floating point ones
+  pub fn equalize_histogram(&self) -> Self {
+    match *self {
+      AnyImage::U8(ref image) => AnyImage::U8(image.equalize_histogram(256)),
+      AnyImage::U16(ref image) => AnyImage::U16(image.equalize_histogram(1 << 16)),
+      AnyImage::F32(ref image) => AnyImage::F32(image.equalize_histogram(FLOAT_BINS))
+    }
+  }
+
+  pub fn threshold(&self, level: f32) -> Self {
+    match *self {
+      AnyImage::U8(ref image) => AnyImage::U8(image.threshold(level)),
+      AnyImage::U16(ref image) => AnyImage::U16(image.threshold(level)),
+      AnyImage::F32(ref image) => AnyImage::F32(image.threshold(level))
+    }
+  }
+
+  pub fn adjust_levels(&self, black: f32, white: f32, gamma: f32) -> Self {
+    match *self {
+      AnyImage::U8(ref image) => AnyImage::U8(image.adjust_levels(black, white, gamma)),
+      AnyImage::U16(ref image) => AnyImage::U16(image.adjust_levels(black, white, gamma)),
+      AnyImage::F32(ref image) => AnyImage::F32(image.adjust_levels(black, white, gamma))
+    }
+  }
+
+  pub fn adjust_gamma(&self, gamma: f32) -> Self {
+    self.adjust_levels(0.0, 1.0, gamma)
+  }
+
+  pub fn denoise(&self, radius: u32) -> Self {
+    match *self {
+      AnyImage::U8(ref image) => AnyImage::U8(image.denoise(radius)),
+      AnyImage::U16(ref image) => AnyImage::U16(image.denoise(radius)),
+      AnyImage::F32(ref image) => AnyImage::F32(image.denoise(radius))
+    }
+  }
+
+  pub fn detect_edges(&self, level: f32) -> Self {
+    match *self {
+      AnyImage::U8(ref image) => AnyImage::U8(image.detect_edges(level)),
+      AnyImage::U16(ref image) => AnyImage::U16(image.detect_edges(level)),
+      AnyImage::F32(ref image) => AnyImage::F32(image.detect_edges(level))
+    }
+  }
+
+  pub fn flip(&self, horizontally: bool) -> Self {
+    match *self {
+      AnyImage::U8(ref image) => AnyImage::U8(image.flip(horizontally)),
+      AnyImage::U16(ref image) => AnyImage::U16(image.flip(horizontally)),
+      AnyImage::F32(ref image) => AnyImage::F32(image.flip(horizontally))
+    }
+  }
+}
+
+// The image crate strips 16 bit PNG samples to 8 bits, so they are decoded
+// here. The samples are big endian.
+fn open_png(path: &PathBuf) -> Result<AnyImage, Error> {
+  let mut decoder = png::Decoder::new(File::open(path)?);
+  decoder.set(png::Transformations::EXPAND);
+  let (info, mut reader) = decoder.read_info().map_err(err_msg)?;
+  let mut bytes = vec![0; info.buffer_size()];
+  reader.next_frame(&mut bytes).map_err(err_msg)?;
+
+  // expanding leaves 16 bit samples alone, though it reports them as 8 bit
+  let channels = reader.output_color_type().0.samples();
+  Ok(match reader.info().bit_depth {
+    png::BitDepth::Sixteen => {
+      let mut samples = vec![0; bytes.len() / 2];
+      BigEndian::read_u16_into(&bytes, &mut samples);
+      AnyImage::U16(TypedImage::new(info.width, info.height, channels, samples)?)
+    },
+    _ => AnyImage::U8(TypedImage::new(info.width, info.height, channels, bytes)?)
+  })
+}
+
+fn save_png<T: Sample>(image: &TypedImage<T>, bit_depth: png::BitDepth, bytes: Vec<u8>, path: &PathBuf) -> Result<(), Error> {
+  let color_type = match image.channels {
+    1 => png::ColorType::Grayscale,
+    2 => png::ColorType::GrayscaleAlpha,
+    3 => png::ColorType::RGB,
+    _ => png::ColorType::RGBA
+  };
+  let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), image.width, image.height);
+  encoder.set(color_type).set(bit_depth);
+  let mut writer = encoder.write_header().map_err(err_msg)?;
+  writer.write_image_data(&bytes).map_err(err_msg)
+}
+
+fn to_be_bytes(samples: &[u16]) -> Vec<u8> {
+  let mut bytes = vec![0; samples.len() * 2];
+  BigEndian::write_u16_into(samples, &mut bytes);
+  bytes
+}
+
+fn open_tiff(path: &PathBuf) -> Result<AnyImage, Error> {
+  let (layout, bytes) = tiff::read_image(&mut File::open(path)?)?;
+  AnyImage::from_tiff(&layout, bytes)
+}
+
+fn save_tiff<T: Sample>(image: &TypedImage<T>, path: &PathBuf) -> Result<(), Error> {
+  tiff::write_image(path, image.width, image.height, image.channels, T::FORMAT, &T::to_bytes(&image.samples), TILE_SIZE)
+}
+
+fn open_hdr(path: &PathBuf) -> Result<AnyImage, Error> {
+  let decoder = HDRDecoder::new(BufReader::new(File::open(path)?)).map_err(err_msg)?;
+  let metadata = decoder.metadata();
+  let pixels = decoder.read_image_hdr().map_err(err_msg)?;
+  let samples = pixels.iter().flat_map(|pixel| pixel.data.to_vec()).collect();
+  Ok(AnyImage::F32(TypedImage::new(metadata.width, metadata.height, 3, samples)?))
+}
+
+// HDR images are RGB, without alpha
+fn save_hdr(image: &TypedImage<f32>, path: &PathBuf) -> Result<(), Error> {
+  let pixels: Vec<Rgb<f32>> = image.samples.chunks(image.channels).map(|pixel| match image.channels {
+    1 | 2 => Rgb([pixel[0], pixel[0], pixel[0]]),
+    _ => Rgb([pixel[0], pixel[1], pixel[2]])
+  }).collect();
+  let file = BufWriter::new(File::create(path)?);
+  HDREncoder::new(file).encode(&pixels, image.width as usize, image.height as usize).map_err(err_msg)
+}
+
+// Any maxval is stretched over 8 bits, or over 16 when it's above 255.
+fn open_pnm(path: &PathBuf) -> Result<AnyImage, Error> {
+  let (header, samples) = pnm::read(path)?;
+  let (width, height, channels) = (header.width, header.height, header.kind.channels());
+  let maxval = header.maxval as u32;
+  Ok(match header.kind {
+    // 1 is black in a bitmap
+    Kind::Bitmap => AnyImage::U8(TypedImage::new(width, height, channels,
+      samples.iter().map(|&sample| if sample == 0 { 255 } else { 0 }).collect())?),
+    _ if maxval <= 255 => AnyImage::U8(TypedImage::new(width, height, channels,
+      samples.iter().map(|&sample| ((sample as u32 * 255 + maxval / 2) / maxval) as u8).collect())?),
+    _ => AnyImage::U16(TypedImage::new(width, height, channels,
+      samples.iter().map(|&sample| ((sample as u32 * 65535 + maxval / 2) / maxval) as u16).collect())?)
+  })
+}
+
+// NetPBM has no alpha, and bitmaps take the pixels darker than middle gray as
+// black.
+fn save_pnm<T: Sample>(image: &TypedImage<T>, maxval: u16, encoding: Encoding, path: &PathBuf) -> Result<(), Error> {
+  let kind = pnm::kind_for(path, image.channels);
+  let colors = color_channels(image.channels);
+  let scale = |level: f32| (level.clamp(0.0, 1.0) * maxval as f32).round() as u16;
+
+  let mut samples = Vec::with_capacity(image.width as usize * image.height as usize * kind.channels());
+  for pixel in image.samples.chunks(image.channels) {
+    match kind {
+      Kind::Bitmap => samples.push(if luminance(pixel, colors) < 0.5 { 1 } else { 0 }),
+      Kind::Graymap => samples.push(scale(luminance(pixel, colors))),
+      Kind::Pixmap => for channel in 0..3 {
+        samples.push(scale(pixel[channel.min(colors - 1)].to_level()));
+      }
+    }
+  }
+
+  let header = Header { kind, encoding, width: image.width, height: image.height, maxval };
+  let bytes = pnm::encode(&header, &samples)?;
+  BufWriter::new(File::create(path)?).write_all(&bytes)?;
+  Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::env;
+  use std::fs;
+  use std::process;
+  use image::image::{RgbImage, Rgba};
+  use image::{FullDepth, Image};
+
+  fn temporary_path(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("image-processing-{}-{}", process::id(), name))
+  }
+
+  // a narrow range of levels, finer than 8 bits can tell apart
+  fn faint_gradient() -> TypedImage<u16> {
+    let samples = (0..1000u32).map(|i| 30000 + i as u16).collect();
+    TypedImage::new(40, 25, 1, samples).unwrap()
+  }
+
+  fn distinct<T: Sample>(samples: &[T]) -> usize {
+    let mut sorted = samples.to_vec();
+    sorted.sort_unstable_by(|a, b| a.partial_cmp(b).unwrap());
+    sorted.windows(2).filter(|pair| pair[0] != pair[1]).count() + 1
+  }
+
+  fn histogram<T: Sample>(samples: Vec<T>, channels: usize, channel: usize, bins: usize) -> Vec<usize> {
+    let width = (samples.len() / channels) as u32;
+    TypedImage::new(width, 1, channels, samples).unwrap().histogram(channel, bins)
+  }
+
+  #[test]
+  fn histograms_bin_samples_of_any_depth() {
+    assert_eq!(histogram(vec![0u8, 1, 1, 255], 1, 0, 256)[1], 2);
+    assert_eq!(histogram(vec![0u8, 63, 64, 255], 1, 0, 4), vec![2, 1, 0, 1]);
+    assert_eq!(histogram(vec![0u16, 255, 256, 65535], 1, 0, 256), vec![2, 1].into_iter()
+               .chain(vec![0; 253]).chain(vec![1]).collect::<Vec<_>>());
+    assert_eq!(histogram(vec![-1.0f32, 0.3, 0.5, 1.0, 7.0], 1, 0, 4), vec![1, 1, 1, 2]);
+    // the second channel
+    assert_eq!(histogram(vec![0u16, 65535, 0, 65535], 2, 1, 2), vec![0, 2]);
+  }
+
+  #[test]
+  fn equalizes_sixteen_bits_without_merging_levels() {
+    let equalized = faint_gradient().equalize_histogram(1 << 16);
+    assert_eq!(distinct(&equalized.samples), 1000);
+    assert_eq!(equalized.samples[0], 0);
+    assert!(*equalized.samples.last().unwrap() > 65000);
+
+    // the same levels at 8 bits make up a handful
+    let eight_bits = faint_gradient().convert::<u8>().equalize_histogram(256);
+    assert!(distinct(&eight_bits.samples) < 10);
+  }
+
+  #[test]
+  fn equalizes_floating_point_samples_in_order() {
+    let samples: Vec<f32> = (0..1000).map(|i| 0.5 + i as f32 * 1e-5).collect();
+    let equalized = TypedImage::new(1000, 1, 1, samples).unwrap().equalize_histogram(FLOAT_BINS);
+    assert!(equalized.samples.windows(2).all(|pair| pair[0] <= pair[1]));
+    // only the samples of the lowest bin all become black
+    assert!(distinct(&equalized.samples) > 950);
+  }
+
+  #[test]
+  fn equalizing_eight_bits_matches_the_image() {
+    let dynamic_image = DynamicImage::ImageRgb8(RgbImage::from_fn(30, 20, |x, y| {
+      Rgb([(x * 3 + y * 7) as u8, 0, 0])
+    }));
+    let expected = Image::new(&PathBuf::from("nobody cares"), dynamic_image.clone()).equalize_histogram();
+    let equalized = TypedImage::<u8>::from_dynamic_image(&dynamic_image).equalize_histogram(256);
+    for (&sample, &level) in equalized.samples.iter().zip(expected.raw_pixels().iter()) {
+      assert!((sample as i32 - level as i32).abs() <= 1);
+    }
+  }
+
+  #[test]
+  fn describes_pixels_at_their_own_depth() {
+    let image = AnyImage::U16(TypedImage::new(2, 1, 3, vec![1, 2, 3, 65535, 32768, 0]).unwrap());
+    assert_eq!(image.describe_pixel(1, 0).unwrap(), "R 65535  G 32768  B 0");
+    assert_eq!(image.describe_pixel(2, 0), None);
+
+    let image = AnyImage::F32(TypedImage::new(1, 1, 2, vec![0.25, 1.0]).unwrap());
+    assert_eq!(image.describe_pixel(0, 0).unwrap(), "gray 0.2500  A 1.0000");
+  }
+
+  #[test]
+  fn keeps_the_depth_through_saving() {
+    let png_path = temporary_path("depth.png");
+    let image = AnyImage::U16(faint_gradient().adjust_gamma(1.5));
+    image.save(&png_path).unwrap();
+    assert!(is_high_depth(&png_path));
+    assert_eq!(AnyImage::open(&png_path).unwrap(), image);
+    // shown at 8 bits
+    assert_eq!(Image::open(&png_path).unwrap().as_dynamic_image().dimensions(), (40, 25));
+
+    let tiff_path = temporary_path("depth.tif");
+    let samples = (0..300 * 300 * 2).map(|i| i as f32 / 1000.0).collect();
+    let image = AnyImage::F32(TypedImage::new(300, 300, 2, samples).unwrap());
+    image.save(&tiff_path).unwrap();
+    assert!(is_high_depth(&tiff_path));
+    assert_eq!(AnyImage::open(&tiff_path).unwrap(), image);
+    assert_eq!(Image::open(&tiff_path).unwrap().as_dynamic_image().dimensions(), (300, 300));
+
+    let pgm_path = temporary_path("depth.pgm");
+    let image = AnyImage::U16(faint_gradient());
+    image.save_netpbm(&pgm_path, Encoding::Plain).unwrap();
+    assert!(is_high_depth(&pgm_path));
+    assert_eq!(AnyImage::open(&pgm_path).unwrap(), image);
+
+    fs::remove_file(png_path).unwrap();
+    fs::remove_file(tiff_path).unwrap();
+    fs::remove_file(pgm_path).unwrap();
+  }
+
+  #[test]
+  fn full_depth_stands_for_the_shown_image_until_it_changes() {
+    let full_depth = FullDepth::new(&PathBuf::from("nobody cares"), AnyImage::U16(faint_gradient()));
+    let mut shown = full_depth.shown().clone();
+    assert!(full_depth.stands_for(&shown));
+    assert_eq!(shown.as_dynamic_image().dimensions(), (40, 25));
+
+    shown.set_dynamic_image(shown.threshold(128));
+    assert!(!full_depth.stands_for(&shown));
+  }
+
+  #[test]
+  fn opens_netpbm_files_the_image_crate_rejects() {
+    let path = temporary_path("legacy.ppm");
+    fs::write(&path, "P3\n# from an old scanner\n2 1\n15\n15 0 0 # red\n0 0 15\n").unwrap();
+    assert!(!is_high_depth(&path));
+    let image = Image::open(&path).unwrap();
+    assert_eq!(image.get_pixel(0, 0), Some(Rgba([255, 0, 0, 255])));
+    assert_eq!(image.get_pixel(1, 0), Some(Rgba([0, 0, 255, 255])));
+
+    // saved back raw, through the codec here
+    image.save_image(None).unwrap();
+    assert_eq!(fs::read(&path).unwrap(), b"P6\n2 1\n255\n\xff\x00\x00\x00\x00\xff".to_vec());
+    fs::remove_file(path).unwrap();
+  }
+}
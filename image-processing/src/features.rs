@@ -0,0 +1,370 @@
+use std::f64::consts::PI;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+
+use image::Image;
+use filters::{gaussian_kernel, blur};
+
+// Harris' sensitivity, usually between 0.04 and 0.06
+const HARRIS_K: f64 = 0.04;
+// the gaussian window the structure tensor is averaged over
+const TENSOR_SIGMA: f64 = 1.0;
+const TENSOR_RADIUS: i64 = 2;
+// FAST-9: a corner needs 9 contiguous pixels of the circle below to be all
+// brighter or all darker than the center
+const FAST_ARC: usize = 9;
+const FAST_RADIUS: u32 = 3;
+const FAST_CIRCLE: [(i64, i64); 16] = [
+  (0, -3), (1, -3), (2, -2), (3, -1), (3, 0), (3, 1), (2, 2), (1, 3),
+  (0, 3), (-1, 3), (-2, 2), (-3, 1), (-3, 0), (-3, -1), (-2, -2), (-1, -3)
+];
+// the FAST settings ORB features are detected with
+const ORB_FAST_THRESHOLD: u8 = 20;
+const ORB_SUPPRESSION_RADIUS: u32 = 3;
+
+// oriented BRIEF: 256 comparisons between pairs of smoothed pixels within
+// PATCH_RADIUS of the keypoint, the pattern being rotated along the patch's
+// orientation
+const DESCRIPTOR_WORDS: usize = 4;
+const PATCH_RADIUS: i64 = 15;
+const PATCH_SIGMA: f64 = 2.0;
+const PATCH_SMOOTHING_RADIUS: i64 = 4;
+// every descriptor has to be built from the same pattern
+const PATTERN_SEED: u64 = 0x0b1e;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Keypoint {
+  pub x: u32,
+  pub y: u32,
+  // the detector's response, higher is stronger
+  pub score: f64
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Descriptor(pub [u64; DESCRIPTOR_WORDS]);
+
+impl Descriptor {
+  // the number of differing bits
+  pub fn distance(&self, other: &Descriptor) -> u32 {
+    self.0.iter().zip(other.0.iter()).map(|(a, b)| (a ^ b).count_ones()).sum()
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Feature {
+  pub keypoint: Keypoint,
+  // of the patch's intensity centroid, in radians
+  pub angle: f64,
+  pub descriptor: Descriptor
+}
+
+impl Image {
+  // Harris corners scoring at least `quality` times the strongest one, with no
+  // stronger corner within `radius` pixels. The strongest come first.
+  pub fn harris_corners(&self, quality: f64, radius: u32) -> Vec<Keypoint> {
+    self.structure_tensor_corners(quality, radius, |xx, xy, yy| {
+      (xx * yy - xy * xy) - HARRIS_K * (xx + yy).powi(2)
+    })
+  }
+
+  // like `harris_corners`, scored by the smaller eigenvalue of the structure tensor
+  pub fn shi_tomasi_corners(&self, quality: f64, radius: u32) -> Vec<Keypoint> {
+    self.structure_tensor_corners(quality, radius, |xx, xy, yy| {
+      (xx + yy) / 2.0 - (((xx - yy) / 2.0).powi(2) + xy * xy).sqrt()
+    })
+  }
+
+  fn structure_tensor_corners<F>(&self, quality: f64, radius: u32, response: F) -> Vec<Keypoint>
+    where F: Fn(f64, f64, f64) -> f64 {
+
+    let (values, width, height) = self.luma_values();
+    if width == 0 || height == 0 {
+      return Vec::new();
+    }
+    let at = |x: i64, y: i64| {
+      let x = x.max(0).min(width as i64 - 1);
+      let y = y.max(0).min(height as i64 - 1);
+      values[(y as usize) * (width as usize) + x as usize]
+    };
+
+    let pixels = values.len();
+    let (mut xx, mut xy, mut yy) = (vec![0.0; pixels], vec![0.0; pixels], vec![0.0; pixels]);
+    for y in 0..height as i64 {
+      for x in 0..width as i64 {
+        // sobel, normalized to intensity differences
+        let gx = (at(x + 1, y - 1) + 2.0 * at(x + 1, y) + at(x + 1, y + 1)
+                - at(x - 1, y - 1) - 2.0 * at(x - 1, y) - at(x - 1, y + 1)) / 8.0;
+        let gy = (at(x - 1, y + 1) + 2.0 * at(x, y + 1) + at(x + 1, y + 1)
+                - at(x - 1, y - 1) - 2.0 * at(x, y - 1) - at(x + 1, y - 1)) / 8.0;
+
+        let i = (y as usize) * (width as usize) + x as usize;
+        xx[i] = gx * gx;
+        xy[i] = gx * gy;
+        yy[i] = gy * gy;
+      }
+    }
+
+    let kernel = gaussian_kernel(TENSOR_SIGMA, TENSOR_RADIUS);
+    let (xx, xy, yy) = (blur(&xx, width, height, &kernel),
+                        blur(&xy, width, height, &kernel),
+                        blur(&yy, width, height, &kernel));
+
+    let scores: Vec<f64> = (0..pixels).map(|i| response(xx[i], xy[i], yy[i])).collect();
+    let strongest = scores.iter().cloned().fold(0.0, f64::max);
+    local_maxima(&scores, width, height, quality * strongest, radius)
+  }
+
+  // FAST-9 corners, the center having to differ from the arc by more than
+  // `threshold`. The score is the summed excess difference over the circle.
+  pub fn fast_corners(&self, threshold: u8, radius: u32) -> Vec<Keypoint> {
+    let (values, width, height) = self.luma_values();
+    let threshold = threshold as f64;
+    let mut scores = vec![0.0; values.len()];
+
+    for y in FAST_RADIUS..height.saturating_sub(FAST_RADIUS) {
+      for x in FAST_RADIUS..width.saturating_sub(FAST_RADIUS) {
+        let center = values[(y as usize) * (width as usize) + x as usize];
+        let mut circle = [0.0; 16];
+        for (value, &(dx, dy)) in circle.iter_mut().zip(FAST_CIRCLE.iter()) {
+          *value = values[((y as i64 + dy) as usize) * (width as usize) + (x as i64 + dx) as usize];
+        }
+
+        let brighter = has_arc(&circle, |value| value > center + threshold);
+        let darker = has_arc(&circle, |value| value < center - threshold);
+        if brighter || darker {
+          scores[(y as usize) * (width as usize) + x as usize] = circle.iter()
+          .map(|value| ((value - center).abs() - threshold).max(0.0))
+          .sum();
+        }
+      }
+    }
+    local_maxima(&scores, width, height, 0.0, radius)
+  }
+
+  // ORB descriptors of the keypoints lying far enough from the border
+  pub fn describe(&self, keypoints: &[Keypoint]) -> Vec<Feature> {
+    let (values, width, height) = self.luma_values();
+    let smoothed = blur(&values, width, height, &gaussian_kernel(PATCH_SIGMA, PATCH_SMOOTHING_RADIUS));
+    let at = |x: i64, y: i64| smoothed[(y as usize) * (width as usize) + x as usize];
+    let pattern = sampling_pattern();
+
+    keypoints.iter()
+    .filter(|k| {
+      k.x as i64 >= PATCH_RADIUS && k.y as i64 >= PATCH_RADIUS &&
+      (k.x as i64) < width as i64 - PATCH_RADIUS && (k.y as i64) < height as i64 - PATCH_RADIUS
+    })
+    .map(|&keypoint| {
+      let (x, y) = (keypoint.x as i64, keypoint.y as i64);
+
+      let (mut m10, mut m01) = (0.0, 0.0);
+      for dy in -PATCH_RADIUS..PATCH_RADIUS + 1 {
+        for dx in -PATCH_RADIUS..PATCH_RADIUS + 1 {
+          if dx * dx + dy * dy <= PATCH_RADIUS * PATCH_RADIUS {
+            let value = at(x + dx, y + dy);
+            m10 += (dx as f64) * value;
+            m01 += (dy as f64) * value;
+          }
+        }
+      }
+      let angle = m01.atan2(m10);
+      let (cos, sin) = (angle.cos(), angle.sin());
+      let rotate = |(px, py): (f64, f64)| ((px * cos - py * sin).round() as i64, (px * sin + py * cos).round() as i64);
+
+      let mut words = [0u64; DESCRIPTOR_WORDS];
+      for (bit, &(a, b)) in pattern.iter().enumerate() {
+        let (a, b) = (rotate(a), rotate(b));
+        if at(x + a.0, y + a.1) < at(x + b.0, y + b.1) {
+          words[bit / 64] |= 1 << (bit % 64);
+        }
+      }
+
+      Feature { keypoint, angle, descriptor: Descriptor(words) }
+    })
+    .collect()
+  }
+
+  // the strongest FAST corners with their ORB descriptors
+  pub fn orb_features(&self, max_features: usize) -> Vec<Feature> {
+    let mut keypoints = self.fast_corners(ORB_FAST_THRESHOLD, ORB_SUPPRESSION_RADIUS);
+    keypoints.truncate(max_features);
+    self.describe(&keypoints)
+  }
+
+  fn luma_values(&self) -> (Vec<f64>, u32, u32) {
+    let luma = self.as_dynamic_image().to_luma();
+    let (width, height) = luma.dimensions();
+    (luma.iter().map(|&v| v as f64).collect(), width, height)
+  }
+}
+
+// Pairs of points within the patch, gaussian distributed around its center. They
+// stay inside the patch whatever the rotation.
+fn sampling_pattern() -> Vec<((f64, f64), (f64, f64))> {
+  let mut rng = StdRng::seed_from_u64(PATTERN_SEED);
+  let limit = (PATCH_RADIUS - 1) as f64;
+  let spread = (PATCH_RADIUS as f64) / 2.5;
+
+  let mut point = || loop {
+    // Box-Muller
+    let (u, v): (f64, f64) = (rng.gen_range(1e-9, 1.0), rng.gen_range(0.0, 1.0));
+    let r = spread * (-2.0 * u.ln()).sqrt();
+    let (x, y) = (r * (2.0 * PI * v).cos(), r * (2.0 * PI * v).sin());
+    if x.hypot(y) <= limit {
+      return (x, y);
+    }
+  };
+  (0..64 * DESCRIPTOR_WORDS).map(|_| (point(), point())).collect()
+}
+
+// Pairs of indices of features that are each other's nearest neighbour, and
+// whose nearest neighbour is closer than `max_ratio` times the second nearest.
+pub fn match_features(features: &[Feature], others: &[Feature], max_ratio: f64) -> Vec<(usize, usize)> {
+  let nearest = |descriptor: &Descriptor, candidates: &[Feature]| -> Option<(usize, u32, u32)> {
+    let mut best: Option<(usize, u32, u32)> = None;
+    for (index, candidate) in candidates.iter().enumerate() {
+      let distance = descriptor.distance(&candidate.descriptor);
+      best = match best {
+        None => Some((index, distance, u32::MAX)),
+        Some((_, first, _)) if distance < first => Some((index, distance, first)),
+        Some((i, first, second)) => Some((i, first, second.min(distance)))
+      };
+    }
+    best
+  };
+
+  features.iter().enumerate().filter_map(|(index, feature)| {
+    let (other, first, second) = nearest(&feature.descriptor, others)?;
+    if (first as f64) >= max_ratio * (second as f64) {
+      return None;
+    }
+    let (back, _, _) = nearest(&others[other].descriptor, features)?;
+    if back == index { Some((index, other)) } else { None }
+  }).collect()
+}
+
+// whether FAST_ARC contiguous pixels of the circle pass the test
+fn has_arc<F: Fn(f64) -> bool>(circle: &[f64; 16], test: F) -> bool {
+  let mut run = 0;
+  // going around twice catches the arcs wrapping past the start
+  for i in 0..2 * circle.len() {
+    if test(circle[i % circle.len()]) {
+      run += 1;
+      if run >= FAST_ARC {
+        return true;
+      }
+    }
+    else {
+      run = 0;
+    }
+  }
+  false
+}
+
+// Non-maximum suppression: positive scores above `min_score` that are the
+// largest within `radius` pixels, the strongest first. Of equal neighbours the
+// first one in row order wins.
+fn local_maxima(scores: &[f64], width: u32, height: u32, min_score: f64, radius: u32) -> Vec<Keypoint> {
+  let radius = radius as i64;
+  let mut keypoints = Vec::new();
+
+  for y in 0..height as i64 {
+    for x in 0..width as i64 {
+      let index = (y as usize) * (width as usize) + x as usize;
+      let score = scores[index];
+      if score <= 0.0 || score < min_score {
+        continue;
+      }
+
+      let mut is_maximum = true;
+      'window: for ny in (y - radius).max(0)..(y + radius + 1).min(height as i64) {
+        for nx in (x - radius).max(0)..(x + radius + 1).min(width as i64) {
+          let neighbour_index = (ny as usize) * (width as usize) + nx as usize;
+          let neighbour = scores[neighbour_index];
+          if neighbour > score || (neighbour == score && neighbour_index < index) {
+            is_maximum = false;
+            break 'window;
+          }
+        }
+      }
+
+      if is_maximum {
+        keypoints.push(Keypoint { x: x as u32, y: y as u32, score });
+      }
+    }
+  }
+
+  keypoints.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap());
+  keypoints
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::path::PathBuf;
+  use image::image::{DynamicImage, GrayImage, Luma};
+
+  const SQUARE_CORNERS: [(i64, i64); 4] = [(10, 10), (29, 10), (10, 29), (29, 29)];
+
+  // a bright square from (10, 10) to (29, 29)
+  fn square() -> Image {
+    let buffer = GrayImage::from_fn(40, 40, |x, y| {
+      Luma([if (10..30).contains(&x) && (10..30).contains(&y) { 200 } else { 20 }])
+    });
+    Image::new(&PathBuf::from("nobody cares"), DynamicImage::ImageLuma8(buffer))
+  }
+
+  fn assert_finds_the_square_corners(keypoints: &[Keypoint]) {
+    assert_eq!(keypoints.len(), 4);
+    for &(x, y) in SQUARE_CORNERS.iter() {
+      assert!(keypoints.iter().any(|k| (k.x as i64 - x).abs() <= 2 && (k.y as i64 - y).abs() <= 2),
+              "no keypoint near ({}, {}) in {:?}", x, y, keypoints);
+    }
+  }
+
+  #[test]
+  fn harris_finds_the_corners_of_a_square() {
+    assert_finds_the_square_corners(&square().harris_corners(0.1, 3));
+  }
+
+  #[test]
+  fn shi_tomasi_finds_the_corners_of_a_square() {
+    assert_finds_the_square_corners(&square().shi_tomasi_corners(0.1, 3));
+  }
+
+  #[test]
+  fn fast_finds_the_corners_of_a_square() {
+    let keypoints = square().fast_corners(50, 3);
+    assert_finds_the_square_corners(&keypoints);
+    for keypoint in keypoints.iter() {
+      assert!(keypoint.score > 0.0);
+    }
+  }
+
+  #[test]
+  fn features_match_their_shifted_copies() {
+    let texture = |x: u32, y: u32| ((x / 5 * 37 + y / 7 * 91 + (x / 11) * (y / 3) * 13) % 200 + 30) as u8;
+    let buffer = GrayImage::from_fn(80, 80, |x, y| Luma([texture(x, y)]));
+    let shifted = GrayImage::from_fn(80, 80, |x, y| Luma([texture(x + 6, y + 4)]));
+    let path = PathBuf::from("nobody cares");
+
+    let features = Image::new(&path, DynamicImage::ImageLuma8(buffer)).orb_features(500);
+    let others = Image::new(&path, DynamicImage::ImageLuma8(shifted)).orb_features(500);
+    let matches = match_features(&features, &others, 0.8);
+
+    assert!(matches.len() >= 10);
+    let consistent = matches.iter().filter(|&&(a, b)| {
+      let (a, b) = (features[a].keypoint, others[b].keypoint);
+      (a.x as i64, a.y as i64) == (b.x as i64 + 6, b.y as i64 + 4)
+    }).count();
+    assert!(consistent * 10 >= matches.len() * 9, "{} of {} matches are right", consistent, matches.len());
+  }
+
+  #[test]
+  fn suppresses_weaker_neighbours() {
+    let scores = [1.0, 3.0, 2.0, 0.0, 0.5, 0.0, 0.5, 3.0];
+    let keypoints = local_maxima(&scores, 8, 1, 0.0, 1);
+
+    assert_eq!(keypoints.iter().map(|k| k.x).collect::<Vec<_>>(), vec![1, 7, 4]);
+  }
+}
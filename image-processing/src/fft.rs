@@ -0,0 +1,331 @@
+use std::f64::consts::PI;
+use std::ops::{Add, Sub, Mul};
+
+use image::Image;
+use image::image::{DynamicImage, GenericImageView, GrayImage, ImageBuffer, Luma};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Complex {
+  pub re: f64,
+  pub im: f64
+}
+
+impl Complex {
+  pub fn new(re: f64, im: f64) -> Self {
+    Self { re, im }
+  }
+
+  pub fn norm(&self) -> f64 {
+    (self.re * self.re + self.im * self.im).sqrt()
+  }
+
+  fn scale(&self, factor: f64) -> Self {
+    Self::new(self.re * factor, self.im * factor)
+  }
+}
+
+impl Add for Complex {
+  type Output = Complex;
+  fn add(self, other: Complex) -> Complex { Complex::new(self.re + other.re, self.im + other.im) }
+}
+
+impl Sub for Complex {
+  type Output = Complex;
+  fn sub(self, other: Complex) -> Complex { Complex::new(self.re - other.re, self.im - other.im) }
+}
+
+impl Mul for Complex {
+  type Output = Complex;
+  fn mul(self, other: Complex) -> Complex {
+    Complex::new(self.re * other.re - self.im * other.im, self.re * other.im + self.im * other.re)
+  }
+}
+
+// Iterative radix-2 Cooley-Tukey, `data.len()` has to be a power of two.
+// The inverse transform isn't normalized.
+fn fft_in_place(data: &mut [Complex], inverse: bool) {
+  let n = data.len();
+  if n < 2 {
+    return;
+  }
+
+  // bit reversal permutation
+  let mut j = 0;
+  for i in 1..n {
+    let mut bit = n >> 1;
+    while j & bit != 0 {
+      j ^= bit;
+      bit >>= 1;
+    }
+    j |= bit;
+    if i < j {
+      data.swap(i, j);
+    }
+  }
+
+  let sign = if inverse { 1.0 } else { -1.0 };
+  let mut length = 2;
+  while length <= n {
+    let angle = sign * 2.0 * PI / (length as f64);
+    let step = Complex::new(angle.cos(), angle.sin());
+    for start in (0..n).step_by(length) {
+      let mut twiddle = Complex::new(1.0, 0.0);
+      for k in 0..length / 2 {
+        let even = data[start + k];
+        let odd = data[start + k + length / 2] * twiddle;
+        data[start + k] = even + odd;
+        data[start + k + length / 2] = even - odd;
+        twiddle = twiddle * step;
+      }
+    }
+    length <<= 1;
+  }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum FilterShape {
+  Ideal,
+  Gaussian,
+  Butterworth { order: u32 }
+}
+
+impl FilterShape {
+  // low-pass response at `distance` from the center, both in cycles per pixel
+  fn low_pass(&self, distance: f64, cutoff: f64) -> f64 {
+    if cutoff <= 0.0 {
+      return if distance == 0.0 { 1.0 } else { 0.0 };
+    }
+    match *self {
+      FilterShape::Ideal => if distance <= cutoff { 1.0 } else { 0.0 },
+      FilterShape::Gaussian => (-(distance * distance) / (2.0 * cutoff * cutoff)).exp(),
+      FilterShape::Butterworth { order } => 1.0 / (1.0 + (distance / cutoff).powi(2 * order.max(1) as i32))
+    }
+  }
+}
+
+// Frequencies are in cycles per pixel, between 0 and 0.5 along each axis.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum FilterBand {
+  LowPass { cutoff: f64 },
+  HighPass { cutoff: f64 },
+  BandPass { low: f64, high: f64 },
+  // rejects the frequency (u, v) and its mirror image, e.g. a periodic scan artifact
+  Notch { u: f64, v: f64, radius: f64 }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct FrequencyFilter {
+  pub band: FilterBand,
+  pub shape: FilterShape
+}
+
+impl FrequencyFilter {
+  pub fn response(&self, u: f64, v: f64) -> f64 {
+    let distance = (u * u + v * v).sqrt();
+    let shape = &self.shape;
+
+    match self.band {
+      FilterBand::LowPass { cutoff } => shape.low_pass(distance, cutoff),
+      FilterBand::HighPass { cutoff } => 1.0 - shape.low_pass(distance, cutoff),
+      FilterBand::BandPass { low, high } =>
+        shape.low_pass(distance, high) * (1.0 - shape.low_pass(distance, low)),
+      FilterBand::Notch { u: notch_u, v: notch_v, radius } => {
+        let to_notch = ((u - notch_u).powi(2) + (v - notch_v).powi(2)).sqrt();
+        let to_mirror = ((u + notch_u).powi(2) + (v + notch_v).powi(2)).sqrt();
+        (1.0 - shape.low_pass(to_notch, radius)) * (1.0 - shape.low_pass(to_mirror, radius))
+      }
+    }
+  }
+}
+
+// The 2D discrete Fourier transform of a single channel. The data is padded to
+// power of two dimensions by repeating the edges.
+#[derive(Debug, Clone)]
+pub struct Spectrum {
+  width: usize,
+  height: usize,
+  image_width: u32,
+  image_height: u32,
+  coefficients: Vec<Complex>
+}
+
+impl Spectrum {
+  pub fn forward(values: &[f64], image_width: u32, image_height: u32) -> Self {
+    let width = (image_width as usize).next_power_of_two();
+    let height = (image_height as usize).next_power_of_two();
+
+    let mut coefficients = Vec::with_capacity(width * height);
+    for y in 0..height {
+      let source_y = y.min(image_height.saturating_sub(1) as usize);
+      for x in 0..width {
+        let source_x = x.min(image_width.saturating_sub(1) as usize);
+        let value = values.get(source_y * (image_width as usize) + source_x).cloned().unwrap_or(0.0);
+        coefficients.push(Complex::new(value, 0.0));
+      }
+    }
+
+    let mut spectrum = Self { width, height, image_width, image_height, coefficients };
+    spectrum.transform(false);
+    spectrum
+  }
+
+  // back to the spatial domain, cropped to the original size
+  pub fn inverse(&self) -> Vec<f64> {
+    let mut spatial = self.clone();
+    spatial.transform(true);
+
+    let normalization = 1.0 / ((self.width * self.height) as f64);
+    let mut values = Vec::with_capacity((self.image_width as usize) * (self.image_height as usize));
+    for y in 0..self.image_height as usize {
+      for x in 0..self.image_width as usize {
+        values.push(spatial.coefficients[y * self.width + x].re * normalization);
+      }
+    }
+    values
+  }
+
+  fn transform(&mut self, inverse: bool) {
+    let (width, height) = (self.width, self.height);
+
+    for row in self.coefficients.chunks_mut(width) {
+      fft_in_place(row, inverse);
+    }
+
+    let mut column = vec![Complex::new(0.0, 0.0); height];
+    for x in 0..width {
+      for (y, coefficient) in column.iter_mut().enumerate() {
+        *coefficient = self.coefficients[y * width + x];
+      }
+      fft_in_place(&mut column, inverse);
+      for (y, &coefficient) in column.iter().enumerate() {
+        self.coefficients[y * width + x] = coefficient;
+      }
+    }
+  }
+
+  // the padded size of the transform
+  pub fn dimensions(&self) -> (u32, u32) {
+    (self.width as u32, self.height as u32)
+  }
+
+  // signed frequency of a coefficient, in cycles per pixel
+  fn frequency(&self, x: usize, y: usize) -> (f64, f64) {
+    let signed = |i: usize, n: usize| if i < n / 2 { i as f64 } else { i as f64 - n as f64 };
+    (signed(x, self.width) / (self.width as f64), signed(y, self.height) / (self.height as f64))
+  }
+
+  // frequency shown at a pixel of `log_magnitude`, where the DC term is centered
+  pub fn display_frequency(&self, x: u32, y: u32) -> (f64, f64) {
+    ((x as f64 - (self.width / 2) as f64) / (self.width as f64),
+     (y as f64 - (self.height / 2) as f64) / (self.height as f64))
+  }
+
+  pub fn apply(&mut self, filter: &FrequencyFilter) {
+    for y in 0..self.height {
+      for x in 0..self.width {
+        let (u, v) = self.frequency(x, y);
+        let index = y * self.width + x;
+        self.coefficients[index] = self.coefficients[index].scale(filter.response(u, v));
+      }
+    }
+  }
+
+  // log(1 + |F|) scaled to 0..255, with the DC term in the center
+  pub fn log_magnitude(&self) -> GrayImage {
+    let magnitudes: Vec<f64> = self.coefficients.iter().map(|c| (1.0 + c.norm()).ln()).collect();
+    let max = magnitudes.iter().cloned().fold(0.0, f64::max);
+    let scale = if max > 0.0 { 255.0 / max } else { 0.0 };
+
+    let (width, height) = (self.width, self.height);
+    ImageBuffer::from_fn(width as u32, height as u32, |x, y| {
+      let source_x = (x as usize + width / 2) % width;
+      let source_y = (y as usize + height / 2) % height;
+      Luma([(magnitudes[source_y * width + source_x] * scale).round() as u8])
+    })
+  }
+}
+
+impl Image {
+  // spectrum of the luminance
+  pub fn spectrum(&self) -> Spectrum {
+    let luma = self.as_dynamic_image().to_luma();
+    let (width, height) = luma.dimensions();
+    let values: Vec<f64> = luma.iter().map(|&v| v as f64).collect();
+    Spectrum::forward(&values, width, height)
+  }
+
+  pub fn filter_frequencies(&self, filter: &FrequencyFilter) -> DynamicImage {
+    let (width, height) = self.as_dynamic_image().dimensions();
+
+    let mut spectrum = self.spectrum();
+    spectrum.apply(filter);
+    let values = spectrum.inverse();
+
+    DynamicImage::ImageLuma8(ImageBuffer::from_fn(width, height, |x, y| {
+      let value = values[(y as usize) * (width as usize) + x as usize];
+      Luma([value.round().clamp(0.0, 255.0) as u8])
+    }))
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::path::PathBuf;
+
+  fn stripes(width: u32, height: u32, period: f64) -> Image {
+    let path = PathBuf::from("nobody cares");
+    let buffer = ImageBuffer::from_fn(width, height, |x, _| {
+      Luma([(128.0 + 50.0 * (2.0 * PI * (x as f64) / period).sin()).round() as u8])
+    });
+    Image::new(&path, DynamicImage::ImageLuma8(buffer))
+  }
+
+  #[test]
+  fn inverse_transform_restores_the_values() {
+    let values: Vec<f64> = (0..15).map(|v| (v * v) as f64).collect();
+    let spectrum = Spectrum::forward(&values, 5, 3);
+
+    assert_eq!(spectrum.dimensions(), (8, 4));
+    for (restored, original) in spectrum.inverse().iter().zip(values.iter()) {
+      assert!((restored - original).abs() < 1e-9);
+    }
+  }
+
+  #[test]
+  fn dc_term_is_the_sum_of_the_values() {
+    let spectrum = Spectrum::forward(&[1.0, 2.0, 3.0, 4.0], 2, 2);
+    assert!((spectrum.coefficients[0].re - 10.0).abs() < 1e-9);
+  }
+
+  #[test]
+  fn notch_filter_removes_periodic_stripes() {
+    let image = stripes(32, 8, 8.0);
+    let filter = FrequencyFilter {
+      band: FilterBand::Notch { u: 1.0 / 8.0, v: 0.0, radius: 0.02 },
+      shape: FilterShape::Ideal
+    };
+    let filtered = image.filter_frequencies(&filter);
+
+    for (_, _, pixel) in filtered.pixels() {
+      assert!((pixel[0] as i32 - 128).abs() <= 1);
+    }
+  }
+
+  #[test]
+  fn low_pass_filter_keeps_the_mean() {
+    let image = stripes(16, 16, 4.0);
+    let filter = FrequencyFilter {
+      band: FilterBand::LowPass { cutoff: 0.1 },
+      shape: FilterShape::Butterworth { order: 4 }
+    };
+    let filtered = image.filter_frequencies(&filter);
+
+    for (_, _, pixel) in filtered.pixels() {
+      assert!((pixel[0] as i32 - 128).abs() <= 2);
+    }
+  }
+}
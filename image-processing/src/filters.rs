@@ -0,0 +1,115 @@
+use std::cmp::Ordering;
+use rayon::prelude::*;
+
+// Filters shared by the measurements and detectors working on planes of f64
+// values, one per pixel, and by the denoising of images of any depth.
+
+// normalized 1D gaussian, 2 * radius + 1 taps
+pub fn gaussian_kernel(sigma: f64, radius: i64) -> Vec<f64> {
+  let kernel: Vec<f64> = (-radius..radius + 1)
+  .map(|i| (-((i * i) as f64) / (2.0 * sigma * sigma)).exp())
+  .collect();
+  let sum: f64 = kernel.iter().sum();
+  kernel.iter().map(|k| k / sum).collect()
+}
+
+// separable convolution, clamping at the borders
+pub fn blur(values: &[f64], width: u32, height: u32, kernel: &[f64]) -> Vec<f64> {
+  let (width, height) = (width as i64, height as i64);
+  let radius = (kernel.len() / 2) as i64;
+  let clamp = |v: i64, max: i64| if v < 0 { 0 } else if v > max { max } else { v };
+
+  let mut horizontal = vec![0.0; values.len()];
+  for y in 0..height {
+    for x in 0..width {
+      horizontal[(y * width + x) as usize] = kernel.iter().enumerate()
+      .map(|(k, weight)| weight * values[(y * width + clamp(x + k as i64 - radius, width - 1)) as usize])
+      .sum();
+    }
+  }
+
+  let mut blurred = vec![0.0; values.len()];
+  for y in 0..height {
+    for x in 0..width {
+      blurred[(y * width + x) as usize] = kernel.iter().enumerate()
+      .map(|(k, weight)| weight * horizontal[(clamp(y + k as i64 - radius, height - 1) * width + x) as usize])
+      .sum();
+    }
+  }
+  blurred
+}
+
+// Median filter over a (2 * radius + 1) square window, clamped at the borders,
+// for every channel of interleaved samples, ordered by `compare`, which has to
+// be a total order. The rows are spread over the threads, each reusing its
+// window from one sample to the next.
+pub fn median<T, F>(samples: &[T], width: u32, height: u32, channels: usize, radius: u32, compare: F) -> Vec<T>
+  where T: Copy + Send + Sync, F: Fn(T, T) -> Ordering + Sync {
+  let (width, height, radius) = (width as i64, height as i64, radius as i64);
+  let mut filtered = samples.to_vec();
+  if width == 0 {
+    return filtered;
+  }
+
+  filtered.par_chunks_mut(width as usize * channels).enumerate().for_each(|(r, row)| {
+    let mut window = Vec::with_capacity(((2 * radius + 1) * (2 * radius + 1)) as usize);
+    for c in 0..width {
+      for channel in 0..channels {
+        window.clear();
+        for dy in -radius..radius + 1 {
+          for dx in -radius..radius + 1 {
+            let x = (c + dx).max(0).min(width - 1);
+            let y = (r as i64 + dy).max(0).min(height - 1);
+            window.push(samples[(y * width + x) as usize * channels + channel]);
+          }
+        }
+        window.sort_unstable_by(|&a, &b| compare(a, b));
+        row[c as usize * channels + channel] = window[window.len() / 2];
+      }
+    }
+  });
+  filtered
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn blurring_keeps_flat_areas_and_spreads_peaks() {
+    let kernel = gaussian_kernel(1.0, 2);
+    assert_eq!(kernel.len(), 5);
+    assert!((kernel.iter().sum::<f64>() - 1.0).abs() < 1e-12);
+
+    let flat = blur(&[3.0; 12], 4, 3, &kernel);
+    assert!(flat.iter().all(|value| (value - 3.0).abs() < 1e-12));
+
+    let mut peak = vec![0.0; 25];
+    peak[12] = 1.0;
+    let blurred = blur(&peak, 5, 5, &kernel);
+    assert!((blurred.iter().sum::<f64>() - 1.0).abs() < 1e-12);
+    assert!(blurred[12] < 1.0 && blurred[11] > 0.0 && blurred[11] == blurred[7]);
+  }
+
+  #[test]
+  fn median_keeps_edges_and_drops_outliers() {
+    // a gray and alpha image, half dark and half light, with a bright speck
+    let mut samples: Vec<u16> = (0..24).flat_map(|i| vec![if i % 6 < 3 { 100 } else { 900 }, 7]).collect();
+    samples[2 * 7] = 65535;
+    let filtered = median(&samples, 6, 4, 2, 1, |a: u16, b: u16| a.cmp(&b));
+    for (i, pixel) in filtered.chunks(2).enumerate() {
+      assert_eq!(pixel, &[if i % 6 < 3 { 100 } else { 900 }, 7][..]);
+    }
+  }
+
+  #[test]
+  fn median_orders_nan_after_every_number() {
+    let samples: Vec<f32> = (0..400).map(|i| if i % 3 == 0 { f32::NAN } else { (i % 7) as f32 }).collect();
+    let filtered = median(&samples, 20, 20, 1, 3, <f32 as ::depth::Sample>::compare);
+    // away from the borders a third of every window is NaN, so the middle is a number
+    for y in 3..17 {
+      assert!((3..17).all(|x| !filtered[y * 20 + x].is_nan()));
+    }
+  }
+}
@@ -0,0 +1,458 @@
+use std::cmp::Ordering;
+use failure::{err_msg, format_err, Error};
+use rayon::prelude::*;
+
+use image::Image;
+use image::image::{DynamicImage, ImageBuffer, Rgb, RgbImage};
+use depth::TypedImage;
+use pyramid::{Level, Pyramid};
+
+// how many pixels the response is recovered from, at most
+const RESPONSE_SAMPLES: usize = 100;
+// how much the smoothness of the response counts against fitting the samples
+pub const SMOOTHNESS: f64 = 50.0;
+// the levels of the pyramid the local tone mapping compresses
+const LOCAL_LEVELS: usize = 6;
+// how many decades of luminance a display shows
+const DISPLAY_DECADES: f64 = 2.0;
+const GAMMA: f64 = 2.2;
+
+// Debevec and Malik's hat, trusting the middle levels most and the clipped ones
+// not at all.
+fn weight(level: u8) -> f64 {
+  let level = level as f64;
+  level.min(255.0 - level)
+}
+
+// seconds, as a number or a fraction like 1/60
+pub fn parse_exposure(text: &str) -> Result<f64, Error> {
+  let text = text.trim();
+  let seconds = match text.find('/') {
+    Some(slash) => match (text[..slash].trim().parse::<f64>(), text[slash + 1..].trim().parse::<f64>()) {
+      (Ok(numerator), Ok(denominator)) => numerator / denominator,
+      _ => f64::NAN
+    },
+    None => text.parse::<f64>().unwrap_or(f64::NAN)
+  };
+  if seconds > 0.0 && seconds.is_finite() {
+    Ok(seconds)
+  } else {
+    Err(format_err!("{} isn't an exposure time in seconds", text))
+  }
+}
+
+// the exposures as RGB, once they are known to make a bracket
+fn brackets(images: &[Image], exposures: &[f64]) -> Result<Vec<RgbImage>, Error> {
+  if images.len() < 2 {
+    return Err(err_msg("merging needs at least two exposures"));
+  }
+  if images.len() != exposures.len() {
+    return Err(format_err!("{} images but {} exposure times", images.len(), exposures.len()));
+  }
+  if let Some(exposure) = exposures.iter().find(|&&exposure| !(exposure > 0.0 && exposure.is_finite())) {
+    return Err(format_err!("{} isn't an exposure time in seconds", exposure));
+  }
+
+  let brackets: Vec<RgbImage> = images.iter().map(|image| image.as_dynamic_image().to_rgb()).collect();
+  let dimensions = brackets[0].dimensions();
+  if brackets.iter().any(|bracket| bracket.dimensions() != dimensions) {
+    return Err(err_msg("the exposures aren't all the same size"));
+  }
+  Ok(brackets)
+}
+
+// The log exposure behind each of the 256 levels of every channel, mid gray
+// being 0.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Response {
+  pub channels: Vec<Vec<f64>>
+}
+
+impl Response {
+  // Debevec and Malik's least squares fit to a grid of pixels of the same scene
+  // taken at different exposure times.
+  pub fn recover(images: &[Image], exposures: &[f64], smoothness: f64) -> Result<Self, Error> {
+    let brackets = brackets(images, exposures)?;
+    let channels = (0..3).into_par_iter()
+    .map(|channel| recover_channel(&brackets, exposures, channel, smoothness))
+    .collect();
+    Ok(Response { channels })
+  }
+
+  pub fn log_exposure(&self, channel: usize, level: u8) -> f64 {
+    self.channels[channel][level as usize]
+  }
+}
+
+fn recover_channel(brackets: &[RgbImage], exposures: &[f64], channel: usize, smoothness: f64) -> Vec<f64> {
+  let (width, height) = brackets[0].dimensions();
+  let side = (RESPONSE_SAMPLES as f64).sqrt() as u32;
+
+  // the levels of every pixel of the grid, leaving out those clipped in every
+  // exposure since nothing tells how bright they are
+  let pixels: Vec<Vec<u8>> = (0..side)
+  .flat_map(|j| (0..side).map(move |i| (i, j)))
+  .map(|(i, j)| ((2 * i + 1) * width / (2 * side), (2 * j + 1) * height / (2 * side)))
+  .map(|(x, y)| brackets.iter().map(|bracket| bracket.get_pixel(x, y)[channel]).collect::<Vec<u8>>())
+  .filter(|levels| levels.iter().any(|&level| weight(level) > 0.0))
+  .collect();
+
+  // the response for the 256 levels, then the log radiance of every pixel,
+  // fitted through the normal equations
+  let unknowns = 256 + pixels.len();
+  let mut normal = vec![vec![0.0; unknowns]; unknowns];
+  let mut right = vec![0.0; unknowns];
+  {
+    let mut add_equation = |terms: &[(usize, f64)], value: f64| {
+      for &(i, a) in terms {
+        for &(j, b) in terms {
+          normal[i][j] += a * b;
+        }
+        right[i] += a * value;
+      }
+    };
+
+    for (pixel, levels) in pixels.iter().enumerate() {
+      for (&level, exposure) in levels.iter().zip(exposures.iter()) {
+        let weight = weight(level);
+        add_equation(&[(level as usize, weight), (256 + pixel, -weight)], weight * exposure.ln());
+      }
+    }
+    add_equation(&[(128, 1.0)], 0.0);
+    for level in 1..255 {
+      let weight = smoothness * weight(level as u8);
+      add_equation(&[(level - 1, weight), (level, -2.0 * weight), (level + 1, weight)], 0.0);
+    }
+  }
+
+  let mut response = solve(normal, right);
+  response.truncate(256);
+  response
+}
+
+// Gaussian elimination with partial pivoting. The unknowns nothing pins down
+// are left at 0.
+fn solve(mut matrix: Vec<Vec<f64>>, mut right: Vec<f64>) -> Vec<f64> {
+  let size = right.len();
+  for column in 0..size {
+    let pivot = (column..size)
+    .max_by(|&a, &b| matrix[a][column].abs().partial_cmp(&matrix[b][column].abs()).unwrap_or(Ordering::Equal))
+    .unwrap();
+    matrix.swap(column, pivot);
+    right.swap(column, pivot);
+
+    let diagonal = matrix[column][column];
+    if diagonal.abs() < 1e-12 {
+      continue;
+    }
+    let (above, below) = matrix.split_at_mut(column + 1);
+    let pivot_row = &above[column];
+    for (offset, row) in below.iter_mut().enumerate() {
+      let factor = row[column] / diagonal;
+      if factor == 0.0 {
+        continue;
+      }
+      for (value, pivot_value) in row[column..].iter_mut().zip(pivot_row[column..].iter()) {
+        *value -= factor * pivot_value;
+      }
+      right[column + 1 + offset] -= factor * right[column];
+    }
+  }
+
+  let mut solution = vec![0.0; size];
+  for row in (0..size).rev() {
+    let diagonal = matrix[row][row];
+    if diagonal.abs() < 1e-12 {
+      continue;
+    }
+    let known: f64 = matrix[row][row + 1..].iter().zip(solution[row + 1..].iter()).map(|(a, x)| a * x).sum();
+    solution[row] = (right[row] - known) / diagonal;
+  }
+  solution
+}
+
+// The radiance of the scene as RGB floats, mid gray at an exposure of a second
+// being 1.
+pub fn merge_exposures(images: &[Image], exposures: &[f64], response: &Response) -> Result<TypedImage<f32>, Error> {
+  let brackets = brackets(images, exposures)?;
+  let (width, height) = brackets[0].dimensions();
+  let log_exposures: Vec<f64> = exposures.iter().map(|exposure| exposure.ln()).collect();
+  let by_exposure = |a: &usize, b: &usize| exposures[*a].partial_cmp(&exposures[*b]).unwrap_or(Ordering::Equal);
+  let shortest = (0..exposures.len()).min_by(by_exposure).unwrap();
+  let longest = (0..exposures.len()).max_by(by_exposure).unwrap();
+
+  let mut samples = vec![0.0; width as usize * height as usize * 3];
+  samples.par_chunks_mut(width as usize * 3).enumerate().for_each(|(y, row)| {
+    for (x, pixel) in row.chunks_mut(3).enumerate() {
+      for (channel, sample) in pixel.iter_mut().enumerate() {
+        let levels: Vec<u8> = brackets.iter().map(|bracket| bracket.get_pixel(x as u32, y as u32)[channel]).collect();
+        let (mut total, mut weights) = (0.0, 0.0);
+        for (&level, log_exposure) in levels.iter().zip(log_exposures.iter()) {
+          let weight = weight(level);
+          total += weight * (response.log_exposure(channel, level) - log_exposure);
+          weights += weight;
+        }
+        let log_radiance = if weights > 0.0 {
+          total / weights
+        } else {
+          // clipped in every exposure, so as bright as the shortest one shows
+          // or as dark as the longest one does
+          let which = if levels[shortest] == 255 { shortest } else { longest };
+          response.log_exposure(channel, levels[which]) - log_exposures[which]
+        };
+        *sample = log_radiance.exp() as f32;
+      }
+    }
+  });
+  TypedImage::new(width, height, 3, samples)
+}
+
+// recovers the response of the camera, then merges with it
+pub fn merge_bracket(images: &[Image], exposures: &[f64]) -> Result<TypedImage<f32>, Error> {
+  let response = Response::recover(images, exposures, SMOOTHNESS)?;
+  merge_exposures(images, exposures, &response)
+}
+
+// Ways of fitting the luminances of a radiance map into what a display shows.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ToneMapping {
+  // Reinhard's global operator. `key` is how bright the average luminance
+  // shows, `white` the luminance, as a multiple of the average, from which on
+  // everything is white, None for the brightest pixel.
+  Reinhard { key: f64, white: Option<f64> },
+  // Drago's adaptive logarithm, a lower `bias` brightening the dark parts.
+  Drago { bias: f64 },
+  // After Mantiuk, compresses the contrast of every band of a laplacian pyramid
+  // of the log luminance to at most `contrast` decades, keeping the details
+  // while the overall range fits the display. `saturation` scales the colors.
+  Local { contrast: f64, saturation: f64 }
+}
+
+impl ToneMapping {
+  pub fn names() -> &'static [&'static str] {
+    &["reinhard", "drago", "local"]
+  }
+
+  // with the default parameters
+  pub fn new(name: &str) -> Result<Self, Error> {
+    match name {
+      "reinhard" => Ok(ToneMapping::Reinhard { key: 0.18, white: None }),
+      "drago" => Ok(ToneMapping::Drago { bias: 0.85 }),
+      "local" => Ok(ToneMapping::Local { contrast: 0.3, saturation: 0.8 }),
+      _ => Err(format_err!("{} isn't a tone mapping, try {}", name, ToneMapping::names().join(", ")))
+    }
+  }
+
+  // the parameters `set` takes, with their values, None for a white point
+  // taken from the image
+  pub fn parameters(&self) -> Vec<(&'static str, Option<f64>)> {
+    match *self {
+      ToneMapping::Reinhard { key, white } => vec![("key", Some(key)), ("white", white)],
+      ToneMapping::Drago { bias } => vec![("bias", Some(bias))],
+      ToneMapping::Local { contrast, saturation } => vec![("contrast", Some(contrast)), ("saturation", Some(saturation))]
+    }
+  }
+
+  // leaves the tone mapping as it was when the value is out of range
+  pub fn set(&mut self, parameter: &str, value: f64) -> Result<(), Error> {
+    let in_range = match (&*self, parameter) {
+      (&ToneMapping::Reinhard { .. }, "key") => value > 0.0,
+      (&ToneMapping::Reinhard { .. }, "white") => value > 0.0,
+      (&ToneMapping::Drago { .. }, "bias") => value > 0.0 && value <= 1.0,
+      (&ToneMapping::Local { .. }, "contrast") => value > 0.0,
+      (&ToneMapping::Local { .. }, "saturation") => value >= 0.0,
+      _ => return Err(format_err!("this tone mapping has no parameter {}", parameter))
+    };
+    if !(in_range && value.is_finite()) {
+      return Err(format_err!("{} is out of range for {}", value, parameter));
+    }
+
+    match (self, parameter) {
+      (&mut ToneMapping::Reinhard { ref mut key, .. }, "key") => *key = value,
+      (&mut ToneMapping::Reinhard { ref mut white, .. }, "white") => *white = Some(value),
+      (&mut ToneMapping::Drago { ref mut bias }, "bias") => *bias = value,
+      (&mut ToneMapping::Local { ref mut contrast, .. }, "contrast") => *contrast = value,
+      (&mut ToneMapping::Local { ref mut saturation, .. }, "saturation") => *saturation = value,
+      _ => ()
+    }
+    Ok(())
+  }
+
+  // to 8 bits, gamma encoded for display
+  pub fn apply(&self, radiance: &TypedImage<f32>) -> Result<DynamicImage, Error> {
+    if radiance.samples.is_empty() {
+      return Err(err_msg("there's nothing to tone map in an empty image"));
+    }
+
+    let pixels: Vec<[f64; 3]> = radiance.samples.par_chunks(radiance.channels).map(|pixel| {
+      let rgb = if pixel.len() < 3 { [pixel[0]; 3] } else { [pixel[0], pixel[1], pixel[2]] };
+      [rgb[0].max(0.0) as f64, rgb[1].max(0.0) as f64, rgb[2].max(0.0) as f64]
+    }).collect();
+    let luminances: Vec<f64> = pixels.par_iter().map(|pixel| 0.2126 * pixel[0] + 0.7152 * pixel[1] + 0.0722 * pixel[2]).collect();
+
+    let (mapped, saturation) = match *self {
+      ToneMapping::Reinhard { key, white } => (reinhard(&luminances, key, white), 1.0),
+      ToneMapping::Drago { bias } => (drago(&luminances, bias), 1.0),
+      ToneMapping::Local { contrast, saturation } =>
+        (local(&luminances, radiance.width, radiance.height, contrast), saturation)
+    };
+
+    let encode = |value: f64| (value.clamp(0.0, 1.0).powf(1.0 / GAMMA) * 255.0).round() as u8;
+    let samples: Vec<u8> = pixels.par_iter().zip(luminances.par_iter()).zip(mapped.par_iter())
+    .flat_map(|((pixel, &luminance), &mapped)| {
+      let color = |value: f64| if luminance > 0.0 { (value / luminance).powf(saturation) * mapped } else { mapped };
+      vec![encode(color(pixel[0])), encode(color(pixel[1])), encode(color(pixel[2]))]
+    })
+    .collect();
+    Ok(DynamicImage::ImageRgb8(ImageBuffer::<Rgb<u8>, _>::from_raw(radiance.width, radiance.height, samples).unwrap()))
+  }
+}
+
+fn log_average(luminances: &[f64]) -> f64 {
+  let total: f64 = luminances.par_iter().map(|&luminance| (1e-6 + luminance).ln()).sum();
+  (total / luminances.len() as f64).exp()
+}
+
+fn reinhard(luminances: &[f64], key: f64, white: Option<f64>) -> Vec<f64> {
+  let scale = key / log_average(luminances);
+  let white = match white {
+    Some(white) => white * key,
+    None => luminances.iter().cloned().fold(0.0, f64::max) * scale
+  };
+  luminances.par_iter().map(|&luminance| {
+    let scaled = luminance * scale;
+    if white > 0.0 { scaled * (1.0 + scaled / (white * white)) / (1.0 + scaled) } else { scaled / (1.0 + scaled) }
+  }).collect()
+}
+
+fn drago(luminances: &[f64], bias: f64) -> Vec<f64> {
+  let average = log_average(luminances);
+  let maximum = luminances.iter().cloned().fold(0.0, f64::max) / average;
+  if maximum <= 0.0 {
+    return vec![0.0; luminances.len()];
+  }
+  let exponent = bias.ln() / 0.5f64.ln();
+  let scale = 1.0 / (maximum + 1.0).log10();
+  luminances.par_iter().map(|&luminance| {
+    let luminance = luminance / average;
+    scale * (luminance + 1.0).ln() / (2.0 + 8.0 * (luminance / maximum).powf(exponent)).ln()
+  }).collect()
+}
+
+fn local(luminances: &[f64], width: u32, height: u32, contrast: f64) -> Vec<f64> {
+  let logs = luminances.iter().map(|&luminance| luminance.max(1e-6).log10()).collect();
+  let mut pyramid = Pyramid::laplacian(Level { width, height, channels: vec![logs] }, LOCAL_LEVELS);
+  let coarsest = pyramid.levels.len() - 1;
+
+  // large contrasts shrink to at most `contrast`, small ones hardly change
+  for level in pyramid.levels[..coarsest].iter_mut() {
+    for detail in level.channels[0].iter_mut() {
+      *detail /= 1.0 + detail.abs() / contrast;
+    }
+  }
+
+  // the coarsest level holds the overall range, which has to fit the display
+  {
+    let base = &mut pyramid.levels[coarsest].channels[0];
+    let minimum = base.iter().cloned().fold(f64::INFINITY, f64::min);
+    let maximum = base.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    let scale = if maximum > minimum { (DISPLAY_DECADES / (maximum - minimum)).min(1.0) } else { 1.0 };
+    for value in base.iter_mut() {
+      *value = (*value - maximum) * scale;
+    }
+  }
+
+  // the brightest percent are white
+  let logs = pyramid.collapse().channels.remove(0);
+  let mut sorted = logs.clone();
+  sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+  let white = sorted[(sorted.len() - 1) * 99 / 100];
+  logs.iter().map(|&log| 10f64.powf(log - white)).collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::path::PathBuf;
+  use image::image::GenericImageView;
+
+  // a scene spanning three decades, taken through a gamma curve
+  fn bracket(exposures: &[f64]) -> (Vec<Image>, Vec<f64>) {
+    let radiance = |x: u32, y: u32| 0.01 * 1000f64.powf(x as f64 / 63.0) * (1.0 + y as f64 / 31.0);
+    let images = exposures.iter().map(|exposure| {
+      let buffer = ImageBuffer::from_fn(64, 32, |x, y| {
+        let level = ((radiance(x, y) * exposure).min(1.0).powf(1.0 / 2.2) * 255.0).round() as u8;
+        Rgb([level, level, level])
+      });
+      Image::new(&PathBuf::from("bracket.png"), DynamicImage::ImageRgb8(buffer))
+    }).collect();
+    let truth = (0..32).flat_map(|y| (0..64).map(move |x| radiance(x, y))).collect();
+    (images, truth)
+  }
+
+  #[test]
+  fn parses_exposure_times() {
+    assert_eq!(parse_exposure("1/4").unwrap(), 0.25);
+    assert_eq!(parse_exposure(" 2.5 ").unwrap(), 2.5);
+    assert!(parse_exposure("0").is_err());
+    assert!(parse_exposure("1/0").is_err());
+    assert!(parse_exposure("fast").is_err());
+  }
+
+  #[test]
+  fn merges_a_bracket_into_relative_radiance() {
+    let exposures = [1.0 / 16.0, 0.25, 1.0, 4.0, 16.0];
+    let (images, truth) = bracket(&exposures);
+    let merged = merge_bracket(&images, &exposures).unwrap();
+    assert_eq!((merged.width, merged.height, merged.channels), (64, 32, 3));
+
+    let reference = merged.samples[0] as f64 / truth[0];
+    for index in (0..truth.len()).step_by(97) {
+      let ratio = merged.samples[index * 3] as f64 / truth[index];
+      assert!((ratio / reference).ln().abs() < 0.15, "off by {} at {}", ratio / reference, index);
+    }
+  }
+
+  #[test]
+  fn refuses_mismatched_brackets() {
+    let (images, _) = bracket(&[1.0, 2.0]);
+    assert!(merge_bracket(&images, &[1.0]).is_err());
+    assert!(merge_bracket(&images[..1], &[1.0]).is_err());
+    assert!(merge_bracket(&images, &[1.0, -2.0]).is_err());
+  }
+
+  #[test]
+  fn tone_maps_a_ramp_in_order() {
+    let samples: Vec<f32> = (0..256).map(|x| 0.001 * 10f32.powf(x as f32 / 51.0)).collect();
+    let ramp = TypedImage::new(256, 1, 1, samples).unwrap();
+    for name in ToneMapping::names() {
+      let mapped = ToneMapping::new(name).unwrap().apply(&ramp).unwrap();
+      let levels: Vec<u8> = (0..256).map(|x| mapped.get_pixel(x, 0)[0]).collect();
+      assert!(levels[255] > 200 && levels[0] < 50, "{} maps the ramp to {:?}", name, levels);
+      if *name != "local" {
+        assert!(levels.windows(2).all(|pair| pair[0] <= pair[1]), "{} maps the ramp to {:?}", name, levels);
+      }
+    }
+  }
+
+  #[test]
+  fn refuses_to_tone_map_an_empty_image() {
+    let empty = TypedImage::new(0, 0, 3, Vec::new()).unwrap();
+    for name in ToneMapping::names() {
+      assert!(ToneMapping::new(name).unwrap().apply(&empty).is_err());
+    }
+  }
+
+  #[test]
+  fn sets_tone_mapping_parameters() {
+    let mut tone_mapping = ToneMapping::new("drago").unwrap();
+    tone_mapping.set("bias", 0.7).unwrap();
+    assert_eq!(tone_mapping, ToneMapping::Drago { bias: 0.7 });
+    assert!(tone_mapping.set("bias", 2.0).is_err());
+    assert_eq!(tone_mapping, ToneMapping::Drago { bias: 0.7 });
+    assert!(tone_mapping.set("key", 0.5).is_err());
+    assert_eq!(ToneMapping::new("reinhard").unwrap().parameters(), vec![("key", Some(0.18)), ("white", None)]);
+    assert!(ToneMapping::new("filmic").is_err());
+  }
+}
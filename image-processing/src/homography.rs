@@ -0,0 +1,254 @@
+use std::cmp::Ordering;
+use std::ops::Mul;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+
+// RANSAC draws its samples in a fixed pseudo-random order, so that the same
+// matches always give the same homography
+const RANSAC_SEED: u64 = 0x4a2d;
+// below this the linear system is considered singular
+const PIVOT_EPSILON: f64 = 1e-12;
+
+// a point and the point it should be mapped on
+pub type PointPair = ((f64, f64), (f64, f64));
+
+// A projective transform of the plane, as a row-major 3x3 matrix.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Homography {
+  pub matrix: [f64; 9]
+}
+
+impl Homography {
+  pub fn identity() -> Self {
+    Self { matrix: [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0] }
+  }
+
+  pub fn translation(x: f64, y: f64) -> Self {
+    Self { matrix: [1.0, 0.0, x, 0.0, 1.0, y, 0.0, 0.0, 1.0] }
+  }
+
+  pub fn apply(&self, point: (f64, f64)) -> (f64, f64) {
+    let m = &self.matrix;
+    let w = m[6] * point.0 + m[7] * point.1 + m[8];
+    ((m[0] * point.0 + m[1] * point.1 + m[2]) / w,
+     (m[3] * point.0 + m[4] * point.1 + m[5]) / w)
+  }
+
+  pub fn inverse(&self) -> Option<Self> {
+    let m = &self.matrix;
+    let cofactors = [
+      m[4] * m[8] - m[5] * m[7], m[2] * m[7] - m[1] * m[8], m[1] * m[5] - m[2] * m[4],
+      m[5] * m[6] - m[3] * m[8], m[0] * m[8] - m[2] * m[6], m[2] * m[3] - m[0] * m[5],
+      m[3] * m[7] - m[4] * m[6], m[1] * m[6] - m[0] * m[7], m[0] * m[4] - m[1] * m[3]
+    ];
+    let determinant = m[0] * cofactors[0] + m[1] * cofactors[3] + m[2] * cofactors[6];
+    if determinant.abs() < PIVOT_EPSILON {
+      return None;
+    }
+
+    let mut matrix = [0.0; 9];
+    for (inverse, cofactor) in matrix.iter_mut().zip(cofactors.iter()) {
+      *inverse = cofactor / determinant;
+    }
+    Some(Self { matrix })
+  }
+
+  // Least squares fit of the homography mapping every `from` point on its
+  // `to` point. Needs at least 4 pairs, no 3 of them on a line.
+  pub fn from_points(pairs: &[PointPair]) -> Option<Self> {
+    if pairs.len() < 4 {
+      return None;
+    }
+
+    // Hartley's normalization keeps the system well conditioned
+    let from = normalization(pairs.iter().map(|pair| pair.0));
+    let to = normalization(pairs.iter().map(|pair| pair.1));
+
+    // the normal equations of A h = b, with the last coefficient set to 1
+    let mut normal = [[0.0; 9]; 8];
+    for &(source, target) in pairs {
+      let (x, y) = from.apply(source);
+      let (u, v) = to.apply(target);
+      let rows = [
+        ([x, y, 1.0, 0.0, 0.0, 0.0, -u * x, -u * y], u),
+        ([0.0, 0.0, 0.0, x, y, 1.0, -v * x, -v * y], v)
+      ];
+      for &(ref row, b) in rows.iter() {
+        for i in 0..8 {
+          for j in 0..8 {
+            normal[i][j] += row[i] * row[j];
+          }
+          normal[i][8] += row[i] * b;
+        }
+      }
+    }
+
+    let h = solve(normal)?;
+    let normalized = Homography { matrix: [h[0], h[1], h[2], h[3], h[4], h[5], h[6], h[7], 1.0] };
+    Some(to.inverse()? * normalized * from)
+  }
+
+  // Fits a homography to the pairs, ignoring the ones it can't bring within
+  // `tolerance` pixels. Returns it along with which pairs are inliers.
+  pub fn ransac(pairs: &[PointPair], iterations: usize, tolerance: f64) -> Option<(Self, Vec<bool>)> {
+    if pairs.len() < 4 {
+      return None;
+    }
+
+    let inliers = |homography: &Homography| -> Vec<bool> {
+      pairs.iter().map(|&(from, to)| {
+        let (x, y) = homography.apply(from);
+        (x - to.0).hypot(y - to.1) <= tolerance
+      }).collect()
+    };
+    let count = |inliers: &[bool]| inliers.iter().filter(|&&inlier| inlier).count();
+
+    let mut rng = StdRng::seed_from_u64(RANSAC_SEED);
+    let mut best: Option<Vec<bool>> = None;
+
+    for _ in 0..iterations {
+      let mut sample = Vec::with_capacity(4);
+      while sample.len() < 4 {
+        let index = rng.gen_range(0, pairs.len());
+        if !sample.contains(&index) {
+          sample.push(index);
+        }
+      }
+
+      let sample: Vec<_> = sample.iter().map(|&index| pairs[index]).collect();
+      if let Some(homography) = Homography::from_points(&sample) {
+        let candidate = inliers(&homography);
+        if best.as_ref().is_none_or(|best| count(&candidate) > count(best)) {
+          best = Some(candidate);
+        }
+      }
+    }
+
+    // refit on every inlier of the best sample
+    let best = best?;
+    let consensus: Vec<_> = pairs.iter().zip(best.iter())
+    .filter(|&(_, &inlier)| inlier)
+    .map(|(&pair, _)| pair)
+    .collect();
+    let homography = Homography::from_points(&consensus)?;
+    let inliers = inliers(&homography);
+    Some((homography, inliers))
+  }
+}
+
+// first applies `other`, then `self`
+impl Mul for Homography {
+  type Output = Homography;
+  fn mul(self, other: Homography) -> Homography {
+    let (a, b) = (&self.matrix, &other.matrix);
+    let mut matrix = [0.0; 9];
+    for row in 0..3 {
+      for column in 0..3 {
+        matrix[row * 3 + column] = (0..3).map(|k| a[row * 3 + k] * b[k * 3 + column]).sum();
+      }
+    }
+    Homography { matrix }
+  }
+}
+
+// moves the points' centroid to the origin and scales their mean distance to it to sqrt(2)
+fn normalization<I: Iterator<Item = (f64, f64)> + Clone>(points: I) -> Homography {
+  let count = points.clone().count() as f64;
+  let (sum_x, sum_y) = points.clone().fold((0.0, 0.0), |sum, point| (sum.0 + point.0, sum.1 + point.1));
+  let (center_x, center_y) = (sum_x / count, sum_y / count);
+
+  let mean_distance = points.map(|point| (point.0 - center_x).hypot(point.1 - center_y)).sum::<f64>() / count;
+  let scale = if mean_distance > 0.0 { 2f64.sqrt() / mean_distance } else { 1.0 };
+
+  Homography { matrix: [scale, 0.0, -scale * center_x, 0.0, scale, -scale * center_y, 0.0, 0.0, 1.0] }
+}
+
+// Gaussian elimination with partial pivoting of an augmented 8x9 system. None
+// when it's singular or holds values that aren't finite.
+fn solve(mut system: [[f64; 9]; 8]) -> Option<[f64; 8]> {
+  if system.iter().any(|row| row.iter().any(|value| !value.is_finite())) {
+    return None;
+  }
+  for column in 0..8 {
+    let pivot = (column..8).max_by(|&a, &b| {
+      system[a][column].abs().partial_cmp(&system[b][column].abs()).unwrap_or(Ordering::Equal)
+    })?;
+    if !system[pivot][column].is_finite() || system[pivot][column].abs() < PIVOT_EPSILON {
+      return None;
+    }
+    system.swap(column, pivot);
+
+    let pivot_row = system[column];
+    for (row, values) in system.iter_mut().enumerate() {
+      if row != column {
+        let factor = values[column] / pivot_row[column];
+        for (value, &pivot_value) in values.iter_mut().zip(pivot_row.iter()).skip(column) {
+          *value -= factor * pivot_value;
+        }
+      }
+    }
+  }
+
+  let mut solution = [0.0; 8];
+  for (i, value) in solution.iter_mut().enumerate() {
+    *value = system[i][8] / system[i][i];
+  }
+  Some(solution)
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn assert_close(a: (f64, f64), b: (f64, f64)) {
+    assert!((a.0 - b.0).abs() < 1e-6 && (a.1 - b.1).abs() < 1e-6, "{:?} != {:?}", a, b);
+  }
+
+  fn perspective() -> Homography {
+    Homography { matrix: [1.1, 0.2, 5.0, -0.1, 0.9, 3.0, 0.001, 0.002, 1.0] }
+  }
+
+  #[test]
+  fn inverse_undoes_the_transform() {
+    let homography = perspective();
+    let inverse = homography.inverse().unwrap();
+
+    assert_close(inverse.apply(homography.apply((12.0, 34.0))), (12.0, 34.0));
+    assert_close((inverse * homography).apply((7.0, -3.0)), (7.0, -3.0));
+  }
+
+  #[test]
+  fn fits_four_point_correspondences() {
+    let homography = perspective();
+    let pairs: Vec<_> = [(0.0, 0.0), (100.0, 0.0), (100.0, 50.0), (0.0, 50.0)].iter()
+    .map(|&point| (point, homography.apply(point)))
+    .collect();
+    let fitted = Homography::from_points(&pairs).unwrap();
+
+    assert_close(fitted.apply((30.0, 20.0)), homography.apply((30.0, 20.0)));
+  }
+
+  #[test]
+  fn refuses_points_that_arent_finite() {
+    let pairs = [((0.0, 0.0), (0.0, 0.0)), ((1.0, 0.0), (1.0, 0.0)), ((1.0, 1.0), (1.0, f64::NAN)), ((0.0, 1.0), (0.0, 1.0))];
+    assert!(Homography::from_points(&pairs).is_none());
+  }
+
+  #[test]
+  fn ransac_ignores_outliers() {
+    let shift = Homography::translation(40.0, -5.0);
+    let mut pairs: Vec<_> = (0..20).map(|i| {
+      let point = ((i * 7 % 50) as f64, (i * 13 % 30) as f64);
+      (point, shift.apply(point))
+    }).collect();
+    pairs.push(((10.0, 10.0), (90.0, 90.0)));
+    pairs.push(((20.0, 5.0), (0.0, 0.0)));
+
+    let (fitted, inliers) = Homography::ransac(&pairs, 200, 1.0).unwrap();
+
+    assert_close(fitted.apply((3.0, 4.0)), (43.0, -1.0));
+    assert_eq!(inliers.iter().filter(|&&inlier| inlier).count(), 20);
+    assert!(!inliers[20] && !inliers[21]);
+  }
+}
@@ -0,0 +1,424 @@
+use std::cmp::Reverse;
+use std::f64::consts::PI;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+
+use image::Image;
+use image::image::{GenericImageView, Pixel};
+
+// pixels at least this bright are edges, e.g. the result of `detect_edges`
+const EDGE_LEVEL: u8 = 128;
+// one degree
+const THETA_STEPS: usize = 180;
+// the probabilistic transform visits the edges in a fixed pseudo-random order,
+// so the same image always gives the same segments
+const SEGMENT_SEED: u64 = 0x5eed;
+// circles whose centers and radii are this close describe the same circle
+const DUPLICATE_CIRCLE_DISTANCE: f64 = 2.0;
+
+// the set of points (x, y) where x * cos(theta) + y * sin(theta) = rho
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Line {
+  pub rho: f64,
+  // in radians, between 0 and pi
+  pub theta: f64,
+  pub votes: u32
+}
+
+impl Line {
+  // the end points of the part of the line crossing a width x height image
+  pub fn clip(&self, width: u32, height: u32) -> Option<((f64, f64), (f64, f64))> {
+    let (cos, sin) = (self.theta.cos(), self.theta.sin());
+    let (max_x, max_y) = ((width as f64 - 1.0).max(0.0), (height as f64 - 1.0).max(0.0));
+    let inside = |v: f64, max: f64| v >= -1e-6 && v <= max + 1e-6;
+
+    let mut points = Vec::new();
+    if sin.abs() > 1e-9 {
+      for &x in [0.0, max_x].iter() {
+        let y = (self.rho - x * cos) / sin;
+        if inside(y, max_y) { points.push((x, y)); }
+      }
+    }
+    if cos.abs() > 1e-9 {
+      for &y in [0.0, max_y].iter() {
+        let x = (self.rho - y * sin) / cos;
+        if inside(x, max_x) { points.push((x, y)); }
+      }
+    }
+
+    // corners show up twice, keep the two points furthest apart
+    let mut farthest = None;
+    let mut max_distance = -1.0;
+    for (i, &a) in points.iter().enumerate() {
+      for &b in points[i + 1..].iter() {
+        let distance = (a.0 - b.0).powi(2) + (a.1 - b.1).powi(2);
+        if distance > max_distance {
+          max_distance = distance;
+          farthest = Some((a, b));
+        }
+      }
+    }
+    farthest
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Segment {
+  pub start: (f64, f64),
+  pub end: (f64, f64)
+}
+
+impl Segment {
+  pub fn length(&self) -> f64 {
+    ((self.end.0 - self.start.0).powi(2) + (self.end.1 - self.start.1).powi(2)).sqrt()
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Circle {
+  pub center_x: f64,
+  pub center_y: f64,
+  pub radius: f64,
+  // share of the circumference lying on edges, between 0 and 1
+  pub coverage: f64
+}
+
+// votes for lines in (rho, theta) space, rho being rounded to whole pixels
+struct LineAccumulator {
+  max_rho: i64,
+  votes: Vec<u32>,
+  cos: Vec<f64>,
+  sin: Vec<f64>
+}
+
+impl LineAccumulator {
+  fn new(width: u32, height: u32) -> Self {
+    let max_rho = ((width as f64).hypot(height as f64)).ceil() as i64;
+    let thetas: Vec<f64> = (0..THETA_STEPS).map(|t| (t as f64) * PI / (THETA_STEPS as f64)).collect();
+
+    Self {
+      max_rho,
+      votes: vec![0; THETA_STEPS * (2 * max_rho as usize + 1)],
+      cos: thetas.iter().map(|theta| theta.cos()).collect(),
+      sin: thetas.iter().map(|theta| theta.sin()).collect()
+    }
+  }
+
+  fn rhos(&self) -> usize {
+    2 * self.max_rho as usize + 1
+  }
+
+  fn rho_index(&self, x: u32, y: u32, theta_index: usize) -> usize {
+    let rho = (x as f64) * self.cos[theta_index] + (y as f64) * self.sin[theta_index];
+    (rho.round() as i64 + self.max_rho) as usize
+  }
+
+  fn get(&self, rho_index: usize, theta_index: usize) -> u32 {
+    self.votes[theta_index * self.rhos() + rho_index]
+  }
+
+  // adds or withdraws the votes of an edge point
+  fn vote(&mut self, x: u32, y: u32, add: bool) {
+    for theta_index in 0..THETA_STEPS {
+      let index = theta_index * self.rhos() + self.rho_index(x, y, theta_index);
+      if add { self.votes[index] += 1; } else { self.votes[index] -= 1; }
+    }
+  }
+
+  fn line(&self, rho_index: usize, theta_index: usize) -> Line {
+    Line {
+      rho: (rho_index as i64 - self.max_rho) as f64,
+      theta: (theta_index as f64) * PI / (THETA_STEPS as f64),
+      votes: self.get(rho_index, theta_index)
+    }
+  }
+}
+
+impl Image {
+  fn edge_points(&self) -> Vec<(u32, u32)> {
+    self.as_dynamic_image().to_luma().enumerate_pixels()
+    .filter(|&(_, _, luma)| luma.channels()[0] >= EDGE_LEVEL)
+    .map(|(x, y, _)| (x, y))
+    .collect()
+  }
+
+  // Standard Hough transform of a binary edge image. Lines with at least
+  // `threshold` edge points are returned, the strongest first.
+  pub fn hough_lines(&self, threshold: u32) -> Vec<Line> {
+    let (width, height) = self.as_dynamic_image().dimensions();
+    let mut accumulator = LineAccumulator::new(width, height);
+    for &(x, y) in self.edge_points().iter() {
+      accumulator.vote(x, y, true);
+    }
+
+    let rhos = accumulator.rhos();
+    let mut lines = Vec::new();
+    for theta_index in 0..THETA_STEPS {
+      for rho_index in 0..rhos {
+        let votes = accumulator.get(rho_index, theta_index);
+        if votes < threshold.max(1) {
+          continue;
+        }
+
+        // a local maximum, plateaus are reported once
+        let mut is_peak = true;
+        for dt in -1i64..2 {
+          for dr in -1i64..2 {
+            let (t, r) = (theta_index as i64 + dt, rho_index as i64 + dr);
+            if (dt, dr) == (0, 0) || t < 0 || r < 0 || t >= THETA_STEPS as i64 || r >= rhos as i64 {
+              continue;
+            }
+            let neighbour = accumulator.get(r as usize, t as usize);
+            let before = (dt, dr) < (0, 0);
+            if neighbour > votes || (before && neighbour == votes) {
+              is_peak = false;
+            }
+          }
+        }
+        if is_peak {
+          lines.push(accumulator.line(rho_index, theta_index));
+        }
+      }
+    }
+
+    lines.sort_by_key(|line| Reverse(line.votes));
+    lines
+  }
+
+  // Progressive probabilistic Hough transform (Matas et al.): edge points vote
+  // one at a time, and as soon as a line gathers `threshold` votes its points
+  // are followed along the line, bridging gaps of up to `max_gap` pixels, and
+  // removed. Segments shorter than `min_length` are dropped.
+  pub fn hough_segments(&self, threshold: u32, min_length: f64, max_gap: u32) -> Vec<Segment> {
+    let (width, height) = self.as_dynamic_image().dimensions();
+    let index = |x: u32, y: u32| (y as usize) * (width as usize) + x as usize;
+
+    let mut points = self.edge_points();
+    let mut remaining = vec![false; (width as usize) * (height as usize)];
+    let mut voted = remaining.clone();
+    for &(x, y) in points.iter() {
+      remaining[index(x, y)] = true;
+    }
+    points.shuffle(&mut StdRng::seed_from_u64(SEGMENT_SEED));
+
+    let mut accumulator = LineAccumulator::new(width, height);
+    let mut segments = Vec::new();
+
+    for &(x, y) in points.iter() {
+      if !remaining[index(x, y)] {
+        continue;
+      }
+      accumulator.vote(x, y, true);
+      voted[index(x, y)] = true;
+
+      let (theta_index, votes) = (0..THETA_STEPS)
+      .map(|t| (t, accumulator.get(accumulator.rho_index(x, y, t), t)))
+      .max_by_key(|&(_, votes)| votes)
+      .unwrap();
+      if votes < threshold.max(1) {
+        continue;
+      }
+
+      // steps of one pixel along the major axis of the line's direction, with
+      // a corridor one pixel wide on either side for edges thicker than a pixel
+      let line = accumulator.line(0, theta_index);
+      let (dx, dy) = (-line.theta.sin(), line.theta.cos());
+      let major = dx.abs().max(dy.abs());
+      let step = (dx / major, dy / major);
+      let normal = (line.theta.cos(), line.theta.sin());
+
+      let pixel = |k: i64, offset: i64| {
+        let px = (x as f64 + (k as f64) * step.0 + (offset as f64) * normal.0).round();
+        let py = (y as f64 + (k as f64) * step.1 + (offset as f64) * normal.1).round();
+        if px < 0.0 || py < 0.0 || px >= width as f64 || py >= height as f64 {
+          None
+        }
+        else {
+          Some((px as u32, py as u32))
+        }
+      };
+
+      // how far the segment reaches in both directions
+      let mut reach = [0i64; 2];
+      for (direction, sign) in [1i64, -1].iter().enumerate() {
+        let mut gap = 0;
+        let mut k = 1;
+        while pixel(k * sign, 0).is_some() {
+          let on_edge = (-1..2).filter_map(|offset| pixel(k * sign, offset)).any(|(px, py)| remaining[index(px, py)]);
+          if on_edge {
+            reach[direction] = k;
+            gap = 0;
+          }
+          else {
+            gap += 1;
+            if gap > max_gap {
+              break;
+            }
+          }
+          k += 1;
+        }
+      }
+
+      for k in -reach[1]..reach[0] + 1 {
+        for (px, py) in (-1..2).filter_map(|offset| pixel(k, offset)) {
+          let i = index(px, py);
+          if remaining[i] {
+            remaining[i] = false;
+            if voted[i] {
+              accumulator.vote(px, py, false);
+              voted[i] = false;
+            }
+          }
+        }
+      }
+
+      let start = pixel(-reach[1], 0).unwrap();
+      let end = pixel(reach[0], 0).unwrap();
+      let segment = Segment {
+        start: (start.0 as f64, start.1 as f64),
+        end: (end.0 as f64, end.1 as f64)
+      };
+      if segment.length() >= min_length {
+        segments.push(segment);
+      }
+    }
+    segments
+  }
+
+  // Hough transform for circles with radii between `min_radius` and
+  // `max_radius`. Circles with at least `min_coverage` of their circumference
+  // on edges are returned, the best covered first.
+  pub fn hough_circles(&self, min_radius: u32, max_radius: u32, min_coverage: f64) -> Vec<Circle> {
+    let (width, height) = self.as_dynamic_image().dimensions();
+    let points = self.edge_points();
+    let mut candidates = Vec::new();
+
+    // no larger circle centered on the image reaches any of its pixels
+    let diagonal = (width as f64).hypot(height as f64).ceil() as u32;
+    for radius in min_radius.max(1)..=max_radius.min(diagonal) {
+      let offsets = ring_offsets(radius);
+      let mut votes = vec![0u32; (width as usize) * (height as usize)];
+
+      for &(x, y) in points.iter() {
+        for &(dx, dy) in offsets.iter() {
+          let (cx, cy) = (x as i64 + dx, y as i64 + dy);
+          if cx >= 0 && cy >= 0 && cx < width as i64 && cy < height as i64 {
+            votes[(cy as usize) * (width as usize) + cx as usize] += 1;
+          }
+        }
+      }
+
+      for cy in 0..height as i64 {
+        for cx in 0..width as i64 {
+          let count = votes[(cy as usize) * (width as usize) + cx as usize];
+          let coverage = (count as f64) / (offsets.len() as f64);
+          if count == 0 || coverage < min_coverage {
+            continue;
+          }
+
+          let is_peak = (-1i64..2).all(|dy| (-1i64..2).all(|dx| {
+            let (nx, ny) = (cx + dx, cy + dy);
+            if (dx, dy) == (0, 0) || nx < 0 || ny < 0 || nx >= width as i64 || ny >= height as i64 {
+              return true;
+            }
+            let neighbour = votes[(ny as usize) * (width as usize) + nx as usize];
+            neighbour < count || (neighbour == count && (dy, dx) > (0, 0))
+          }));
+          if is_peak {
+            candidates.push(Circle { center_x: cx as f64, center_y: cy as f64, radius: radius as f64, coverage });
+          }
+        }
+      }
+    }
+
+    // edges a few pixels thick match several nearby circles, keep the best one
+    candidates.sort_by(|a, b| b.coverage.partial_cmp(&a.coverage).unwrap());
+    let mut circles: Vec<Circle> = Vec::new();
+    for candidate in candidates {
+      let duplicate = circles.iter().any(|circle| {
+        (circle.center_x - candidate.center_x).hypot(circle.center_y - candidate.center_y) <= DUPLICATE_CIRCLE_DISTANCE &&
+        (circle.radius - candidate.radius).abs() <= DUPLICATE_CIRCLE_DISTANCE
+      });
+      if !duplicate {
+        circles.push(candidate);
+      }
+    }
+    circles
+  }
+}
+
+// the distinct pixel offsets on a circle of the given radius
+fn ring_offsets(radius: u32) -> Vec<(i64, i64)> {
+  let radius = radius as f64;
+  let steps = ((4.0 * PI * radius).ceil() as usize).max(8);
+
+  let mut offsets: Vec<(i64, i64)> = (0..steps).map(|i| {
+    let angle = 2.0 * PI * (i as f64) / (steps as f64);
+    ((radius * angle.cos()).round() as i64, (radius * angle.sin()).round() as i64)
+  }).collect();
+  offsets.sort();
+  offsets.dedup();
+  offsets
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::path::PathBuf;
+  use image::image::{DynamicImage, GrayImage, Luma};
+
+  fn edges(width: u32, height: u32, points: &[(i64, i64)]) -> Image {
+    let mut buffer = GrayImage::new(width, height);
+    for &(x, y) in points {
+      buffer.put_pixel(x as u32, y as u32, Luma([255]));
+    }
+    Image::new(&PathBuf::from("nobody cares"), DynamicImage::ImageLuma8(buffer))
+  }
+
+  #[test]
+  fn finds_a_horizontal_line() {
+    let points: Vec<(i64, i64)> = (0..20).map(|x| (x, 5)).collect();
+    let lines = edges(20, 20, &points).hough_lines(15);
+
+    assert_eq!(lines[0].votes, 20);
+    assert_eq!(lines[0].rho, 5.0);
+    // rho is rounded to whole pixels, so the neighbouring angles collect the same votes
+    assert!((lines[0].theta - PI / 2.0).abs() <= PI / (THETA_STEPS as f64) + 1e-9);
+  }
+
+  #[test]
+  fn clips_lines_to_the_image() {
+    let vertical = Line { rho: 3.0, theta: 0.0, votes: 0 };
+    assert_eq!(vertical.clip(10, 8), Some(((3.0, 0.0), (3.0, 7.0))));
+
+    let outside = Line { rho: 20.0, theta: 0.0, votes: 0 };
+    assert_eq!(outside.clip(10, 8), None);
+  }
+
+  #[test]
+  fn finds_segments_across_small_gaps() {
+    // a diagonal with a two pixel gap, and a short horizontal piece
+    let mut points: Vec<(i64, i64)> = (2..20).filter(|&i| i != 9 && i != 10).map(|i| (i, i)).collect();
+    points.extend((20..25).map(|x| (x, 3)));
+    let mut segments = edges(30, 30, &points).hough_segments(10, 10.0, 2);
+
+    assert_eq!(segments.len(), 1);
+    let segment = segments.pop().unwrap();
+    let (start, end) = if segment.start < segment.end { (segment.start, segment.end) } else { (segment.end, segment.start) };
+    assert_eq!((start, end), ((2.0, 2.0), (19.0, 19.0)));
+  }
+
+  #[test]
+  fn finds_a_circle() {
+    let points: Vec<(i64, i64)> = ring_offsets(6).iter().map(|&(dx, dy)| (15 + dx, 12 + dy)).collect();
+    let circles = edges(30, 30, &points).hough_circles(3, 9, 0.8);
+
+    assert_eq!(circles.len(), 1);
+    assert_eq!((circles[0].center_x, circles[0].center_y, circles[0].radius), (15.0, 12.0, 6.0));
+    assert_eq!(circles[0].coverage, 1.0);
+    // radii past the image's diagonal are left out
+    assert_eq!(edges(30, 30, &points).hough_circles(3, u32::MAX, 0.8), circles);
+  }
+}
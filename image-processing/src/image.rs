@@ -1,54 +1,83 @@
 pub extern crate image;
 use image::image::{
   GenericImageView,
-  GenericImage,
   DynamicImage,
+  ColorType,
   ImageError,
-  Pixel
+  Pixel,
+  Rgba,
+  ImageBuffer
 };
-use std::path::PathBuf;
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use rayon::prelude::*;
+use rayon::slice::Chunks;
+#[cfg(target_arch = "x86_64")]
+use std::arch::x86_64::*;
 use failure::{err_msg, Error};
+use depth::{AnyImage, TypedImage};
+use pnm::is_netpbm;
+use selection::Mask;
+use filters::median;
 
 
 const MAX_COLOR_INTENSITY_U8: u8 = 255;
 const MAX_COLOR_INTENSITY_USIZE: usize = 255;
-type ColorIntensityBuckets = [usize; MAX_COLOR_INTENSITY_USIZE + 1];
+pub type ColorIntensityBuckets = [usize; MAX_COLOR_INTENSITY_USIZE + 1];
 
+// Clones share the pixels, which are only copied when a shared image gets
+// modified.
 #[derive(Clone)]
 pub struct Image {
   image_path: PathBuf,
-  dynamic_image: DynamicImage
+  dynamic_image: Arc<DynamicImage>
 }
 
 impl Image {
-  pub fn new(image_path: &PathBuf, dynamic_image: &DynamicImage) -> Self {
+  pub fn new(image_path: &Path, dynamic_image: DynamicImage) -> Self {
     Self {
       image_path: image_path.to_path_buf(),
-      dynamic_image: dynamic_image.clone()
+      dynamic_image: Arc::new(dynamic_image)
     }
   }
 
-  pub fn open(image_path: &PathBuf) -> Result<Self, ImageError> {
-    return match image::open(image_path) {
-      Err(error) => Err(error),
-      Ok(dynamic_image) => Ok(Self {
-        image_path: image_path.to_path_buf(),
-        dynamic_image
-      })
+  // the same pixels under another path
+  pub fn with_path(&self, image_path: &Path) -> Self {
+    Self {
+      image_path: image_path.to_path_buf(),
+      dynamic_image: self.dynamic_image.clone()
     }
   }
 
-  pub fn save_image(& self, path: Option<&PathBuf>) -> Result<PathBuf, Error> {
+  // 16 bit and floating point images the image crate can't read are opened
+  // at 8 bits, and NetPBM ones are always read by pnm.rs
+  pub fn open(image_path: &PathBuf) -> Result<Self, ImageError> {
+    if is_netpbm(image_path) {
+      return AnyImage::open(image_path)
+        .map(|image| Image::new(image_path, image.to_dynamic_image()))
+        .map_err(|error| ImageError::FormatError(error.to_string()));
+    }
+    match image::open(image_path) {
+      Err(error) => match AnyImage::open_at_full_depth(image_path) {
+        Some(Ok(image)) => Ok(Image::new(image_path, image.to_dynamic_image())),
+        _ => Err(error)
+      },
+      Ok(dynamic_image) => Ok(Image::new(image_path, dynamic_image))
+    }
+  }
 
+  pub fn save_image(&self, path: Option<&PathBuf>) -> Result<PathBuf, Error> {
     let path = match path {
-      Some(path) => &path,
-      None => &self.image_path
+      Some(path) => path.to_path_buf(),
+      None => self.get_image_path()
     };
 
-    match self.dynamic_image.save(&path) {
-      Ok(_) => return Ok(path.to_path_buf()),
-      Err(error) => return Err(err_msg(error))
+    if is_netpbm(&path) {
+      return AnyImage::U8(TypedImage::from_dynamic_image(self.as_dynamic_image())).save(&path);
     }
+    self.as_dynamic_image().save(&path).map_err(err_msg)?;
+    Ok(path)
   }
 
   pub fn get_dir(&self) -> Option<PathBuf> {
@@ -58,41 +87,261 @@ impl Image {
   pub fn get_image_path(&self) -> PathBuf {self.image_path.clone()}
 
 
-  pub fn get_dynamic_image(&self) -> DynamicImage {
-    self.dynamic_image.clone()
+  pub fn as_dynamic_image(&self) -> &DynamicImage {
+    &self.dynamic_image
+  }
+
+  // copies the pixels only if another image shares them
+  pub fn into_dynamic_image(self) -> DynamicImage {
+    Arc::try_unwrap(self.dynamic_image).unwrap_or_else(|shared| (*shared).clone())
+  }
+
+  // for changing the pixels in place, copied first if another image shares them
+  pub fn dynamic_image_mut(&mut self) -> &mut DynamicImage {
+    Arc::make_mut(&mut self.dynamic_image)
+  }
+
+  pub fn set_dynamic_image(&mut self, dynamic_image: DynamicImage) {
+    self.dynamic_image = Arc::new(dynamic_image);
+  }
+
+  pub fn shares_pixels_with(&self, other: &Image) -> bool {
+    Arc::ptr_eq(&self.dynamic_image, &other.dynamic_image)
+  }
+
+  pub fn get_color_type(&self) -> ColorType {
+    self.dynamic_image.color()
+  }
+
+  // None when the coordinates fall outside of the image
+  pub fn get_pixel(&self, x: u32, y: u32) -> Option<Rgba<u8>> {
+    if self.dynamic_image.in_bounds(x, y) {
+      Some(self.dynamic_image.get_pixel(x, y))
+    }
+    else {
+      None
+    }
   }
 
   pub fn equalize_histogram(&self) -> DynamicImage {
+    self.equalize(None)
+  }
+
+  // equalizes only the selected pixels, using the histogram of the selection
+  pub fn equalize_histogram_within(&self, mask: &Mask) -> DynamicImage {
+    self.equalize(Some(mask))
+  }
+
+  fn equalize(&self, mask: Option<&Mask>) -> DynamicImage {
 
     let (width, height) = self.dynamic_image.dimensions();
-    let mut new_image_buffer: DynamicImage =
-    DynamicImage::new_rgb8(width, height);
-    
-    let histogram = self.calculate_histogram();
-    let cumulative_distributions = self.calculate_cumulative_distributions(histogram);
-
-    let cdf_min =
-    match cumulative_distributions.iter().find(|&&cd| cd != 0usize) {
-      Some(val) => *val,
-      None => 0usize
+
+    let histogram = self.calculate_histogram_within(mask);
+    let pixel_count = match mask {
+      Some(mask) => mask.count(),
+      None => (width * height) as usize
     };
 
-    for (c, r, mut rgb) in self.dynamic_image.pixels() {
-      let cd = cumulative_distributions[rgb[0] as usize];
-      let val = ((((cd - cdf_min) as f32) / ((width * height)as f32)) * (MAX_COLOR_INTENSITY_U8 as f32) )as u8;
-      new_image_buffer.put_pixel(c,r, Pixel::from_channels(val,val,val,rgb[3]));
+    let lookup_table = equalization_lut_u8(&histogram, pixel_count);
+
+    match mask {
+      Some(mask) => map_rows(&self.dynamic_image, |c, r, rgba, rgb| {
+        if mask.contains(c, r) {
+          let val = lookup_table[rgba[0] as usize];
+          rgb.copy_from_slice(&[val, val, val]);
+        }
+        else {
+          rgb.copy_from_slice(&rgba[..3]);
+        }
+      }),
+      None => self.equalize_with(&lookup_table)
     }
+  }
+
+  // Equalizes with a lookup table built from another histogram, such as the one
+  // of a whole image this is a tile of.
+  pub fn equalize_with(&self, lookup_table: &[u8; MAX_COLOR_INTENSITY_USIZE + 1]) -> DynamicImage {
+
+    let (width, height) = self.dynamic_image.dimensions();
+    let mut levels = self.plane(|rgba| rgba[0]);
+    apply_lut_parallel(&mut levels, lookup_table);
+    gray_to_rgb(width, height, levels)
+  }
+
+  // takes the selected pixels from `processed` and the rest from this image
+  pub fn blend_within(&self, processed: &DynamicImage, mask: &Mask) -> DynamicImage {
+
+    let processed = Samples::of(processed);
+    map_rows(&self.dynamic_image, |c, r, rgba, rgb| {
+      if mask.contains(c, r) {
+        rgb.copy_from_slice(&processed.pixel(c, r)[..3]);
+      }
+      else {
+        rgb.copy_from_slice(&rgba[..3]);
+      }
+    })
+  }
 
-    new_image_buffer
+  // remaps every color channel so that its histogram follows the reference's
+  pub fn match_histogram(&self, reference: &Image) -> DynamicImage {
+    self.match_histogram_to(&reference.histogram_reference(), None)
   }
 
-  fn calculate_histogram(&self) -> ColorIntensityBuckets {
-    let mut gray_level_distribution = [0; MAX_COLOR_INTENSITY_USIZE + 1];
+  // Within a mask only the selected pixels are counted and remapped, the others
+  // are left as they are.
+  pub fn match_histogram_to(&self, reference: &HistogramReference, mask: Option<&Mask>) -> DynamicImage {
 
-    for (_, _, rgb) in self.dynamic_image.pixels() {
-      gray_level_distribution[rgb[0] as usize] += 1;
+    let mut lookup_tables = [[0u8; MAX_COLOR_INTENSITY_USIZE + 1]; 3];
+    for (channel, lookup_table) in lookup_tables.iter_mut().enumerate() {
+      let source = self.calculate_cumulative_distributions(self.calculate_channel_histogram(channel, mask));
+      *lookup_table = match_cumulative_distributions(&source, &reference.cumulative_distributions[channel]);
     }
-    gray_level_distribution
+
+    map_rows(&self.dynamic_image, |c, r, rgba, rgb| {
+      if mask.is_none_or(|mask| mask.contains(c, r)) {
+        for channel in 0..3 {
+          rgb[channel] = lookup_tables[channel][rgba[channel] as usize];
+        }
+      }
+      else {
+        rgb.copy_from_slice(&rgba[..3]);
+      }
+    })
+  }
+
+  // what matching other images to this one takes, worth keeping rather than
+  // opening the image again
+  pub fn histogram_reference(&self) -> HistogramReference {
+    let mut cumulative_distributions = [[0; MAX_COLOR_INTENSITY_USIZE + 1]; 3];
+    for (channel, distribution) in cumulative_distributions.iter_mut().enumerate() {
+      *distribution = self.calculate_cumulative_distributions(self.calculate_channel_histogram(channel, None));
+    }
+    HistogramReference { cumulative_distributions }
+  }
+
+  pub fn threshold(&self, level: u8) -> DynamicImage {
+
+    let (width, height) = self.dynamic_image.dimensions();
+    let mut levels = self.plane(|rgba| Rgba::from_slice(&rgba).to_luma()[0]);
+    apply_lut_parallel(&mut levels, &threshold_lut_u8(level));
+    gray_to_rgb(width, height, levels)
+  }
+
+  // Maps the color levels through a gamma curve, above 1 brightening the
+  // midtones.
+  pub fn adjust_gamma(&self, gamma: f64) -> DynamicImage {
+    self.adjust_levels(0, MAX_COLOR_INTENSITY_U8, gamma)
+  }
+
+  // Stretches the color levels from `black` to `white` over the whole range,
+  // then applies the gamma.
+  pub fn adjust_levels(&self, black: u8, white: u8, gamma: f64) -> DynamicImage {
+
+    let mut rgb = self.dynamic_image.to_rgb();
+    apply_lut_parallel(&mut rgb, &levels_lut_u8(black, white, gamma));
+    DynamicImage::ImageRgb8(rgb)
+  }
+
+  // one level per pixel, computed from its RGBA values
+  fn plane<F>(&self, level: F) -> Vec<u8>
+    where F: Fn([u8; 4]) -> u8 + Sync {
+
+    let samples = Samples::of(&self.dynamic_image);
+    let mut plane = vec![0u8; samples.data.len() / samples.channels];
+    if samples.width > 0 {
+      plane.par_chunks_mut(samples.width as usize).zip(samples.rows())
+      .for_each(|(levels, row)| {
+        for (value, pixel) in levels.iter_mut().zip(row.chunks(samples.channels)) {
+          *value = level(to_rgba(pixel));
+        }
+      });
+    }
+    plane
+  }
+
+  // median filter over a (2 * radius + 1) square window, clamped at the borders
+  pub fn denoise(&self, radius: u32) -> DynamicImage {
+
+    let (width, height) = self.dynamic_image.dimensions();
+    let source = Samples::of(&self.dynamic_image);
+    let channels = source.channels;
+    let denoised = median(&source.data, width, height, channels, radius, |a: u8, b: u8| a.cmp(&b));
+
+    map_rows(&self.dynamic_image, |c, r, _, rgb| {
+      let start = (r as usize * width as usize + c as usize) * channels;
+      rgb.copy_from_slice(&to_rgba(&denoised[start..start + channels])[..3]);
+    })
+  }
+
+  // Sobel gradient magnitude of the luminance, pixels above `level` are marked
+  // as edges
+  pub fn detect_edges(&self, level: u8) -> DynamicImage {
+
+    let (width, height) = self.dynamic_image.dimensions();
+
+    let luma = self.dynamic_image.to_luma();
+    let at = |x: i64, y: i64| {
+      let x = clamp(x, 0, width as i64 - 1) as u32;
+      let y = clamp(y, 0, height as i64 - 1) as u32;
+      luma.get_pixel(x, y)[0] as f64
+    };
+
+    map_rows(&self.dynamic_image, |c, r, _, rgb| {
+      let (x, y) = (c as i64, r as i64);
+      let gx = at(x + 1, y - 1) + 2.0 * at(x + 1, y) + at(x + 1, y + 1)
+             - at(x - 1, y - 1) - 2.0 * at(x - 1, y) - at(x - 1, y + 1);
+      let gy = at(x - 1, y + 1) + 2.0 * at(x, y + 1) + at(x + 1, y + 1)
+             - at(x - 1, y - 1) - 2.0 * at(x, y - 1) - at(x + 1, y - 1);
+
+      // the kernels weigh 4 times the intensity difference
+      let magnitude = (gx * gx + gy * gy).sqrt() / 4.0;
+      let val = if magnitude > level as f64 { MAX_COLOR_INTENSITY_U8 } else { 0 };
+      rgb.copy_from_slice(&[val, val, val]);
+    })
+  }
+
+  pub fn calculate_histogram(&self) -> ColorIntensityBuckets {
+    self.calculate_histogram_within(None)
+  }
+
+  fn calculate_histogram_within(&self, mask: Option<&Mask>) -> ColorIntensityBuckets {
+    self.calculate_channel_histogram(0, mask)
+  }
+
+  // every thread counts its own part of the image, the counts are summed up in
+  // the end
+  fn calculate_channel_histogram(&self, channel: usize, mask: Option<&Mask>) -> ColorIntensityBuckets {
+    let samples = Samples::of(&self.dynamic_image);
+    if samples.width == 0 {
+      return [0; MAX_COLOR_INTENSITY_USIZE + 1];
+    }
+
+    // gray images have the same level in every color channel
+    let channel = if samples.channels < 3 { 0 } else { channel };
+
+    let mask = match mask {
+      Some(mask) => mask,
+      None => {
+        let mut histogram = [0; MAX_COLOR_INTENSITY_USIZE + 1];
+        histogram.copy_from_slice(&channel_histogram(&samples.data, samples.channels, channel,
+                                                     MAX_COLOR_INTENSITY_USIZE + 1, |level: u8| level as usize));
+        return histogram;
+      }
+    };
+
+    samples.rows().enumerate()
+    .fold(|| [0; MAX_COLOR_INTENSITY_USIZE + 1], |mut gray_level_distribution, (r, row)| {
+      for (c, pixel) in row.chunks(samples.channels).enumerate() {
+        if mask.contains(c as u32, r as u32) {
+          gray_level_distribution[pixel[channel] as usize] += 1;
+        }
+      }
+      gray_level_distribution
+    })
+    .reduce(|| [0; MAX_COLOR_INTENSITY_USIZE + 1], |mut total, counts| {
+      add_histograms(&mut total, &counts);
+      total
+    })
   }
 
   fn calculate_cumulative_distributions(&self, histogram:[usize; MAX_COLOR_INTENSITY_USIZE + 1]) -> ColorIntensityBuckets {
@@ -110,10 +359,363 @@ impl Image {
 
 }
 
+// An image opened at its own depth, along with the 8 bit image shown for it.
+// The samples stand for the shown image as long as its pixels are the ones
+// made from them.
+#[derive(Clone)]
+pub struct FullDepth {
+  shown: Image,
+  image: Arc<AnyImage>
+}
+
+impl FullDepth {
+  pub fn new(path: &Path, image: AnyImage) -> Self {
+    FullDepth { shown: Image::new(path, image.to_dynamic_image()), image: Arc::new(image) }
+  }
+
+  pub fn open(path: &PathBuf) -> Result<Self, Error> {
+    Ok(FullDepth::new(path, AnyImage::open(path)?))
+  }
+
+  pub fn shown(&self) -> &Image {
+    &self.shown
+  }
+
+  pub fn image(&self) -> &AnyImage {
+    &self.image
+  }
+
+  // false once the image was changed by something that only works at 8 bits
+  pub fn stands_for(&self, image: &Image) -> bool {
+    self.shown.shares_pixels_with(image)
+  }
+}
+
+// The cumulative distributions of the color channels of an image others are
+// matched to.
+pub struct HistogramReference {
+  cumulative_distributions: [ColorIntensityBuckets; 3]
+}
+
+// Maps every intensity to the lowest reference intensity whose cumulative
+// share of pixels reaches the source's.
+fn match_cumulative_distributions(source: &ColorIntensityBuckets,
+                                  target: &ColorIntensityBuckets,
+                                  ) -> [u8; MAX_COLOR_INTENSITY_USIZE + 1] {
+  let mut lookup_table = [0u8; MAX_COLOR_INTENSITY_USIZE + 1];
+
+  let source_total = source[MAX_COLOR_INTENSITY_USIZE] as f64;
+  let target_total = target[MAX_COLOR_INTENSITY_USIZE] as f64;
+  if source_total == 0.0 || target_total == 0.0 {
+    for (i, value) in lookup_table.iter_mut().enumerate() {
+      *value = i as u8;
+    }
+    return lookup_table;
+  }
+
+  let mut target_intensity = 0;
+  for (i, value) in lookup_table.iter_mut().enumerate() {
+    let share = (source[i] as f64) / source_total;
+    while target_intensity < MAX_COLOR_INTENSITY_USIZE &&
+          (target[target_intensity] as f64) / target_total < share {
+      target_intensity += 1;
+    }
+    *value = target_intensity as u8;
+  }
+  lookup_table
+}
+
+// Point operations come down to counting the levels of the samples, building a
+// lookup table from the counts, then passing every sample through the table.
+// The kernels below count and look up 8 and 16 bit samples, the lookups
+// vectorized with AVX2 on processors that have it.
+
+// how many samples the threads get at a time
+pub const CHUNK_SIZE: usize = 1 << 16;
+
+const LEVELS_U16: usize = 1 << 16;
+const MAX_LEVEL_U16: u16 = 65535;
+
+// the levels of one channel of interleaved samples
+pub fn histogram_u8(samples: &[u8], channels: usize, channel: usize) -> ColorIntensityBuckets {
+  let mut histogram = [0; MAX_COLOR_INTENSITY_USIZE + 1];
+  histogram.copy_from_slice(&count_levels(samples, channels, channel, MAX_COLOR_INTENSITY_USIZE + 1, |level| level as usize));
+  histogram
+}
+
+pub fn histogram_u16(samples: &[u16], channels: usize, channel: usize) -> Vec<usize> {
+  count_levels(samples, channels, channel, LEVELS_U16, |level| level as usize)
+}
+
+// Counts one channel of interleaved samples of any depth into `bins`, `bin`
+// telling which one a sample falls in. Every thread counts its own chunks, the
+// counts are summed up in the end.
+pub fn channel_histogram<T, F>(samples: &[T], channels: usize, channel: usize, bins: usize, bin: F) -> Vec<usize>
+  where T: Copy + Sync, F: Fn(T) -> usize + Sync {
+  samples.par_chunks(CHUNK_SIZE * channels)
+  .map(|chunk| count_levels(chunk, channels, channel, bins, &bin))
+  .reduce(|| vec![0; bins], |mut total, counts| {
+    add_histograms(&mut total, &counts);
+    total
+  })
+}
+
+fn count_levels<T: Copy, F: Fn(T) -> usize>(samples: &[T], channels: usize, channel: usize, levels: usize, level: F) -> Vec<usize> {
+  // The counts go to four tables in turn, so that runs of the same level don't
+  // wait on each other's increments. Histograms don't vectorize.
+  let mut tables = vec![0usize; 4 * levels];
+  let mut pixels = samples.chunks_exact(4 * channels);
+  for pixel in &mut pixels {
+    for table in 0..4 {
+      tables[table * levels + level(pixel[table * channels + channel])] += 1;
+    }
+  }
+  for pixel in pixels.remainder().chunks_exact(channels) {
+    tables[level(pixel[channel])] += 1;
+  }
+
+  (0..levels).map(|level| (0..4).map(|table| tables[table * levels + level]).sum()).collect()
+}
+
+fn add_histograms(total: &mut [usize], counts: &[usize]) {
+  for (sum, count) in total.iter_mut().zip(counts.iter()) {
+    *sum += count;
+  }
+}
+
+pub fn lut_u8<F: Fn(u8) -> u8>(f: F) -> [u8; MAX_COLOR_INTENSITY_USIZE + 1] {
+  let mut lut = [0; MAX_COLOR_INTENSITY_USIZE + 1];
+  for (level, value) in lut.iter_mut().enumerate() {
+    *value = f(level as u8);
+  }
+  lut
+}
+
+pub fn lut_u16<F: Fn(u16) -> u16>(f: F) -> Vec<u16> {
+  (0..LEVELS_U16).map(|level| f(level as u16)).collect()
+}
+
+// Spreads the levels so that their cumulative distribution grows linearly,
+// `count` being how many samples the histogram holds. The levels are from 0 to
+// 1.
+pub fn equalized_levels(histogram: &[usize], count: usize) -> Vec<f32> {
+  let mut accum = 0;
+  let cumulative_distributions: Vec<usize> = histogram.iter().map(|pixel_count| {
+    accum += pixel_count;
+    accum
+  }).collect();
+
+  let cdf_min = cumulative_distributions.iter().cloned().find(|&cd| cd != 0).unwrap_or(0);
+  cumulative_distributions.iter().map(|&cd| (cd.saturating_sub(cdf_min) as f32) / (count as f32)).collect()
+}
+
+pub fn equalization_lut_u8(histogram: &ColorIntensityBuckets, count: usize) -> [u8; MAX_COLOR_INTENSITY_USIZE + 1] {
+  let levels = equalized_levels(histogram, count);
+  lut_u8(|level| (levels[level as usize] * (MAX_COLOR_INTENSITY_U8 as f32)) as u8)
+}
+
+pub fn equalization_lut_u16(histogram: &[usize], count: usize) -> Vec<u16> {
+  let levels = equalized_levels(histogram, count);
+  lut_u16(|level| (levels[level as usize] * (MAX_LEVEL_U16 as f32)) as u16)
+}
+
+// the levels above `level` become white, the others black
+pub fn threshold_lut_u8(level: u8) -> [u8; MAX_COLOR_INTENSITY_USIZE + 1] {
+  lut_u8(|value| if value > level { MAX_COLOR_INTENSITY_U8 } else { 0 })
+}
+
+pub fn threshold_lut_u16(level: u16) -> Vec<u16> {
+  lut_u16(|value| if value > level { MAX_LEVEL_U16 } else { 0 })
+}
+
+// from 0 to 1, the levels stretched from black to white and through the gamma
+fn levels_curve(black: f64, white: f64, gamma: f64) -> impl Fn(f64) -> f64 {
+  let range = (white - black).max(1.0);
+  move |level| ((level - black) / range).clamp(0.0, 1.0).powf(1.0 / gamma)
+}
+
+pub fn levels_lut_u8(black: u8, white: u8, gamma: f64) -> [u8; MAX_COLOR_INTENSITY_USIZE + 1] {
+  let curve = levels_curve(black as f64, white as f64, gamma);
+  lut_u8(|level| (curve(level as f64) * (MAX_COLOR_INTENSITY_U8 as f64)).round() as u8)
+}
+
+pub fn levels_lut_u16(black: u16, white: u16, gamma: f64) -> Vec<u16> {
+  let curve = levels_curve(black as f64, white as f64, gamma);
+  lut_u16(|level| (curve(level as f64) * (MAX_LEVEL_U16 as f64)).round() as u16)
+}
+
+pub fn apply_lut_u8(samples: &mut [u8], lut: &[u8; MAX_COLOR_INTENSITY_USIZE + 1]) {
+  #[cfg(target_arch = "x86_64")]
+  {
+    if is_x86_feature_detected!("avx2") {
+      return unsafe { apply_lut_u8_avx2(samples, lut) };
+    }
+  }
+  apply_lut_u8_scalar(samples, lut)
+}
+
+pub fn apply_lut_u8_scalar(samples: &mut [u8], lut: &[u8; MAX_COLOR_INTENSITY_USIZE + 1]) {
+  for sample in samples.iter_mut() {
+    *sample = lut[*sample as usize];
+  }
+}
+
+// A shuffle looks up 16 entries at a time, so the table is split into 16 of
+// them. Adding 0x70 with saturation keeps the samples within the current part
+// of the table below 0x80 with their low bits as the index, and pushes the
+// others to 0x80 or above, which the shuffle turns into zeros.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn apply_lut_u8_avx2(samples: &mut [u8], lut: &[u8; MAX_COLOR_INTENSITY_USIZE + 1]) {
+  let mut parts = [_mm256_setzero_si256(); 16];
+  for (i, part) in parts.iter_mut().enumerate() {
+    *part = _mm256_broadcastsi128_si256(_mm_loadu_si128(lut.as_ptr().add(16 * i) as *const __m128i));
+  }
+  let in_part = _mm256_set1_epi8(0x70);
+  let part_size = _mm256_set1_epi8(16);
+
+  let mut chunks = samples.chunks_exact_mut(32);
+  for chunk in &mut chunks {
+    let mut levels = _mm256_loadu_si256(chunk.as_ptr() as *const __m256i);
+    let mut values = _mm256_setzero_si256();
+    for part in parts.iter() {
+      values = _mm256_or_si256(values, _mm256_shuffle_epi8(*part, _mm256_adds_epu8(levels, in_part)));
+      levels = _mm256_sub_epi8(levels, part_size);
+    }
+    _mm256_storeu_si256(chunk.as_mut_ptr() as *mut __m256i, values);
+  }
+  apply_lut_u8_scalar(chunks.into_remainder(), lut);
+}
+
+// the table has a value for each of the 65536 levels
+pub fn apply_lut_u16(samples: &mut [u16], lut: &[u16]) {
+  assert_eq!(lut.len(), LEVELS_U16);
+  #[cfg(target_arch = "x86_64")]
+  {
+    if is_x86_feature_detected!("avx2") {
+      return unsafe { apply_lut_u16_avx2(samples, lut) };
+    }
+  }
+  apply_lut_u16_scalar(samples, lut)
+}
+
+pub fn apply_lut_u16_scalar(samples: &mut [u16], lut: &[u16]) {
+  let lut = &lut[..LEVELS_U16];
+  for sample in samples.iter_mut() {
+    *sample = lut[*sample as usize];
+  }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn apply_lut_u16_avx2(samples: &mut [u16], lut: &[u16]) {
+  let mut chunks = samples.chunks_exact_mut(16);
+  for chunk in &mut chunks {
+    let levels = _mm256_loadu_si256(chunk.as_ptr() as *const __m256i);
+    let low = gather_u16_avx2(lut, _mm256_cvtepu16_epi32(_mm256_castsi256_si128(levels)));
+    let high = gather_u16_avx2(lut, _mm256_cvtepu16_epi32(_mm256_extracti128_si256(levels, 1)));
+    // packing works within each half, the permutation puts the values back in order
+    let values = _mm256_permute4x64_epi64(_mm256_packus_epi32(low, high), 0b11_01_10_00);
+    _mm256_storeu_si256(chunk.as_mut_ptr() as *mut __m256i, values);
+  }
+  apply_lut_u16_scalar(chunks.into_remainder(), lut);
+}
+
+// Looks up eight 32 bit levels. The gathers read 32 bits, the last level's
+// from one entry earlier, so as not to read past the table.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn gather_u16_avx2(lut: &[u16], levels: __m256i) -> __m256i {
+  let within = _mm256_min_epu32(levels, _mm256_set1_epi32(LEVELS_U16 as i32 - 2));
+  let values = _mm256_i32gather_epi32(lut.as_ptr() as *const i32, within, 2);
+  let shift = _mm256_slli_epi32(_mm256_sub_epi32(levels, within), 4);
+  _mm256_and_si256(_mm256_srlv_epi32(values, shift), _mm256_set1_epi32(0xffff))
+}
+
+fn apply_lut_parallel(samples: &mut [u8], lut: &[u8; MAX_COLOR_INTENSITY_USIZE + 1]) {
+  samples.par_chunks_mut(CHUNK_SIZE).for_each(|chunk| apply_lut_u8(chunk, lut));
+}
+
+fn gray_to_rgb(width: u32, height: u32, levels: Vec<u8>) -> DynamicImage {
+  let mut pixels = vec![0u8; levels.len() * 3];
+  pixels.par_chunks_mut(CHUNK_SIZE * 3).zip(levels.par_chunks(CHUNK_SIZE))
+  .for_each(|(pixels, levels)| {
+    for (rgb, &level) in pixels.chunks_mut(3).zip(levels.iter()) {
+      rgb.copy_from_slice(&[level, level, level]);
+    }
+  });
+  DynamicImage::ImageRgb8(ImageBuffer::from_raw(width, height, pixels).unwrap())
+}
+
+// The samples of an image, borrowed as they are when they are 8 bit gray, RGB
+// or RGBA and converted to RGBA otherwise.
+struct Samples<'a> {
+  data: Cow<'a, [u8]>,
+  channels: usize,
+  width: u32
+}
+
+impl<'a> Samples<'a> {
+  fn of(image: &'a DynamicImage) -> Self {
+    let (data, channels) = match *image {
+      DynamicImage::ImageLuma8(ref buffer) => (Cow::Borrowed(&**buffer), 1),
+      DynamicImage::ImageLumaA8(ref buffer) => (Cow::Borrowed(&**buffer), 2),
+      DynamicImage::ImageRgb8(ref buffer) => (Cow::Borrowed(&**buffer), 3),
+      DynamicImage::ImageRgba8(ref buffer) => (Cow::Borrowed(&**buffer), 4),
+      _ => (Cow::Owned(image.to_rgba().into_raw()), 4)
+    };
+    Samples { data, channels, width: image.width() }
+  }
+
+  fn rows(&self) -> Chunks<'_, u8> {
+    self.data.par_chunks(self.width as usize * self.channels)
+  }
+
+  fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+    let start = (y as usize * self.width as usize + x as usize) * self.channels;
+    to_rgba(&self.data[start..start + self.channels])
+  }
+}
+
+// the same conversion as `DynamicImage::to_rgba`
+fn to_rgba(pixel: &[u8]) -> [u8; 4] {
+  match pixel.len() {
+    1 => [pixel[0], pixel[0], pixel[0], MAX_COLOR_INTENSITY_U8],
+    2 => [pixel[0], pixel[0], pixel[0], pixel[1]],
+    3 => [pixel[0], pixel[1], pixel[2], MAX_COLOR_INTENSITY_U8],
+    _ => [pixel[0], pixel[1], pixel[2], pixel[3]]
+  }
+}
+
+// Builds an RGB image a row at a time, the rows spread over the threads. `f`
+// gets the column and row of every pixel, its RGBA source and RGB destination.
+fn map_rows<F>(source: &DynamicImage, f: F) -> DynamicImage
+  where F: Fn(u32, u32, [u8; 4], &mut [u8]) + Sync {
+
+  let (width, height) = source.dimensions();
+  let source = Samples::of(source);
+  let mut pixels = vec![0u8; (width as usize) * (height as usize) * 3];
+  if width > 0 {
+    pixels.par_chunks_mut(width as usize * 3).zip(source.rows()).enumerate()
+    .for_each(|(r, (destination, row))| {
+      for (c, (rgb, pixel)) in destination.chunks_mut(3).zip(row.chunks(source.channels)).enumerate() {
+        f(c as u32, r as u32, to_rgba(pixel), rgb);
+      }
+    });
+  }
+
+  DynamicImage::ImageRgb8(ImageBuffer::from_raw(width, height, pixels).unwrap())
+}
+
+fn clamp(value: i64, min: i64, max: i64) -> i64 {
+  if value < min { min } else if value > max { max } else { value }
+}
+
 
 #[cfg(test)]
 mod tests {
   use super::*;
+  use image::image::GenericImage;
   extern crate rand;
   use image::tests::rand::{Rng};
   use image::tests::rand::distributions::Alphanumeric;
@@ -133,21 +735,33 @@ mod tests {
   fn save_image_raises_error_if_the_specified_path_doesnt_have_a_specifier() {
     let path = PathBuf::from("nobody cares");
     let dynamic_image = DynamicImage::new_rgb8(0,0);
-    let image = Image::new(&path, &dynamic_image);
+    let image = Image::new(&path, dynamic_image);
 
     let invalid_path = PathBuf::from("../tests/fixtures/unequalized");
     assert!(image.save_image(Some(&invalid_path)).is_err(), "file should not exist");
   }
 
+  #[test]
+  fn get_pixel_returns_none_outside_of_the_image() {
+    let path = PathBuf::from("nobody cares");
+    let mut dynamic_image = DynamicImage::new_rgb8(2,1);
+    dynamic_image.put_pixel(1,0, Pixel::from_channels(1,2,3,255));
+    let image = Image::new(&path, dynamic_image);
+
+    assert_eq!(image.get_pixel(1,0), Some(Pixel::from_channels(1,2,3,255)));
+    assert_eq!(image.get_pixel(2,0), None);
+    assert_eq!(image.get_pixel(0,1), None);
+  }
+
   #[test]
   fn calculates_the_histogram_correcty() {
     let path = PathBuf::from("nobody cares");
     let dynamic_image = DynamicImage::new_rgb8(1,3);
-    let mut image = Image::new(&path, &dynamic_image);
+    let mut image = Image::new(&path, dynamic_image);
 
     let mut valid_hist: ColorIntensityBuckets = [0; MAX_COLOR_INTENSITY_USIZE + 1];
     for i in 0..3 {
-      image.dynamic_image.put_pixel(0,i as u32, Pixel::from_channels(i as u8,i as u8,i as u8,i as u8));
+      image.dynamic_image_mut().put_pixel(0,i as u32, Pixel::from_channels(i as u8,i as u8,i as u8,i as u8));
       valid_hist[i as usize] +=1;
     }
     let result_hist = image.calculate_histogram();
@@ -161,12 +775,12 @@ mod tests {
   fn calculates_the_cumulative_distribution_correcty() {
     let path = PathBuf::from("nobody cares");
     let dynamic_image = DynamicImage::new_rgb8(1,3);
-    let mut image = Image::new(&path, &dynamic_image);
+    let mut image = Image::new(&path, dynamic_image);
 
     let mut valid_distributions = [0; 3];
-    for i in 0..3 {
-      image.dynamic_image.put_pixel(0,i as u32, Pixel::from_channels(i as u8,i as u8,i as u8,i as u8));
-      valid_distributions[i as usize] += i + 1;
+    for (i, valid_distribution) in valid_distributions.iter_mut().enumerate() {
+      image.dynamic_image_mut().put_pixel(0,i as u32, Pixel::from_channels(i as u8,i as u8,i as u8,i as u8));
+      *valid_distribution += i + 1;
     }
     let hist = image.calculate_histogram();
     let result_cd = image.calculate_cumulative_distributions(hist);
@@ -180,10 +794,10 @@ mod tests {
   fn equalizes_the_histogram_correcty() {
     let path = PathBuf::from("nobody cares");
     let dynamic_image = DynamicImage::new_rgb8(1,3);
-    let mut image = Image::new(&path, &dynamic_image);
+    let mut image = Image::new(&path, dynamic_image);
 
     for i in 0..3 {
-      image.dynamic_image.put_pixel(0,i as u32, Pixel::from_channels(i as u8,i as u8,i as u8,i as u8));
+      image.dynamic_image_mut().put_pixel(0,i as u32, Pixel::from_channels(i as u8,i as u8,i as u8,i as u8));
     }
     let image_eq_hist = image.equalize_histogram();
     for (_,_,rgb) in image_eq_hist.pixels() {
@@ -193,4 +807,208 @@ mod tests {
     assert_eq!(image_eq_hist.get_pixel(0,1)[0], 85);
     assert_eq!(image_eq_hist.get_pixel(0,2)[0], 170);
   }
+
+  #[test]
+  fn equalizes_only_the_selected_region() {
+    let path = PathBuf::from("nobody cares");
+    let dynamic_image = DynamicImage::new_rgb8(1,4);
+    let mut image = Image::new(&path, dynamic_image);
+
+    for i in 0..4 {
+      image.dynamic_image_mut().put_pixel(0,i as u32, Pixel::from_channels(i as u8,i as u8,i as u8,255));
+    }
+    let mut mask = Mask::new(1,4);
+    mask.select(0,1);
+    mask.select(0,2);
+
+    let hist = image.calculate_histogram_within(Some(&mask));
+    assert_eq!(hist[0], 0);
+    assert_eq!(hist[1], 1);
+    assert_eq!(hist[2], 1);
+
+    let image_eq_hist = image.equalize_histogram_within(&mask);
+    assert_eq!(image_eq_hist.get_pixel(0,0)[0], 0);
+    assert_eq!(image_eq_hist.get_pixel(0,1)[0], 0);
+    assert_eq!(image_eq_hist.get_pixel(0,2)[0], 127);
+    assert_eq!(image_eq_hist.get_pixel(0,3)[0], 3);
+  }
+
+  #[test]
+  fn matching_an_image_to_itself_leaves_it_unchanged() {
+    let path = PathBuf::from("nobody cares");
+    let dynamic_image = DynamicImage::new_rgb8(1,3);
+    let mut image = Image::new(&path, dynamic_image);
+
+    for i in 0..3 {
+      let val = (i * 50) as u8;
+      image.dynamic_image_mut().put_pixel(0,i as u32, Pixel::from_channels(val,val + 1,val + 2,255));
+    }
+    let matched = image.match_histogram(&image);
+
+    for i in 0..3 {
+      assert_eq!(matched.get_pixel(0,i), image.dynamic_image.get_pixel(0,i));
+    }
+  }
+
+  #[test]
+  fn matches_the_histogram_of_the_reference() {
+    let path = PathBuf::from("nobody cares");
+    let mut image = Image::new(&path, DynamicImage::new_rgb8(1,4));
+    let mut reference = Image::new(&path, DynamicImage::new_rgb8(1,4));
+
+    for i in 0..4 {
+      let val = i as u8;
+      image.dynamic_image_mut().put_pixel(0,i as u32, Pixel::from_channels(val,val,val,255));
+      let val = 100 + (i * 50) as u8;
+      reference.dynamic_image_mut().put_pixel(0,i as u32, Pixel::from_channels(val,val,val,255));
+    }
+    let matched = image.match_histogram(&reference);
+
+    assert_eq!(matched.get_pixel(0,0)[0], 100);
+    assert_eq!(matched.get_pixel(0,1)[1], 150);
+    assert_eq!(matched.get_pixel(0,2)[2], 200);
+    assert_eq!(matched.get_pixel(0,3)[0], 250);
+  }
+
+  #[test]
+  fn matches_only_the_selected_pixels_to_the_reference() {
+    let path = PathBuf::from("nobody cares");
+    let mut image = Image::new(&path, DynamicImage::new_rgb8(1,4));
+    let mut reference = Image::new(&path, DynamicImage::new_rgb8(1,4));
+
+    for i in 0..4 {
+      let val = i as u8;
+      image.dynamic_image_mut().put_pixel(0,i as u32, Pixel::from_channels(val,val,val,255));
+      let val = 100 + (i * 50) as u8;
+      reference.dynamic_image_mut().put_pixel(0,i as u32, Pixel::from_channels(val,val,val,255));
+    }
+    let mask = ::selection::Selection::rectangle((0.0, 2.0), (1.0, 4.0)).to_mask(1, 4);
+    let matched = image.match_histogram_to(&reference.histogram_reference(), Some(&mask));
+
+    // the histogram of the selection alone is matched
+    assert_eq!(matched.get_pixel(0,0)[0], 0);
+    assert_eq!(matched.get_pixel(0,1)[0], 1);
+    assert_eq!(matched.get_pixel(0,2)[0], 150);
+    assert_eq!(matched.get_pixel(0,3)[0], 250);
+  }
+
+  #[test]
+  fn thresholds_the_image_correctly() {
+    let path = PathBuf::from("nobody cares");
+    let dynamic_image = DynamicImage::new_rgb8(1,3);
+    let mut image = Image::new(&path, dynamic_image);
+
+    for i in 0..3 {
+      let val = (i * 100) as u8;
+      image.dynamic_image_mut().put_pixel(0,i as u32, Pixel::from_channels(val,val,val,255));
+    }
+    let thresholded = image.threshold(100);
+
+    assert_eq!(thresholded.get_pixel(0,0)[0], 0);
+    assert_eq!(thresholded.get_pixel(0,1)[0], 0);
+    assert_eq!(thresholded.get_pixel(0,2)[0], 255);
+  }
+
+  #[test]
+  fn denoise_removes_isolated_pixels() {
+    let path = PathBuf::from("nobody cares");
+    let dynamic_image = DynamicImage::new_rgb8(3,3);
+    let mut image = Image::new(&path, dynamic_image);
+
+    image.dynamic_image_mut().put_pixel(1,1, Pixel::from_channels(255,255,255,255));
+    let denoised = image.denoise(1);
+
+    for (_,_,rgb) in denoised.pixels() {
+      assert_eq!(rgb[0], 0);
+    }
+  }
+
+  #[test]
+  fn detects_the_edge_between_two_regions() {
+    let path = PathBuf::from("nobody cares");
+    let dynamic_image = DynamicImage::new_rgb8(4,2);
+    let mut image = Image::new(&path, dynamic_image);
+
+    for r in 0..2 {
+      for c in 2..4 {
+        image.dynamic_image_mut().put_pixel(c,r, Pixel::from_channels(200,200,200,255));
+      }
+    }
+    let edges = image.detect_edges(50);
+
+    let row: Vec<u8> = (0..4).map(|c| edges.get_pixel(c,0)[0]).collect();
+    assert_eq!(row, vec![0, 255, 255, 0]);
+  }
+
+  #[test]
+  fn clones_share_pixels_until_modified() {
+    let path = PathBuf::from("nobody cares");
+    let image = Image::new(&path, DynamicImage::new_rgb8(2,2));
+    let mut copy = image.clone();
+    assert!(copy.shares_pixels_with(&image));
+
+    copy.dynamic_image_mut().put_pixel(0,0, Pixel::from_channels(9,9,9,255));
+    assert!(!copy.shares_pixels_with(&image));
+    assert_eq!(image.get_pixel(0,0).unwrap()[0], 0);
+    assert_eq!(copy.get_pixel(0,0).unwrap()[0], 9);
+  }
+
+  #[test]
+  fn lookups_agree_with_the_scalar_ones() {
+    // lengths that don't fill the last vector, and every level
+    let samples: Vec<u8> = (0..1000usize).map(|i| (i * 97 % 256) as u8).collect();
+    let lut = lut_u8(|level| level.wrapping_mul(7) ^ 0x5a);
+    let (mut vectorized, mut scalar) = (samples.clone(), samples.clone());
+    apply_lut_u8(&mut vectorized, &lut);
+    apply_lut_u8_scalar(&mut scalar, &lut);
+    assert_eq!(vectorized, scalar);
+
+    let samples: Vec<u16> = (0..70000usize).map(|i| (i * 40503 % 65536) as u16).chain(vec![65535, 65534, 0]).collect();
+    let lut = lut_u16(|level| level.wrapping_mul(3) ^ 0x1234);
+    let (mut vectorized, mut scalar) = (samples.clone(), samples.clone());
+    apply_lut_u16(&mut vectorized, &lut);
+    apply_lut_u16_scalar(&mut scalar, &lut);
+    assert_eq!(vectorized, scalar);
+  }
+
+  #[test]
+  fn histograms_count_one_channel() {
+    let samples: Vec<u8> = (0..9).flat_map(|i| vec![i as u8, 200, 7]).collect();
+    let histogram = histogram_u8(&samples, 3, 0);
+    assert!((0..9).all(|level| histogram[level] == 1));
+    assert_eq!(histogram_u8(&samples, 3, 1)[200], 9);
+
+    let samples: Vec<u16> = vec![65535, 3, 65535, 3, 65535];
+    let histogram = histogram_u16(&samples, 1, 0);
+    assert_eq!((histogram[3], histogram[65535]), (2, 3));
+  }
+
+  #[test]
+  fn levels_stretch_the_range() {
+    let lut = levels_lut_u8(50, 150, 1.0);
+    assert_eq!((lut[0], lut[50], lut[100], lut[150], lut[255]), (0, 0, 128, 255, 255));
+    // brighter midtones with a higher gamma, the ends left alone
+    let lut = levels_lut_u8(0, 255, 2.0);
+    assert!(lut[64] > 64 && lut[0] == 0 && lut[255] == 255);
+  }
+
+  #[test]
+  fn reads_every_format_like_a_conversion_to_rgba() {
+    let rgba = ::image::image::RgbaImage::from_fn(5, 3, |x, y| Rgba([(x * 40) as u8, (y * 60) as u8, 7, (x + y) as u8]));
+    let dynamic_image = DynamicImage::ImageRgba8(rgba);
+    let formats = vec![
+      DynamicImage::ImageLuma8(dynamic_image.to_luma()),
+      DynamicImage::ImageLumaA8(dynamic_image.to_luma_alpha()),
+      DynamicImage::ImageRgb8(dynamic_image.to_rgb()),
+      DynamicImage::ImageBgra8(dynamic_image.to_bgra()),
+      dynamic_image
+    ];
+
+    for format in formats {
+      let samples = Samples::of(&format);
+      for (x, y, pixel) in format.to_rgba().enumerate_pixels() {
+        assert_eq!(samples.pixel(x, y), pixel.data);
+      }
+    }
+  }
 }
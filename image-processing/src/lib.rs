@@ -0,0 +1,40 @@
+// The image processing itself, without the graphical interface, so that the
+// benchmarks can get at it too.
+pub extern crate failure;
+pub extern crate serde;
+pub extern crate serde_json;
+extern crate rand;
+extern crate rayon;
+extern crate libloading;
+extern crate byteorder;
+extern crate inflate;
+extern crate lzw;
+extern crate png;
+extern crate gif;
+#[macro_use]
+extern crate lazy_static;
+#[macro_use]
+extern crate serde_derive;
+pub mod image;
+pub mod selection;
+pub mod pipeline;
+pub mod statistics;
+pub mod filters;
+pub mod fft;
+pub mod blobs;
+pub mod hough;
+pub mod features;
+pub mod homography;
+pub mod stitching;
+pub mod perspective;
+pub mod pyramid;
+pub mod templates;
+pub mod operations;
+pub mod tiff;
+pub mod tiles;
+pub mod depth;
+pub mod hdr;
+pub mod animation;
+pub mod pnm;
+pub mod thumbnails;
+pub mod annotations;
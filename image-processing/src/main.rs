@@ -1,10 +1,49 @@
 pub extern crate failure;
 pub extern crate gtk;
+pub extern crate gdk;
 pub extern crate gdk_pixbuf;
-mod image;
+pub extern crate cairo;
+extern crate image_processing;
+use image_processing::{
+  image,
+  selection,
+  pipeline,
+  statistics,
+  fft,
+  blobs,
+  stitching,
+  perspective,
+  templates,
+  operations,
+  tiles,
+  depth,
+  hdr,
+  animation,
+  pnm,
+  thumbnails,
+  annotations
+};
+mod cli;
 mod ui;
+use std::{env, process};
 use ui::App;
 
 fn main() {
-  App::new().connect_events().then_execute();
+  let args: Vec<String> = env::args().skip(1).collect();
+
+  if let Some(dir) = operations::plugins_dir() {
+    for loaded in operations::registry().write().unwrap().load_plugins(&dir) {
+      if let Err(error) = loaded {
+        eprintln!("{}", error);
+      }
+    }
+  }
+
+  if args.is_empty() {
+    App::new().connect_events().then_execute();
+  }
+  else if let Err(error) = cli::run(&args) {
+    eprintln!("{}", error);
+    process::exit(1);
+  }
 }
@@ -0,0 +1,590 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::ffi::{CStr, CString};
+use std::fmt;
+use std::fs;
+use std::os::raw::{c_char, c_int};
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use failure::{err_msg, format_err, Error};
+use libloading::Library;
+use serde_json;
+
+use image::Image;
+use image::image::{DynamicImage, ImageBuffer};
+use selection::Mask;
+use depth::{AnyImage, TypedImage};
+
+// overrides where plugins are looked for, by default a `plugins` folder next to
+// the executable
+pub const PLUGINS_VARIABLE: &str = "IMAGE_PROCESSING_PLUGINS";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum Value {
+  Boolean(bool),
+  Integer(i64),
+  Number(f64),
+  Text(String)
+}
+
+impl fmt::Display for Value {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      Value::Boolean(value) => write!(f, "{}", value),
+      Value::Integer(value) => write!(f, "{}", value),
+      Value::Number(value) => write!(f, "{}", value),
+      Value::Text(ref value) => write!(f, "{}", value)
+    }
+  }
+}
+
+// the arguments of an operation, by parameter name
+pub type Arguments = BTreeMap<String, Value>;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ParameterKind {
+  Integer { min: i64, max: i64, default: i64 },
+  Number { min: f64, max: f64, default: f64 },
+  // the options as value and label pairs
+  Choice { options: Vec<(String, String)>, default: String },
+  Boolean { default: bool }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Parameter {
+  pub name: String,
+  pub label: String,
+  #[serde(flatten)]
+  pub kind: ParameterKind
+}
+
+impl Parameter {
+  pub fn integer(name: &str, label: &str, min: i64, max: i64, default: i64) -> Self {
+    Self { name: name.to_string(), label: label.to_string(), kind: ParameterKind::Integer { min, max, default } }
+  }
+
+  pub fn number(name: &str, label: &str, min: f64, max: f64, default: f64) -> Self {
+    Self { name: name.to_string(), label: label.to_string(), kind: ParameterKind::Number { min, max, default } }
+  }
+
+  pub fn choice(name: &str, label: &str, options: &[(&str, &str)], default: &str) -> Self {
+    let options = options.iter().map(|&(value, label)| (value.to_string(), label.to_string())).collect();
+    Self { name: name.to_string(), label: label.to_string(), kind: ParameterKind::Choice { options, default: default.to_string() } }
+  }
+
+  pub fn boolean(name: &str, label: &str, default: bool) -> Self {
+    Self { name: name.to_string(), label: label.to_string(), kind: ParameterKind::Boolean { default } }
+  }
+
+  pub fn default_value(&self) -> Value {
+    match self.kind {
+      ParameterKind::Integer { default, .. } => Value::Integer(default),
+      ParameterKind::Number { default, .. } => Value::Number(default),
+      ParameterKind::Choice { ref default, .. } => Value::Text(default.clone()),
+      ParameterKind::Boolean { default } => Value::Boolean(default)
+    }
+  }
+
+  // Checks that the value suits the parameter, converting between integers and
+  // numbers where needed.
+  pub fn check(&self, value: &Value) -> Result<Value, Error> {
+    let invalid = || format_err!("{} isn't a valid {}", value, self.name);
+    match (&self.kind, value) {
+      (&ParameterKind::Integer { min, max, .. }, &Value::Integer(value)) if value >= min && value <= max =>
+        Ok(Value::Integer(value)),
+      (&ParameterKind::Number { min, max, .. }, &Value::Number(value)) if value >= min && value <= max =>
+        Ok(Value::Number(value)),
+      (&ParameterKind::Number { min, max, .. }, &Value::Integer(value)) if value as f64 >= min && value as f64 <= max =>
+        Ok(Value::Number(value as f64)),
+      (ParameterKind::Choice { options, .. }, Value::Text(value)) if options.iter().any(|option| &option.0 == value) =>
+        Ok(Value::Text(value.clone())),
+      (&ParameterKind::Boolean { .. }, &Value::Boolean(value)) => Ok(Value::Boolean(value)),
+      _ => Err(invalid())
+    }
+  }
+
+  // parses a value typed on the command line
+  pub fn parse(&self, text: &str) -> Result<Value, Error> {
+    let value = match self.kind {
+      ParameterKind::Integer { .. } => text.parse().map(Value::Integer).map_err(err_msg)?,
+      ParameterKind::Number { .. } => text.parse().map(Value::Number).map_err(err_msg)?,
+      ParameterKind::Choice { .. } => Value::Text(text.to_string()),
+      ParameterKind::Boolean { .. } => text.parse().map(Value::Boolean).map_err(err_msg)?
+    };
+    self.check(&value)
+  }
+}
+
+// An operation that can be applied to an image, listed in the side menu and on
+// the command line, and recorded in recipes by its name.
+pub trait ImageOperation: Send + Sync {
+  fn name(&self) -> &str;
+  fn label(&self) -> &str;
+  fn parameters(&self) -> Vec<Parameter>;
+  // the arguments are checked against the parameters beforehand
+  fn apply(&self, image: &Image, arguments: &Arguments) -> Result<DynamicImage, Error>;
+
+  fn apply_within(&self, image: &Image, arguments: &Arguments, mask: &Mask) -> Result<DynamicImage, Error> {
+    Ok(image.blend_within(&self.apply(image, arguments)?, mask))
+  }
+
+  // How far around each pixel the operation looks, for running it a tile at a
+  // time. None when it needs the whole image.
+  fn halo(&self, _: &Arguments) -> Option<u32> {
+    None
+  }
+
+  // The operation on an image of any depth, keeping its precision. Levels given
+  // as integers are from 0 to 255 whatever the depth. Operations without a way
+  // to keep it run on the 8 bit copy.
+  fn apply_precise(&self, image: &AnyImage, arguments: &Arguments) -> Result<AnyImage, Error> {
+    apply_at_8_bits(image, |image| self.apply(image, arguments))
+  }
+}
+
+// an 8 bit operation on the display copy of an image of any depth
+pub fn apply_at_8_bits<F>(image: &AnyImage, apply: F) -> Result<AnyImage, Error>
+  where F: FnOnce(&Image) -> Result<DynamicImage, Error> {
+  let image = Image::new(&PathBuf::new(), image.to_dynamic_image());
+  Ok(AnyImage::U8(TypedImage::from_dynamic_image(&apply(&image)?)))
+}
+
+// the argument accessors below expect checked arguments
+
+pub fn integer_argument(arguments: &Arguments, name: &str) -> Result<i64, Error> {
+  match arguments.get(name) {
+    Some(&Value::Integer(value)) => Ok(value),
+    _ => Err(format_err!("missing integer {}", name))
+  }
+}
+
+pub fn number_argument(arguments: &Arguments, name: &str) -> Result<f64, Error> {
+  match arguments.get(name) {
+    Some(&Value::Number(value)) => Ok(value),
+    _ => Err(format_err!("missing number {}", name))
+  }
+}
+
+pub fn text_argument<'a>(arguments: &'a Arguments, name: &str) -> Result<&'a str, Error> {
+  match arguments.get(name) {
+    Some(Value::Text(value)) => Ok(value),
+    _ => Err(format_err!("missing choice {}", name))
+  }
+}
+
+pub fn boolean_argument(arguments: &Arguments, name: &str) -> Result<bool, Error> {
+  match arguments.get(name) {
+    Some(&Value::Boolean(value)) => Ok(value),
+    _ => Err(format_err!("missing boolean {}", name))
+  }
+}
+
+struct EqualizeHistogram;
+
+impl ImageOperation for EqualizeHistogram {
+  fn name(&self) -> &str { "equalize_histogram" }
+  fn label(&self) -> &str { "equalize histogram" }
+  fn parameters(&self) -> Vec<Parameter> { Vec::new() }
+
+  fn apply(&self, image: &Image, _: &Arguments) -> Result<DynamicImage, Error> {
+    Ok(image.equalize_histogram())
+  }
+
+  fn apply_precise(&self, image: &AnyImage, _: &Arguments) -> Result<AnyImage, Error> {
+    Ok(image.equalize_histogram())
+  }
+
+  // the histogram of the selection only
+  fn apply_within(&self, image: &Image, _: &Arguments, mask: &Mask) -> Result<DynamicImage, Error> {
+    Ok(image.equalize_histogram_within(mask))
+  }
+}
+
+struct Threshold;
+
+impl ImageOperation for Threshold {
+  fn name(&self) -> &str { "threshold" }
+  fn label(&self) -> &str { "threshold" }
+  fn parameters(&self) -> Vec<Parameter> { vec![Parameter::integer("level", "level", 0, 255, 128)] }
+
+  fn apply(&self, image: &Image, arguments: &Arguments) -> Result<DynamicImage, Error> {
+    Ok(image.threshold(integer_argument(arguments, "level")? as u8))
+  }
+
+  fn apply_precise(&self, image: &AnyImage, arguments: &Arguments) -> Result<AnyImage, Error> {
+    Ok(image.threshold(integer_argument(arguments, "level")? as f32 / 255.0))
+  }
+
+  fn halo(&self, _: &Arguments) -> Option<u32> { Some(0) }
+}
+
+struct Gamma;
+
+impl ImageOperation for Gamma {
+  fn name(&self) -> &str { "gamma" }
+  fn label(&self) -> &str { "gamma" }
+  fn parameters(&self) -> Vec<Parameter> { vec![Parameter::number("gamma", "gamma", 0.1, 5.0, 1.0)] }
+
+  fn apply(&self, image: &Image, arguments: &Arguments) -> Result<DynamicImage, Error> {
+    Ok(image.adjust_gamma(number_argument(arguments, "gamma")?))
+  }
+
+  fn apply_precise(&self, image: &AnyImage, arguments: &Arguments) -> Result<AnyImage, Error> {
+    Ok(image.adjust_gamma(number_argument(arguments, "gamma")? as f32))
+  }
+
+  fn halo(&self, _: &Arguments) -> Option<u32> { Some(0) }
+}
+
+struct Levels;
+
+impl ImageOperation for Levels {
+  fn name(&self) -> &str { "levels" }
+  fn label(&self) -> &str { "levels" }
+  fn parameters(&self) -> Vec<Parameter> {
+    vec![
+      Parameter::integer("black", "black", 0, 255, 0),
+      Parameter::integer("white", "white", 0, 255, 255),
+      Parameter::number("gamma", "gamma", 0.1, 5.0, 1.0)
+    ]
+  }
+
+  fn apply(&self, image: &Image, arguments: &Arguments) -> Result<DynamicImage, Error> {
+    let (black, white) = (integer_argument(arguments, "black")?, integer_argument(arguments, "white")?);
+    if white <= black {
+      return Err(err_msg("the white level has to be above the black one"));
+    }
+    Ok(image.adjust_levels(black as u8, white as u8, number_argument(arguments, "gamma")?))
+  }
+
+  fn apply_precise(&self, image: &AnyImage, arguments: &Arguments) -> Result<AnyImage, Error> {
+    let (black, white) = (integer_argument(arguments, "black")?, integer_argument(arguments, "white")?);
+    if white <= black {
+      return Err(err_msg("the white level has to be above the black one"));
+    }
+    Ok(image.adjust_levels(black as f32 / 255.0, white as f32 / 255.0, number_argument(arguments, "gamma")? as f32))
+  }
+
+  fn halo(&self, _: &Arguments) -> Option<u32> { Some(0) }
+}
+
+struct Denoise;
+
+impl ImageOperation for Denoise {
+  fn name(&self) -> &str { "denoise" }
+  fn label(&self) -> &str { "denoise" }
+  fn parameters(&self) -> Vec<Parameter> { vec![Parameter::integer("radius", "radius", 1, 10, 1)] }
+
+  fn apply(&self, image: &Image, arguments: &Arguments) -> Result<DynamicImage, Error> {
+    Ok(image.denoise(integer_argument(arguments, "radius")? as u32))
+  }
+
+  fn apply_precise(&self, image: &AnyImage, arguments: &Arguments) -> Result<AnyImage, Error> {
+    Ok(image.denoise(integer_argument(arguments, "radius")? as u32))
+  }
+
+  fn halo(&self, arguments: &Arguments) -> Option<u32> {
+    integer_argument(arguments, "radius").ok().map(|radius| radius as u32)
+  }
+}
+
+struct DetectEdges;
+
+impl ImageOperation for DetectEdges {
+  fn name(&self) -> &str { "detect_edges" }
+  fn label(&self) -> &str { "edge detection" }
+  fn parameters(&self) -> Vec<Parameter> { vec![Parameter::integer("level", "level", 0, 255, 64)] }
+
+  fn apply(&self, image: &Image, arguments: &Arguments) -> Result<DynamicImage, Error> {
+    Ok(image.detect_edges(integer_argument(arguments, "level")? as u8))
+  }
+
+  fn apply_precise(&self, image: &AnyImage, arguments: &Arguments) -> Result<AnyImage, Error> {
+    Ok(image.detect_edges(integer_argument(arguments, "level")? as f32 / 255.0))
+  }
+
+  fn halo(&self, _: &Arguments) -> Option<u32> { Some(1) }
+}
+
+struct Flip;
+
+impl ImageOperation for Flip {
+  fn name(&self) -> &str { "flip" }
+  fn label(&self) -> &str { "flip" }
+  fn parameters(&self) -> Vec<Parameter> {
+    vec![Parameter::choice("direction", "direction", &[("horizontal", "horizontally"), ("vertical", "vertically")], "horizontal")]
+  }
+
+  fn apply(&self, image: &Image, arguments: &Arguments) -> Result<DynamicImage, Error> {
+    let image = image.as_dynamic_image();
+    Ok(match text_argument(arguments, "direction")? {
+      "vertical" => image.flipv(),
+      _ => image.fliph()
+    })
+  }
+
+  fn apply_precise(&self, image: &AnyImage, arguments: &Arguments) -> Result<AnyImage, Error> {
+    Ok(image.flip(text_argument(arguments, "direction")? != "vertical"))
+  }
+}
+
+// Plugins are shared libraries exporting, with the C calling convention:
+//   const char *operation_name(void);
+//   const char *operation_label(void);
+//   const char *operation_parameters(void);  the parameters, as JSON
+//   int apply_operation(uint8_t *rgba, uint32_t width, uint32_t height, const char *arguments);
+// `apply_operation` works in place on the RGBA pixels, with the arguments as a
+// JSON object, and returns 0 when it succeeds.
+type DescribeFunction = unsafe extern "C" fn() -> *const c_char;
+type ApplyFunction = unsafe extern "C" fn(*mut u8, u32, u32, *const c_char) -> c_int;
+
+struct PluginOperation {
+  name: String,
+  label: String,
+  parameters: Vec<Parameter>,
+  // valid as long as the registry holds the library
+  apply: ApplyFunction
+}
+
+impl ImageOperation for PluginOperation {
+  fn name(&self) -> &str { &self.name }
+  fn label(&self) -> &str { &self.label }
+  fn parameters(&self) -> Vec<Parameter> { self.parameters.clone() }
+
+  fn apply(&self, image: &Image, arguments: &Arguments) -> Result<DynamicImage, Error> {
+    let mut rgba = image.as_dynamic_image().to_rgba();
+    let (width, height) = rgba.dimensions();
+    let arguments = CString::new(serde_json::to_string(arguments).map_err(err_msg)?).map_err(err_msg)?;
+
+    let status = unsafe { (self.apply)(rgba.as_mut_ptr(), width, height, arguments.as_ptr()) };
+    if status != 0 {
+      return Err(format_err!("{} failed with status {}", self.name, status));
+    }
+    match ImageBuffer::from_raw(width, height, rgba.into_raw()) {
+      Some(buffer) => Ok(DynamicImage::ImageRgba8(buffer)),
+      None => Err(format_err!("{} returned a broken image", self.name))
+    }
+  }
+}
+
+unsafe fn describe(library: &Library, symbol: &[u8]) -> Result<String, Error> {
+  let function = library.get::<DescribeFunction>(symbol).map_err(err_msg)?;
+  let text = function();
+  if text.is_null() {
+    return Err(err_msg("the plugin described itself with a null string"));
+  }
+  Ok(CStr::from_ptr(text).to_string_lossy().into_owned())
+}
+
+#[derive(Default)]
+pub struct Registry {
+  operations: Vec<Box<dyn ImageOperation>>,
+  // declared last, so that the plugins' operations are dropped before their code
+  libraries: Vec<Library>
+}
+
+impl Registry {
+  pub fn new() -> Self {
+    Self {
+      operations: Vec::new(),
+      libraries: Vec::new()
+    }
+  }
+
+  pub fn with_builtins() -> Self {
+    Self {
+      operations: vec![
+        Box::new(EqualizeHistogram),
+        Box::new(Threshold),
+        Box::new(Gamma),
+        Box::new(Levels),
+        Box::new(Denoise),
+        Box::new(DetectEdges),
+        Box::new(Flip)
+      ],
+      libraries: Vec::new()
+    }
+  }
+
+  // Fails when there's already an operation of the same name, so that recipes
+  // naming it keep doing what they did.
+  pub fn register(&mut self, operation: Box<dyn ImageOperation>) -> Result<(), Error> {
+    if self.get(operation.name()).is_some() {
+      return Err(format_err!("there's already an operation called {}", operation.name()));
+    }
+    self.operations.push(operation);
+    Ok(())
+  }
+
+  pub fn get(&self, name: &str) -> Option<&dyn ImageOperation> {
+    self.operations.iter().find(|operation| operation.name() == name).map(|operation| operation.as_ref())
+  }
+
+  pub fn operations(&self) -> &[Box<dyn ImageOperation>] {
+    &self.operations
+  }
+
+  // the given arguments checked, with defaults for the missing ones
+  pub fn arguments(&self, name: &str, given: &Arguments) -> Result<Arguments, Error> {
+    let operation = self.get(name).ok_or_else(|| format_err!("there's no operation called {}", name))?;
+    let parameters = operation.parameters();
+
+    if let Some(unknown) = given.keys().find(|key| !parameters.iter().any(|parameter| &parameter.name == *key)) {
+      return Err(format_err!("{} has no parameter called {}", name, unknown));
+    }
+    let mut arguments = Arguments::new();
+    for parameter in parameters.iter() {
+      let value = match given.get(&parameter.name) {
+        Some(value) => parameter.check(value)?,
+        None => parameter.default_value()
+      };
+      arguments.insert(parameter.name.clone(), value);
+    }
+    Ok(arguments)
+  }
+
+  pub fn apply(&self, name: &str, image: &Image, arguments: &Arguments, mask: Option<&Mask>) -> Result<DynamicImage, Error> {
+    let arguments = self.arguments(name, arguments)?;
+    let operation = self.get(name).unwrap();
+    match mask {
+      Some(mask) => operation.apply_within(image, &arguments, mask),
+      None => operation.apply(image, &arguments)
+    }
+  }
+
+  // see `ImageOperation::apply_precise`
+  pub fn apply_precise(&self, name: &str, image: &AnyImage, arguments: &Arguments) -> Result<AnyImage, Error> {
+    let arguments = self.arguments(name, arguments)?;
+    self.get(name).unwrap().apply_precise(image, &arguments)
+  }
+
+  // see `ImageOperation::halo`
+  pub fn halo(&self, name: &str, arguments: &Arguments) -> Result<Option<u32>, Error> {
+    let arguments = self.arguments(name, arguments)?;
+    Ok(self.get(name).unwrap().halo(&arguments))
+  }
+
+  // Loads the operation of a plugin. Returns its name.
+  pub fn load_plugin(&mut self, path: &Path) -> Result<String, Error> {
+    let library = Library::new(path).map_err(err_msg)?;
+    let operation = unsafe {
+      let parameters = describe(&library, b"operation_parameters\0")?;
+      PluginOperation {
+        name: describe(&library, b"operation_name\0")?,
+        label: describe(&library, b"operation_label\0")?,
+        parameters: serde_json::from_str(&parameters).map_err(err_msg)?,
+        apply: *library.get::<ApplyFunction>(b"apply_operation\0").map_err(err_msg)?
+      }
+    };
+
+    let name = operation.name.clone();
+    self.register(Box::new(operation))?;
+    self.libraries.push(library);
+    Ok(name)
+  }
+
+  // Loads every shared library in the folder, if there is one. A broken plugin
+  // doesn't keep the others from loading.
+  pub fn load_plugins(&mut self, dir: &Path) -> Vec<Result<String, Error>> {
+    let entries = match fs::read_dir(dir) {
+      Ok(entries) => entries,
+      Err(_) => return Vec::new()
+    };
+
+    let mut paths: Vec<PathBuf> = entries.filter_map(|entry| entry.ok()).map(|entry| entry.path())
+    .filter(|path| matches!(path.extension().and_then(|extension| extension.to_str()),
+                            Some("so") | Some("dylib") | Some("dll")))
+    .collect();
+    paths.sort();
+
+    paths.iter()
+    .map(|path| self.load_plugin(path).map_err(|error| format_err!("{}: {}", path.display(), error)))
+    .collect()
+  }
+}
+
+pub fn plugins_dir() -> Option<PathBuf> {
+  match env::var_os(PLUGINS_VARIABLE) {
+    Some(dir) => Some(PathBuf::from(dir)),
+    None => env::current_exe().ok().and_then(|exe| exe.parent().map(|dir| dir.join("plugins")))
+  }
+}
+
+lazy_static! {
+  static ref REGISTRY: RwLock<Registry> = RwLock::new(Registry::with_builtins());
+}
+
+// the operations available to the whole application
+pub fn registry() -> &'static RwLock<Registry> {
+  &REGISTRY
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use image::image::{GenericImageView, GrayImage, Luma};
+
+  fn gradient_image() -> Image {
+    let buffer = GrayImage::from_fn(4, 1, |x, _| Luma([(x * 80) as u8]));
+    Image::new(&PathBuf::from("nobody cares"), DynamicImage::ImageLuma8(buffer))
+  }
+
+  #[test]
+  fn fills_in_default_arguments() {
+    let registry = Registry::with_builtins();
+    let arguments = registry.arguments("threshold", &Arguments::new()).unwrap();
+
+    assert_eq!(arguments.get("level"), Some(&Value::Integer(128)));
+  }
+
+  #[test]
+  fn rejects_arguments_that_dont_fit() {
+    let registry = Registry::with_builtins();
+    let mut arguments = Arguments::new();
+    arguments.insert("level".to_string(), Value::Integer(300));
+    assert!(registry.arguments("threshold", &arguments).is_err());
+
+    let mut arguments = Arguments::new();
+    arguments.insert("colour".to_string(), Value::Integer(3));
+    assert!(registry.arguments("threshold", &arguments).is_err());
+
+    assert!(registry.arguments("sharpen", &Arguments::new()).is_err());
+  }
+
+  #[test]
+  fn applies_operations_by_name() {
+    let registry = Registry::with_builtins();
+    let mut arguments = Arguments::new();
+    arguments.insert("level".to_string(), Value::Integer(100));
+    let thresholded = registry.apply("threshold", &gradient_image(), &arguments, None).unwrap();
+    assert_eq!(thresholded.get_pixel(1, 0)[0], 0);
+    assert_eq!(thresholded.get_pixel(2, 0)[0], 255);
+
+    let direction = Parameter::choice("direction", "direction", &[("horizontal", ""), ("vertical", "")], "horizontal");
+    let mut arguments = Arguments::new();
+    arguments.insert("direction".to_string(), direction.parse("horizontal").unwrap());
+    let flipped = registry.apply("flip", &gradient_image(), &arguments, None).unwrap();
+    assert_eq!(flipped.get_pixel(0, 0)[0], 240);
+  }
+
+  #[test]
+  fn refuses_to_replace_an_operation() {
+    let mut registry = Registry::with_builtins();
+    assert!(registry.register(Box::new(Threshold)).is_err());
+    assert_eq!(registry.operations().iter().filter(|operation| operation.name() == "threshold").count(), 1);
+  }
+
+  #[test]
+  fn parameters_read_from_json() {
+    let parameters: Vec<Parameter> = serde_json::from_str(r#"[
+      {"name": "amount", "label": "amount", "type": "number", "min": 0, "max": 2, "default": 1},
+      {"name": "invert", "label": "invert", "type": "boolean", "default": false}
+    ]"#).unwrap();
+
+    assert_eq!(parameters, vec![Parameter::number("amount", "amount", 0.0, 2.0, 1.0), Parameter::boolean("invert", "invert", false)]);
+    assert_eq!(parameters[0].parse("1").unwrap(), Value::Number(1.0));
+  }
+}
@@ -0,0 +1,189 @@
+use failure::{err_msg, Error};
+
+use image::Image;
+use image::image::{DynamicImage, ImageBuffer, Rgb, RgbImage};
+use homography::Homography;
+
+// in square pixels
+const MIN_TRIANGLE_AREA: f64 = 0.5;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Interpolation {
+  Nearest,
+  Bilinear,
+  // Catmull-Rom
+  Bicubic
+}
+
+// Samples the image at (x, y), pixel centers lying on whole coordinates. Points
+// outside of the image take the color of its closest edge.
+pub fn sample(image: &RgbImage, x: f64, y: f64, interpolation: Interpolation) -> [f64; 3] {
+  let (max_x, max_y) = (image.width() as i64 - 1, image.height() as i64 - 1);
+  let pixel = |px: i64, py: i64| image.get_pixel(px.max(0).min(max_x) as u32, py.max(0).min(max_y) as u32);
+
+  let mut color = [0.0; 3];
+  match interpolation {
+    Interpolation::Nearest => {
+      let nearest = pixel(x.round() as i64, y.round() as i64);
+      for (channel, value) in color.iter_mut().enumerate() {
+        *value = nearest[channel] as f64;
+      }
+    },
+    Interpolation::Bilinear | Interpolation::Bicubic => {
+      let (x0, y0) = (x.floor() as i64, y.floor() as i64);
+      let (fx, fy) = (x - x0 as f64, y - y0 as f64);
+
+      // the taps and their weights along each axis
+      let (taps, x_weights, y_weights): (Vec<i64>, Vec<f64>, Vec<f64>) = match interpolation {
+        Interpolation::Bilinear => (vec![0, 1], vec![1.0 - fx, fx], vec![1.0 - fy, fy]),
+        _ => (vec![-1, 0, 1, 2], catmull_rom(fx).to_vec(), catmull_rom(fy).to_vec())
+      };
+
+      for (j, &dy) in taps.iter().enumerate() {
+        for (i, &dx) in taps.iter().enumerate() {
+          let neighbour = pixel(x0 + dx, y0 + dy);
+          let weight = x_weights[i] * y_weights[j];
+          for (channel, value) in color.iter_mut().enumerate() {
+            *value += weight * neighbour[channel] as f64;
+          }
+        }
+      }
+    }
+  }
+  color
+}
+
+// weights of the 4 taps around a point `t` past the second one
+fn catmull_rom(t: f64) -> [f64; 4] {
+  let (t2, t3) = (t * t, t * t * t);
+  [(-t3 + 2.0 * t2 - t) / 2.0,
+   (3.0 * t3 - 5.0 * t2 + 2.0) / 2.0,
+   (-3.0 * t3 + 4.0 * t2 + t) / 2.0,
+   (t3 - t2) / 2.0]
+}
+
+// Orders the corners of a quadrilateral clockwise on screen, starting from the
+// top left one, whatever order they were clicked in.
+pub fn order_corners(corners: [(f64, f64); 4]) -> [(f64, f64); 4] {
+  let center_x = corners.iter().map(|c| c.0).sum::<f64>() / 4.0;
+  let center_y = corners.iter().map(|c| c.1).sum::<f64>() / 4.0;
+
+  let mut ordered = corners;
+  // y grows downwards, so increasing angles go clockwise
+  ordered.sort_by(|a, b| {
+    let angle = |c: &(f64, f64)| (c.1 - center_y).atan2(c.0 - center_x);
+    angle(a).partial_cmp(&angle(b)).unwrap()
+  });
+
+  let top_left = (0..4).min_by(|&a, &b| {
+    (ordered[a].0 + ordered[a].1).partial_cmp(&(ordered[b].0 + ordered[b].1)).unwrap()
+  }).unwrap();
+  ordered.rotate_left(top_left);
+  ordered
+}
+
+// the output size that keeps the longer of each pair of opposite sides
+pub fn rectified_size(corners: &[(f64, f64); 4]) -> (u32, u32) {
+  let length = |a: (f64, f64), b: (f64, f64)| (a.0 - b.0).hypot(a.1 - b.1);
+  let width = length(corners[0], corners[1]).max(length(corners[3], corners[2]));
+  let height = length(corners[0], corners[3]).max(length(corners[1], corners[2]));
+  (width.round().max(1.0) as u32, height.round().max(1.0) as u32)
+}
+
+// whether any three of the corners span less than half a pixel
+fn has_three_on_a_line(corners: &[(f64, f64); 4]) -> bool {
+  (0..4).any(|left_out| {
+    let triangle: Vec<(f64, f64)> = (0..4).filter(|&i| i != left_out).map(|i| corners[i]).collect();
+    let (a, b, c) = (triangle[0], triangle[1], triangle[2]);
+    let area = ((b.0 - a.0) * (c.1 - a.1) - (c.0 - a.0) * (b.1 - a.1)).abs() / 2.0;
+    !area.is_finite() || area < MIN_TRIANGLE_AREA
+  })
+}
+
+impl Image {
+  // Warps the quadrilateral with the given top left, top right, bottom right
+  // and bottom left corners onto a width x height rectangle. Fails when the
+  // corners don't make a quadrilateral, e.g. with three of them on a line.
+  pub fn warp_perspective(&self, corners: &[(f64, f64); 4], width: u32, height: u32, interpolation: Interpolation) -> Result<DynamicImage, Error> {
+    let source = self.as_dynamic_image().to_rgb();
+    let (w, h) = (width as f64, height as f64);
+    let rectangle = [(0.0, 0.0), (w, 0.0), (w, h), (0.0, h)];
+
+    if has_three_on_a_line(corners) {
+      return Err(err_msg("the corners don't make a quadrilateral to correct"));
+    }
+    let pairs: Vec<_> = rectangle.iter().cloned().zip(corners.iter().cloned()).collect();
+    let homography = Homography::from_points(&pairs)
+    .ok_or_else(|| err_msg("the corners don't make a quadrilateral to correct"))?;
+
+    Ok(DynamicImage::ImageRgb8(ImageBuffer::from_fn(width, height, |x, y| {
+      // pixels cover the unit square right and below their coordinates
+      let (source_x, source_y) = homography.apply((x as f64 + 0.5, y as f64 + 0.5));
+      let color = sample(&source, source_x - 0.5, source_y - 0.5, interpolation);
+      Rgb([clamp(color[0]), clamp(color[1]), clamp(color[2])])
+    })))
+  }
+}
+
+fn clamp(value: f64) -> u8 {
+  value.round().clamp(0.0, 255.0) as u8
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::path::PathBuf;
+  use image::image::{GenericImageView, GrayImage, Luma};
+
+  #[test]
+  fn orders_corners_clockwise_from_the_top_left() {
+    let clicked = [(90.0, 80.0), (10.0, 5.0), (5.0, 70.0), (100.0, 10.0)];
+    assert_eq!(order_corners(clicked), [(10.0, 5.0), (100.0, 10.0), (90.0, 80.0), (5.0, 70.0)]);
+  }
+
+  #[test]
+  fn rectifies_a_quadrilateral() {
+    // a bright square, seen from an angle
+    let corners = [(10.0, 10.0), (50.0, 14.0), (46.0, 50.0), (14.0, 42.0)];
+    let quad = Homography::from_points(&[
+      ((0.0, 0.0), corners[0]), ((1.0, 0.0), corners[1]), ((1.0, 1.0), corners[2]), ((0.0, 1.0), corners[3])
+    ]).unwrap().inverse().unwrap();
+    let buffer = GrayImage::from_fn(60, 60, |x, y| {
+      let (u, v) = quad.apply((x as f64 + 0.5, y as f64 + 0.5));
+      Luma([if u > 0.0 && u < 1.0 && v > 0.0 && v < 1.0 { 220 } else { 20 }])
+    });
+    let image = Image::new(&PathBuf::from("nobody cares"), DynamicImage::ImageLuma8(buffer));
+
+    let (width, height) = rectified_size(&corners);
+    let warped = image.warp_perspective(&corners, width, height, Interpolation::Bilinear).unwrap();
+
+    assert_eq!(warped.dimensions(), (40, 36));
+    // away from the edges, everything lies inside the square
+    for (x, y, pixel) in warped.pixels() {
+      if x > 2 && y > 2 && x < width - 3 && y < height - 3 {
+        assert!(pixel[0] > 200, "({}, {}) is {}", x, y, pixel[0]);
+      }
+    }
+  }
+
+  #[test]
+  fn refuses_corners_on_a_line() {
+    let image = Image::new(&PathBuf::from("nobody cares"), DynamicImage::new_rgb8(10, 10));
+    let corners = [(1.0, 1.0), (5.0, 5.0), (9.0, 9.0), (1.0, 9.0)];
+    assert!(image.warp_perspective(&corners, 8, 8, Interpolation::Nearest).is_err());
+  }
+
+  #[test]
+  fn interpolations_agree_on_pixel_centers() {
+    let image = GrayImage::from_fn(4, 4, |x, y| Luma([(x * 40 + y * 10) as u8]));
+    let image = DynamicImage::ImageLuma8(image).to_rgb();
+
+    for &interpolation in [Interpolation::Nearest, Interpolation::Bilinear, Interpolation::Bicubic].iter() {
+      let color = sample(&image, 2.0, 1.0, interpolation);
+      assert!((color[0] - 90.0).abs() < 1e-9);
+    }
+    assert!((sample(&image, 1.5, 1.0, Interpolation::Bilinear)[0] - 70.0).abs() < 1e-9);
+  }
+}
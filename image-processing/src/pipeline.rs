@@ -0,0 +1,620 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use failure::{err_msg, format_err, Error};
+use serde_json;
+
+use image::{HistogramReference, Image};
+use image::image::{DynamicImage, GenericImageView, ImageError};
+use selection::{Mask, Selection};
+use fft::FrequencyFilter;
+use perspective::Interpolation;
+use operations::{apply_at_8_bits, registry, Arguments, Value};
+use tiles::{apply_tiled, TiledImage};
+use depth::{is_high_depth, AnyImage};
+use animation::{apply_to_frames, is_animated};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "operation", rename_all = "snake_case")]
+pub enum Operation {
+  FilterFrequencies(FrequencyFilter),
+  MatchHistogram { reference: PathBuf },
+  // corners from the top left one, clockwise
+  WarpPerspective { corners: [(f64, f64); 4], width: u32, height: u32, interpolation: Interpolation },
+  // blends in the selected part of another image of the same size, all of it
+  // without a selection
+  BlendImage { source: PathBuf, levels: u32 },
+  // any operation of the registry, built in or from a plugin
+  Registered { name: String, #[serde(default)] arguments: Arguments }
+}
+
+impl Operation {
+  // an operation of the registry, with the given arguments and the defaults
+  pub fn registered(name: &str, arguments: &[(&str, Value)]) -> Self {
+    Operation::Registered {
+      name: name.to_string(),
+      arguments: arguments.iter().map(|&(name, ref value)| (name.to_string(), value.clone())).collect()
+    }
+  }
+
+  pub fn apply(&self, image: &Image) -> Result<DynamicImage, Error> {
+    Ok(match *self {
+      Operation::FilterFrequencies(ref filter) => image.filter_frequencies(filter),
+      Operation::MatchHistogram { ref reference } => image.match_histogram_to(&*histogram_reference(reference)?, None),
+      Operation::WarpPerspective { ref corners, width, height, interpolation } =>
+        image.warp_perspective(corners, width, height, interpolation)?,
+      Operation::BlendImage { .. } => {
+        let (width, height) = image.as_dynamic_image().dimensions();
+        self.apply_within(image, &Mask::full(width, height))?
+      },
+      Operation::Registered { ref name, ref arguments } => match registry().read() {
+        Ok(registry) => registry.apply(name, image, arguments, None)?,
+        Err(error) => return Err(format_err!("{}", error))
+      }
+    })
+  }
+
+  // pixels outside of the mask are left untouched, except by the operations
+  // changing the image's geometry, which ignore the mask
+  pub fn apply_within(&self, image: &Image, mask: &Mask) -> Result<DynamicImage, Error> {
+    match *self {
+      Operation::WarpPerspective { .. } => self.apply(image),
+      Operation::MatchHistogram { ref reference } => Ok(image.match_histogram_to(&*histogram_reference(reference)?, Some(mask))),
+      Operation::BlendImage { ref source, levels } => match Image::open(source) {
+        Ok(source) => image.blend_multiband(&source, mask, levels as usize),
+        Err(error) => Err(format_err!("{}: {}", source.display(), error))
+      },
+      Operation::Registered { ref name, ref arguments } => match registry().read() {
+        Ok(registry) => registry.apply(name, image, arguments, Some(mask)),
+        Err(error) => Err(format_err!("{}", error))
+      },
+      _ => Ok(image.blend_within(&self.apply(image)?, mask))
+    }
+  }
+
+  // How far around each pixel the operation looks, for running it a tile at a
+  // time. None when it needs the whole image.
+  pub fn halo(&self) -> Result<Option<u32>, Error> {
+    Ok(match *self {
+      Operation::Registered { ref name, ref arguments } => match registry().read() {
+        Ok(registry) => registry.halo(name, arguments)?,
+        Err(error) => return Err(format_err!("{}", error))
+      },
+      _ => None
+    })
+  }
+
+  // On images of any depth, keeping their precision for the operations able
+  // to, the others running on the 8 bit copy. Levels are from 0 to 255
+  // whatever the depth.
+  pub fn apply_precise(&self, image: &AnyImage) -> Result<AnyImage, Error> {
+    match *self {
+      Operation::Registered { ref name, ref arguments } => match registry().read() {
+        Ok(registry) => registry.apply_precise(name, image, arguments),
+        Err(error) => Err(format_err!("{}", error))
+      },
+      _ => apply_at_8_bits(image, |image| self.apply(image))
+    }
+  }
+
+  // equalizing needs the histogram of the whole image first
+  pub fn is_equalization(&self) -> bool {
+    match *self {
+      Operation::Registered { ref name, .. } => name == "equalize_histogram",
+      _ => false
+    }
+  }
+}
+
+// a reference image's histograms, with when the file was modified
+type CachedReference = (Option<SystemTime>, Arc<HistogramReference>);
+
+lazy_static! {
+  static ref HISTOGRAM_REFERENCES: Mutex<HashMap<PathBuf, CachedReference>> = Mutex::new(HashMap::new());
+}
+
+// The histograms of a reference image, opened again only once the file changes,
+// so that replaying a recipe on a folder reads it once.
+fn histogram_reference(path: &PathBuf) -> Result<Arc<HistogramReference>, Error> {
+  let modified = fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+  let mut references = HISTOGRAM_REFERENCES.lock().map_err(|error| format_err!("{}", error))?;
+  if let Some(&(cached_modified, ref reference)) = references.get(path) {
+    if modified.is_some() && cached_modified == modified {
+      return Ok(reference.clone());
+    }
+  }
+
+  let reference = match Image::open(path) {
+    Ok(image) => Arc::new(image.histogram_reference()),
+    Err(error) => return Err(format_err!("{}: {}", path.display(), error))
+  };
+  references.insert(path.to_path_buf(), (modified, reference.clone()));
+  Ok(reference)
+}
+
+// The built in operations as recipes held them before they went through the
+// registry, still read from older recipes.
+#[derive(Deserialize)]
+#[serde(tag = "operation", rename_all = "snake_case")]
+enum LegacyOperation {
+  EqualizeHistogram,
+  Denoise { radius: u32 },
+  Threshold { level: u8 },
+  DetectEdges { level: u8 }
+}
+
+impl From<LegacyOperation> for Operation {
+  fn from(legacy: LegacyOperation) -> Self {
+    match legacy {
+      LegacyOperation::EqualizeHistogram => Operation::registered("equalize_histogram", &[]),
+      LegacyOperation::Denoise { radius } => Operation::registered("denoise", &[("radius", Value::Integer(radius as i64))]),
+      LegacyOperation::Threshold { level } => Operation::registered("threshold", &[("level", Value::Integer(level as i64))]),
+      LegacyOperation::DetectEdges { level } => Operation::registered("detect_edges", &[("level", Value::Integer(level as i64))])
+    }
+  }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RecordedOperation {
+  Current(Operation),
+  Legacy(LegacyOperation)
+}
+
+#[derive(Deserialize)]
+struct RecordedStep {
+  #[serde(flatten)]
+  operation: RecordedOperation,
+  #[serde(default)]
+  selection: Option<Selection>
+}
+
+impl From<RecordedStep> for Step {
+  fn from(recorded: RecordedStep) -> Self {
+    let operation = match recorded.operation {
+      RecordedOperation::Current(operation) => operation,
+      RecordedOperation::Legacy(legacy) => legacy.into()
+    };
+    Step { operation, selection: recorded.selection }
+  }
+}
+
+// An operation along with the region of interest it was restricted to, if any.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(from = "RecordedStep")]
+pub struct Step {
+  #[serde(flatten)]
+  pub operation: Operation,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub selection: Option<Selection>
+}
+
+impl Step {
+  pub fn apply(&self, image: &Image) -> Result<DynamicImage, Error> {
+    match self.selection {
+      Some(ref selection) => {
+        let (width, height) = image.as_dynamic_image().dimensions();
+        self.operation.apply_within(image, &selection.to_mask(width, height))
+      },
+      None => self.operation.apply(image)
+    }
+  }
+
+  // Applies the step to a copy of the image no larger than `proxy_size`: quick
+  // enough to follow the parameters as they change. The result stays at the
+  // copy's size, `proxy_scale` tells how much to enlarge it to show it in place
+  // of the image. Spatial parameters act more strongly on the smaller copy.
+  pub fn preview(&self, image: &Image, proxy_size: u32) -> Result<DynamicImage, Error> {
+    let dynamic_image = image.as_dynamic_image();
+    let (width, height) = dynamic_image.dimensions();
+    if width <= proxy_size && height <= proxy_size {
+      return self.apply(image);
+    }
+
+    let proxy = Image::new(&image.get_image_path(), dynamic_image.thumbnail(proxy_size, proxy_size));
+    let (proxy_width, proxy_height) = proxy.as_dynamic_image().dimensions();
+    let result = self.operation.apply(&proxy)?;
+
+    // operations changing the geometry are shown as a whole
+    match self.selection {
+      Some(ref selection) if result.dimensions() == (proxy_width, proxy_height) => {
+        let factor = proxy_width as f64 / width as f64;
+        Ok(proxy.blend_within(&result, &selection.scale(factor).to_mask(proxy_width, proxy_height)))
+      },
+      _ => Ok(result)
+    }
+  }
+}
+
+// how many times larger the image is than the copy previews are computed on
+pub fn proxy_scale(dimensions: (u32, u32), proxy_size: u32) -> f64 {
+  let largest = dimensions.0.max(dimensions.1);
+  if largest <= proxy_size || proxy_size == 0 {
+    1.0
+  } else {
+    largest as f64 / proxy_size as f64
+  }
+}
+
+// A recipe: an ordered list of operations that can be written to disk
+// and replayed on any other image.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Pipeline {
+  pub steps: Vec<Step>
+}
+
+impl Pipeline {
+  pub fn new() -> Self {
+    Self {
+      steps: Vec::new()
+    }
+  }
+
+  pub fn open(path: &PathBuf) -> Result<Self, Error> {
+    let file = File::open(path)?;
+    match serde_json::from_reader(file) {
+      Ok(pipeline) => Ok(pipeline),
+      Err(error) => Err(err_msg(error))
+    }
+  }
+
+  pub fn save(&self, path: &PathBuf) -> Result<PathBuf, Error> {
+    let file = File::create(path)?;
+    match serde_json::to_writer_pretty(file, self) {
+      Ok(_) => Ok(path.to_path_buf()),
+      Err(error) => Err(err_msg(error))
+    }
+  }
+
+  pub fn record(&mut self, operation: Operation, selection: Option<Selection>) {
+    self.steps.push(Step { operation, selection });
+  }
+
+  pub fn clear(&mut self) {
+    self.steps.clear();
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.steps.is_empty()
+  }
+
+  pub fn apply(&self, image: &Image) -> Result<Image, Error> {
+    // shares the pixels with `image` until the first step replaces them
+    let mut current = image.clone();
+    for step in &self.steps {
+      let result = step.apply(&current)?;
+      current.set_dynamic_image(result);
+    }
+    Ok(current)
+  }
+
+  // Replays the recipe on 16 bit and floating point images, going through 8
+  // bits only for the steps that can't keep the depth. Selections aren't
+  // supported.
+  pub fn apply_precise(&self, image: &AnyImage) -> Result<AnyImage, Error> {
+    let mut current = image.clone();
+    for step in &self.steps {
+      if step.selection.is_some() {
+        return Err(err_msg("steps restricted to a selection only work on 8 bit images"));
+      }
+      current = step.operation.apply_precise(&current)?;
+    }
+    Ok(current)
+  }
+
+  // Replays the recipe on every image in `input_dir`, writing the results under
+  // the same file names in `output_dir`. Files that aren't images are skipped,
+  // 16 bit and floating point images keep their depth, pages of TIFF files
+  // included, and large TIFF images are processed a tile at a time. An image
+  // that fails doesn't stop the others.
+  pub fn apply_to_dir(&self, input_dir: &PathBuf, output_dir: &PathBuf) -> Result<Batch, Error> {
+    fs::create_dir_all(output_dir)?;
+
+    let mut batch = Batch { saved: Vec::new(), failed: Vec::new() };
+    for entry in fs::read_dir(input_dir)? {
+      let input_path = entry?.path();
+      if !input_path.is_file() {
+        continue;
+      }
+
+      let output_path = match input_path.file_name() {
+        Some(file_name) => output_dir.join(file_name),
+        None => continue
+      };
+      match self.apply_to_file(&input_path, &output_path) {
+        Ok(Some(saved)) => batch.saved.push(saved),
+        Ok(None) => (),
+        Err(error) => batch.failed.push((input_path, error))
+      }
+    }
+    batch.saved.sort();
+    batch.failed.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(batch)
+  }
+
+  // None when the file isn't an image
+  fn apply_to_file(&self, input_path: &PathBuf, output_path: &PathBuf) -> Result<Option<PathBuf>, Error> {
+    if is_animated(input_path) {
+      return Ok(Some(apply_to_frames(self, input_path, output_path, "all")?));
+    }
+    if is_high_depth(input_path) {
+      return Ok(Some(self.apply_precise(&AnyImage::open(input_path)?)?.save(output_path)?));
+    }
+    if TiledImage::should_tile(input_path) {
+      return Ok(Some(apply_tiled(self, input_path, output_path)?));
+    }
+
+    let image = match Image::open(input_path) {
+      Ok(image) => image,
+      Err(ImageError::UnsupportedError(_)) => return Ok(None),
+      Err(error) => return Err(format_err!("{}", error))
+    };
+    Ok(Some(self.apply(&image)?.save_image(Some(output_path))?))
+  }
+}
+
+// What replaying a recipe on a folder came to: the images saved, and those that
+// failed along with why.
+#[derive(Debug)]
+pub struct Batch {
+  pub saved: Vec<PathBuf>,
+  pub failed: Vec<(PathBuf, Error)>
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use image::image::{GenericImage, Pixel};
+  use fft::{FilterBand, FilterShape};
+
+  fn gradient_image() -> Image {
+    let path = PathBuf::from("nobody cares");
+    let mut dynamic_image = DynamicImage::new_rgb8(1,3);
+    for i in 0..3 {
+      let val = (i * 100) as u8;
+      dynamic_image.put_pixel(0, i, Pixel::from_channels(val,val,val,255));
+    }
+    Image::new(&path, dynamic_image)
+  }
+
+  fn threshold(level: i64) -> Operation {
+    Operation::registered("threshold", &[("level", Value::Integer(level))])
+  }
+
+  fn equalize_histogram() -> Operation {
+    Operation::registered("equalize_histogram", &[])
+  }
+
+  #[test]
+  fn serializes_operations_with_their_parameters() {
+    let mut pipeline = Pipeline::new();
+    pipeline.record(equalize_histogram(), None);
+    pipeline.record(threshold(128), Some(Selection::rectangle((0.0, 0.0), (1.0, 2.0))));
+
+    let json = serde_json::to_string(&pipeline).unwrap();
+    assert_eq!(json, concat!(r#"{"steps":[{"operation":"registered","name":"equalize_histogram","arguments":{}},"#,
+                             r#"{"operation":"registered","name":"threshold","arguments":{"level":128},"#,
+                             r#""selection":{"shape":"rectangle","x":0.0,"y":0.0,"width":1.0,"height":2.0}}]}"#));
+    assert_eq!(serde_json::from_str::<Pipeline>(&json).unwrap(), pipeline);
+  }
+
+  #[test]
+  fn reads_the_built_in_operations_of_older_recipes() {
+    let json = concat!(r#"{"steps":[{"operation":"equalize_histogram"},"#,
+                       r#"{"operation":"threshold","level":128,"#,
+                       r#""selection":{"shape":"rectangle","x":0.0,"y":0.0,"width":1.0,"height":2.0}},"#,
+                       r#"{"operation":"denoise","radius":2},{"operation":"detect_edges","level":64}]}"#);
+
+    let mut pipeline = Pipeline::new();
+    pipeline.record(equalize_histogram(), None);
+    pipeline.record(threshold(128), Some(Selection::rectangle((0.0, 0.0), (1.0, 2.0))));
+    pipeline.record(Operation::registered("denoise", &[("radius", Value::Integer(2))]), None);
+    pipeline.record(Operation::registered("detect_edges", &[("level", Value::Integer(64))]), None);
+    assert_eq!(serde_json::from_str::<Pipeline>(json).unwrap(), pipeline);
+  }
+
+  #[test]
+  fn serializes_frequency_filters() {
+    let mut pipeline = Pipeline::new();
+    pipeline.record(Operation::FilterFrequencies(FrequencyFilter {
+      band: FilterBand::LowPass { cutoff: 0.25 },
+      shape: FilterShape::Butterworth { order: 2 }
+    }), None);
+
+    let json = serde_json::to_string(&pipeline).unwrap();
+    assert_eq!(json, concat!(r#"{"steps":[{"operation":"filter_frequencies","#,
+                             r#""band":{"type":"low_pass","cutoff":0.25},"#,
+                             r#""shape":{"type":"butterworth","order":2}}]}"#));
+    assert_eq!(serde_json::from_str::<Pipeline>(&json).unwrap(), pipeline);
+  }
+
+  #[test]
+  fn applies_the_operations_in_order() {
+    let image = gradient_image();
+
+    let mut pipeline = Pipeline::new();
+    pipeline.record(equalize_histogram(), None);
+    pipeline.record(threshold(100), None);
+
+    let expected = image.equalize_histogram();
+    let expected = Image::new(&image.get_image_path(), expected).threshold(100);
+    let result = pipeline.apply(&image).unwrap().into_dynamic_image();
+
+    for r in 0..3 {
+      assert_eq!(result.get_pixel(0, r), expected.get_pixel(0, r));
+    }
+  }
+
+  #[test]
+  fn restricts_steps_to_their_selection() {
+    let image = gradient_image();
+
+    let mut pipeline = Pipeline::new();
+    pipeline.record(threshold(50), Some(Selection::rectangle((0.0, 1.0), (1.0, 3.0))));
+    let result = pipeline.apply(&image).unwrap().into_dynamic_image();
+
+    assert_eq!(result.get_pixel(0, 0)[0], 0);
+    assert_eq!(result.get_pixel(0, 1)[0], 255);
+    assert_eq!(result.get_pixel(0, 2)[0], 255);
+
+    let mut pipeline = Pipeline::new();
+    pipeline.record(threshold(50), Some(Selection::rectangle((0.0, 0.0), (1.0, 1.0))));
+    let result = pipeline.apply(&image).unwrap().into_dynamic_image();
+
+    assert_eq!(result.get_pixel(0, 1)[0], 100);
+    assert_eq!(result.get_pixel(0, 2)[0], 200);
+  }
+
+  #[test]
+  fn fails_when_the_histogram_reference_is_missing() {
+    let mut pipeline = Pipeline::new();
+    pipeline.record(Operation::MatchHistogram { reference: PathBuf::from("missing.png") }, None);
+
+    assert!(pipeline.apply(&gradient_image()).is_err());
+  }
+
+  #[test]
+  fn carries_on_past_images_that_fail() {
+    let dir = ::std::env::temp_dir().join(format!("image-processing-{}-batch", ::std::process::id()));
+    let (input_dir, output_dir) = (dir.join("input"), dir.join("output"));
+    fs::create_dir_all(&input_dir).unwrap();
+    gradient_image().save_image(Some(&input_dir.join("a.png"))).unwrap();
+    fs::write(input_dir.join("b.png"), b"not a png").unwrap();
+    gradient_image().save_image(Some(&input_dir.join("c.png"))).unwrap();
+
+    let mut pipeline = Pipeline::new();
+    pipeline.record(equalize_histogram(), None);
+    let batch = pipeline.apply_to_dir(&input_dir, &output_dir).unwrap();
+
+    assert_eq!(batch.saved, vec![output_dir.join("a.png"), output_dir.join("c.png")]);
+    assert_eq!(batch.failed.len(), 1);
+    assert_eq!(batch.failed[0].0, input_dir.join("b.png"));
+    fs::remove_dir_all(dir).unwrap();
+  }
+
+  #[test]
+  fn an_empty_pipeline_leaves_the_image_untouched() {
+    let image = gradient_image();
+    let result = Pipeline::new().apply(&image).unwrap().into_dynamic_image();
+
+    assert_eq!(result.raw_pixels(), image.as_dynamic_image().raw_pixels());
+  }
+
+  #[test]
+  fn warping_changes_the_size_whatever_the_selection() {
+    let operation = Operation::WarpPerspective {
+      corners: [(0.0, 0.0), (1.0, 0.0), (1.0, 3.0), (0.0, 3.0)],
+      width: 2,
+      height: 6,
+      interpolation: Interpolation::Nearest
+    };
+    let step = Step { operation, selection: Some(Selection::rectangle((0.0, 0.0), (1.0, 1.0))) };
+    let warped = step.apply(&gradient_image()).unwrap();
+
+    assert_eq!(warped.dimensions(), (2, 6));
+    assert_eq!(warped.get_pixel(1, 5)[0], 200);
+  }
+
+  #[test]
+  fn blends_the_whole_image_in_without_a_selection() {
+    let source = ::std::env::temp_dir().join(format!("image-processing-{}-blend-source.png", ::std::process::id()));
+    let mut other = gradient_image().into_dynamic_image();
+    other.put_pixel(0, 1, Pixel::from_channels(30, 30, 30, 255));
+    Image::new(&source, other).save_image(None).unwrap();
+
+    let mut pipeline = Pipeline::new();
+    pipeline.record(Operation::BlendImage { source: source.clone(), levels: 2 }, None);
+    let result = pipeline.apply(&gradient_image()).unwrap().into_dynamic_image();
+    assert_eq!(result.get_pixel(0, 1)[0], 30);
+    fs::remove_file(&source).unwrap();
+
+    let mut pipeline = Pipeline::new();
+    pipeline.record(Operation::BlendImage { source: PathBuf::from("missing.png"), levels: 4 }, None);
+    assert!(pipeline.apply(&gradient_image()).is_err());
+  }
+
+  #[test]
+  fn replays_registered_operations() {
+    let mut arguments = Arguments::new();
+    arguments.insert("level".to_string(), ::operations::Value::Integer(150));
+    let mut pipeline = Pipeline::new();
+    pipeline.record(Operation::Registered { name: "threshold".to_string(), arguments }, None);
+
+    let json = serde_json::to_string(&pipeline).unwrap();
+    assert_eq!(json, r#"{"steps":[{"operation":"registered","name":"threshold","arguments":{"level":150}}]}"#);
+    let pipeline = serde_json::from_str::<Pipeline>(&json).unwrap();
+
+    let result = pipeline.apply(&gradient_image()).unwrap().into_dynamic_image();
+    assert_eq!(result.get_pixel(0, 1)[0], 0);
+    assert_eq!(result.get_pixel(0, 2)[0], 255);
+  }
+
+  #[test]
+  fn previews_stay_at_the_proxy_size_and_keep_the_selection() {
+    let buffer = ::image::image::GrayImage::from_fn(40, 20, |x, _| ::image::image::Luma([if x < 20 { 50 } else { 200 }]));
+    let image = Image::new(&PathBuf::from("nobody cares"), DynamicImage::ImageLuma8(buffer));
+    let step = Step {
+      operation: threshold(128),
+      selection: Some(Selection::rectangle((0.0, 0.0), (30.0, 20.0)))
+    };
+
+    let preview = step.preview(&image, 10).unwrap();
+    assert_eq!(preview.dimensions(), (10, 5));
+    assert_eq!(proxy_scale((40, 20), 10), 4.0);
+    assert_eq!(preview.get_pixel(1, 2)[0], 0);
+    assert_eq!(preview.get_pixel(6, 2)[0], 255);
+    // outside of the selection, the image is left as it is
+    assert_eq!(preview.get_pixel(9, 2)[0], 200);
+  }
+
+  #[test]
+  fn replays_recipes_at_full_depth() {
+    // 32800 and 32950 both come to 128 at 8 bits
+    let samples = vec![1000, 32800, 32950, 65000];
+    let image = AnyImage::U16(::depth::TypedImage::new(2, 2, 1, samples).unwrap());
+
+    let mut arguments = Arguments::new();
+    arguments.insert("gamma".to_string(), ::operations::Value::Number(1.0));
+    let mut pipeline = Pipeline::new();
+    pipeline.record(Operation::Registered { name: "gamma".to_string(), arguments }, None);
+    pipeline.record(threshold(128), None);
+
+    match pipeline.apply_precise(&image).unwrap() {
+      AnyImage::U16(result) => assert_eq!(result.samples, vec![0, 0, 65535, 65535]),
+      other => panic!("the depth changed to {:?}", other)
+    }
+
+    pipeline.record(equalize_histogram(), Some(Selection::rectangle((0.0, 0.0), (1.0, 1.0))));
+    assert!(pipeline.apply_precise(&image).is_err());
+  }
+
+  #[test]
+  fn replays_mixed_recipes_at_full_depth_then_at_8_bits() {
+    let samples = vec![1000, 32800, 32950, 65000];
+    let image = AnyImage::U16(::depth::TypedImage::new(2, 2, 1, samples).unwrap());
+
+    let mut pipeline = Pipeline::new();
+    pipeline.record(threshold(128), None);
+    pipeline.record(Operation::registered("flip", &[("direction", Value::Text("vertical".to_string()))]), None);
+    match pipeline.apply_precise(&image).unwrap() {
+      AnyImage::U16(result) => assert_eq!(result.samples, vec![65535, 65535, 0, 0]),
+      other => panic!("the depth changed to {:?}", other)
+    }
+
+    pipeline.record(Operation::registered("detect_edges", &[("level", Value::Integer(64))]), None);
+    match pipeline.apply_precise(&image).unwrap() {
+      AnyImage::U16(result) => assert_eq!((result.channels, result.samples[0]), (3, 65535)),
+      other => panic!("the depth changed to {:?}", other)
+    }
+
+    // filtering frequencies has no precise version, and goes on at 8 bits
+    let filter = FrequencyFilter { band: FilterBand::LowPass { cutoff: 1000.0 }, shape: FilterShape::Ideal };
+    pipeline.record(Operation::FilterFrequencies(filter), None);
+    match pipeline.apply_precise(&image).unwrap() {
+      AnyImage::U8(result) => assert_eq!((result.width, result.height), (2, 2)),
+      other => panic!("{:?} should have come to 8 bits", other)
+    }
+  }
+}
@@ -0,0 +1,430 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use failure::{format_err, Error};
+
+// NetPBM bitmaps, graymaps and pixmaps, P1 to P6, read and written here since
+// the image crate rejects the plain ones with comments that older tools write.
+// The samples are kept as stored, from 0 to the maxval, where 1 is black in a
+// bitmap.
+
+// enough for the header of any file that isn't mostly comments
+const HEADER_LIMIT: u64 = 1 << 16;
+// the plain format keeps its lines shorter than this
+const LINE_LENGTH: usize = 70;
+// more samples than this are taken for a broken header
+const MAX_SAMPLES: u64 = 1 << 32;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+  Bitmap,
+  Graymap,
+  Pixmap
+}
+
+impl Kind {
+  pub fn channels(self) -> usize {
+    if self == Kind::Pixmap { 3 } else { 1 }
+  }
+}
+
+// plain files are ASCII, raw ones binary
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+  Plain,
+  Raw
+}
+
+impl Encoding {
+  pub fn new(name: &str) -> Result<Self, Error> {
+    match name {
+      "plain" | "ascii" => Ok(Encoding::Plain),
+      "raw" | "binary" => Ok(Encoding::Raw),
+      _ => Err(format_err!("unknown NetPBM encoding {}, expected plain or raw", name))
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Header {
+  pub kind: Kind,
+  pub encoding: Encoding,
+  pub width: u32,
+  pub height: u32,
+  // 1 for bitmaps
+  pub maxval: u16
+}
+
+impl Header {
+  fn magic(&self) -> &'static str {
+    match (self.kind, self.encoding) {
+      (Kind::Bitmap, Encoding::Plain) => "P1",
+      (Kind::Graymap, Encoding::Plain) => "P2",
+      (Kind::Pixmap, Encoding::Plain) => "P3",
+      (Kind::Bitmap, Encoding::Raw) => "P4",
+      (Kind::Graymap, Encoding::Raw) => "P5",
+      (Kind::Pixmap, Encoding::Raw) => "P6"
+    }
+  }
+
+  fn samples(&self) -> usize {
+    self.width as usize * self.height as usize * self.kind.channels()
+  }
+}
+
+fn extension_of(path: &Path) -> String {
+  path.extension().and_then(|extension| extension.to_str()).unwrap_or("").to_lowercase()
+}
+
+pub fn is_netpbm(path: &Path) -> bool {
+  matches!(extension_of(path).as_str(), "pbm" | "pgm" | "ppm" | "pnm")
+}
+
+// what to write an image of `channels` as: .pnm takes whichever fits
+pub fn kind_for(path: &Path, channels: usize) -> Kind {
+  match extension_of(path).as_str() {
+    "pbm" => Kind::Bitmap,
+    "pgm" => Kind::Graymap,
+    "ppm" => Kind::Pixmap,
+    _ => if channels <= 2 { Kind::Graymap } else { Kind::Pixmap }
+  }
+}
+
+fn describe(byte: Option<u8>) -> String {
+  match byte {
+    Some(byte) if byte.is_ascii_graphic() => format!("'{}'", byte as char),
+    Some(byte) => format!("byte {:#04x}", byte),
+    None => "the end of the file".to_string()
+  }
+}
+
+// Walks through the file keeping count of the lines, for the errors.
+struct Parser<'a> {
+  bytes: &'a [u8],
+  position: usize,
+  line: usize
+}
+
+impl<'a> Parser<'a> {
+  fn new(bytes: &'a [u8]) -> Self {
+    Self { bytes, position: 0, line: 1 }
+  }
+
+  fn error(&self, message: String) -> Error {
+    format_err!("line {}: {}", self.line, message)
+  }
+
+  fn peek(&self) -> Option<u8> {
+    self.bytes.get(self.position).cloned()
+  }
+
+  fn advance(&mut self) {
+    if self.peek() == Some(b'\n') {
+      self.line += 1;
+    }
+    self.position += 1;
+  }
+
+  // comments run from # to the end of the line
+  fn skip_comment(&mut self) {
+    if self.peek() == Some(b'#') {
+      while self.peek().is_some_and(|byte| byte != b'\n' && byte != b'\r') {
+        self.advance();
+      }
+    }
+  }
+
+  fn skip_space(&mut self) {
+    while let Some(byte) = self.peek() {
+      if byte == b'#' {
+        self.skip_comment();
+      }
+      else if byte.is_ascii_whitespace() {
+        self.advance();
+      }
+      else {
+        break;
+      }
+    }
+  }
+
+  fn magic(&mut self) -> Result<(Kind, Encoding), Error> {
+    if self.peek() != Some(b'P') {
+      return Err(self.error(format!("expected a NetPBM magic number like P6 but found {}", describe(self.peek()))));
+    }
+    self.advance();
+    let magic = match self.peek() {
+      Some(b'1') => (Kind::Bitmap, Encoding::Plain),
+      Some(b'2') => (Kind::Graymap, Encoding::Plain),
+      Some(b'3') => (Kind::Pixmap, Encoding::Plain),
+      Some(b'4') => (Kind::Bitmap, Encoding::Raw),
+      Some(b'5') => (Kind::Graymap, Encoding::Raw),
+      Some(b'6') => (Kind::Pixmap, Encoding::Raw),
+      byte => return Err(self.error(format!("expected a magic number from P1 to P6 but found P followed by {}", describe(byte))))
+    };
+    self.advance();
+    Ok(magic)
+  }
+
+  // a decimal number, which has to be followed by whitespace or a comment
+  fn number(&mut self, what: &str) -> Result<u64, Error> {
+    self.skip_space();
+    if !self.peek().is_some_and(|byte| byte.is_ascii_digit()) {
+      return Err(self.error(format!("expected the {} but found {}", what, describe(self.peek()))));
+    }
+    let mut number = 0u64;
+    while let Some(byte) = self.peek().filter(|byte| byte.is_ascii_digit()) {
+      number = number * 10 + (byte - b'0') as u64;
+      if number > u32::MAX as u64 {
+        return Err(self.error(format!("the {} is too large", what)));
+      }
+      self.advance();
+    }
+    match self.peek() {
+      Some(byte) if !byte.is_ascii_whitespace() && byte != b'#' =>
+        Err(self.error(format!("expected whitespace after the {} but found {}", what, describe(Some(byte))))),
+      _ => Ok(number)
+    }
+  }
+
+  // in a plain bitmap the 0s and 1s don't have to be apart
+  fn bit(&mut self) -> Result<u64, Error> {
+    self.skip_space();
+    let bit = match self.peek() {
+      Some(b'0') => 0,
+      Some(b'1') => 1,
+      byte => return Err(self.error(format!("expected a 0 or 1 but found {}", describe(byte))))
+    };
+    self.advance();
+    Ok(bit)
+  }
+
+  fn header(&mut self) -> Result<Header, Error> {
+    let (kind, encoding) = self.magic()?;
+    let width = self.number("width")?;
+    let height = self.number("height")?;
+    if width == 0 || height == 0 {
+      return Err(self.error(format!("an image can't be {}x{}", width, height)));
+    }
+    let samples = width.checked_mul(height).and_then(|pixels| pixels.checked_mul(kind.channels() as u64));
+    if samples.is_none_or(|samples| samples > MAX_SAMPLES) {
+      return Err(self.error(format!("a {}x{} image is too large", width, height)));
+    }
+    let maxval = match kind {
+      Kind::Bitmap => 1,
+      _ => match self.number("maxval")? {
+        maxval @ 1..=65535 => maxval as u16,
+        maxval => return Err(self.error(format!("the maxval has to be from 1 to 65535, not {}", maxval)))
+      }
+    };
+
+    // the pixels of a raw file start after a single whitespace character, which
+    // may end a comment
+    if encoding == Encoding::Raw {
+      self.skip_comment();
+      match self.peek() {
+        Some(byte) if byte.is_ascii_whitespace() => self.advance(),
+        byte => return Err(self.error(format!("expected whitespace before the pixels but found {}", describe(byte))))
+      }
+    }
+    Ok(Header { kind, encoding, width: width as u32, height: height as u32, maxval })
+  }
+
+  fn plain_samples(&mut self, header: &Header) -> Result<Vec<u16>, Error> {
+    // every sample takes at least a byte, so a short file can't claim more
+    let mut samples = Vec::with_capacity(header.samples().min(self.bytes.len() - self.position));
+    while samples.len() < header.samples() {
+      let sample = match header.kind {
+        Kind::Bitmap => self.bit(),
+        _ => {
+          self.skip_space();
+          if self.peek().is_none() {
+            return Err(self.error(format!("the pixels end after {} of {} samples", samples.len(), header.samples())));
+          }
+          self.number("sample")
+        }
+      }?;
+      if sample > header.maxval as u64 {
+        return Err(self.error(format!("sample {} is above the maxval of {}", sample, header.maxval)));
+      }
+      samples.push(sample as u16);
+    }
+    Ok(samples)
+  }
+
+  // big endian when they take two bytes, and bitmap rows padded to whole bytes
+  fn raw_samples(&mut self, header: &Header) -> Result<Vec<u16>, Error> {
+    let (width, height) = (header.width as usize, header.height as usize);
+    let length = match (header.kind, header.maxval) {
+      (Kind::Bitmap, _) => width.div_ceil(8) * height,
+      (_, 0..=255) => header.samples(),
+      _ => header.samples() * 2
+    };
+    let start = self.position;
+    if self.bytes.len() - start < length {
+      return Err(self.error(format!("the pixels end after {} of {} bytes", self.bytes.len() - start, length)));
+    }
+    let bytes = &self.bytes[start..start + length];
+
+    let samples: Vec<u16> = match (header.kind, header.maxval) {
+      (Kind::Bitmap, _) => bytes.chunks(width.div_ceil(8))
+        .flat_map(|row| (0..width).map(move |x| ((row[x / 8] >> (7 - x % 8)) & 1) as u16))
+        .collect(),
+      (_, 0..=255) => bytes.iter().map(|&byte| byte as u16).collect(),
+      _ => bytes.chunks(2).map(|pair| (pair[0] as u16) << 8 | pair[1] as u16).collect()
+    };
+    if let Some((index, sample)) = samples.iter().enumerate().find(|&(_, &sample)| sample > header.maxval) {
+      let offset = start + index * if header.maxval > 255 { 2 } else { 1 };
+      return Err(format_err!("byte {}: sample {} is above the maxval of {}", offset, sample, header.maxval));
+    }
+    self.position += length;
+    Ok(samples)
+  }
+
+  // Only whitespace and comments may follow the pixels. Lines don't count in
+  // raw pixels, so what follows them is placed by its offset instead.
+  fn end(&mut self, encoding: Encoding) -> Result<(), Error> {
+    self.skip_space();
+    let message = match self.peek() {
+      None => return Ok(()),
+      byte => format!("expected the end of the file after the pixels but found {}", describe(byte))
+    };
+    match encoding {
+      Encoding::Plain => Err(self.error(message)),
+      Encoding::Raw => Err(format_err!("byte {}: {}", self.position, message))
+    }
+  }
+}
+
+pub fn decode(bytes: &[u8]) -> Result<(Header, Vec<u16>), Error> {
+  let mut parser = Parser::new(bytes);
+  let header = parser.header()?;
+  let samples = match header.encoding {
+    Encoding::Plain => parser.plain_samples(&header),
+    Encoding::Raw => parser.raw_samples(&header)
+  }?;
+  parser.end(header.encoding)?;
+  Ok((header, samples))
+}
+
+pub fn read_header(path: &PathBuf) -> Result<Header, Error> {
+  let mut bytes = Vec::new();
+  File::open(path)?.take(HEADER_LIMIT).read_to_end(&mut bytes)?;
+  Parser::new(&bytes).header()
+}
+
+pub fn read(path: &PathBuf) -> Result<(Header, Vec<u16>), Error> {
+  let mut bytes = Vec::new();
+  File::open(path)?.read_to_end(&mut bytes)?;
+  decode(&bytes).map_err(|error| format_err!("{}: {}", path.display(), error))
+}
+
+pub fn encode(header: &Header, samples: &[u16]) -> Result<Vec<u8>, Error> {
+  if header.width == 0 || header.height == 0 || samples.len() != header.samples() {
+    return Err(format_err!("{} samples don't make a {}x{} {}", samples.len(), header.width, header.height, header.magic()));
+  }
+  let maxval = if header.kind == Kind::Bitmap { 1 } else { header.maxval };
+  if maxval == 0 {
+    return Err(format_err!("the maxval has to be from 1 to 65535"));
+  }
+  if let Some(sample) = samples.iter().find(|&&sample| sample > maxval) {
+    return Err(format_err!("sample {} is above the maxval of {}", sample, maxval));
+  }
+
+  let mut bytes = format!("{}\n{} {}\n", header.magic(), header.width, header.height).into_bytes();
+  if header.kind != Kind::Bitmap {
+    bytes.extend(format!("{}\n", maxval).bytes());
+  }
+
+  match header.encoding {
+    Encoding::Plain => {
+      let mut line = String::new();
+      for sample in samples.iter() {
+        let text = sample.to_string();
+        if !line.is_empty() && line.len() + 1 + text.len() > LINE_LENGTH {
+          bytes.extend(line.bytes());
+          bytes.push(b'\n');
+          line.clear();
+        }
+        if !line.is_empty() {
+          line.push(' ');
+        }
+        line.push_str(&text);
+      }
+      bytes.extend(line.bytes());
+      bytes.push(b'\n');
+    },
+    Encoding::Raw => match header.kind {
+      Kind::Bitmap => for row in samples.chunks(header.width as usize) {
+        for bits in row.chunks(8) {
+          bytes.push(bits.iter().enumerate().fold(0, |byte, (i, &bit)| byte | (bit as u8) << (7 - i)));
+        }
+      },
+      _ if maxval <= 255 => bytes.extend(samples.iter().map(|&sample| sample as u8)),
+      _ => for sample in samples.iter() {
+        bytes.push((sample >> 8) as u8);
+        bytes.push(*sample as u8);
+      }
+    }
+  }
+  Ok(bytes)
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn header(kind: Kind, encoding: Encoding, maxval: u16) -> Header {
+    Header { kind, encoding, width: 3, height: 2, maxval }
+  }
+
+  #[test]
+  fn reads_plain_files_with_comments() {
+    let text = b"P2\n# written by an old tool\n3 2 # the size\n# the maxval\n1000\n0 1 2\n# halfway\n500 999 1000\n";
+    let (read, samples) = decode(text).unwrap();
+    assert_eq!(read, header(Kind::Graymap, Encoding::Plain, 1000));
+    assert_eq!(samples, vec![0, 1, 2, 500, 999, 1000]);
+
+    // the bits of a plain bitmap may run together
+    let (_, bits) = decode(b"P1 3 2\n101\n0 1 0").unwrap();
+    assert_eq!(bits, vec![1, 0, 1, 0, 1, 0]);
+
+    let (_, raw) = decode(b"P5 2 1 255# a comment before the pixels\n\x07\x20").unwrap();
+    assert_eq!(raw, vec![7, 32]);
+  }
+
+  #[test]
+  fn writes_and_reads_every_kind_and_encoding() {
+    let cases = vec![
+      (Kind::Bitmap, 1, vec![1, 0, 1, 0, 0, 1]),
+      (Kind::Graymap, 255, vec![0, 17, 255, 128, 3, 9]),
+      (Kind::Graymap, 65535, vec![0, 300, 65535, 256, 1, 40000]),
+      (Kind::Pixmap, 7, (0..18).map(|i| i % 8).collect()),
+      (Kind::Pixmap, 4095, (0..18).map(|i| i * 200).collect())
+    ];
+    for (kind, maxval, samples) in cases {
+      for &encoding in [Encoding::Plain, Encoding::Raw].iter() {
+        let written = header(kind, encoding, maxval);
+        let bytes = encode(&written, &samples).unwrap();
+        assert_eq!(decode(&bytes).unwrap(), (written, samples.clone()), "{}", written.magic());
+      }
+    }
+  }
+
+  #[test]
+  fn reports_where_a_file_is_broken() {
+    let error = |bytes: &[u8]| decode(bytes).unwrap_err().to_string();
+    assert_eq!(error(b"P7\n1 1\n255\n"), "line 1: expected a magic number from P1 to P6 but found P followed by '7'");
+    assert_eq!(error(b"P2\n# size\n3 x\n"), "line 3: expected the height but found 'x'");
+    assert_eq!(error(b"P2\n3 2\n70000\n"), "line 3: the maxval has to be from 1 to 65535, not 70000");
+    assert_eq!(error(b"P2\n3 2\n9\n1 2 3\n4 10 5\n"), "line 5: sample 10 is above the maxval of 9");
+    assert_eq!(error(b"P3\n1 1\n9\n1 2\n"), "line 5: the pixels end after 2 of 3 samples");
+    assert_eq!(error(b"P5\n2 2\n255\n\x01\x02\x03"), "line 4: the pixels end after 3 of 4 bytes");
+    assert_eq!(error(b"P5\n1 1\n255\n\x01junk"), "byte 12: expected the end of the file after the pixels but found 'j'");
+    assert_eq!(error(b"P2\n1 1\n9\n1\n2\n"), "line 5: expected the end of the file after the pixels but found '2'");
+    assert_eq!(error(b"P5\n1 1\n200\n\xff"), "byte 11: sample 255 is above the maxval of 200");
+    assert_eq!(error(b"P3\n4294967295 4294967295\n255\n"), "line 2: a 4294967295x4294967295 image is too large");
+    assert_eq!(error(b"P2\n65536 65536\n255\n1 2"), "line 4: the pixels end after 2 of 4294967296 samples");
+  }
+}
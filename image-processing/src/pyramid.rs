@@ -0,0 +1,258 @@
+use failure::{format_err, Error};
+
+use image::Image;
+use image::image::{DynamicImage, GenericImageView, ImageBuffer, Rgb};
+use selection::Mask;
+
+// Burt and Adelson's 5 tap binomial kernel
+const KERNEL: [f64; 5] = [1.0 / 16.0, 4.0 / 16.0, 6.0 / 16.0, 4.0 / 16.0, 1.0 / 16.0];
+
+// One level of a pyramid, its channels stored as floats since Laplacian levels
+// hold signed differences.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Level {
+  pub width: u32,
+  pub height: u32,
+  pub channels: Vec<Vec<f64>>
+}
+
+impl Level {
+  pub fn from_image(image: &DynamicImage) -> Self {
+    let rgb = image.to_rgb();
+    let (width, height) = rgb.dimensions();
+    let channels = (0..3)
+    .map(|channel| rgb.pixels().map(|pixel| pixel[channel] as f64).collect())
+    .collect();
+    Self { width, height, channels }
+  }
+
+  // the selected pixels are 1, the others 0
+  pub fn from_mask(mask: &Mask) -> Self {
+    let (width, height) = mask.dimensions();
+    let weights = (0..height)
+    .flat_map(|y| (0..width).map(move |x| (x, y)))
+    .map(|(x, y)| if mask.contains(x, y) { 1.0 } else { 0.0 })
+    .collect();
+    Self { width, height, channels: vec![weights] }
+  }
+
+  pub fn to_image(&self) -> DynamicImage {
+    let channel = |x: u32, y: u32, c: usize| {
+      let value = self.channels[c.min(self.channels.len() - 1)][(y * self.width + x) as usize];
+      value.round().clamp(0.0, 255.0) as u8
+    };
+    DynamicImage::ImageRgb8(ImageBuffer::from_fn(self.width, self.height, |x, y| {
+      Rgb([channel(x, y, 0), channel(x, y, 1), channel(x, y, 2)])
+    }))
+  }
+
+  // blurs, then keeps every other pixel
+  pub fn reduce(&self) -> Self {
+    let (width, height) = (self.width.div_ceil(2), self.height.div_ceil(2));
+    let channels = self.channels.iter().map(|values| {
+      let mut reduced = Vec::with_capacity((width * height) as usize);
+      for y in 0..height {
+        for x in 0..width {
+          let mut sum = 0.0;
+          for (j, weight_y) in KERNEL.iter().enumerate() {
+            let source_y = clamp(2 * y as i64 + j as i64 - 2, self.height);
+            for (i, weight_x) in KERNEL.iter().enumerate() {
+              let source_x = clamp(2 * x as i64 + i as i64 - 2, self.width);
+              sum += weight_x * weight_y * values[(source_y * self.width + source_x) as usize];
+            }
+          }
+          reduced.push(sum);
+        }
+      }
+      reduced
+    }).collect();
+    Self { width, height, channels }
+  }
+
+  // Upsamples to the given size, which should be the one this level was
+  // reduced from.
+  pub fn expand(&self, width: u32, height: u32) -> Self {
+    // the taps landing on a pixel of this level, with their weights
+    let taps = |position: u32, size: u32| -> Vec<(u32, f64)> {
+      (0..KERNEL.len())
+      .filter(|&k| (position as i64 + k as i64 - 2) % 2 == 0)
+      .map(|k| {
+        let source = (position as i64 + k as i64 - 2) / 2;
+        // twice the weight, since only every other tap lands on a pixel
+        (clamp(source, size), 2.0 * KERNEL[k])
+      })
+      .collect()
+    };
+
+    // the same for every row, so they are worked out once
+    let columns: Vec<Vec<(u32, f64)>> = (0..width).map(|x| taps(x, self.width)).collect();
+    let channels = self.channels.iter().map(|values| {
+      let mut expanded = Vec::with_capacity((width * height) as usize);
+      for y in 0..height {
+        let rows = taps(y, self.height);
+        for column in columns.iter() {
+          let mut sum = 0.0;
+          for &(source_y, weight_y) in rows.iter() {
+            for &(source_x, weight_x) in column.iter() {
+              sum += weight_x * weight_y * values[(source_y * self.width + source_x) as usize];
+            }
+          }
+          expanded.push(sum);
+        }
+      }
+      expanded
+    }).collect();
+    Self { width, height, channels }
+  }
+
+  fn zip_with<F: Fn(f64, f64) -> f64>(&self, other: &Level, combine: F) -> Self {
+    let channels = self.channels.iter().zip(other.channels.iter())
+    .map(|(a, b)| a.iter().zip(b.iter()).map(|(&a, &b)| combine(a, b)).collect())
+    .collect();
+    Self { width: self.width, height: self.height, channels }
+  }
+}
+
+fn clamp(position: i64, size: u32) -> u32 {
+  position.max(0).min(size as i64 - 1) as u32
+}
+
+// The levels of a pyramid, from the full resolution one down to the coarsest.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pyramid {
+  pub levels: Vec<Level>
+}
+
+impl Pyramid {
+  // Successively blurred and halved levels. Stops early once a level is down to
+  // a single pixel.
+  pub fn gaussian(base: Level, levels: usize) -> Self {
+    let mut pyramid = vec![base];
+    while pyramid.len() < levels.max(1) {
+      let next = {
+        let last = pyramid.last().unwrap();
+        if last.width == 1 && last.height == 1 {
+          break;
+        }
+        last.reduce()
+      };
+      pyramid.push(next);
+    }
+    Pyramid { levels: pyramid }
+  }
+
+  // Every level holds the details lost by the one below it, the last one the
+  // coarsest gaussian level itself.
+  pub fn laplacian(base: Level, levels: usize) -> Self {
+    let gaussian = Pyramid::gaussian(base, levels).levels;
+    let mut pyramid: Vec<Level> = gaussian.windows(2)
+    .map(|pair| pair[0].zip_with(&pair[1].expand(pair[0].width, pair[0].height), |a, b| a - b))
+    .collect();
+    pyramid.push(gaussian.last().unwrap().clone());
+    Pyramid { levels: pyramid }
+  }
+
+  // rebuilds the full resolution level of a laplacian pyramid
+  pub fn collapse(&self) -> Level {
+    let mut levels = self.levels.iter().rev();
+    let coarsest = levels.next().unwrap().clone();
+    levels.fold(coarsest, |image, details| {
+      details.zip_with(&image.expand(details.width, details.height), |a, b| a + b)
+    })
+  }
+}
+
+impl Image {
+  pub fn gaussian_pyramid(&self, levels: usize) -> Pyramid {
+    Pyramid::gaussian(Level::from_image(self.as_dynamic_image()), levels)
+  }
+
+  pub fn laplacian_pyramid(&self, levels: usize) -> Pyramid {
+    Pyramid::laplacian(Level::from_image(self.as_dynamic_image()), levels)
+  }
+
+  // Takes `other` within the mask and this image elsewhere, blending every band
+  // of frequencies over a transition as wide as its wavelength, so that seams
+  // don't show.
+  pub fn blend_multiband(&self, other: &Image, mask: &Mask, levels: usize) -> Result<DynamicImage, Error> {
+    let dimensions = self.as_dynamic_image().dimensions();
+    if other.as_dynamic_image().dimensions() != dimensions || mask.dimensions() != dimensions {
+      return Err(format_err!("{} isn't the size of the image", other.get_image_path().display()));
+    }
+
+    let outside = self.laplacian_pyramid(levels);
+    let inside = other.laplacian_pyramid(levels);
+    let weights = Pyramid::gaussian(Level::from_mask(mask), levels);
+
+    let blended = outside.levels.iter().zip(inside.levels.iter()).zip(weights.levels.iter())
+    .map(|((outside, inside), weights)| {
+      let channels = outside.channels.iter().zip(inside.channels.iter())
+      .map(|(a, b)| {
+        a.iter().zip(b.iter()).zip(weights.channels[0].iter())
+        .map(|((a, b), weight)| a + weight * (b - a))
+        .collect()
+      })
+      .collect();
+      Level { width: outside.width, height: outside.height, channels }
+    })
+    .collect();
+
+    Ok(Pyramid { levels: blended }.collapse().to_image())
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::path::PathBuf;
+  use image::image::{GrayImage, Luma};
+
+  fn image(width: u32, height: u32, color: fn(u32, u32) -> u8) -> Image {
+    let buffer = GrayImage::from_fn(width, height, |x, y| Luma([color(x, y)]));
+    Image::new(&PathBuf::from("nobody cares"), DynamicImage::ImageLuma8(buffer))
+  }
+
+  #[test]
+  fn gaussian_levels_halve_down_to_a_pixel() {
+    let pyramid = image(13, 6, |x, y| (x * 10 + y) as u8).gaussian_pyramid(10);
+
+    let sizes: Vec<_> = pyramid.levels.iter().map(|level| (level.width, level.height)).collect();
+    assert_eq!(sizes, vec![(13, 6), (7, 3), (4, 2), (2, 1), (1, 1)]);
+  }
+
+  #[test]
+  fn collapsing_a_laplacian_pyramid_gives_the_image_back() {
+    let original = image(37, 23, |x, y| ((x * 31 + y * 17) % 251) as u8);
+    let rebuilt = original.laplacian_pyramid(4).collapse().to_image();
+
+    assert_eq!(rebuilt.to_rgb().into_raw(), original.as_dynamic_image().to_rgb().into_raw());
+  }
+
+  #[test]
+  fn blends_smoothly_across_the_mask() {
+    let dark = image(64, 16, |_, _| 40);
+    let bright = image(64, 16, |_, _| 200);
+    let mut mask = Mask::new(64, 16);
+    for y in 0..16 {
+      for x in 32..64 {
+        mask.select(x, y);
+      }
+    }
+
+    let blended = dark.blend_multiband(&bright, &mask, 4).unwrap().to_luma();
+
+    // each side keeps its color, away from the seam
+    assert_eq!(blended.get_pixel(2, 8)[0], 40);
+    assert_eq!(blended.get_pixel(61, 8)[0], 200);
+    // and the transition is monotonic, without any step in it
+    let row: Vec<i32> = (0..64).map(|x| blended.get_pixel(x, 8)[0] as i32).collect();
+    assert!(row.windows(2).all(|pair| pair[1] >= pair[0] && pair[1] - pair[0] < 80), "{:?}", row);
+  }
+
+  #[test]
+  fn refuses_images_of_another_size() {
+    let mask = Mask::new(10, 10);
+    assert!(image(10, 10, |_, _| 0).blend_multiband(&image(10, 12, |_, _| 0), &mask, 3).is_err());
+  }
+}
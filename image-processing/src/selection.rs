@@ -0,0 +1,213 @@
+// Regions of interest, in image pixel coordinates.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "shape", rename_all = "snake_case")]
+pub enum Selection {
+  Rectangle { x: f64, y: f64, width: f64, height: f64 },
+  Ellipse { center_x: f64, center_y: f64, radius_x: f64, radius_y: f64 },
+  Freehand { points: Vec<(f64, f64)> }
+}
+
+impl Selection {
+  // the rectangle spanned by two opposite corners, in any order
+  pub fn rectangle(from: (f64, f64), to: (f64, f64)) -> Self {
+    Selection::Rectangle {
+      x: from.0.min(to.0),
+      y: from.1.min(to.1),
+      width: (to.0 - from.0).abs(),
+      height: (to.1 - from.1).abs()
+    }
+  }
+
+  // the ellipse inscribed in the rectangle spanned by two opposite corners
+  pub fn ellipse(from: (f64, f64), to: (f64, f64)) -> Self {
+    Selection::Ellipse {
+      center_x: (from.0 + to.0) / 2.0,
+      center_y: (from.1 + to.1) / 2.0,
+      radius_x: (to.0 - from.0).abs() / 2.0,
+      radius_y: (to.1 - from.1).abs() / 2.0
+    }
+  }
+
+  // the smallest rectangle holding the selection, as x, y, width and height
+  pub fn bounds(&self) -> (f64, f64, f64, f64) {
+    match *self {
+      Selection::Rectangle { x, y, width, height } => (x, y, width, height),
+      Selection::Ellipse { center_x, center_y, radius_x, radius_y } =>
+        (center_x - radius_x, center_y - radius_y, 2.0 * radius_x, 2.0 * radius_y),
+      Selection::Freehand { ref points } => {
+        if points.is_empty() {
+          return (0.0, 0.0, 0.0, 0.0);
+        }
+        let left = points.iter().map(|p| p.0).fold(f64::INFINITY, f64::min);
+        let top = points.iter().map(|p| p.1).fold(f64::INFINITY, f64::min);
+        let right = points.iter().map(|p| p.0).fold(f64::NEG_INFINITY, f64::max);
+        let bottom = points.iter().map(|p| p.1).fold(f64::NEG_INFINITY, f64::max);
+        (left, top, right - left, bottom - top)
+      }
+    }
+  }
+
+  pub fn contains(&self, x: f64, y: f64) -> bool {
+    match *self {
+      Selection::Rectangle { x: left, y: top, width, height } =>
+        x >= left && x < left + width && y >= top && y < top + height,
+      Selection::Ellipse { center_x, center_y, radius_x, radius_y } => {
+        if radius_x <= 0.0 || radius_y <= 0.0 {
+          return false;
+        }
+        let dx = (x - center_x) / radius_x;
+        let dy = (y - center_y) / radius_y;
+        dx * dx + dy * dy <= 1.0
+      },
+      Selection::Freehand { ref points } => polygon_contains(points, x, y)
+    }
+  }
+
+  // the same selection on a copy of the image resized by `factor`
+  pub fn scale(&self, factor: f64) -> Self {
+    match *self {
+      Selection::Rectangle { x, y, width, height } =>
+        Selection::Rectangle { x: x * factor, y: y * factor, width: width * factor, height: height * factor },
+      Selection::Ellipse { center_x, center_y, radius_x, radius_y } =>
+        Selection::Ellipse {
+          center_x: center_x * factor,
+          center_y: center_y * factor,
+          radius_x: radius_x * factor,
+          radius_y: radius_y * factor
+        },
+      Selection::Freehand { ref points } =>
+        Selection::Freehand { points: points.iter().map(|&(x, y)| (x * factor, y * factor)).collect() }
+    }
+  }
+
+  // Rasterizes the selection, sampling every pixel at its center.
+  pub fn to_mask(&self, width: u32, height: u32) -> Mask {
+    let mut mask = Mask::new(width, height);
+    for y in 0..height {
+      for x in 0..width {
+        if self.contains(x as f64 + 0.5, y as f64 + 0.5) {
+          mask.select(x, y);
+        }
+      }
+    }
+    mask
+  }
+}
+
+// even-odd rule, the polygon is implicitly closed
+fn polygon_contains(points: &[(f64, f64)], x: f64, y: f64) -> bool {
+  if points.len() < 3 {
+    return false;
+  }
+
+  let mut inside = false;
+  let mut previous = points[points.len() - 1];
+  for &current in points {
+    let (x1, y1) = previous;
+    let (x2, y2) = current;
+    if (y1 > y) != (y2 > y) && x < x1 + (y - y1) * (x2 - x1) / (y2 - y1) {
+      inside = !inside;
+    }
+    previous = current;
+  }
+  inside
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mask {
+  width: u32,
+  height: u32,
+  selected: Vec<bool>
+}
+
+impl Mask {
+  // an empty mask, nothing is selected
+  pub fn new(width: u32, height: u32) -> Self {
+    Self {
+      width,
+      height,
+      selected: vec![false; (width as usize) * (height as usize)]
+    }
+  }
+
+  // every pixel is selected
+  pub fn full(width: u32, height: u32) -> Self {
+    Self {
+      width,
+      height,
+      selected: vec![true; (width as usize) * (height as usize)]
+    }
+  }
+
+  pub fn dimensions(&self) -> (u32, u32) {
+    (self.width, self.height)
+  }
+
+  pub fn select(&mut self, x: u32, y: u32) {
+    if x < self.width && y < self.height {
+      self.selected[(y as usize) * (self.width as usize) + x as usize] = true;
+    }
+  }
+
+  // pixels outside of the mask's bounds are never selected
+  pub fn contains(&self, x: u32, y: u32) -> bool {
+    x < self.width && y < self.height &&
+    self.selected[(y as usize) * (self.width as usize) + x as usize]
+  }
+
+  pub fn count(&self) -> usize {
+    self.selected.iter().filter(|&&selected| selected).count()
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn rectangle_masks_cover_the_spanned_pixels() {
+    let mask = Selection::rectangle((3.0, 2.0), (1.0, 0.0)).to_mask(4, 4);
+
+    assert_eq!(mask.count(), 4);
+    assert!(mask.contains(1, 0));
+    assert!(mask.contains(2, 1));
+    assert!(!mask.contains(3, 2));
+  }
+
+  #[test]
+  fn ellipse_masks_leave_out_the_corners() {
+    let mask = Selection::ellipse((0.0, 0.0), (5.0, 5.0)).to_mask(5, 5);
+
+    assert!(mask.contains(2, 2));
+    assert!(mask.contains(0, 2));
+    assert!(!mask.contains(0, 0));
+    assert!(!mask.contains(4, 4));
+  }
+
+  #[test]
+  fn freehand_masks_fill_the_polygon() {
+    let triangle = Selection::Freehand { points: vec![(0.0, 0.0), (4.0, 0.0), (0.0, 4.0)] };
+    let mask = triangle.to_mask(4, 4);
+
+    assert!(mask.contains(0, 0));
+    assert!(mask.contains(1, 1));
+    assert!(!mask.contains(3, 3));
+    assert!(!Selection::Freehand { points: vec![(0.0, 0.0), (4.0, 4.0)] }.contains(2.0, 2.0));
+  }
+
+  #[test]
+  fn scaled_selections_cover_the_same_part_of_the_image() {
+    let mask = Selection::rectangle((0.0, 0.0), (30.0, 20.0)).scale(0.25).to_mask(10, 5);
+    assert_eq!(mask.count(), 7 * 5);
+    assert!(mask.contains(6, 4));
+    assert!(!mask.contains(7, 2));
+  }
+
+  #[test]
+  fn bounds_hold_every_shape() {
+    assert_eq!(Selection::ellipse((1.0, 2.0), (5.0, 4.0)).bounds(), (1.0, 2.0, 4.0, 2.0));
+    let triangle = Selection::Freehand { points: vec![(3.0, 1.0), (6.0, 4.0), (2.0, 5.0)] };
+    assert_eq!(triangle.bounds(), (2.0, 1.0, 4.0, 4.0));
+  }
+}
@@ -0,0 +1,216 @@
+use failure::{format_err, Error};
+
+use image::Image;
+use image::image::{ColorType, GenericImageView};
+use filters::{gaussian_kernel, blur};
+
+const MAX_COLOR_INTENSITY: f64 = 255.0;
+
+// SSIM constants from Wang et al., "Image quality assessment: from error
+// visibility to structural similarity"
+const SSIM_K1: f64 = 0.01;
+const SSIM_K2: f64 = 0.03;
+const SSIM_SIGMA: f64 = 1.5;
+const SSIM_RADIUS: i64 = 5;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelStatistics {
+  pub channel: &'static str,
+  pub mean: f64,
+  pub std_dev: f64,
+  pub min: u8,
+  pub max: u8,
+  // in bits
+  pub entropy: f64
+}
+
+impl ChannelStatistics {
+  fn from_histogram(channel: &'static str, histogram: &[usize; 256]) -> Self {
+    let count: usize = histogram.iter().sum();
+    if count == 0 {
+      return Self { channel, mean: 0.0, std_dev: 0.0, min: 0, max: 0, entropy: 0.0 };
+    }
+    let count = count as f64;
+
+    let mean = histogram.iter().enumerate()
+    .map(|(value, &n)| (value as f64) * (n as f64))
+    .sum::<f64>() / count;
+
+    let variance = histogram.iter().enumerate()
+    .map(|(value, &n)| (value as f64 - mean).powi(2) * (n as f64))
+    .sum::<f64>() / count;
+
+    let entropy = histogram.iter()
+    .filter(|&&n| n != 0)
+    .map(|&n| { let p = (n as f64) / count; -p * p.log2() })
+    .sum::<f64>();
+
+    let min = histogram.iter().position(|&n| n != 0).unwrap_or(0) as u8;
+    let max = histogram.iter().rposition(|&n| n != 0).unwrap_or(0) as u8;
+
+    Self { channel, mean, std_dev: variance.sqrt(), min, max, entropy }
+  }
+}
+
+impl Image {
+  // one entry per channel the image actually has
+  pub fn channel_statistics(&self) -> Vec<ChannelStatistics> {
+    let channels: &[&'static str] = match self.get_color_type() {
+      ColorType::Gray(_) => &["gray"],
+      ColorType::GrayA(_) => &["gray", "alpha"],
+      ColorType::RGB(_) | ColorType::BGR(_) => &["red", "green", "blue"],
+      _ => &["red", "green", "blue", "alpha"]
+    };
+
+    // gray images read the same from every color channel, alpha is always last
+    let offsets: Vec<usize> = match channels.len() {
+      1 => vec![0],
+      2 => vec![0, 3],
+      n => (0..n).collect()
+    };
+
+    let mut histograms = vec![[0usize; 256]; channels.len()];
+    for (_, _, rgba) in self.as_dynamic_image().pixels() {
+      for (histogram, &offset) in histograms.iter_mut().zip(offsets.iter()) {
+        histogram[rgba[offset] as usize] += 1;
+      }
+    }
+
+    channels.iter().zip(histograms.iter())
+    .map(|(&channel, histogram)| ChannelStatistics::from_histogram(channel, histogram))
+    .collect()
+  }
+
+  // averaged over the red, green and blue channels
+  pub fn mean_squared_error(&self, other: &Image) -> Result<f64, Error> {
+    let (this, other) = (self.as_dynamic_image().to_rgb(), other.as_dynamic_image().to_rgb());
+    if this.dimensions() != other.dimensions() {
+      return Err(format_err!("can't compare a {:?} image with a {:?} one", this.dimensions(), other.dimensions()));
+    }
+    if this.is_empty() {
+      return Ok(0.0);
+    }
+
+    let squared_error: f64 = this.iter().zip(other.iter())
+    .map(|(&a, &b)| (a as f64 - b as f64).powi(2))
+    .sum();
+    Ok(squared_error / (this.len() as f64))
+  }
+
+  // in decibels, infinite for identical images
+  pub fn peak_signal_to_noise_ratio(&self, other: &Image) -> Result<f64, Error> {
+    let mse = self.mean_squared_error(other)?;
+    Ok(10.0 * (MAX_COLOR_INTENSITY * MAX_COLOR_INTENSITY / mse).log10())
+  }
+
+  // mean SSIM of the luminance, using a gaussian weighted 11x11 window
+  pub fn structural_similarity(&self, other: &Image) -> Result<f64, Error> {
+    let (this, other) = (self.as_dynamic_image().to_luma(), other.as_dynamic_image().to_luma());
+    if this.dimensions() != other.dimensions() {
+      return Err(format_err!("can't compare a {:?} image with a {:?} one", this.dimensions(), other.dimensions()));
+    }
+    let (width, height) = this.dimensions();
+    if width == 0 || height == 0 {
+      return Ok(1.0);
+    }
+
+    let x: Vec<f64> = this.iter().map(|&v| v as f64).collect();
+    let y: Vec<f64> = other.iter().map(|&v| v as f64).collect();
+    let xx: Vec<f64> = x.iter().map(|v| v * v).collect();
+    let yy: Vec<f64> = y.iter().map(|v| v * v).collect();
+    let xy: Vec<f64> = x.iter().zip(y.iter()).map(|(a, b)| a * b).collect();
+
+    let kernel = gaussian_kernel(SSIM_SIGMA, SSIM_RADIUS);
+    let mu_x = blur(&x, width, height, &kernel);
+    let mu_y = blur(&y, width, height, &kernel);
+    let sigma_xx = blur(&xx, width, height, &kernel);
+    let sigma_yy = blur(&yy, width, height, &kernel);
+    let sigma_xy = blur(&xy, width, height, &kernel);
+
+    let c1 = (SSIM_K1 * MAX_COLOR_INTENSITY).powi(2);
+    let c2 = (SSIM_K2 * MAX_COLOR_INTENSITY).powi(2);
+
+    let total: f64 = (0..x.len()).map(|i| {
+      let (mx, my) = (mu_x[i], mu_y[i]);
+      let vx = sigma_xx[i] - mx * mx;
+      let vy = sigma_yy[i] - my * my;
+      let cxy = sigma_xy[i] - mx * my;
+      ((2.0 * mx * my + c1) * (2.0 * cxy + c2)) / ((mx * mx + my * my + c1) * (vx + vy + c2))
+    }).sum();
+
+    Ok(total / (x.len() as f64))
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::path::PathBuf;
+  use image::image::{DynamicImage, GenericImage, Pixel};
+
+  fn fixture(name: &str) -> Image {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(name);
+    Image::open(&path).unwrap()
+  }
+
+  #[test]
+  fn calculates_the_channel_statistics() {
+    let path = PathBuf::from("nobody cares");
+    let mut dynamic_image = DynamicImage::new_luma8(1,4);
+    for (i, &val) in [0u8, 0, 255, 255].iter().enumerate() {
+      dynamic_image.put_pixel(0, i as u32, Pixel::from_channels(val,val,val,255));
+    }
+    let statistics = Image::new(&path, dynamic_image).channel_statistics();
+
+    assert_eq!(statistics.len(), 1);
+    assert_eq!(statistics[0].channel, "gray");
+    assert_eq!(statistics[0].mean, 127.5);
+    assert_eq!(statistics[0].std_dev, 127.5);
+    assert_eq!((statistics[0].min, statistics[0].max), (0, 255));
+    assert_eq!(statistics[0].entropy, 1.0);
+  }
+
+  #[test]
+  fn identical_images_are_a_perfect_match() {
+    let image = fixture("unequalized.jpg");
+
+    assert_eq!(image.mean_squared_error(&image).unwrap(), 0.0);
+    assert!(image.peak_signal_to_noise_ratio(&image).unwrap().is_infinite());
+    assert!((image.structural_similarity(&image).unwrap() - 1.0).abs() < 1e-9);
+  }
+
+  #[test]
+  fn comparing_images_of_different_sizes_fails() {
+    let path = PathBuf::from("nobody cares");
+    let small = Image::new(&path, DynamicImage::new_rgb8(1,1));
+    let large = Image::new(&path, DynamicImage::new_rgb8(2,1));
+
+    assert!(small.mean_squared_error(&large).is_err());
+    assert!(small.structural_similarity(&large).is_err());
+  }
+
+  #[test]
+  fn equalizing_the_histogram_stretches_the_intensities() {
+    let image = fixture("unequalized.jpg");
+    let equalized = Image::new(&image.get_image_path(), image.equalize_histogram());
+
+    let before = &image.channel_statistics()[0];
+    let after = &equalized.channel_statistics()[0];
+
+    assert!(after.std_dev > before.std_dev);
+    assert!(after.max as i32 - after.min as i32 > before.max as i32 - before.min as i32);
+    assert!((after.mean - 127.5).abs() < 10.0);
+  }
+
+  #[test]
+  fn equalizing_the_histogram_matches_the_reference_fixture() {
+    let image = fixture("unequalized.jpg");
+    let equalized = Image::new(&image.get_image_path(), image.equalize_histogram());
+    let reference = fixture("equalized.jpg");
+
+    // the reference went through a lossy jpeg round trip
+    assert!(equalized.peak_signal_to_noise_ratio(&reference).unwrap() > 30.0);
+    assert!(equalized.structural_similarity(&reference).unwrap() > 0.95);
+  }
+}
@@ -0,0 +1,163 @@
+use failure::{format_err, Error};
+
+use image::Image;
+use image::image::{DynamicImage, GenericImageView, ImageBuffer, Rgb};
+use features::match_features;
+use homography::Homography;
+use perspective::{Interpolation, sample};
+
+const MAX_FEATURES: usize = 1500;
+const MATCH_RATIO: f64 = 0.8;
+const RANSAC_ITERATIONS: usize = 2000;
+// in pixels
+const RANSAC_TOLERANCE: f64 = 3.0;
+// fewer agreeing matches than this are taken for a failed alignment
+const MIN_INLIERS: usize = 10;
+// a panorama larger than this many times its parts comes from a bad alignment
+const MAX_GROWTH: u64 = 8;
+// where none of the images reach, like the margin of a scanned page
+const BACKGROUND: [u8; 3] = [255, 255, 255];
+// how far the estimated bounds may overshoot a whole pixel
+const ROUNDING_SLACK: f64 = 1e-6;
+
+// the homography bringing `image` onto `reference`, from their matching features
+pub fn align(image: &Image, reference: &Image) -> Result<Homography, Error> {
+  let features = image.orb_features(MAX_FEATURES);
+  let reference_features = reference.orb_features(MAX_FEATURES);
+
+  let pairs: Vec<_> = match_features(&features, &reference_features, MATCH_RATIO).iter()
+  .map(|&(a, b)| {
+    let (from, to) = (features[a].keypoint, reference_features[b].keypoint);
+    ((from.x as f64, from.y as f64), (to.x as f64, to.y as f64))
+  })
+  .collect();
+
+  let failure = || format_err!("couldn't align {} with {}", image.get_image_path().display(), reference.get_image_path().display());
+  match Homography::ransac(&pairs, RANSAC_ITERATIONS, RANSAC_TOLERANCE) {
+    Some((homography, ref inliers)) if inliers.iter().filter(|&&inlier| inlier).count() >= MIN_INLIERS => Ok(homography),
+    _ => Err(failure())
+  }
+}
+
+// Stitches overlapping images, each of them overlapping the one before it, in
+// the frame of the first one. The overlaps are feathered.
+pub fn stitch(images: &[Image]) -> Result<DynamicImage, Error> {
+  if images.is_empty() {
+    return Err(format_err!("there's nothing to stitch"));
+  }
+
+  // chained alignments, each image onto the first one
+  let mut homographies = vec![Homography::identity()];
+  for pair in images.windows(2) {
+    let onto_previous = align(&pair[1], &pair[0])?;
+    let onto_first = *homographies.last().unwrap() * onto_previous;
+    homographies.push(onto_first);
+  }
+
+  let (mut min_x, mut min_y, mut max_x, mut max_y) = (0.0f64, 0.0f64, 0.0f64, 0.0f64);
+  let mut parts_area = 0;
+  for (image, homography) in images.iter().zip(homographies.iter()) {
+    let (width, height) = image.as_dynamic_image().dimensions();
+    parts_area += (width as u64) * (height as u64);
+    for &corner in [(0.0, 0.0), (width as f64, 0.0), (0.0, height as f64), (width as f64, height as f64)].iter() {
+      let (x, y) = homography.apply(corner);
+      min_x = min_x.min(x);
+      min_y = min_y.min(y);
+      max_x = max_x.max(x);
+      max_y = max_y.max(y);
+    }
+  }
+
+  // whole pixels, so that the first image isn't resampled
+  let (min_x, min_y) = ((min_x + ROUNDING_SLACK).floor(), (min_y + ROUNDING_SLACK).floor());
+  let (max_x, max_y) = ((max_x - ROUNDING_SLACK).ceil(), (max_y - ROUNDING_SLACK).ceil());
+  let (width, height) = ((max_x - min_x) as u32, (max_y - min_y) as u32);
+  if !(max_x - min_x).is_finite() || (width as u64) * (height as u64) > MAX_GROWTH * parts_area {
+    return Err(format_err!("the images don't line up"));
+  }
+
+  // from the panorama back into every image
+  let offset = Homography::translation(min_x, min_y);
+  let mut sources = Vec::with_capacity(images.len());
+  for (image, homography) in images.iter().zip(homographies.iter()) {
+    let inverse = homography.inverse().ok_or_else(|| format_err!("the images don't line up"))?;
+    sources.push((image.as_dynamic_image().to_rgb(), inverse * offset));
+  }
+
+  Ok(DynamicImage::ImageRgb8(ImageBuffer::from_fn(width, height, |x, y| {
+    let mut sum = [0.0; 3];
+    let mut total_weight = 0.0;
+
+    for (source, homography) in sources.iter() {
+      // pixels cover the unit square right and below their coordinates
+      let (source_x, source_y) = homography.apply((x as f64 + 0.5, y as f64 + 0.5));
+      let (source_width, source_height) = (source.width() as f64, source.height() as f64);
+      if source_x < 0.0 || source_y < 0.0 || source_x > source_width || source_y > source_height {
+        continue;
+      }
+      let source_x = (source_x - 0.5).max(0.0).min(source_width - 1.0);
+      let source_y = (source_y - 0.5).max(0.0).min(source_height - 1.0);
+
+      // pixels weigh less the closer they are to their image's border
+      let weight = 1.0 + source_x.min(source_y).min(source_width - 1.0 - source_x).min(source_height - 1.0 - source_y);
+      let color = sample(source, source_x, source_y, Interpolation::Bilinear);
+      for (channel, value) in sum.iter_mut().zip(color.iter()) {
+        *channel += weight * value;
+      }
+      total_weight += weight;
+    }
+
+    if total_weight == 0.0 {
+      Rgb(BACKGROUND)
+    }
+    else {
+      Rgb([(sum[0] / total_weight).round() as u8, (sum[1] / total_weight).round() as u8, (sum[2] / total_weight).round() as u8])
+    }
+  })))
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::path::PathBuf;
+  use image::image::{GrayImage, Luma};
+
+  fn texture(x: u32, y: u32) -> u8 {
+    ((x / 5 * 37 + y / 7 * 91 + (x / 11) * (y / 3) * 13) % 200 + 30) as u8
+  }
+
+  // the columns from `start` to `end` of the texture
+  fn strip(start: u32, end: u32) -> Image {
+    let buffer = GrayImage::from_fn(end - start, 90, |x, y| Luma([texture(x + start, y)]));
+    Image::new(&PathBuf::from(format!("strip {}", start)), DynamicImage::ImageLuma8(buffer))
+  }
+
+  #[test]
+  fn aligns_overlapping_strips() {
+    let homography = align(&strip(50, 160), &strip(0, 110)).unwrap();
+    let (x, y) = homography.apply((10.0, 20.0));
+
+    assert!((x - 60.0).abs() < 0.5 && (y - 20.0).abs() < 0.5, "({}, {})", x, y);
+  }
+
+  #[test]
+  fn stitches_strips_back_together() {
+    let stitched = stitch(&[strip(0, 110), strip(50, 160), strip(100, 210)]).unwrap();
+
+    let (width, height) = stitched.dimensions();
+    assert_eq!((width, height), (210, 90));
+
+    let luma = stitched.to_luma();
+    let mismatched = (0..width).flat_map(|x| (0..height).map(move |y| (x, y)))
+    .filter(|&(x, y)| (luma.get_pixel(x, y)[0] as i32 - texture(x, y) as i32).abs() > 8)
+    .count();
+    assert!(mismatched * 100 < (width * height) as usize, "{} pixels differ", mismatched);
+  }
+
+  #[test]
+  fn unrelated_images_dont_stitch() {
+    let blank = Image::new(&PathBuf::from("blank"), DynamicImage::new_luma8(100, 90));
+    assert!(stitch(&[strip(0, 110), blank]).is_err());
+  }
+}
@@ -0,0 +1,268 @@
+use failure::{format_err, Error};
+
+use image::Image;
+use image::image::{DynamicImage, GenericImageView, GrayImage, ImageBuffer, Luma};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MatchMethod {
+  // mean squared difference, the lower the better
+  SquaredDifferences,
+  // normalized cross-correlation, up to 1
+  CrossCorrelation,
+  // zero-mean normalized cross-correlation, from -1 to 1, insensitive to
+  // brightness and contrast changes
+  ZeroMeanCrossCorrelation
+}
+
+impl MatchMethod {
+  fn is_better(&self, score: f64, than: f64) -> bool {
+    match *self {
+      MatchMethod::SquaredDifferences => score < than,
+      _ => score > than
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Match {
+  // top left corner of the template
+  pub x: u32,
+  pub y: u32,
+  pub score: f64
+}
+
+// The score of every position of the template's top left corner.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScoreMap {
+  pub width: u32,
+  pub height: u32,
+  pub template_width: u32,
+  pub template_height: u32,
+  pub method: MatchMethod,
+  scores: Vec<f64>
+}
+
+impl ScoreMap {
+  pub fn get(&self, x: u32, y: u32) -> f64 {
+    self.scores[(y * self.width + x) as usize]
+  }
+
+  // Up to `count` best matches, best first, none of them overlapping a better
+  // one by more than half of the template in each direction.
+  pub fn best_matches(&self, count: usize) -> Vec<Match> {
+    let mut candidates: Vec<Match> = (0..self.height)
+    .flat_map(|y| (0..self.width).map(move |x| (x, y)))
+    .map(|(x, y)| Match { x, y, score: self.get(x, y) })
+    .collect();
+    let method = self.method;
+    candidates.sort_by(|a, b| {
+      if method.is_better(a.score, b.score) { ::std::cmp::Ordering::Less }
+      else if method.is_better(b.score, a.score) { ::std::cmp::Ordering::Greater }
+      else { (a.y, a.x).cmp(&(b.y, b.x)) }
+    });
+
+    let (min_dx, min_dy) = (self.template_width.div_ceil(2), self.template_height.div_ceil(2));
+    let mut matches: Vec<Match> = Vec::new();
+    for candidate in candidates {
+      if matches.len() == count {
+        break;
+      }
+      let overlaps = matches.iter().any(|other| {
+        (candidate.x as i64 - other.x as i64).abs() < min_dx as i64 &&
+        (candidate.y as i64 - other.y as i64).abs() < min_dy as i64
+      });
+      if !overlaps {
+        matches.push(candidate);
+      }
+    }
+    matches
+  }
+
+  // the scores stretched over the gray levels, the best matches brightest
+  pub fn to_image(&self) -> DynamicImage {
+    let min = self.scores.iter().cloned().fold(f64::INFINITY, f64::min);
+    let max = self.scores.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    let range = if max > min { max - min } else { 1.0 };
+
+    let buffer: GrayImage = ImageBuffer::from_fn(self.width, self.height, |x, y| {
+      let level = (self.get(x, y) - min) / range;
+      let level = if self.method == MatchMethod::SquaredDifferences { 1.0 - level } else { level };
+      Luma([(level * 255.0).round() as u8])
+    });
+    DynamicImage::ImageLuma8(buffer)
+  }
+}
+
+// sums over any rectangle in constant time
+struct SummedArea {
+  width: usize,
+  sums: Vec<f64>
+}
+
+impl SummedArea {
+  fn new(values: &[f64], width: u32, height: u32) -> Self {
+    let (width, height) = (width as usize, height as usize);
+    let mut sums = vec![0.0; (width + 1) * (height + 1)];
+    for y in 0..height {
+      let mut row = 0.0;
+      for x in 0..width {
+        row += values[y * width + x];
+        sums[(y + 1) * (width + 1) + x + 1] = sums[y * (width + 1) + x + 1] + row;
+      }
+    }
+    Self { width: width + 1, sums }
+  }
+
+  fn sum(&self, x: u32, y: u32, width: u32, height: u32) -> f64 {
+    let (x, y, width, height) = (x as usize, y as usize, width as usize, height as usize);
+    let at = |x: usize, y: usize| self.sums[y * self.width + x];
+    at(x + width, y + height) - at(x, y + height) - at(x + width, y) + at(x, y)
+  }
+}
+
+fn luma_values(image: &Image) -> (Vec<f64>, u32, u32) {
+  let luma = image.as_dynamic_image().to_luma();
+  let (width, height) = luma.dimensions();
+  (luma.pixels().map(|pixel| pixel[0] as f64).collect(), width, height)
+}
+
+impl Image {
+  // Slides the template over this image, comparing their luma.
+  pub fn match_template(&self, template: &Image, method: MatchMethod) -> Result<ScoreMap, Error> {
+    let (values, width, height) = luma_values(self);
+    let (template_values, template_width, template_height) = luma_values(template);
+    if template_width == 0 || template_height == 0 {
+      return Err(format_err!("the template is empty"));
+    }
+    if template_width > width || template_height > height {
+      return Err(format_err!("the template is larger than the image"));
+    }
+
+    let area = (template_width * template_height) as f64;
+    let template_sum: f64 = template_values.iter().sum();
+    let template_squares: f64 = template_values.iter().map(|value| value * value).sum();
+    let template_variance = template_squares - template_sum * template_sum / area;
+
+    let sums = SummedArea::new(&values, width, height);
+    let squares: Vec<f64> = values.iter().map(|value| value * value).collect();
+    let squares = SummedArea::new(&squares, width, height);
+
+    let (map_width, map_height) = (width - template_width + 1, height - template_height + 1);
+    let mut scores = Vec::with_capacity((map_width * map_height) as usize);
+    for y in 0..map_height {
+      for x in 0..map_width {
+        let mut product = 0.0;
+        for ty in 0..template_height {
+          let row = ((y + ty) * width + x) as usize;
+          let template_row = (ty * template_width) as usize;
+          for tx in 0..template_width as usize {
+            product += values[row + tx] * template_values[template_row + tx];
+          }
+        }
+
+        let window_sum = sums.sum(x, y, template_width, template_height);
+        let window_squares = squares.sum(x, y, template_width, template_height);
+        let score = match method {
+          MatchMethod::SquaredDifferences => (window_squares - 2.0 * product + template_squares) / area,
+          MatchMethod::CrossCorrelation => {
+            let norm = (window_squares * template_squares).sqrt();
+            if norm > 0.0 { product / norm } else { 0.0 }
+          },
+          MatchMethod::ZeroMeanCrossCorrelation => {
+            let window_variance = window_squares - window_sum * window_sum / area;
+            let norm = (window_variance * template_variance).sqrt();
+            // a flat window or template correlates with nothing
+            if norm > 1e-9 { (product - window_sum * template_sum / area) / norm } else { 0.0 }
+          }
+        };
+        scores.push(score);
+      }
+    }
+
+    Ok(ScoreMap {
+      width: map_width,
+      height: map_height,
+      template_width,
+      template_height,
+      method,
+      scores
+    })
+  }
+
+  // the part of the image within the rectangle, clipped to the image
+  pub fn crop(&self, x: u32, y: u32, width: u32, height: u32) -> DynamicImage {
+    let image = self.as_dynamic_image();
+    let (image_width, image_height) = image.dimensions();
+    let (x, y) = (x.min(image_width), y.min(image_height));
+    DynamicImage::ImageRgba8(image.view(x, y, width.min(image_width - x), height.min(image_height - y)).to_image())
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::path::PathBuf;
+
+  fn texture(x: u32, y: u32) -> u8 {
+    ((x * 37 + y * 91 + (x / 3) * (y / 2) * 13) % 200 + 30) as u8
+  }
+
+  fn image(buffer: GrayImage) -> Image {
+    Image::new(&PathBuf::from("nobody cares"), DynamicImage::ImageLuma8(buffer))
+  }
+
+  #[test]
+  fn finds_the_template_with_every_method() {
+    let source = image(GrayImage::from_fn(40, 30, |x, y| Luma([texture(x, y)])));
+    let template = image(GrayImage::from_fn(8, 6, |x, y| Luma([texture(x + 21, y + 13)])));
+
+    for &method in [MatchMethod::SquaredDifferences, MatchMethod::CrossCorrelation, MatchMethod::ZeroMeanCrossCorrelation].iter() {
+      let scores = source.match_template(&template, method).unwrap();
+      assert_eq!((scores.width, scores.height), (33, 25));
+      let best = scores.best_matches(1)[0];
+      assert_eq!((best.x, best.y), (21, 13), "{:?}", method);
+    }
+  }
+
+  #[test]
+  fn zero_mean_correlation_ignores_brightness_and_contrast() {
+    let source = image(GrayImage::from_fn(40, 30, |x, y| Luma([texture(x, y)])));
+    let template = image(GrayImage::from_fn(10, 10, |x, y| Luma([texture(x + 5, y + 17) / 2 + 40])));
+
+    let best = source.match_template(&template, MatchMethod::ZeroMeanCrossCorrelation).unwrap().best_matches(1)[0];
+    assert_eq!((best.x, best.y), (5, 17));
+    assert!(best.score > 0.99);
+  }
+
+  #[test]
+  fn finds_every_copy_of_a_mark_once() {
+    // a white background with three crosses on it
+    let crosses = [(5, 4), (30, 8), (14, 22)];
+    let source = image(GrayImage::from_fn(48, 32, |x, y| {
+      let on_cross = crosses.iter().any(|&(cx, cy)| {
+        (x == cx + 2 && y >= cy && y < cy + 5) || (y == cy + 2 && x >= cx && x < cx + 5)
+      });
+      Luma([if on_cross { 0 } else { 255 }])
+    }));
+    let template = image(GrayImage::from_fn(5, 5, |x, y| Luma([if x == 2 || y == 2 { 0 } else { 255 }])));
+
+    let mut found: Vec<_> = source.match_template(&template, MatchMethod::ZeroMeanCrossCorrelation).unwrap()
+    .best_matches(3).iter().map(|m| (m.x, m.y)).collect();
+    found.sort();
+    assert_eq!(found, vec![(5, 4), (14, 22), (30, 8)]);
+  }
+
+  #[test]
+  fn refuses_a_template_larger_than_the_image() {
+    let source = image(GrayImage::new(10, 10));
+    assert!(source.match_template(&image(GrayImage::new(11, 4)), MatchMethod::CrossCorrelation).is_err());
+  }
+
+  #[test]
+  fn refuses_an_empty_template() {
+    let source = image(GrayImage::new(10, 10));
+    let outside = Image::new(&PathBuf::from("nobody cares"), source.crop(12, 3, 4, 4));
+    assert!(source.match_template(&outside, MatchMethod::SquaredDifferences).is_err());
+  }
+}
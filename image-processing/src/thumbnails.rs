@@ -0,0 +1,168 @@
+use std::collections::hash_map::DefaultHasher;
+use std::env;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use failure::{err_msg, format_err, Error};
+
+use image::Image;
+use image::image::{self, DynamicImage};
+use tiles::TiledImage;
+
+// the largest side of a thumbnail
+pub const THUMBNAIL_SIZE: u32 = 128;
+// tells apart the thumbnails being written at the same time
+static WRITES: AtomicUsize = AtomicUsize::new(0);
+
+const IMAGE_EXTENSIONS: &[&str] = &[
+  "png", "jpg", "jpeg", "gif", "bmp", "ico", "tif", "tiff", "webp", "tga", "hdr", "pbm", "pgm", "ppm", "pnm"
+];
+
+fn is_image_file(path: &Path) -> bool {
+  let extension = path.extension().and_then(|extension| extension.to_str()).unwrap_or("").to_lowercase();
+  // overviews stand in for the large images next to them
+  let is_overview = path.file_stem().and_then(|stem| stem.to_str()).is_some_and(|stem| stem.ends_with(".overview"));
+  path.is_file() && IMAGE_EXTENSIONS.contains(&extension.as_str()) && !is_overview
+}
+
+// The images in the same folder as `path`, by name, `path` among them.
+pub fn sibling_images(path: &Path) -> Result<Vec<PathBuf>, Error> {
+  let dir = match path.parent() {
+    Some(dir) if dir.as_os_str().is_empty() => PathBuf::from("."),
+    Some(dir) => dir.to_path_buf(),
+    None => return Err(format_err!("{} isn't in a folder", path.display()))
+  };
+  let mut images = Vec::new();
+  for entry in fs::read_dir(&dir)? {
+    let path = entry?.path();
+    if is_image_file(&path) {
+      images.push(path);
+    }
+  }
+  images.sort();
+  Ok(images)
+}
+
+// Thumbnails saved as PNG images, named after the path of the image they show
+// and made again once the image changes.
+pub struct ThumbnailCache {
+  dir: PathBuf,
+  size: u32
+}
+
+impl ThumbnailCache {
+  pub fn new(dir: &Path, size: u32) -> Self {
+    Self { dir: dir.to_path_buf(), size: size.max(1) }
+  }
+
+  // in the user's cache folder, or the temporary one when there is none
+  pub fn in_user_cache() -> Self {
+    let cache_home = env::var_os("XDG_CACHE_HOME").map(PathBuf::from)
+      .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+      .unwrap_or_else(env::temp_dir);
+    Self::new(&cache_home.join("image-processing").join("thumbnails"), THUMBNAIL_SIZE)
+  }
+
+  pub fn path_for(&self, path: &PathBuf) -> PathBuf {
+    let absolute = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let mut hasher = DefaultHasher::new();
+    absolute.hash(&mut hasher);
+    self.size.hash(&mut hasher);
+    self.dir.join(format!("{:016x}.png", hasher.finish()))
+  }
+
+  fn is_fresh(&self, path: &PathBuf, thumbnail_path: &PathBuf) -> bool {
+    let modified = |path: &PathBuf| fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+    match (modified(path), modified(thumbnail_path)) {
+      (Some(image), Some(thumbnail)) => thumbnail >= image,
+      _ => false
+    }
+  }
+
+  // The path of the thumbnail of the image at `path`, made first when it isn't
+  // cached yet or is older than the image. It's written under another name and
+  // then renamed, so that a thumbnail is never read half written.
+  pub fn thumbnail(&self, path: &PathBuf) -> Result<PathBuf, Error> {
+    let thumbnail_path = self.path_for(path);
+    if self.is_fresh(path, &thumbnail_path) {
+      return Ok(thumbnail_path);
+    }
+
+    let thumbnail = self.make_thumbnail(path).map_err(|error| format_err!("{}: {}", path.display(), error))?;
+    fs::create_dir_all(&self.dir)?;
+    let write = WRITES.fetch_add(1, Ordering::SeqCst);
+    let partial_path = thumbnail_path.with_extension(format!("{}-{}.partial.png", process::id(), write));
+    let saved = thumbnail.save(&partial_path).map_err(Error::from)
+      .and_then(|_| fs::rename(&partial_path, &thumbnail_path).map_err(Error::from));
+    if saved.is_err() {
+      let _ = fs::remove_file(&partial_path);
+    }
+    saved.map(|_| thumbnail_path)
+  }
+
+  fn make_thumbnail(&self, path: &PathBuf) -> Result<DynamicImage, Error> {
+    // large images are shrunk a block at a time, without reading them whole
+    let image = if TiledImage::should_tile(path) {
+      TiledImage::open(path)?.overview(self.size)?
+    }
+    else {
+      Image::open(path).map_err(err_msg)?.into_dynamic_image()
+    };
+    Ok(image.thumbnail(self.size, self.size))
+  }
+
+  // the cached thumbnail as it is, without checking that it's still fresh
+  pub fn cached(&self, path: &PathBuf) -> Option<DynamicImage> {
+    image::open(self.path_for(path)).ok()
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use image::image::{GenericImageView, RgbImage};
+
+  fn temporary_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("image-processing-{}-{}", process::id(), name));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+  }
+
+  #[test]
+  fn lists_the_images_next_to_one() {
+    let dir = temporary_dir("siblings");
+    for name in ["b.png", "a.JPG", "notes.txt", "c.pgm", "large.overview.png"].iter() {
+      fs::write(dir.join(name), b"").unwrap();
+    }
+    fs::create_dir_all(dir.join("folder.png")).unwrap();
+
+    let images = sibling_images(&dir.join("b.png")).unwrap();
+    assert_eq!(images, vec![dir.join("a.JPG"), dir.join("b.png"), dir.join("c.pgm")]);
+    fs::remove_dir_all(dir).unwrap();
+  }
+
+  #[test]
+  fn makes_thumbnails_once() {
+    let dir = temporary_dir("thumbnails");
+    let path = dir.join("wide.png");
+    DynamicImage::ImageRgb8(RgbImage::new(400, 100)).save(&path).unwrap();
+
+    let cache = ThumbnailCache::new(&dir.join("cache"), 64);
+    let thumbnail_path = cache.thumbnail(&path).unwrap();
+    assert_eq!(thumbnail_path, cache.path_for(&path));
+    assert_eq!(cache.cached(&path).unwrap().dimensions(), (64, 16));
+    // nothing is left behind but the thumbnail
+    assert_eq!(fs::read_dir(dir.join("cache")).unwrap().count(), 1);
+
+    // a fresh thumbnail is taken as it is
+    DynamicImage::ImageRgb8(RgbImage::new(3, 3)).save(&thumbnail_path).unwrap();
+    cache.thumbnail(&path).unwrap();
+    assert_eq!(cache.cached(&path).unwrap().dimensions(), (3, 3));
+
+    assert!(cache.thumbnail(&dir.join("missing.png")).is_err());
+    fs::remove_dir_all(dir).unwrap();
+  }
+}
@@ -0,0 +1,928 @@
+use std::fs::File;
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use byteorder::{BigEndian, ByteOrder, LittleEndian, WriteBytesExt};
+use failure::{err_msg, format_err, Error};
+use inflate;
+use lzw;
+
+// The parts of TIFF needed to read large images a tile at a time, which the
+// image crate can't do, and to write them back tiled, a page or several.
+
+const IMAGE_WIDTH: u16 = 256;
+const IMAGE_LENGTH: u16 = 257;
+const BITS_PER_SAMPLE: u16 = 258;
+const COMPRESSION: u16 = 259;
+const PHOTOMETRIC_INTERPRETATION: u16 = 262;
+const STRIP_OFFSETS: u16 = 273;
+const SAMPLES_PER_PIXEL: u16 = 277;
+const ROWS_PER_STRIP: u16 = 278;
+const STRIP_BYTE_COUNTS: u16 = 279;
+const PLANAR_CONFIGURATION: u16 = 284;
+const PREDICTOR: u16 = 317;
+const TILE_WIDTH: u16 = 322;
+const TILE_LENGTH: u16 = 323;
+const TILE_OFFSETS: u16 = 324;
+const TILE_BYTE_COUNTS: u16 = 325;
+const EXTRA_SAMPLES: u16 = 338;
+const SAMPLE_FORMAT: u16 = 339;
+
+const SHORT: u16 = 3;
+const LONG: u16 = 4;
+const LONG8: u16 = 16;
+
+// the most rows of an uncompressed strip read at once
+const SPLIT_ROWS: u32 = 64;
+// more pages than this are taken for a broken file
+const MAX_PAGES: usize = 1 << 16;
+// and so are blocks of more bytes than this once decoded
+const MAX_BLOCK_BYTES: u64 = 1 << 31;
+
+const BLACK_IS_ZERO: u64 = 1;
+const RGB: u64 = 2;
+const UNASSOCIATED_ALPHA: u64 = 2;
+const HORIZONTAL_DIFFERENCING: u64 = 2;
+const UNSIGNED: u64 = 1;
+const FLOATING_POINT: u64 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Compression {
+  None,
+  Lzw,
+  Deflate
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SampleFormat {
+  U8,
+  U16,
+  F32
+}
+
+impl SampleFormat {
+  pub fn bytes(&self) -> usize {
+    match *self {
+      SampleFormat::U8 => 1,
+      SampleFormat::U16 => 2,
+      SampleFormat::F32 => 4
+    }
+  }
+}
+
+// Where the samples of an image are in the file, and how to decode them.
+// Strips are read as blocks as wide as the image, tiles as blocks of their own
+// size.
+#[derive(Debug, Clone)]
+pub struct Layout {
+  pub width: u32,
+  pub height: u32,
+  pub channels: usize,
+  pub format: SampleFormat,
+  pub big_endian: bool,
+  pub block_width: u32,
+  pub block_height: u32,
+  pub tiled: bool,
+  pub offsets: Vec<u64>,
+  pub byte_counts: Vec<u64>,
+  pub compression: Compression,
+  pub predictor: bool
+}
+
+// reads numbers in the byte order of the file
+#[derive(Clone, Copy)]
+struct Fields {
+  big_endian: bool
+}
+
+impl Fields {
+  fn u16(&self, bytes: &[u8]) -> u16 {
+    if self.big_endian { BigEndian::read_u16(bytes) } else { LittleEndian::read_u16(bytes) }
+  }
+
+  fn u32(&self, bytes: &[u8]) -> u32 {
+    if self.big_endian { BigEndian::read_u32(bytes) } else { LittleEndian::read_u32(bytes) }
+  }
+
+  fn u64(&self, bytes: &[u8]) -> u64 {
+    if self.big_endian { BigEndian::read_u64(bytes) } else { LittleEndian::read_u64(bytes) }
+  }
+}
+
+// lengths come from the file, so they're checked against it before anything
+// is allocated
+fn read_at<R: Read + Seek>(file: &mut R, offset: u64, length: usize) -> Result<Vec<u8>, Error> {
+  let file_length = file.seek(SeekFrom::End(0))?;
+  if offset.checked_add(length as u64).is_none_or(|end| end > file_length) {
+    return Err(format_err!("the TIFF file has no {} bytes at {}", length, offset));
+  }
+  let mut bytes = vec![0; length];
+  file.seek(SeekFrom::Start(offset))?;
+  file.read_exact(&mut bytes)?;
+  Ok(bytes)
+}
+
+// the byte order, whether the file is a big TIFF and where its first directory
+// is
+fn read_header<R: Read + Seek>(file: &mut R) -> Result<(Fields, bool, u64), Error> {
+  let header = read_at(file, 0, 16).map_err(|_| err_msg("not a TIFF file"))?;
+  let fields = match &header[..2] {
+    b"II" => Fields { big_endian: false },
+    b"MM" => Fields { big_endian: true },
+    _ => return Err(err_msg("not a TIFF file"))
+  };
+  match fields.u16(&header[2..4]) {
+    42 => Ok((fields, false, fields.u32(&header[4..8]) as u64)),
+    43 => Ok((fields, true, fields.u64(&header[8..16]))),
+    _ => Err(err_msg("not a TIFF file"))
+  }
+}
+
+// The number of entries of a directory and where the offset of the next one
+// is. Entries hold their values in place when they fit in the last field.
+fn read_entry_count<R: Read + Seek>(file: &mut R, fields: Fields, big: bool, offset: u64) -> Result<(usize, u64), Error> {
+  let (count_size, entry_size) = if big { (8, 20) } else { (2, 12) };
+  let count = read_at(file, offset, count_size)?;
+  let count = if big { fields.u64(&count) } else { fields.u16(&count) as u64 } as usize;
+  let length = count.checked_mul(entry_size).ok_or_else(|| format_err!("the TIFF directory at {} is too large", offset))?;
+  Ok((count, offset + count_size as u64 + length as u64))
+}
+
+// Where the directory of every page is, following the links from one to the
+// next.
+fn directory_offsets<R: Read + Seek>(file: &mut R) -> Result<(Fields, bool, Vec<u64>), Error> {
+  let (fields, big, first) = read_header(file)?;
+  let mut offsets = Vec::new();
+  let mut offset = first;
+  while offset != 0 {
+    if offsets.contains(&offset) || offsets.len() >= MAX_PAGES {
+      return Err(err_msg("the pages of the TIFF file go round in circles"));
+    }
+    offsets.push(offset);
+    let (_, link) = read_entry_count(file, fields, big, offset)?;
+    let next = read_at(file, link, if big { 8 } else { 4 })?;
+    offset = if big { fields.u64(&next) } else { fields.u32(&next) as u64 };
+  }
+  if offsets.is_empty() {
+    return Err(err_msg("the TIFF file has no images"));
+  }
+  Ok((fields, big, offsets))
+}
+
+pub fn page_count<R: Read + Seek>(file: &mut R) -> Result<usize, Error> {
+  directory_offsets(file).map(|(_, _, offsets)| offsets.len())
+}
+
+impl Layout {
+  // Reads the layout of the first image of a classic or big TIFF file.
+  pub fn read<R: Read + Seek>(file: &mut R) -> Result<Self, Error> {
+    let (fields, big, first) = read_header(file)?;
+    Layout::read_directory(file, fields, big, first)
+  }
+
+  // the layouts of every page, in order
+  pub fn read_pages<R: Read + Seek>(file: &mut R) -> Result<Vec<Self>, Error> {
+    let (fields, big, offsets) = directory_offsets(file)?;
+    offsets.into_iter().map(|offset| Layout::read_directory(file, fields, big, offset)).collect()
+  }
+
+  fn read_directory<R: Read + Seek>(file: &mut R, fields: Fields, big: bool, offset: u64) -> Result<Self, Error> {
+    let (count_size, entry_size, inline_size) = if big { (8, 20, 8) } else { (2, 12, 4) };
+    let (count, _) = read_entry_count(file, fields, big, offset)?;
+    // read_entry_count made sure this doesn't overflow
+    let entries = read_at(file, offset + count_size as u64, count * entry_size)?;
+
+    let mut tags: Vec<(u16, Vec<u64>)> = Vec::new();
+    for entry in entries.chunks(entry_size) {
+      let tag = fields.u16(&entry[0..2]);
+      let field_type = fields.u16(&entry[2..4]);
+      let count = if big { fields.u64(&entry[4..12]) } else { fields.u32(&entry[4..8]) as u64 } as usize;
+      let value_size = match field_type {
+        1 | 2 | 6 | 7 => 1,
+        SHORT | 8 => 2,
+        LONG | 9 | 13 => 4,
+        LONG8 | 17 | 18 => 8,
+        // rationals and floats, none of which are needed
+        _ => continue
+      };
+
+      let length = count.checked_mul(value_size).ok_or_else(|| format_err!("tag {} has too many values", tag))?;
+      let value_field = &entry[entry_size - inline_size..];
+      let bytes = if length <= inline_size {
+        value_field[..length].to_vec()
+      }
+      else {
+        let offset = if big { fields.u64(value_field) } else { fields.u32(value_field) as u64 };
+        read_at(file, offset, length)?
+      };
+      let values = bytes.chunks(value_size).map(|value| match value_size {
+        1 => value[0] as u64,
+        2 => fields.u16(value) as u64,
+        4 => fields.u32(value) as u64,
+        _ => fields.u64(value)
+      }).collect();
+      tags.push((tag, values));
+    }
+
+    let values = |tag: u16| tags.iter().find(|&&(found, _)| found == tag).map(|(_, values)| values.clone());
+    let value = |tag: u16, default: Option<u64>| match values(tag) {
+      Some(ref values) if !values.is_empty() => Ok(values[0]),
+      _ => default.ok_or_else(|| format_err!("the TIFF file has no tag {}", tag))
+    };
+
+    let width = value(IMAGE_WIDTH, None)? as u32;
+    let height = value(IMAGE_LENGTH, None)? as u32;
+    let channels = value(SAMPLES_PER_PIXEL, Some(1))? as usize;
+    if !(1..=4).contains(&channels) {
+      return Err(format_err!("{} samples per pixel aren't supported", channels));
+    }
+    let bits = values(BITS_PER_SAMPLE).unwrap_or_else(|| vec![1]);
+    let first_bits = match bits.first() {
+      Some(&first_bits) => first_bits,
+      None => return Err(err_msg("the TIFF file gives no size for its samples"))
+    };
+    if bits.iter().any(|&other| other != first_bits) {
+      return Err(err_msg("samples of different sizes aren't supported"));
+    }
+    let format = match (first_bits, value(SAMPLE_FORMAT, Some(UNSIGNED))?) {
+      (8, UNSIGNED) => SampleFormat::U8,
+      (16, UNSIGNED) => SampleFormat::U16,
+      (32, FLOATING_POINT) => SampleFormat::F32,
+      (bits, _) => return Err(format_err!("{} bit samples of this format aren't supported", bits))
+    };
+    if value(PLANAR_CONFIGURATION, Some(1))? != 1 {
+      return Err(err_msg("samples stored in separate planes aren't supported"));
+    }
+    match value(PHOTOMETRIC_INTERPRETATION, None)? {
+      BLACK_IS_ZERO | RGB => (),
+      other => return Err(format_err!("photometric interpretation {} isn't supported", other))
+    }
+    let compression = match value(COMPRESSION, Some(1))? {
+      1 => Compression::None,
+      5 => Compression::Lzw,
+      8 | 32946 => Compression::Deflate,
+      other => return Err(format_err!("compression {} isn't supported", other))
+    };
+    let predictor = match value(PREDICTOR, Some(1))? {
+      1 => false,
+      HORIZONTAL_DIFFERENCING if format != SampleFormat::F32 => true,
+      other => return Err(format_err!("predictor {} isn't supported", other))
+    };
+
+    let tiled = values(TILE_OFFSETS).is_some();
+    let (block_width, block_height, offsets, byte_counts) = if tiled {
+      (value(TILE_WIDTH, None)? as u32, value(TILE_LENGTH, None)? as u32,
+       values(TILE_OFFSETS).unwrap(), values(TILE_BYTE_COUNTS).unwrap_or_default())
+    }
+    else {
+      let rows_per_strip = value(ROWS_PER_STRIP, Some(height as u64))?.min(height as u64) as u32;
+      (width, rows_per_strip,
+       values(STRIP_OFFSETS).ok_or_else(|| err_msg("the TIFF file has neither strips nor tiles"))?,
+       values(STRIP_BYTE_COUNTS).unwrap_or_default())
+    };
+
+    let mut layout = Layout {
+      width,
+      height,
+      channels,
+      format,
+      big_endian: fields.big_endian,
+      block_width: block_width.max(1),
+      block_height: block_height.max(1),
+      tiled,
+      offsets,
+      byte_counts,
+      compression,
+      predictor
+    };
+    layout.check_blocks()?;
+    if !layout.tiled && layout.compression == Compression::None && layout.block_height > SPLIT_ROWS {
+      layout.split_strips();
+    }
+    Ok(layout)
+  }
+
+  // Whole images are often stored as a single strip, which is read a few rows
+  // at a time when it isn't compressed.
+  fn split_strips(&mut self) {
+    let rows = (1..SPLIT_ROWS + 1).rev().find(|rows| self.block_height.is_multiple_of(*rows)).unwrap_or(1);
+    let row_length = (self.width as usize * self.channels * self.format.bytes()) as u64;
+
+    let mut offsets = Vec::new();
+    for (strip, &offset) in self.offsets.iter().enumerate().take(self.blocks_down() as usize) {
+      let first_row = strip as u32 * self.block_height;
+      for part in 0..self.block_height / rows {
+        if first_row + part * rows >= self.height {
+          break;
+        }
+        offsets.push(offset + (part * rows) as u64 * row_length);
+      }
+    }
+
+    self.block_height = rows;
+    self.offsets = offsets;
+    self.byte_counts = (0..self.offsets.len() as u32).map(|y| self.stored_rows(y) as u64 * row_length).collect();
+  }
+
+  // Blocks too large to decode, or more of them than the file has offsets for,
+  // aren't read. The blocks can then be numbered with a u32, and a block's
+  // length in bytes fits in a usize.
+  fn check_blocks(&self) -> Result<(), Error> {
+    let block_length = (self.block_width as u64).checked_mul(self.block_height as u64)
+      .and_then(|pixels| pixels.checked_mul((self.channels * self.format.bytes()) as u64));
+    if block_length.is_none_or(|length| length > MAX_BLOCK_BYTES) {
+      return Err(format_err!("blocks of {}x{} pixels are too large", self.block_width, self.block_height));
+    }
+    let blocks = self.blocks_across() as u64 * self.blocks_down() as u64;
+    if blocks > u32::MAX as u64 || (self.offsets.len() as u64) < blocks || (self.byte_counts.len() as u64) < blocks {
+      return Err(format_err!("the TIFF file should have {} blocks", blocks));
+    }
+    Ok(())
+  }
+
+  pub fn blocks_across(&self) -> u32 {
+    (self.width as u64).div_ceil(self.block_width as u64) as u32
+  }
+
+  pub fn blocks_down(&self) -> u32 {
+    (self.height as u64).div_ceil(self.block_height as u64) as u32
+  }
+
+  pub fn block_count(&self) -> usize {
+    self.blocks_across() as usize * self.blocks_down() as usize
+  }
+
+  // Tiles are padded to their full size at the image's edges, while the last
+  // strip only holds the rows left.
+  pub fn stored_rows(&self, block_y: u32) -> u32 {
+    if self.tiled { self.block_height } else { self.block_height.min(self.height - block_y * self.block_height) }
+  }
+
+  // the bytes of a block as they are in the file, for `decode`
+  pub fn block_bytes<R: Read + Seek>(&self, file: &mut R, index: usize) -> Result<Vec<u8>, Error> {
+    match (self.offsets.get(index), self.byte_counts.get(index)) {
+      (Some(&offset), Some(&count)) if index < self.block_count() => read_at(file, offset, count as usize),
+      _ => Err(format_err!("there is no block {}", index))
+    }
+  }
+
+  pub fn read_block<R: Read + Seek>(&self, file: &mut R, index: usize) -> Result<Vec<u8>, Error> {
+    let bytes = self.block_bytes(file, index)?;
+    self.decode(index, bytes)
+  }
+
+  // The samples of a block from its bytes in the file, samples of more than a
+  // byte in little endian order.
+  pub fn decode(&self, index: usize, bytes: Vec<u8>) -> Result<Vec<u8>, Error> {
+    let row_length = self.block_width as usize * self.channels * self.format.bytes();
+    let length = row_length * self.stored_rows(index as u32 / self.blocks_across()) as usize;
+
+    let mut samples = match self.compression {
+      Compression::None => bytes,
+      Compression::Deflate => inflate::inflate_bytes_zlib(&bytes).map_err(err_msg)?,
+      Compression::Lzw => {
+        let mut decoder = lzw::DecoderEarlyChange::new(lzw::MsbReader::new(), 8);
+        // grown as the codes come, the header alone not being trusted
+        let mut samples = Vec::with_capacity(length.min(bytes.len().saturating_mul(4)));
+        let mut read = 0;
+        while read < bytes.len() && samples.len() < length {
+          let (consumed, decoded) = decoder.decode_bytes(&bytes[read..])?;
+          if consumed == 0 {
+            break;
+          }
+          read += consumed;
+          samples.extend_from_slice(decoded);
+        }
+        samples
+      }
+    };
+    if samples.len() < length {
+      return Err(format_err!("block {} is truncated", index));
+    }
+    samples.truncate(length);
+
+    if self.big_endian {
+      for sample in samples.chunks_mut(self.format.bytes()) {
+        sample.reverse();
+      }
+    }
+
+    // each sample was stored as the difference with the one on its left
+    if self.predictor {
+      for row in samples.chunks_mut(row_length) {
+        match self.format {
+          SampleFormat::U16 => for i in self.channels..row.len() / 2 {
+            let left = LittleEndian::read_u16(&row[2 * (i - self.channels)..]);
+            let sample = LittleEndian::read_u16(&row[2 * i..]).wrapping_add(left);
+            LittleEndian::write_u16(&mut row[2 * i..], sample);
+          },
+          _ => for i in self.channels..row.len() {
+            row[i] = row[i].wrapping_add(row[i - self.channels]);
+          }
+        }
+      }
+    }
+    Ok(samples)
+  }
+}
+
+// Reads a whole image, its samples by row and in little endian order.
+pub fn read_image<R: Read + Seek>(file: &mut R) -> Result<(Layout, Vec<u8>), Error> {
+  let layout = Layout::read(file)?;
+  let samples = read_samples(file, &layout)?;
+  Ok((layout, samples))
+}
+
+// every page of the file, as `read_image` reads the first
+pub fn read_pages<R: Read + Seek>(file: &mut R) -> Result<Vec<(Layout, Vec<u8>)>, Error> {
+  Layout::read_pages(file)?.into_iter()
+  .map(|layout| read_samples(file, &layout).map(|samples| (layout, samples)))
+  .collect()
+}
+
+// the samples of the page `layout` describes, as `read_image` gives them
+pub fn read_samples<R: Read + Seek>(file: &mut R, layout: &Layout) -> Result<Vec<u8>, Error> {
+  let pixel_length = layout.channels * layout.format.bytes();
+  let row_length = layout.width as usize * pixel_length;
+  let block_row_length = layout.block_width as usize * pixel_length;
+
+  let length = row_length.checked_mul(layout.height as usize)
+    .ok_or_else(|| format_err!("a {}x{} image is too large to read whole", layout.width, layout.height))?;
+  let mut samples = vec![0u8; length];
+  let across = layout.blocks_across();
+  for index in 0..layout.block_count() {
+    let block = layout.read_block(file, index)?;
+
+    // tiles are padded at the edges of the image
+    let (left, top) = ((index as u32 % across) * layout.block_width, (index as u32 / across) * layout.block_height);
+    let length = layout.block_width.min(layout.width - left) as usize * pixel_length;
+    let rows = layout.block_height.min(layout.height - top) as usize;
+    for (row, source) in block.chunks(block_row_length).take(rows).enumerate() {
+      let start = (top as usize + row) * row_length + left as usize * pixel_length;
+      samples[start..start + length].copy_from_slice(&source[..length]);
+    }
+  }
+  Ok(samples)
+}
+
+// Writes a whole image as a tiled TIFF, from its samples by row and in little
+// endian order.
+pub fn write_image(path: &PathBuf, width: u32, height: u32, channels: usize, format: SampleFormat,
+                   samples: &[u8], tile_size: u32) -> Result<(), Error> {
+  write_pages(path, width, height, channels, format, &[samples], tile_size)
+}
+
+// Writes images of the same size and format as the pages of a tiled TIFF.
+pub fn write_pages(path: &PathBuf, width: u32, height: u32, channels: usize, format: SampleFormat,
+                   pages: &[&[u8]], tile_size: u32) -> Result<(), Error> {
+  let row_length = width as usize * channels * format.bytes();
+  if let Some(samples) = pages.iter().find(|samples| samples.len() != row_length * height as usize) {
+    return Err(format_err!("a {}x{} image can't have {} bytes of samples", width, height, samples.len()));
+  }
+  if pages.is_empty() {
+    return Err(err_msg("a TIFF file needs at least one page"));
+  }
+
+  let mut writer = TiffWriter::create_pages(path, width, height, channels, format, tile_size, pages.len())?;
+  for (page, samples) in pages.iter().enumerate() {
+    if page > 0 {
+      writer.next_page()?;
+    }
+    write_tiles(&mut writer, samples)?;
+  }
+  writer.finish()
+}
+
+fn write_tiles(writer: &mut TiffWriter, samples: &[u8]) -> Result<(), Error> {
+  let (width, height, tile_size) = (writer.width, writer.height, writer.tile_size);
+  let pixel_length = writer.channels * writer.format.bytes();
+  let row_length = width as usize * pixel_length;
+  let tile_row_length = tile_size as usize * pixel_length;
+  let tiles_across = width.div_ceil(tile_size);
+  let tiles_down = height.div_ceil(tile_size);
+  let mut tile = vec![0u8; writer.tile_length()];
+  for tile_y in 0..tiles_down {
+    for tile_x in 0..tiles_across {
+      // padded with zeros at the edges
+      for byte in tile.iter_mut() {
+        *byte = 0;
+      }
+      let (left, top) = (tile_x * tile_size, tile_y * tile_size);
+      let length = tile_size.min(width - left) as usize * pixel_length;
+      for row in 0..tile_size.min(height - top) as usize {
+        let start = (top as usize + row) * row_length + left as usize * pixel_length;
+        tile[row * tile_row_length..row * tile_row_length + length].copy_from_slice(&samples[start..start + length]);
+      }
+      writer.write_tile((tile_y * tiles_across + tile_x) as usize, &tile)?;
+    }
+  }
+  Ok(())
+}
+
+// Writes an uncompressed tiled TIFF, the tiles in any order, a page after the
+// other. Files too large for 32 bit offsets are written as big TIFFs.
+pub struct TiffWriter {
+  file: BufWriter<File>,
+  big: bool,
+  // where the offset of the next directory goes
+  link: u64,
+  width: u32,
+  height: u32,
+  channels: usize,
+  format: SampleFormat,
+  tile_size: u32,
+  offsets: Vec<u64>,
+  end: u64
+}
+
+impl TiffWriter {
+  pub fn create(path: &PathBuf, width: u32, height: u32, channels: usize, format: SampleFormat,
+                tile_size: u32) -> Result<Self, Error> {
+    TiffWriter::create_pages(path, width, height, channels, format, tile_size, 1)
+  }
+
+  pub fn create_pages(path: &PathBuf, width: u32, height: u32, channels: usize, format: SampleFormat,
+                      tile_size: u32, pages: usize) -> Result<Self, Error> {
+    if tile_size == 0 {
+      return Err(err_msg("tiles need at least a pixel"));
+    }
+    let tiles = (width.div_ceil(tile_size) * height.div_ceil(tile_size)) as u64;
+    let tile_length = tile_size as u64 * tile_size as u64 * channels as u64 * format.bytes() as u64;
+    let size = pages as u64 * (tiles * (tile_length + 8) + 4096);
+    let big = size >> 32 != 0;
+
+    let mut file = BufWriter::new(File::create(path)?);
+    // the offset of the directory is filled in once all the tiles are written
+    file.write_all(b"II")?;
+    if big {
+      file.write_u16::<LittleEndian>(43)?;
+      file.write_u16::<LittleEndian>(8)?;
+      file.write_u16::<LittleEndian>(0)?;
+      file.write_u64::<LittleEndian>(0)?;
+    }
+    else {
+      file.write_u16::<LittleEndian>(42)?;
+      file.write_u32::<LittleEndian>(0)?;
+    }
+
+    Ok(TiffWriter {
+      file,
+      big,
+      link: if big { 8 } else { 4 },
+      width,
+      height,
+      channels,
+      format,
+      tile_size,
+      offsets: vec![0; tiles as usize],
+      end: if big { 16 } else { 8 }
+    })
+  }
+
+  pub fn channels(&self) -> usize {
+    self.channels
+  }
+
+  pub fn format(&self) -> SampleFormat {
+    self.format
+  }
+
+  // in bytes
+  pub fn tile_length(&self) -> usize {
+    self.tile_size as usize * self.tile_size as usize * self.channels * self.format.bytes()
+  }
+
+  // The tiles are numbered by row, each holding `tile_length` bytes of samples,
+  // in little endian order when they are larger than a byte.
+  pub fn write_tile(&mut self, index: usize, samples: &[u8]) -> Result<(), Error> {
+    if samples.len() != self.tile_length() || index >= self.offsets.len() {
+      return Err(format_err!("tile {} doesn't fit the image", index));
+    }
+    self.file.write_all(samples)?;
+    self.offsets[index] = self.end;
+    self.end += samples.len() as u64;
+    Ok(())
+  }
+
+  // ends the page, the next one being written the same way
+  pub fn next_page(&mut self) -> Result<(), Error> {
+    self.write_directory()?;
+    for offset in self.offsets.iter_mut() {
+      *offset = 0;
+    }
+    Ok(())
+  }
+
+  pub fn finish(mut self) -> Result<(), Error> {
+    self.write_directory()?;
+    self.file.flush()?;
+    Ok(())
+  }
+
+  fn write_directory(&mut self) -> Result<(), Error> {
+    if let Some(missing) = self.offsets.iter().position(|&offset| offset == 0) {
+      return Err(format_err!("tile {} was never written", missing));
+    }
+
+    let tiles = self.offsets.len() as u64;
+    let offset_type = if self.big { LONG8 } else { LONG };
+    let mut entries: Vec<(u16, u16, Vec<u64>)> = vec![
+      (IMAGE_WIDTH, LONG, vec![self.width as u64]),
+      (IMAGE_LENGTH, LONG, vec![self.height as u64]),
+      (BITS_PER_SAMPLE, SHORT, vec![8 * self.format.bytes() as u64; self.channels]),
+      (COMPRESSION, SHORT, vec![1]),
+      (PHOTOMETRIC_INTERPRETATION, SHORT, vec![if self.channels < 3 { BLACK_IS_ZERO } else { RGB }]),
+      (SAMPLES_PER_PIXEL, SHORT, vec![self.channels as u64]),
+      (PLANAR_CONFIGURATION, SHORT, vec![1]),
+      (TILE_WIDTH, LONG, vec![self.tile_size as u64]),
+      (TILE_LENGTH, LONG, vec![self.tile_size as u64]),
+      (TILE_OFFSETS, offset_type, self.offsets.clone()),
+      (TILE_BYTE_COUNTS, offset_type, vec![self.tile_length() as u64; tiles as usize])
+    ];
+    if self.channels == 2 || self.channels == 4 {
+      entries.push((EXTRA_SAMPLES, SHORT, vec![UNASSOCIATED_ALPHA]));
+    }
+    let sample_format = if self.format == SampleFormat::F32 { FLOATING_POINT } else { UNSIGNED };
+    entries.push((SAMPLE_FORMAT, SHORT, vec![sample_format; self.channels]));
+
+    // values that don't fit in their entry go before the directory
+    let inline_size = if self.big { 8 } else { 4 };
+    let mut placed = Vec::with_capacity(entries.len());
+    for &(_, field_type, ref values) in entries.iter() {
+      let size = if field_type == SHORT { 2 } else if field_type == LONG { 4 } else { 8 };
+      if values.len() * size <= inline_size {
+        placed.push(None);
+        continue;
+      }
+      placed.push(Some(self.end));
+      for &value in values.iter() {
+        self.write_value(field_type, value)?;
+      }
+      self.end += (values.len() * size) as u64;
+    }
+
+    // directories start on a word boundary
+    if self.end % 2 == 1 {
+      self.file.write_u8(0)?;
+      self.end += 1;
+    }
+    let directory = self.end;
+    if self.big {
+      self.file.write_u64::<LittleEndian>(entries.len() as u64)?;
+    }
+    else {
+      self.file.write_u16::<LittleEndian>(entries.len() as u16)?;
+    }
+    for (&(tag, field_type, ref values), placed) in entries.iter().zip(placed.iter()) {
+      self.file.write_u16::<LittleEndian>(tag)?;
+      self.file.write_u16::<LittleEndian>(field_type)?;
+      self.write_offset(values.len() as u64)?;
+      match *placed {
+        Some(offset) => self.write_offset(offset)?,
+        None => {
+          let size = if field_type == SHORT { 2 } else if field_type == LONG { 4 } else { 8 };
+          for &value in values.iter() {
+            self.write_value(field_type, value)?;
+          }
+          for _ in values.len() * size..inline_size {
+            self.file.write_u8(0)?;
+          }
+        }
+      }
+    }
+    self.write_offset(0)?;
+
+    // links the directory from the header or the page before
+    let (count_size, entry_size, offset_size) = if self.big { (8, 20, 8) } else { (2, 12, 4) };
+    self.file.seek(SeekFrom::Start(self.link))?;
+    self.write_offset(directory)?;
+    self.file.seek(SeekFrom::End(0))?;
+    self.link = directory + count_size + (entries.len() * entry_size) as u64;
+    self.end = self.link + offset_size;
+    Ok(())
+  }
+
+  fn write_value(&mut self, field_type: u16, value: u64) -> Result<(), Error> {
+    match field_type {
+      SHORT => self.file.write_u16::<LittleEndian>(value as u16)?,
+      LONG => self.file.write_u32::<LittleEndian>(value as u32)?,
+      _ => self.file.write_u64::<LittleEndian>(value)?
+    }
+    Ok(())
+  }
+
+  // counts and offsets are 64 bit in big TIFFs
+  fn write_offset(&mut self, value: u64) -> Result<(), Error> {
+    if self.big {
+      self.file.write_u64::<LittleEndian>(value)?;
+    }
+    else {
+      self.file.write_u32::<LittleEndian>(value as u32)?;
+    }
+    Ok(())
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::env;
+  use std::fs;
+  use std::io::Cursor;
+  use std::process;
+  extern crate deflate;
+
+  // A gray image stored in strips, with the arrays of the strips after them and
+  // the directory last.
+  fn strip_tiff(width: u32, height: u32, rows_per_strip: u32, compression: u16, predictor: u16, strips: &[Vec<u8>]) -> Vec<u8> {
+    let mut file = b"II".to_vec();
+    file.write_u16::<LittleEndian>(42).unwrap();
+    file.write_u32::<LittleEndian>(0).unwrap();
+
+    let mut offsets = Vec::new();
+    for strip in strips {
+      offsets.push(file.len() as u32);
+      file.extend_from_slice(strip);
+    }
+    let arrays = file.len() as u32;
+    for &offset in &offsets {
+      file.write_u32::<LittleEndian>(offset).unwrap();
+    }
+    for strip in strips {
+      file.write_u32::<LittleEndian>(strip.len() as u32).unwrap();
+    }
+
+    let (offsets_value, counts_value) = if strips.len() == 1 {
+      (offsets[0], strips[0].len() as u32)
+    }
+    else {
+      (arrays, arrays + 4 * strips.len() as u32)
+    };
+    let entries = [
+      (IMAGE_WIDTH, LONG, 1, width),
+      (IMAGE_LENGTH, LONG, 1, height),
+      (BITS_PER_SAMPLE, SHORT, 1, 8),
+      (COMPRESSION, SHORT, 1, compression as u32),
+      (PHOTOMETRIC_INTERPRETATION, SHORT, 1, 1),
+      (STRIP_OFFSETS, LONG, strips.len() as u32, offsets_value),
+      (SAMPLES_PER_PIXEL, SHORT, 1, 1),
+      (ROWS_PER_STRIP, LONG, 1, rows_per_strip),
+      (STRIP_BYTE_COUNTS, LONG, strips.len() as u32, counts_value),
+      (PREDICTOR, SHORT, 1, predictor as u32)
+    ];
+    let directory = file.len() as u32;
+    file.write_u16::<LittleEndian>(entries.len() as u16).unwrap();
+    for &(tag, field_type, count, value) in entries.iter() {
+      file.write_u16::<LittleEndian>(tag).unwrap();
+      file.write_u16::<LittleEndian>(field_type).unwrap();
+      file.write_u32::<LittleEndian>(count).unwrap();
+      if field_type == SHORT {
+        file.write_u16::<LittleEndian>(value as u16).unwrap();
+        file.write_u16::<LittleEndian>(0).unwrap();
+      }
+      else {
+        file.write_u32::<LittleEndian>(value).unwrap();
+      }
+    }
+    file.write_u32::<LittleEndian>(0).unwrap();
+    LittleEndian::write_u32(&mut file[4..8], directory);
+    file
+  }
+
+  fn decode_all(file: Vec<u8>) -> (Layout, Vec<u8>) {
+    let mut file = Cursor::new(file);
+    let layout = Layout::read(&mut file).unwrap();
+    let mut samples = Vec::new();
+    for index in 0..layout.offsets.len() {
+      samples.extend(layout.read_block(&mut file, index).unwrap());
+    }
+    (layout, samples)
+  }
+
+  // literal codes only, starting over before the codes grow past 9 bits
+  fn lzw_literals(samples: &[u8]) -> Vec<u8> {
+    let mut codes = vec![256];
+    for run in samples.chunks(200) {
+      codes.extend(run.iter().map(|&sample| sample as u32));
+      codes.push(256);
+    }
+    codes.push(257);
+
+    let (mut bits, mut count, mut bytes) = (0u32, 0, Vec::new());
+    for code in codes {
+      bits = (bits << 9) | code;
+      count += 9;
+      while count >= 8 {
+        bytes.push((bits >> (count - 8)) as u8);
+        count -= 8;
+      }
+      bits &= (1 << count) - 1;
+    }
+    if count > 0 {
+      bytes.push((bits << (8 - count)) as u8);
+    }
+    bytes
+  }
+
+  fn gradient(width: u32, height: u32) -> Vec<u8> {
+    (0..width * height).map(|i| (i % width * 3 + i / width * 5) as u8).collect()
+  }
+
+  #[test]
+  fn reads_deflated_strips_with_a_predictor() {
+    let samples = gradient(5, 3);
+    let strips: Vec<Vec<u8>> = samples.chunks(10).map(|strip| {
+      let mut differences = strip.to_vec();
+      for row in differences.chunks_mut(5) {
+        for i in (1..row.len()).rev() {
+          row[i] = row[i].wrapping_sub(row[i - 1]);
+        }
+      }
+      deflate::deflate_bytes_zlib(&differences)
+    }).collect();
+
+    let (layout, decoded) = decode_all(strip_tiff(5, 3, 2, 8, 2, &strips));
+    assert_eq!((layout.blocks_across(), layout.blocks_down()), (1, 2));
+    assert_eq!(decoded, samples);
+  }
+
+  #[test]
+  fn reads_lzw_strips() {
+    let samples = gradient(30, 20);
+    let (layout, decoded) = decode_all(strip_tiff(30, 20, 20, 5, 1, &[lzw_literals(&samples)]));
+    assert_eq!(layout.compression, Compression::Lzw);
+    assert_eq!(decoded, samples);
+  }
+
+  #[test]
+  fn reads_long_uncompressed_strips_a_few_rows_at_a_time() {
+    let samples = gradient(3, 200);
+    let (layout, decoded) = decode_all(strip_tiff(3, 200, 200, 1, 1, ::std::slice::from_ref(&samples)));
+    assert_eq!((layout.block_height, layout.offsets.len()), (50, 4));
+    assert_eq!(decoded, samples);
+  }
+
+  #[test]
+  fn refuses_strips_past_the_end_of_the_file() {
+    let samples = gradient(5, 3);
+    let mut file = strip_tiff(5, 3, 3, 8, 1, &[deflate::deflate_bytes_zlib(&samples)]);
+    // the value of the strip byte counts, the ninth entry of the directory
+    let directory = LittleEndian::read_u32(&file[4..8]) as usize;
+    LittleEndian::write_u32(&mut file[directory + 2 + 8 * 12 + 8..], u32::MAX);
+
+    assert!(read_image(&mut Cursor::new(file)).is_err());
+  }
+
+  #[test]
+  fn refuses_samples_without_a_size() {
+    let mut file = strip_tiff(5, 3, 3, 1, 1, &[gradient(5, 3)]);
+    // no values for the bits per sample, the third entry of the directory
+    let directory = LittleEndian::read_u32(&file[4..8]) as usize;
+    LittleEndian::write_u32(&mut file[directory + 2 + 2 * 12 + 4..], 0);
+
+    assert!(Layout::read(&mut Cursor::new(file)).is_err());
+  }
+
+  #[test]
+  fn refuses_more_blocks_than_offsets_and_oversized_blocks() {
+    let (width, height) = (5, 3);
+    let mut file = Cursor::new(strip_tiff(width, height, 3, 1, 1, &[gradient(width, height)]));
+    let mut layout = Layout::read(&mut file).unwrap();
+
+    // 65536x65536 one pixel tiles would wrap the count of blocks to 0
+    layout.width = 1 << 16;
+    layout.height = 1 << 16;
+    layout.block_width = 1;
+    layout.block_height = 1;
+    assert!(layout.check_blocks().is_err());
+    assert!(layout.block_bytes(&mut file, 1 << 20).is_err());
+
+    layout.block_width = 1 << 16;
+    layout.block_height = 1 << 16;
+    assert!(layout.check_blocks().is_err());
+  }
+
+  #[test]
+  fn refuses_empty_tiles() {
+    let path = env::temp_dir().join(format!("image-processing-{}-empty-tiles.tif", process::id()));
+    assert!(TiffWriter::create(&path, 4, 4, 1, SampleFormat::U8, 0).is_err());
+  }
+
+  #[test]
+  fn writes_and_reads_several_pages() {
+    let path = env::temp_dir().join(format!("image-processing-{}-pages.tif", process::id()));
+    let pages: Vec<Vec<u8>> = (0..3u8).map(|page| (0..300 * 20 * 2).map(|i| (i as u8).wrapping_add(page)).collect()).collect();
+    let borrowed: Vec<&[u8]> = pages.iter().map(|page| page.as_slice()).collect();
+    write_pages(&path, 300, 20, 2, SampleFormat::U8, &borrowed, 256).unwrap();
+
+    let mut file = File::open(&path).unwrap();
+    assert_eq!(page_count(&mut file).unwrap(), 3);
+    let read = read_pages(&mut file).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(read.len(), 3);
+    for ((layout, samples), page) in read.iter().zip(pages.iter()) {
+      assert_eq!((layout.width, layout.height, layout.channels), (300, 20, 2));
+      assert_eq!(samples, page);
+    }
+  }
+}
@@ -0,0 +1,502 @@
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use failure::{err_msg, format_err, Error};
+use rayon::prelude::*;
+
+use image::{equalization_lut_u8, histogram_u8, ColorIntensityBuckets, Image};
+use image::image::{DynamicImage, GenericImageView, ImageBuffer};
+use pipeline::Pipeline;
+use tiff::{write_image, Layout, SampleFormat, TiffWriter};
+
+pub const TILE_SIZE: u32 = 256;
+// images from this many pixels on are processed a tile at a time
+pub const LARGE_IMAGE_PIXELS: u64 = 1 << 26;
+// the largest side of the overviews shown in place of large images
+pub const OVERVIEW_SIZE: u32 = 2048;
+// how much of the decoded blocks is kept around
+const CACHE_BYTES: usize = 64 << 20;
+
+// An 8 bit TIFF image read a block at a time as the blocks are needed, so that
+// it never has to fit in memory as a whole.
+pub struct TiledImage {
+  path: PathBuf,
+  layout: Layout,
+  file: Mutex<File>,
+  // the most recently used last
+  cache: Mutex<Vec<(usize, Arc<Vec<u8>>)>>
+}
+
+impl TiledImage {
+  pub fn open(path: &PathBuf) -> Result<Self, Error> {
+    let mut file = File::open(path).map_err(|error| format_err!("{}: {}", path.display(), error))?;
+    let layout = Layout::read(&mut file).map_err(|error| format_err!("{}: {}", path.display(), error))?;
+    if layout.format != SampleFormat::U8 {
+      return Err(format_err!("{}: only 8 bit images are read a tile at a time", path.display()));
+    }
+    Ok(Self {
+      path: path.to_path_buf(),
+      layout,
+      file: Mutex::new(file),
+      cache: Mutex::new(Vec::new())
+    })
+  }
+
+  // 8 bit TIFF images too large to load at once, and tiled ones, which the
+  // image crate can't read.
+  pub fn should_tile(path: &PathBuf) -> bool {
+    match File::open(path).map_err(Error::from).and_then(|mut file| Layout::read(&mut file)) {
+      Ok(ref layout) if layout.format == SampleFormat::U8 =>
+        layout.tiled || layout.width as u64 * layout.height as u64 >= LARGE_IMAGE_PIXELS,
+      _ => false
+    }
+  }
+
+  pub fn get_image_path(&self) -> PathBuf {self.path.clone()}
+
+  pub fn dimensions(&self) -> (u32, u32) {
+    (self.layout.width, self.layout.height)
+  }
+
+  pub fn channels(&self) -> usize {
+    self.layout.channels
+  }
+
+  // the decoded samples of a block, from the cache when they are still there
+  fn block(&self, index: usize) -> Result<Arc<Vec<u8>>, Error> {
+    {
+      let mut cache = self.cache.lock().map_err(|error| format_err!("{}", error))?;
+      if let Some(position) = cache.iter().position(|&(cached, _)| cached == index) {
+        let entry = cache.remove(position);
+        let samples = entry.1.clone();
+        cache.push(entry);
+        return Ok(samples);
+      }
+    }
+
+    let bytes = {
+      let mut file = self.file.lock().map_err(|error| format_err!("{}", error))?;
+      self.layout.block_bytes(&mut *file, index)?
+    };
+    let samples = Arc::new(self.layout.decode(index, bytes)?);
+
+    let mut cache = self.cache.lock().map_err(|error| format_err!("{}", error))?;
+    if !cache.iter().any(|&(cached, _)| cached == index) {
+      cache.push((index, samples.clone()));
+    }
+    let mut cached_bytes: usize = cache.iter().map(|(_, samples)| samples.len()).sum();
+    while cached_bytes > CACHE_BYTES && cache.len() > 1 {
+      cached_bytes -= cache.remove(0).1.len();
+    }
+    Ok(samples)
+  }
+
+  // the columns and rows of a block within the image, tiles being padded at its
+  // edges
+  fn block_size(&self, index: usize) -> (u32, u32) {
+    let layout = &self.layout;
+    let across = layout.blocks_across();
+    let (block_x, block_y) = (index as u32 % across, index as u32 / across);
+    (layout.block_width.min(layout.width - block_x * layout.block_width),
+     layout.block_height.min(layout.height - block_y * layout.block_height))
+  }
+
+  fn block_count(&self) -> usize {
+    self.layout.block_count()
+  }
+
+  // the `width` by `height` pixels from (x, y), which have to be within the
+  // image
+  pub fn read_region(&self, x: u32, y: u32, width: u32, height: u32) -> Result<DynamicImage, Error> {
+    let layout = &self.layout;
+    if x as u64 + width as u64 > layout.width as u64 || y as u64 + height as u64 > layout.height as u64 {
+      return Err(format_err!("the region at ({}, {}) doesn't fit in the image", x, y));
+    }
+
+    let channels = layout.channels;
+    let (block_width, block_height) = (layout.block_width, layout.block_height);
+    let mut samples = vec![0u8; width as usize * height as usize * channels];
+    if width > 0 && height > 0 {
+      for block_y in y / block_height..(y + height - 1) / block_height + 1 {
+        for block_x in x / block_width..(x + width - 1) / block_width + 1 {
+          let block = self.block((block_y * layout.blocks_across() + block_x) as usize)?;
+
+          // the part of the region within the block
+          let left = x.max(block_x * block_width);
+          let right = (x + width).min((block_x + 1) * block_width);
+          let top = y.max(block_y * block_height);
+          let bottom = (y + height).min((block_y + 1) * block_height);
+          let length = (right - left) as usize * channels;
+          for row in top..bottom {
+            let from = ((row - block_y * block_height) as usize * block_width as usize
+                        + (left - block_x * block_width) as usize) * channels;
+            let to = ((row - y) as usize * width as usize + (left - x) as usize) * channels;
+            samples[to..to + length].copy_from_slice(&block[from..from + length]);
+          }
+        }
+      }
+    }
+    Ok(to_dynamic_image(width, height, channels, samples))
+  }
+
+  // The whole image shrunk by an integer factor to fit within `max_size`, each
+  // pixel the average of those it covers.
+  pub fn overview(&self, max_size: u32) -> Result<DynamicImage, Error> {
+    let (width, height) = self.dimensions();
+    let max_size = max_size.max(1);
+    let factor = width.max(height).div_ceil(max_size).max(1);
+    let (overview_width, overview_height) = (width.div_ceil(factor), height.div_ceil(factor));
+
+    let channels = self.layout.channels;
+    let (block_width, block_height) = (self.layout.block_width, self.layout.block_height);
+    let mut sums = vec![0u64; overview_width as usize * overview_height as usize * channels];
+    let mut counts = vec![0u64; overview_width as usize * overview_height as usize];
+    for index in 0..self.block_count() {
+      let block = self.block(index)?;
+      let (columns, rows) = self.block_size(index);
+      let across = self.layout.blocks_across();
+      let (left, top) = ((index as u32 % across) * block_width, (index as u32 / across) * block_height);
+
+      for row in 0..rows {
+        let y = ((top + row) / factor) as usize;
+        for column in 0..columns {
+          let pixel = y * overview_width as usize + ((left + column) / factor) as usize;
+          let from = (row as usize * block_width as usize + column as usize) * channels;
+          counts[pixel] += 1;
+          for channel in 0..channels {
+            sums[pixel * channels + channel] += block[from + channel] as u64;
+          }
+        }
+      }
+    }
+
+    let samples = sums.iter().enumerate().map(|(i, &sum)| {
+      let count = counts[i / channels].max(1);
+      ((sum + count / 2) / count) as u8
+    }).collect();
+    Ok(to_dynamic_image(overview_width, overview_height, channels, samples))
+  }
+
+  // the histogram of one channel over the whole image
+  pub fn histogram(&self, channel: usize) -> Result<ColorIntensityBuckets, Error> {
+    let channels = self.layout.channels;
+    let row_length = self.layout.block_width as usize * channels;
+
+    (0..self.block_count()).into_par_iter().map(|index| {
+      let block = self.block(index)?;
+      let (columns, rows) = self.block_size(index);
+      let mut histogram = [0; 256];
+      for row in block.chunks(row_length).take(rows as usize) {
+        let counts = histogram_u8(&row[..columns as usize * channels], channels, channel);
+        for (total, count) in histogram.iter_mut().zip(counts.iter()) {
+          *total += count;
+        }
+      }
+      Ok(histogram)
+    })
+    .try_reduce(|| [0; 256], |mut total, counts| {
+      for (total, count) in total.iter_mut().zip(counts.iter()) {
+        *total += count;
+      }
+      Ok(total)
+    })
+  }
+
+  // Runs `filter` over the image a tile at a time, writing what it returns as a
+  // tiled TIFF to `output`. Tiles come with `halo` more pixels on every side
+  // where the image has them, for filters looking at the neighbours, and are
+  // cropped back once filtered. The tiles of a row are filtered in parallel.
+  pub fn map_tiles<F>(&self, output: &PathBuf, halo: u32, filter: F) -> Result<TiledImage, Error>
+    where F: Fn(&Image) -> Result<DynamicImage, Error> + Sync {
+
+    let (width, height) = self.dimensions();
+    let tiles_across = width.div_ceil(TILE_SIZE);
+    let tiles_down = height.div_ceil(TILE_SIZE);
+
+    // created once the first tile tells how many channels the result has
+    let mut writer: Option<TiffWriter> = None;
+    for tile_y in 0..tiles_down {
+      let tiles = (0..tiles_across).into_par_iter()
+      .map(|tile_x| self.filter_tile(tile_x, tile_y, halo, &filter))
+      .collect::<Result<Vec<DynamicImage>, Error>>()?;
+
+      for (tile_x, tile) in tiles.iter().enumerate() {
+        if writer.is_none() {
+          writer = Some(TiffWriter::create(output, width, height, channels_of(tile), SampleFormat::U8, TILE_SIZE)?);
+        }
+        let writer = writer.as_mut().unwrap();
+        let samples = padded(tile, writer.channels());
+        writer.write_tile((tile_y * tiles_across) as usize + tile_x, &samples)?;
+      }
+    }
+
+    match writer {
+      Some(writer) => writer.finish()?,
+      None => return Err(err_msg("the image is empty"))
+    }
+    TiledImage::open(output)
+  }
+
+  fn filter_tile<F>(&self, tile_x: u32, tile_y: u32, halo: u32, filter: &F) -> Result<DynamicImage, Error>
+    where F: Fn(&Image) -> Result<DynamicImage, Error> {
+
+    let (width, height) = self.dimensions();
+    let (x, y) = (tile_x * TILE_SIZE, tile_y * TILE_SIZE);
+    let (tile_width, tile_height) = (TILE_SIZE.min(width - x), TILE_SIZE.min(height - y));
+    let (left, top) = (x.saturating_sub(halo), y.saturating_sub(halo));
+    let right = (x as u64 + tile_width as u64 + halo as u64).min(width as u64) as u32;
+    let bottom = (y as u64 + tile_height as u64 + halo as u64).min(height as u64) as u32;
+
+    let region = Image::new(&self.path, self.read_region(left, top, right - left, bottom - top)?);
+    let mut filtered = filter(&region)?;
+    if filtered.dimensions() != (right - left, bottom - top) {
+      return Err(err_msg("operations changing the size of the image can't run a tile at a time"));
+    }
+    Ok(filtered.crop(x - left, y - top, tile_width, tile_height))
+  }
+
+  // equalizes over the histogram of the whole image, like
+  // `Image::equalize_histogram`
+  pub fn equalize_histogram(&self, output: &PathBuf) -> Result<TiledImage, Error> {
+    let (width, height) = self.dimensions();
+    let lookup_table = equalization_lut_u8(&self.histogram(0)?, width as usize * height as usize);
+    self.map_tiles(output, 0, |tile| Ok(tile.equalize_with(&lookup_table)))
+  }
+}
+
+// Saves an image as a tiled TIFF, which `TiledImage` reads back.
+pub fn save_tiled(image: &DynamicImage, path: &PathBuf) -> Result<PathBuf, Error> {
+  let (width, height) = image.dimensions();
+  let channels = channels_of(image);
+  write_image(path, width, height, channels, SampleFormat::U8, &samples_with(image, channels), TILE_SIZE)?;
+  Ok(path.to_path_buf())
+}
+
+// Where the overview of a large image goes, so that saving the overview never
+// overwrites the image itself.
+pub fn overview_path(path: &Path) -> PathBuf {
+  path.with_extension("overview.png")
+}
+
+// Replays a recipe on a TIFF image a tile at a time, saving the result as a
+// tiled TIFF. Every step but the last writes to a temporary file, removed once
+// the next step has read it. Steps restricted to a selection, or needing the
+// whole image at once, can't be replayed that way.
+pub fn apply_tiled(pipeline: &Pipeline, input: &PathBuf, output: &PathBuf) -> Result<PathBuf, Error> {
+  let is_tiff = output.extension().and_then(|extension| extension.to_str())
+  .map(|extension| extension.eq_ignore_ascii_case("tif") || extension.eq_ignore_ascii_case("tiff"))
+  .unwrap_or(false);
+  if !is_tiff {
+    return Err(format_err!("{}: large images can only be saved as TIFF", output.display()));
+  }
+  if input == output {
+    return Err(format_err!("{}: large images can't be overwritten in place", output.display()));
+  }
+
+  // the halo of every step, None for equalizing, checked before anything runs
+  let mut halos = Vec::with_capacity(pipeline.steps.len());
+  for step in &pipeline.steps {
+    if step.selection.is_some() {
+      return Err(err_msg("steps restricted to a selection can't run a tile at a time"));
+    }
+    if step.operation.is_equalization() {
+      halos.push(None);
+      continue;
+    }
+    match step.operation.halo()? {
+      Some(halo) => halos.push(Some(halo)),
+      None => return Err(format_err!("{:?} needs the whole image and can't run a tile at a time", step.operation))
+    }
+  }
+
+  let mut temporary: Vec<PathBuf> = Vec::new();
+  let result = replay_tiles(pipeline, &halos, input, output, &mut temporary);
+  for path in temporary {
+    let _ = fs::remove_file(path);
+  }
+  result.map(|_| output.to_path_buf())
+}
+
+fn replay_tiles(pipeline: &Pipeline, halos: &[Option<u32>], input: &PathBuf, output: &PathBuf,
+                temporary: &mut Vec<PathBuf>) -> Result<(), Error> {
+  let mut current = TiledImage::open(input)?;
+  if pipeline.steps.is_empty() {
+    current.map_tiles(output, 0, |tile| Ok(tile.as_dynamic_image().clone()))?;
+    return Ok(());
+  }
+
+  for (i, (step, halo)) in pipeline.steps.iter().zip(halos.iter()).enumerate() {
+    let path = if i + 1 == pipeline.steps.len() {
+      output.to_path_buf()
+    }
+    else {
+      let path = output.with_extension(format!("step{}.tif", i + 1));
+      temporary.push(path.clone());
+      path
+    };
+
+    current = match *halo {
+      Some(halo) => current.map_tiles(&path, halo, |tile| step.operation.apply(tile))?,
+      None => current.equalize_histogram(&path)?
+    };
+  }
+  Ok(())
+}
+
+fn channels_of(image: &DynamicImage) -> usize {
+  match *image {
+    DynamicImage::ImageLuma8(_) => 1,
+    DynamicImage::ImageLumaA8(_) => 2,
+    DynamicImage::ImageRgb8(_) => 3,
+    _ => 4
+  }
+}
+
+fn to_dynamic_image(width: u32, height: u32, channels: usize, samples: Vec<u8>) -> DynamicImage {
+  match channels {
+    1 => DynamicImage::ImageLuma8(ImageBuffer::from_raw(width, height, samples).unwrap()),
+    2 => DynamicImage::ImageLumaA8(ImageBuffer::from_raw(width, height, samples).unwrap()),
+    3 => DynamicImage::ImageRgb8(ImageBuffer::from_raw(width, height, samples).unwrap()),
+    _ => DynamicImage::ImageRgba8(ImageBuffer::from_raw(width, height, samples).unwrap())
+  }
+}
+
+fn samples_with(image: &DynamicImage, channels: usize) -> Vec<u8> {
+  match channels {
+    1 => image.to_luma().into_raw(),
+    2 => image.to_luma_alpha().into_raw(),
+    3 => image.to_rgb().into_raw(),
+    _ => image.to_rgba().into_raw()
+  }
+}
+
+// the samples of a tile with the given number of channels, padded with zeros to
+// the full tile size
+fn padded(tile: &DynamicImage, channels: usize) -> Vec<u8> {
+  let samples = samples_with(tile, channels);
+  let (width, height) = tile.dimensions();
+  if width == TILE_SIZE && height == TILE_SIZE {
+    return samples;
+  }
+
+  let row_length = TILE_SIZE as usize * channels;
+  let mut padded = vec![0u8; row_length * TILE_SIZE as usize];
+  if width > 0 {
+    for (row, source) in samples.chunks(width as usize * channels).enumerate() {
+      padded[row * row_length..row * row_length + source.len()].copy_from_slice(source);
+    }
+  }
+  padded
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::env;
+  use std::process;
+  use image::image::{GrayImage, Luma, Rgb, RgbImage};
+  use pipeline::Operation;
+  use operations::Value;
+  use fft::{FilterBand, FilterShape, FrequencyFilter};
+
+  fn temporary_path(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("image-processing-{}-{}.tif", process::id(), name))
+  }
+
+  // spans partial tiles on the right and at the bottom
+  fn scan() -> DynamicImage {
+    DynamicImage::ImageRgb8(RgbImage::from_fn(600, 300, |x, y| {
+      let level = ((x * 7 + y * 13 + (x * y) % 11) % 200 + 20) as u8;
+      Rgb([level, level / 2, 255 - level])
+    }))
+  }
+
+  fn read_all(image: &TiledImage) -> DynamicImage {
+    let (width, height) = image.dimensions();
+    image.read_region(0, 0, width, height).unwrap()
+  }
+
+  #[test]
+  fn regions_read_back_what_was_saved() {
+    let path = temporary_path("regions");
+    let mut scan = scan();
+    save_tiled(&scan, &path).unwrap();
+
+    let tiled = TiledImage::open(&path).unwrap();
+    assert_eq!(tiled.dimensions(), (600, 300));
+    assert!(TiledImage::should_tile(&path));
+    let region = tiled.read_region(250, 10, 300, 270).unwrap();
+    assert_eq!(region.raw_pixels(), scan.crop(250, 10, 300, 270).raw_pixels());
+    assert!(tiled.read_region(500, 0, 101, 1).is_err());
+    fs::remove_file(path).unwrap();
+  }
+
+  #[test]
+  fn filters_a_tile_at_a_time_like_on_the_whole_image() {
+    let (input, output) = (temporary_path("filter-input"), temporary_path("filter-output"));
+    let scan = scan();
+    save_tiled(&scan, &input).unwrap();
+    let image = Image::new(&input, scan);
+    let tiled = TiledImage::open(&input).unwrap();
+
+    let denoised = tiled.map_tiles(&output, 2, |tile| Ok(tile.denoise(2))).unwrap();
+    assert_eq!(read_all(&denoised).raw_pixels(), image.denoise(2).raw_pixels());
+
+    let edges = tiled.map_tiles(&output, 1, |tile| Ok(tile.detect_edges(20))).unwrap();
+    assert_eq!(read_all(&edges).raw_pixels(), image.detect_edges(20).raw_pixels());
+
+    let equalized = tiled.equalize_histogram(&output).unwrap();
+    assert_eq!(read_all(&equalized).raw_pixels(), image.equalize_histogram().raw_pixels());
+    fs::remove_file(input).unwrap();
+    fs::remove_file(output).unwrap();
+  }
+
+  #[test]
+  fn overviews_average_the_pixels() {
+    let path = temporary_path("overview");
+    let image = DynamicImage::ImageLuma8(GrayImage::from_fn(300, 10, |x, _| Luma([(x % 4) as u8 * 10])));
+    save_tiled(&image, &path).unwrap();
+
+    let overview = TiledImage::open(&path).unwrap().overview(75).unwrap();
+    assert_eq!(overview.dimensions(), (75, 3));
+    // the last row of the overview covers two rows of the image only
+    assert!(overview.to_luma().pixels().all(|pixel| pixel[0] == 15));
+    fs::remove_file(path).unwrap();
+  }
+
+  #[test]
+  fn replays_recipes_a_tile_at_a_time() {
+    let (input, output) = (temporary_path("replay-input"), temporary_path("replay-output"));
+    let scan = scan();
+    save_tiled(&scan, &input).unwrap();
+
+    let mut pipeline = Pipeline::new();
+    pipeline.record(Operation::registered("equalize_histogram", &[]), None);
+    pipeline.record(Operation::registered("denoise", &[("radius", Value::Integer(1))]), None);
+    pipeline.record(Operation::registered("threshold", &[("level", Value::Integer(100))]), None);
+    apply_tiled(&pipeline, &input, &output).unwrap();
+
+    let expected = pipeline.apply(&Image::new(&input, scan)).unwrap().into_dynamic_image();
+    assert_eq!(read_all(&TiledImage::open(&output).unwrap()).raw_pixels(), expected.raw_pixels());
+    assert!(!output.with_extension("step1.tif").exists());
+    fs::remove_file(input).unwrap();
+    fs::remove_file(output).unwrap();
+  }
+
+  #[test]
+  fn refuses_operations_needing_the_whole_image() {
+    let (input, output) = (temporary_path("refuse-input"), temporary_path("refuse-output"));
+    save_tiled(&scan(), &input).unwrap();
+
+    let mut pipeline = Pipeline::new();
+    pipeline.record(Operation::FilterFrequencies(FrequencyFilter {
+      band: FilterBand::LowPass { cutoff: 0.25 },
+      shape: FilterShape::Ideal
+    }), None);
+    assert!(apply_tiled(&pipeline, &input, &output).is_err());
+    assert!(!output.exists());
+    assert!(apply_tiled(&Pipeline::new(), &input, &output.with_extension("png")).is_err());
+    fs::remove_file(input).unwrap();
+  }
+}
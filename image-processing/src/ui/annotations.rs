@@ -0,0 +1,513 @@
+use failure::{format_err, Error};
+use std::f64::consts::PI;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::{Arc, RwLock};
+use gtk::*;
+use gdk::ContextExt;
+use cairo::{Context, Format, ImageSurface, FontSlant, FontWeight, LineCap, LineJoin};
+
+use annotations::{Annotation, Annotations, Shape};
+use image::Image as MyImage;
+use image::image::{ColorType, DynamicImage, GenericImageView, ImageBuffer};
+use operations::text_argument;
+use pipeline::Operation;
+use super::ImageContainer;
+use super::image_container::to_pixbuf;
+use super::sidemenu::AnnotationControls;
+
+pub const ARROW_ANNOTATION: &str = "arrow_annotation";
+pub const RECTANGLE_ANNOTATION: &str = "rectangle_annotation";
+pub const ELLIPSE_ANNOTATION: &str = "ellipse_annotation";
+pub const FREEHAND_ANNOTATION: &str = "freehand_annotation";
+pub const TEXT_ANNOTATION: &str = "text_annotation";
+// picks annotations to move, restyle or delete
+pub const EDIT_ANNOTATIONS: &str = "edit_annotations";
+
+// how close to an annotation a click picks it, in screen pixels
+const PICK_DISTANCE: f64 = 4.0;
+// the length of an arrow's head, relative to the width of its line
+const ARROW_HEAD: f64 = 4.0;
+const TEXT_FONT: &str = "Sans";
+
+// The annotations of the open image, drawn over it without touching its
+// pixels and saved to its sidecar as they change.
+pub struct AnnotationLayer {
+	pub annotations: Annotations,
+	// the image whose sidecar they're saved to
+	pub image_path: Option<PathBuf>,
+	// the annotation being edited
+	pub selected: Option<usize>,
+	// one being drawn, not yet among the others
+	drawing: Option<Annotation>,
+	// where the pointer was pressed, or last moved to while dragging
+	anchor: Option<(f64, f64)>,
+	// set while the controls take on the style of the selected annotation, so
+	// that doesn't count as restyling it
+	showing: bool
+}
+
+impl AnnotationLayer {
+	pub fn new() -> Self {
+		Self {
+			annotations: Annotations::new(),
+			image_path: None,
+			selected: None,
+			drawing: None,
+			anchor: None,
+			showing: false
+		}
+	}
+
+	// those of the image at `image_path`, unless they're shown already
+	pub fn follow(&mut self, image_path: &PathBuf) -> Result<(), Error> {
+		if self.image_path.as_ref() == Some(image_path) {
+			return Ok(());
+		}
+		self.selected = None;
+		self.drawing = None;
+		self.anchor = None;
+		match Annotations::open(image_path) {
+			Ok(annotations) => {
+				self.annotations = annotations;
+				self.image_path = Some(image_path.to_path_buf());
+				Ok(())
+			},
+			// a sidecar that can't be read is left alone rather than saved over
+			Err(error) => {
+				self.annotations = Annotations::new();
+				self.image_path = None;
+				Err(error)
+			}
+		}
+	}
+
+	// into the sidecar of another image, as when the open one is saved as it
+	pub fn move_to(&mut self, image_path: &PathBuf) -> Result<(), Error> {
+		self.image_path = Some(image_path.to_path_buf());
+		self.save()
+	}
+
+	pub fn save(&self) -> Result<(), Error> {
+		match self.image_path {
+			Some(ref image_path) => self.annotations.save(image_path),
+			None => Ok(())
+		}
+	}
+
+	pub fn selected_annotation(&self) -> Option<&Annotation> {
+		self.selected.and_then(|index| self.annotations.annotations.get(index))
+	}
+
+	pub fn delete_selected(&mut self) -> bool {
+		match self.selected.take() {
+			Some(index) if index < self.annotations.annotations.len() => {
+				self.annotations.annotations.remove(index);
+				true
+			},
+			_ => false
+		}
+	}
+
+	pub fn clear(&mut self) {
+		self.annotations.annotations.clear();
+		self.selected = None;
+	}
+
+	// styles the selected annotation after the controls, its text too when
+	// `retext` is set, and tells whether there was one
+	fn restyle(&mut self, controls: &AnnotationControls, retext: bool) -> bool {
+		if self.showing {
+			return false;
+		}
+		let index = match self.selected {
+			Some(index) if index < self.annotations.annotations.len() => index,
+			_ => return false
+		};
+		let annotation = &mut self.annotations.annotations[index];
+		let restyled = controls.annotate(annotation.shape.clone());
+		annotation.color = restyled.color;
+		annotation.size = restyled.size;
+		if let Shape::Text { ref mut text, .. } = annotation.shape {
+			let new_text = controls.text();
+			// text can't be taken away altogether, only deleted with its annotation
+			if retext && !new_text.trim().is_empty() {
+				*text = new_text;
+			}
+		}
+		true
+	}
+
+	fn begin(&mut self, tool: &str, point: (f64, f64), tolerance: f64, controls: &AnnotationControls) -> bool {
+		let shape = match tool {
+			EDIT_ANNOTATIONS => {
+				self.selected = self.annotations.find(point, tolerance);
+				self.anchor = self.selected.map(|_| point);
+				return true;
+			},
+			TEXT_ANNOTATION => {
+				let text = controls.text();
+				if text.trim().is_empty() {
+					return false;
+				}
+				self.annotations.annotations.push(controls.annotate(Shape::Text { x: point.0, y: point.1, text }));
+				self.selected = Some(self.annotations.annotations.len() - 1);
+				return true;
+			},
+			ARROW_ANNOTATION => Shape::Arrow { from: point, to: point },
+			RECTANGLE_ANNOTATION => Shape::rectangle(point, point),
+			ELLIPSE_ANNOTATION => Shape::ellipse(point, point),
+			FREEHAND_ANNOTATION => Shape::Freehand { points: vec![point] },
+			_ => return false
+		};
+		self.drawing = Some(controls.annotate(shape));
+		self.anchor = Some(point);
+		true
+	}
+
+	// reshapes the annotation being drawn, or moves the one being edited
+	fn extend(&mut self, point: (f64, f64)) -> bool {
+		let anchor = match self.anchor {
+			Some(anchor) => anchor,
+			None => return false
+		};
+
+		if let Some(ref mut annotation) = self.drawing {
+			let is_ellipse = match annotation.shape {
+				Shape::Arrow { ref mut to, .. } => {
+					*to = point;
+					return true;
+				},
+				Shape::Freehand { ref mut points } => {
+					points.push(point);
+					return true;
+				},
+				Shape::Ellipse { .. } => true,
+				_ => false
+			};
+			annotation.shape = if is_ellipse { Shape::ellipse(anchor, point) } else { Shape::rectangle(anchor, point) };
+			return true;
+		}
+
+		match self.selected {
+			Some(index) if index < self.annotations.annotations.len() => {
+				self.annotations.annotations[index].shape.translate(point.0 - anchor.0, point.1 - anchor.1);
+				self.anchor = Some(point);
+				true
+			},
+			_ => false
+		}
+	}
+
+	// tells whether the annotations changed
+	fn finish(&mut self) -> bool {
+		if self.anchor.take().is_none() {
+			return false;
+		}
+
+		// a click without a drag draws nothing
+		if let Some(annotation) = self.drawing.take() {
+			if annotation.shape.is_degenerate() {
+				return false;
+			}
+			self.annotations.annotations.push(annotation);
+			self.selected = Some(self.annotations.annotations.len() - 1);
+		}
+		true
+	}
+}
+
+fn is_annotation_tool(tool: &str) -> bool {
+	match tool {
+		ARROW_ANNOTATION | RECTANGLE_ANNOTATION | ELLIPSE_ANNOTATION |
+		FREEHAND_ANNOTATION | TEXT_ANNOTATION | EDIT_ANNOTATIONS => true,
+		_ => false
+	}
+}
+
+// the controls take on the style of the selected annotation, if any
+fn show_selected(controls: &AnnotationControls, layer: &RwLock<AnnotationLayer>) {
+	let selected = layer.read().unwrap().selected_annotation().cloned();
+	if let Some(annotation) = selected {
+		layer.write().unwrap().showing = true;
+		controls.show(&annotation);
+		layer.write().unwrap().showing = false;
+	}
+}
+
+fn save_layer(layer: &AnnotationLayer) {
+	if let Err(error) = layer.save() {
+		println!("{:?}", error);
+	}
+}
+
+pub fn connect_annotation_events(image_container: &ImageContainer,
+                                 tool: &ComboBoxText,
+                                 controls: &AnnotationControls,
+                                 layer: &Arc<RwLock<AnnotationLayer>>) {
+
+	let drawing_area = &image_container.drawing_area;
+	{
+		let image_container = image_container.clone();
+		let tool = tool.clone();
+		let controls = controls.clone();
+		let layer = layer.clone();
+		drawing_area.connect_button_press_event(move |da, event| {
+			if event.get_button() == 1 {
+				if let Some(tool) = tool.get_active_id().filter(|tool| is_annotation_tool(tool)) {
+					let point = image_container.to_image_coordinates(event.get_position());
+					let tolerance = PICK_DISTANCE / image_container.zoom();
+					let begun = layer.write().unwrap().begin(&tool, point, tolerance, &controls);
+					if begun {
+						// a text annotation is placed at once
+						if tool == TEXT_ANNOTATION {
+							save_layer(&layer.read().unwrap());
+						}
+						show_selected(&controls, &layer);
+						da.queue_draw();
+					}
+				}
+			}
+			Inhibit(false)
+		});
+	}
+
+	{
+		let image_container = image_container.clone();
+		let layer = layer.clone();
+		drawing_area.connect_motion_notify_event(move |da, event| {
+			let point = image_container.to_image_coordinates(event.get_position());
+			if layer.write().unwrap().extend(point) {
+				da.queue_draw();
+			}
+			Inhibit(false)
+		});
+	}
+
+	{
+		let controls = controls.clone();
+		let layer = layer.clone();
+		drawing_area.connect_button_release_event(move |da, event| {
+			if event.get_button() == 1 {
+				let finished = layer.write().unwrap().finish();
+				if finished {
+					save_layer(&layer.read().unwrap());
+					show_selected(&controls, &layer);
+					da.queue_draw();
+				}
+			}
+			Inhibit(false)
+		});
+	}
+
+	// Changing the style restyles the annotation being edited. Its text only
+	// changes with the edit tool, as the text tool types that of the next one.
+	let restyle = {
+		let tool = tool.clone();
+		let controls = controls.clone();
+		let layer = layer.clone();
+		let drawing_area = drawing_area.clone();
+		move |text_changed: bool| {
+			let editing = tool.get_active_id().map_or(false, |tool| tool == EDIT_ANNOTATIONS);
+			if text_changed && !editing {
+				return;
+			}
+			let mut layer = layer.write().unwrap();
+			if layer.restyle(&controls, editing) {
+				save_layer(&layer);
+				drawing_area.queue_draw();
+			}
+		}
+	};
+	let restyle = Rc::new(restyle);
+	{
+		let restyle = restyle.clone();
+		controls.text.connect_changed(move |_| restyle(true));
+	}
+	{
+		let restyle = restyle.clone();
+		controls.color.connect_color_set(move |_| restyle(false));
+	}
+	{
+		let restyle = restyle.clone();
+		controls.line_width.connect_value_changed(move |_| restyle(false));
+	}
+	controls.text_size.connect_value_changed(move |_| restyle(false));
+
+	let image_container = image_container.clone();
+	let layer = layer.clone();
+	drawing_area.connect_draw(move |_, cr| {
+		draw_layer(cr, &layer.read().unwrap(), image_container.zoom());
+		Inhibit(false)
+	});
+}
+
+pub fn delete_annotation(image_container: &ImageContainer, layer: &RwLock<AnnotationLayer>) {
+	let mut layer = layer.write().unwrap();
+	if layer.delete_selected() {
+		save_layer(&layer);
+		image_container.drawing_area.queue_draw();
+	}
+}
+
+pub fn clear_annotations(image_container: &ImageContainer, layer: &RwLock<AnnotationLayer>) {
+	let mut layer = layer.write().unwrap();
+	layer.clear();
+	save_layer(&layer);
+	image_container.drawing_area.queue_draw();
+}
+
+// Annotations follow the open image as an operation flips it. Callers leave
+// them alone when only a selection was flipped.
+pub fn follow_flip(image_container: &ImageContainer,
+                   current_file: &RwLock<Option<MyImage>>,
+                   layer: &RwLock<AnnotationLayer>,
+                   operation: &Operation) {
+	let horizontally = match *operation {
+		Operation::Registered { ref name, ref arguments } if name == "flip" =>
+			text_argument(arguments, "direction").ok() != Some("vertical"),
+		_ => return
+	};
+	let (width, height) = match *current_file.read().unwrap() {
+		Some(ref image) => image.as_dynamic_image().dimensions(),
+		None => return
+	};
+	let mut layer = layer.write().unwrap();
+	layer.annotations.flip(horizontally, width, height);
+	save_layer(&layer);
+	image_container.drawing_area.queue_draw();
+}
+
+// the annotations of whichever image is open now, like a panorama replacing the
+// one before
+pub fn follow_current_image(image_container: &ImageContainer,
+                            current_file: &RwLock<Option<MyImage>>,
+                            layer: &RwLock<AnnotationLayer>) {
+	let image_path = match *current_file.read().unwrap() {
+		Some(ref image) => image.get_image_path(),
+		None => return
+	};
+	if let Err(error) = layer.write().unwrap().follow(&image_path) {
+		println!("{:?}", error);
+	}
+	image_container.drawing_area.queue_draw();
+}
+
+fn draw_annotation(cr: &Context, annotation: &Annotation) {
+	let (red, green, blue) = annotation.color;
+	let size = annotation.size;
+	cr.set_source_rgb(red, green, blue);
+	cr.set_line_width(size);
+	cr.set_line_cap(LineCap::Round);
+	cr.set_line_join(LineJoin::Round);
+
+	match annotation.shape {
+		Shape::Arrow { from, to } => {
+			cr.move_to(from.0, from.1);
+			cr.line_to(to.0, to.1);
+			cr.stroke();
+
+			// a filled head, a third as wide as it's long
+			let angle = (to.1 - from.1).atan2(to.0 - from.0);
+			let length = ARROW_HEAD * size;
+			let spread = (1.0f64 / 3.0).atan();
+			cr.move_to(to.0, to.1);
+			cr.line_to(to.0 - length * (angle - spread).cos(), to.1 - length * (angle - spread).sin());
+			cr.line_to(to.0 - length * (angle + spread).cos(), to.1 - length * (angle + spread).sin());
+			cr.close_path();
+			cr.fill();
+		},
+		Shape::Rectangle { x, y, width, height } => {
+			cr.rectangle(x, y, width, height);
+			cr.stroke();
+		},
+		Shape::Ellipse { center_x, center_y, radius_x, radius_y } => {
+			// scaled back before stroking, so that the line keeps its width
+			cr.save();
+			cr.translate(center_x, center_y);
+			cr.scale(radius_x.max(1e-6), radius_y.max(1e-6));
+			cr.arc(0.0, 0.0, 1.0, 0.0, 2.0 * PI);
+			cr.restore();
+			cr.stroke();
+		},
+		Shape::Freehand { ref points } => {
+			if let Some(first) = points.first() {
+				cr.move_to(first.0, first.1);
+				for point in points.iter().skip(1) {
+					cr.line_to(point.0, point.1);
+				}
+				cr.stroke();
+			}
+		},
+		Shape::Text { x, y, ref text } => {
+			cr.select_font_face(TEXT_FONT, FontSlant::Normal, FontWeight::Bold);
+			cr.set_font_size(size);
+			cr.move_to(x, y);
+			cr.show_text(text);
+		}
+	}
+}
+
+fn draw_layer(cr: &Context, layer: &AnnotationLayer, zoom: f64) {
+	cr.save();
+	cr.scale(zoom, zoom);
+
+	for annotation in layer.annotations.annotations.iter().chain(layer.drawing.iter()) {
+		draw_annotation(cr, annotation);
+	}
+
+	// the annotation being edited is framed, in a dashed line that stays as thin
+	// whatever the zoom
+	if let Some(annotation) = layer.selected_annotation() {
+		let margin = annotation.size / 2.0 + PICK_DISTANCE / zoom;
+		let (x, y, width, height) = annotation.shape.bounds(annotation.size);
+		cr.rectangle(x - margin, y - margin, width + 2.0 * margin, height + 2.0 * margin);
+		cr.set_line_width(1.0 / zoom);
+		cr.set_dash(&[4.0 / zoom, 4.0 / zoom], 0.0);
+		cr.set_source_rgb(0.0, 0.0, 0.0);
+		cr.stroke_preserve();
+		cr.set_dash(&[4.0 / zoom, 4.0 / zoom], 4.0 / zoom);
+		cr.set_source_rgb(1.0, 1.0, 1.0);
+		cr.stroke();
+	}
+
+	cr.restore();
+}
+
+// A copy of the image with the annotations drawn into it, for exporting. It
+// only has an alpha channel if the image had one.
+pub fn flatten(image: &MyImage, annotations: &Annotations) -> Result<MyImage, Error> {
+	let (width, height) = image.as_dynamic_image().dimensions();
+	let mut surface = ImageSurface::create(Format::ARgb32, width as i32, height as i32)
+		.map_err(|status| format_err!("can't draw the annotations: {:?}", status))?;
+	{
+		let cr = Context::new(&surface);
+		cr.set_source_pixbuf(&to_pixbuf(image), 0.0, 0.0);
+		cr.paint();
+		for annotation in annotations.annotations.iter() {
+			draw_annotation(&cr, annotation);
+		}
+	}
+	surface.flush();
+
+	// cairo keeps premultiplied pixels in native endian 32 bit words
+	let stride = surface.get_stride() as usize;
+	let (red, green, blue, alpha) = if cfg!(target_endian = "little") { (2, 1, 0, 3) } else { (1, 2, 3, 0) };
+	let data = surface.get_data().map_err(|error| format_err!("can't read the annotated image: {:?}", error))?;
+	let mut pixels = Vec::with_capacity(width as usize * height as usize * 4);
+	for row in data.chunks(stride).take(height as usize) {
+		for pixel in row[..width as usize * 4].chunks(4) {
+			let a = pixel[alpha] as u32;
+			let unpremultiply = |value: u8| if a == 0 { 0 } else { ((value as u32 * 255 + a / 2) / a).min(255) as u8 };
+			pixels.extend_from_slice(&[unpremultiply(pixel[red]), unpremultiply(pixel[green]), unpremultiply(pixel[blue]), a as u8]);
+		}
+	}
+
+	let flattened = DynamicImage::ImageRgba8(ImageBuffer::from_raw(width, height, pixels).unwrap());
+	let has_alpha = match image.get_color_type() {
+		ColorType::GrayA(_) | ColorType::RGBA(_) | ColorType::BGRA(_) => true,
+		_ => false
+	};
+	let flattened = if has_alpha { flattened } else { DynamicImage::ImageRgb8(flattened.to_rgb()) };
+	Ok(MyImage::new(&image.get_image_path(), flattened))
+}
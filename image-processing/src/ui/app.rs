@@ -1,23 +1,49 @@
 use std::process;
+use std::rc::Rc;
 use std::sync::{Arc, RwLock};
+use failure::Error;
 
 use gtk::*;
+use gdk::enums::key;
 
 use super::{ 
  Header,
  Content,
+ StatusBar,
  ConnectedApp,
- open::open,
+ open::{open, open_path},
  save::save,
- equalize_histogram::equalize_histogram
+ apply_operation::apply_operation_at_full_depth,
+ preview::{preview_operation, end_preview},
+ recipe::{save_recipe, load_recipe, apply_recipe_to_folder, clear_recipe},
+ selection_tool::{SelectionState, connect_selection_events},
+ sidemenu::OperationControls,
+ pixel_inspector::connect_pixel_inspector,
+ statistics::show_statistics,
+ spectrum::show_spectrum,
+ match_histogram::match_histogram,
+ blend::blend_image,
+ markers::{Markers, connect_markers},
+ blobs::{label_blobs, export_blobs},
+ hough::{detect_lines, detect_segments, detect_circles},
+ corners::detect_corners,
+ templates::{crop_template, find_template},
+ stitch::stitch_images,
+ hdr::merge_exposures,
+ frames::{forget_replaced_animation, show_frame, apply_recipe_to_frames, save_animation},
+ perspective::correct_perspective,
+ annotations::{AnnotationLayer, connect_annotation_events, delete_annotation, clear_annotations, follow_current_image, follow_flip}
 };
 
-use image::Image;
+use image::{FullDepth, Image};
+use animation::Animation;
+use pipeline::{Operation, Pipeline};
 
 pub struct App {
 	pub window: Window,
 	pub header: Header,
-  pub content: Content
+  pub content: Content,
+  pub status_bar: StatusBar
 }
 
 impl App {
@@ -43,74 +69,627 @@ impl App {
 
 
       let content = Content::new();
-      window.add(&content.container);
+      let status_bar = StatusBar::new();
+
+      let layout = Box::new(Orientation::Vertical, 0);
+      layout.pack_start(&content.container, true, true, 0);
+      layout.pack_end(&status_bar.container, false, false, 0);
+      window.add(&layout);
 
       window.connect_delete_event(move |_, _| {
        main_quit();
        Inhibit(false)
      });
 
-      App { window, header, content }
+      App { window, header, content, status_bar }
     }
 
     pub fn connect_events(self) -> ConnectedApp {
 
       let current_file = Arc::new(RwLock::new(None));
+      // the samples of a 16 bit or floating point image, saved in its place
+      let full_depth = Arc::new(RwLock::new(None));
+      let recipe = Arc::new(RwLock::new(Pipeline::new()));
+      let selection = Arc::new(RwLock::new(SelectionState::new()));
+      let markers = Arc::new(RwLock::new(Markers::new()));
+      let animation = Arc::new(RwLock::new(None));
+      let annotations = Arc::new(RwLock::new(AnnotationLayer::new()));
 
       {
         let save = &self.header.save;
         let save_as = &self.header.save_as;
+        let side_menu = &self.content.side_menu;
 
       // Connect all of the events that this UI will act upon.
-      self.open_file(current_file.clone());
-      self.save_event(&save, current_file.clone(), false);
-      self.save_event(&save_as, current_file.clone(), true);
-      self.equalize_histogram(current_file.clone());
+      self.open_file(current_file.clone(), full_depth.clone(), markers.clone(), animation.clone(), annotations.clone());
+      self.stitch_event(current_file.clone(), markers.clone(), animation.clone(), annotations.clone());
+      self.hdr_event(current_file.clone(), markers.clone(), animation.clone(), annotations.clone());
+      self.frame_events(current_file.clone(), recipe.clone(), markers.clone(), animation.clone());
+      self.filmstrip_events(current_file.clone(), full_depth.clone(), markers.clone(), animation.clone(), annotations.clone());
+      self.save_event(&save, current_file.clone(), full_depth.clone(), annotations.clone(), false);
+      self.save_event(&save_as, current_file.clone(), full_depth.clone(), annotations.clone(), true);
+      self.zoom_event(&self.header.zoom_in, |zoom| zoom * 2.0);
+      self.zoom_event(&self.header.zoom_out, |zoom| zoom / 2.0);
+      self.zoom_event(&self.header.zoom_reset, |_| 1.0);
+      self.annotation_events(annotations.clone());
+      self.marker_events(markers.clone());
+      self.selection_events(selection.clone());
+      connect_pixel_inspector(&self.content.image_container, &self.status_bar, current_file.clone(), full_depth.clone());
+
+      for controls in side_menu.operations.iter() {
+        self.registered_operation_events(controls, current_file.clone(), full_depth.clone(), recipe.clone(), selection.clone(),
+                                         annotations.clone());
+      }
+
+      self.match_histogram_event(current_file.clone(), recipe.clone(), selection.clone());
+      self.blend_event(current_file.clone(), recipe.clone(), selection.clone());
+      self.perspective_event(current_file.clone(), recipe.clone(), selection.clone(), annotations.clone());
+
+      let frequency_controls = side_menu.frequency_controls.clone();
+      self.operation_event(&side_menu.filter_frequencies, current_file.clone(), full_depth.clone(), recipe.clone(),
+                           selection.clone(), annotations.clone(), move || Operation::FilterFrequencies(frequency_controls.filter()));
+
+      self.recipe_events(current_file.clone(), recipe.clone());
+      self.statistics_event(current_file.clone());
+      self.spectrum_event(current_file.clone());
+      self.blob_events(current_file.clone(), markers.clone());
+
+      let hough_controls = &side_menu.hough_controls;
+      self.detection_event(&side_menu.detect_lines, current_file.clone(), markers.clone(), hough_controls.clone(),
+                           &hough_controls.count, "lines", detect_lines);
+      self.detection_event(&side_menu.detect_segments, current_file.clone(), markers.clone(), hough_controls.clone(),
+                           &hough_controls.count, "segments", detect_segments);
+      self.detection_event(&side_menu.detect_circles, current_file.clone(), markers.clone(), hough_controls.clone(),
+                           &hough_controls.count, "circles", detect_circles);
+
+      let corner_controls = &side_menu.corner_controls;
+      self.detection_event(&side_menu.detect_corners, current_file.clone(), markers.clone(), corner_controls.clone(),
+                           &corner_controls.count, "corners", detect_corners);
+
+      self.template_events(current_file.clone(), selection.clone(), markers.clone());
     }
 
     ConnectedApp::new(self)
   }
 
-  fn equalize_histogram(&self, current_file: Arc<RwLock<Option<Image>>>) {
+  fn operation_event<F>(&self,
+                        button: &Button,
+                        current_file: Arc<RwLock<Option<Image>>>,
+                        full_depth: Arc<RwLock<Option<FullDepth>>>,
+                        recipe: Arc<RwLock<Pipeline>>,
+                        selection: Arc<RwLock<SelectionState>>,
+                        annotations: Arc<RwLock<AnnotationLayer>>,
+                        operation: F,
+                        ) where F: Fn() -> Operation + 'static {
+
+    let image_container = self.content.image_container.clone();
+
+    button.connect_clicked(move |ob| {
+      ob.set_sensitive(false);
+      let operation = operation();
+      let whole_image = selection.read().unwrap().selection.is_none();
+      match apply_operation_at_full_depth(&image_container, &current_file, &full_depth, &recipe, &selection, operation.clone()) {
+        Err(error) => println!("{:?}", error),
+        Ok(()) => if whole_image {
+          follow_flip(&image_container, &current_file, &annotations, &operation);
+        }
+      }
+      ob.set_sensitive(true);
+    });
+  }
+
+  fn registered_operation_events(&self,
+                                 controls: &OperationControls,
+                                 current_file: Arc<RwLock<Option<Image>>>,
+                                 full_depth: Arc<RwLock<Option<FullDepth>>>,
+                                 recipe: Arc<RwLock<Pipeline>>,
+                                 selection: Arc<RwLock<SelectionState>>,
+                                 annotations: Arc<RwLock<AnnotationLayer>>,
+                                 ) {
+
+    let operation = {
+      let controls = controls.clone();
+      move || Operation::Registered { name: controls.name.clone(), arguments: controls.arguments() }
+    };
+    self.operation_event(&controls.apply, current_file.clone(), full_depth, recipe, selection.clone(), annotations,
+                         operation.clone());
+
+    let panel = match controls.panel {
+      Some(ref panel) => panel.clone(),
+      None => return
+    };
+    let image_container = self.content.image_container.clone();
+
+    // the preview follows the parameters while the panel is open
+    let preview: Rc<dyn Fn()> = {
+      let image_container = image_container.clone();
+      let current_file = current_file.clone();
+      let panel = panel.clone();
+      Rc::new(move || {
+        if panel.get_expanded() {
+          if let Err(error) = preview_operation(&image_container, &current_file, &selection, operation()) {
+            println!("{:?}", error);
+          }
+        }
+      })
+    };
+    controls.connect_changed(preview.clone());
+
+    panel.connect_property_expanded_notify(move |panel| {
+      if panel.get_expanded() {
+        preview();
+      }
+      else if let Err(error) = end_preview(&image_container, &current_file) {
+        println!("{:?}", error);
+      }
+    });
+  }
+
+  fn match_histogram_event(&self,
+                           current_file: Arc<RwLock<Option<Image>>>,
+                           recipe: Arc<RwLock<Pipeline>>,
+                           selection: Arc<RwLock<SelectionState>>,
+                           ) {
+
+    let image_container = self.content.image_container.clone();
+
+    self.content.side_menu.match_histogram.connect_clicked(move |mb| {
+      mb.set_sensitive(false);
+      match match_histogram(&image_container, &current_file, &recipe, &selection) {
+        Err(error) => println!("{:?}", error),
+        Ok(()) => ()
+      }
+      mb.set_sensitive(true);
+    });
+  }
+
+  fn blend_event(&self,
+                 current_file: Arc<RwLock<Option<Image>>>,
+                 recipe: Arc<RwLock<Pipeline>>,
+                 selection: Arc<RwLock<SelectionState>>,
+                 ) {
+
+    let image_container = self.content.image_container.clone();
+    let levels = self.content.side_menu.blend_levels.clone();
+
+    self.content.side_menu.blend_image.connect_clicked(move |bb| {
+      bb.set_sensitive(false);
+      match blend_image(&image_container, &current_file, &recipe, &selection, levels.get_value_as_int() as u32) {
+        Err(error) => println!("{:?}", error),
+        Ok(()) => ()
+      }
+      bb.set_sensitive(true);
+    });
+  }
+
+  fn perspective_event(&self,
+                       current_file: Arc<RwLock<Option<Image>>>,
+                       recipe: Arc<RwLock<Pipeline>>,
+                       selection: Arc<RwLock<SelectionState>>,
+                       annotations: Arc<RwLock<AnnotationLayer>>,
+                       ) {
+
+    let image_container = self.content.image_container.clone();
+    let controls = self.content.side_menu.perspective_controls.clone();
+
+    self.content.side_menu.correct_perspective.connect_clicked(move |pb| {
+      pb.set_sensitive(false);
+      match correct_perspective(&image_container, &current_file, &recipe, &selection, &controls) {
+        Err(error) => println!("{:?}", error),
+        // they were drawn on the image before it was warped
+        Ok(()) => clear_annotations(&image_container, &annotations)
+      }
+      pb.set_sensitive(true);
+    });
+  }
+
+  fn zoom_event(&self, button: &Button, zoom: fn(f64) -> f64) {
+
+    let image_container = self.content.image_container.clone();
+    let zoom_label = self.status_bar.zoom.clone();
+
+    button.connect_clicked(move |_| {
+      image_container.set_zoom(zoom(image_container.zoom()));
+      zoom_label.set_text(&format!("{}%", (image_container.zoom() * 100.0).round()));
+    });
+  }
+
+  fn selection_events(&self, selection: Arc<RwLock<SelectionState>>) {
+
+    let drawing_area = self.content.image_container.drawing_area.clone();
+    connect_selection_events(&self.content.image_container, &self.content.side_menu.tool, &selection);
+
+    self.content.side_menu.clear_selection.connect_clicked(move |_| {
+      selection.write().unwrap().clear();
+      drawing_area.queue_draw();
+    });
+  }
+
+  fn annotation_events(&self, annotations: Arc<RwLock<AnnotationLayer>>) {
+
+    let image_container = self.content.image_container.clone();
+    let side_menu = &self.content.side_menu;
+    connect_annotation_events(&image_container, &side_menu.tool, &side_menu.annotation_controls, &annotations);
+
+    {
+      let image_container = image_container.clone();
+      let annotations = annotations.clone();
+      side_menu.delete_annotation.connect_clicked(move |_| delete_annotation(&image_container, &annotations));
+    }
+    side_menu.clear_annotations.connect_clicked(move |_| clear_annotations(&image_container, &annotations));
+  }
+
+  fn marker_events(&self, markers: Arc<RwLock<Markers>>) {
+
+    let drawing_area = self.content.image_container.drawing_area.clone();
+    connect_markers(&self.content.image_container, &markers);
+
+    self.content.side_menu.clear_markers.connect_clicked(move |_| {
+      markers.write().unwrap().clear();
+      drawing_area.queue_draw();
+    });
+  }
 
-    let image_container = self.content.image_container.image_widget.clone();
+  fn blob_events(&self, current_file: Arc<RwLock<Option<Image>>>, markers: Arc<RwLock<Markers>>) {
 
-    let equalize_histogram_button = &self.content.side_menu.equalize_histogram;
-    equalize_histogram_button.connect_clicked(move |ehb| {
-      ehb.set_sensitive(false);
-      match equalize_histogram(&image_container, &current_file) {
+    let side_menu = &self.content.side_menu;
+    let blobs = Arc::new(RwLock::new(Vec::new()));
+
+    {
+      let drawing_area = self.content.image_container.drawing_area.clone();
+      let blob_controls = side_menu.blob_controls.clone();
+      let current_file = current_file.clone();
+      let blobs = blobs.clone();
+      side_menu.label_blobs.connect_clicked(move |lb| {
+        lb.set_sensitive(false);
+        match label_blobs(&current_file, &markers, &blobs, blob_controls.connectivity()) {
+          Err(error) => println!("{:?}", error),
+          Ok(count) => {
+            blob_controls.count.set_text(&format!("{} blobs", count));
+            drawing_area.queue_draw();
+          }
+        }
+        lb.set_sensitive(true);
+      });
+    }
+
+    side_menu.export_blobs.connect_clicked(move |eb| {
+      eb.set_sensitive(false);
+      match export_blobs(&current_file, &blobs) {
         Err(error) => println!("{:?}", error),
         Ok(()) => ()
       }
-      ehb.set_sensitive(true);
+      eb.set_sensitive(true);
     });
   }
 
-  fn open_file(&self, current_file: Arc<RwLock<Option<Image>>>) {
+  // runs a detector whose results are drawn over the image, counting them in `count`
+  fn detection_event<C>(&self,
+                        button: &Button,
+                        current_file: Arc<RwLock<Option<Image>>>,
+                        markers: Arc<RwLock<Markers>>,
+                        controls: C,
+                        count: &Label,
+                        found: &'static str,
+                        detect: fn(&RwLock<Option<Image>>, &RwLock<Markers>, &C) -> Result<usize, Error>,
+                        ) where C: 'static {
+
+    let drawing_area = self.content.image_container.drawing_area.clone();
+    let count = count.clone();
+
+    button.connect_clicked(move |db| {
+      db.set_sensitive(false);
+      match detect(&current_file, &markers, &controls) {
+        Err(error) => println!("{:?}", error),
+        Ok(found_count) => {
+          count.set_text(&format!("{} {}", found_count, found));
+          drawing_area.queue_draw();
+        }
+      }
+      db.set_sensitive(true);
+    });
+  }
+
+  fn template_events(&self,
+                     current_file: Arc<RwLock<Option<Image>>>,
+                     selection: Arc<RwLock<SelectionState>>,
+                     markers: Arc<RwLock<Markers>>,
+                     ) {
+
+    let side_menu = &self.content.side_menu;
+    let template = Arc::new(RwLock::new(None));
+
+    {
+      let count = side_menu.template_controls.count.clone();
+      let current_file = current_file.clone();
+      let template = template.clone();
+      side_menu.crop_template.connect_clicked(move |cb| {
+        cb.set_sensitive(false);
+        match crop_template(&current_file, &selection, &template) {
+          Err(error) => println!("{:?}", error),
+          Ok((width, height)) => count.set_text(&format!("{}x{} template", width, height))
+        }
+        cb.set_sensitive(true);
+      });
+    }
+
+    let drawing_area = self.content.image_container.drawing_area.clone();
+    let controls = side_menu.template_controls.clone();
+    side_menu.find_template.connect_clicked(move |fb| {
+      fb.set_sensitive(false);
+      match find_template(&current_file, &markers, &template, &controls) {
+        Err(error) => println!("{:?}", error),
+        Ok(found) => {
+          controls.count.set_text(&format!("{} matches", found));
+          drawing_area.queue_draw();
+        }
+      }
+      fb.set_sensitive(true);
+    });
+  }
+
+  fn statistics_event(&self, current_file: Arc<RwLock<Option<Image>>>) {
+
+    self.content.side_menu.statistics.connect_clicked(move |sb| {
+      sb.set_sensitive(false);
+      match show_statistics(&current_file) {
+        Err(error) => println!("{:?}", error),
+        Ok(()) => ()
+      }
+      sb.set_sensitive(true);
+    });
+  }
+
+  fn spectrum_event(&self, current_file: Arc<RwLock<Option<Image>>>) {
+
+    let frequency_controls = self.content.side_menu.frequency_controls.clone();
+
+    self.content.side_menu.show_spectrum.connect_clicked(move |sb| {
+      sb.set_sensitive(false);
+      match show_spectrum(&current_file, &frequency_controls) {
+        Err(error) => println!("{:?}", error),
+        Ok(()) => ()
+      }
+      sb.set_sensitive(true);
+    });
+  }
+
+  fn recipe_events(&self, current_file: Arc<RwLock<Option<Image>>>, recipe: Arc<RwLock<Pipeline>>) {
+
+    let side_menu = &self.content.side_menu;
+
+    {
+      let current_file = current_file.clone();
+      let recipe = recipe.clone();
+      side_menu.save_recipe.connect_clicked(move |sb| {
+        sb.set_sensitive(false);
+        match save_recipe(&current_file, &recipe) {
+          Err(error) => println!("{:?}", error),
+          Ok(()) => ()
+        }
+        sb.set_sensitive(true);
+      });
+    }
+
+    {
+      let image_container = self.content.image_container.clone();
+      let current_file = current_file.clone();
+      let recipe = recipe.clone();
+      side_menu.load_recipe.connect_clicked(move |lb| {
+        lb.set_sensitive(false);
+        match load_recipe(&image_container, &current_file, &recipe) {
+          Err(error) => println!("{:?}", error),
+          Ok(()) => ()
+        }
+        lb.set_sensitive(true);
+      });
+    }
+
+    {
+      let current_file = current_file.clone();
+      let recipe = recipe.clone();
+      // the button is made sensitive again once the batch is over
+      side_menu.apply_recipe_to_folder.connect_clicked(move |ab| {
+        match apply_recipe_to_folder(ab, &current_file, &recipe) {
+          Err(error) => println!("{:?}", error),
+          Ok(()) => ()
+        }
+      });
+    }
+
+    side_menu.clear_recipe.connect_clicked(move |_| {
+      match clear_recipe(&recipe) {
+        Err(error) => println!("{:?}", error),
+        Ok(()) => ()
+      }
+    });
+  }
+
+  fn open_file(&self,
+               current_file: Arc<RwLock<Option<Image>>>,
+               full_depth: Arc<RwLock<Option<FullDepth>>>,
+               markers: Arc<RwLock<Markers>>,
+               animation: Arc<RwLock<Option<Animation>>>,
+               annotations: Arc<RwLock<AnnotationLayer>>,
+               ) {
 
     let headerbar = self.header.container.clone();
-    let image_container = self.content.image_container.image_widget.clone();
+    let image_container = self.content.image_container.clone();
+    let frame_bar = self.content.frame_bar.clone();
+    let filmstrip = self.content.filmstrip.clone();
 
     self.header.open.connect_clicked(move |ob| {
       ob.set_sensitive(false);
-      match open(&headerbar, &image_container, &current_file) {
+      match open(&headerbar, &image_container, &frame_bar, &current_file, &full_depth, &animation) {
         Err(error) => println!("{:?}", error),
-        Ok(()) => ()
+        Ok(None) => (),
+        Ok(Some(path)) => {
+          // the markers belonged to the previous image
+          markers.write().unwrap().clear();
+          follow_current_image(&image_container, &current_file, &annotations);
+          if let Err(error) = filmstrip.show(&path) {
+            println!("{:?}", error);
+          }
+        }
       }
       ob.set_sensitive(true);
     });
   }
 
+  fn stitch_event(&self,
+                current_file: Arc<RwLock<Option<Image>>>,
+                markers: Arc<RwLock<Markers>>,
+                animation: Arc<RwLock<Option<Animation>>>,
+                annotations: Arc<RwLock<AnnotationLayer>>,
+                ) {
+
+    let headerbar = self.header.container.clone();
+    let image_container = self.content.image_container.clone();
+    let frame_bar = self.content.frame_bar.clone();
+
+    self.header.stitch.connect_clicked(move |sb| {
+      sb.set_sensitive(false);
+      match stitch_images(&headerbar, &image_container, &current_file) {
+        Err(error) => println!("{:?}", error),
+        Ok(()) => {
+          markers.write().unwrap().clear();
+          forget_replaced_animation(&frame_bar, &current_file, &animation);
+          // a panorama made before under the same name is replaced, annotations and all
+          follow_current_image(&image_container, &current_file, &annotations);
+          clear_annotations(&image_container, &annotations);
+        }
+      }
+      sb.set_sensitive(true);
+    });
+  }
+
+  fn hdr_event(&self,
+             current_file: Arc<RwLock<Option<Image>>>,
+             markers: Arc<RwLock<Markers>>,
+             animation: Arc<RwLock<Option<Animation>>>,
+             annotations: Arc<RwLock<AnnotationLayer>>,
+             ) {
+
+    let headerbar = self.header.container.clone();
+    let image_container = self.content.image_container.clone();
+    let frame_bar = self.content.frame_bar.clone();
+
+    self.header.hdr.connect_clicked(move |hb| {
+      hb.set_sensitive(false);
+      match merge_exposures(&headerbar, &image_container, &current_file) {
+        Err(error) => println!("{:?}", error),
+        Ok(()) => {
+          markers.write().unwrap().clear();
+          forget_replaced_animation(&frame_bar, &current_file, &animation);
+          // as with panoramas, a merge made before is replaced
+          follow_current_image(&image_container, &current_file, &annotations);
+          clear_annotations(&image_container, &annotations);
+        }
+      }
+      hb.set_sensitive(true);
+    });
+  }
+
+  fn filmstrip_events(&self,
+                      current_file: Arc<RwLock<Option<Image>>>,
+                      full_depth: Arc<RwLock<Option<FullDepth>>>,
+                      markers: Arc<RwLock<Markers>>,
+                      animation: Arc<RwLock<Option<Animation>>>,
+                      annotations: Arc<RwLock<AnnotationLayer>>,
+                      ) {
+
+    let headerbar = self.header.container.clone();
+    let image_container = self.content.image_container.clone();
+    let frame_bar = self.content.frame_bar.clone();
+    let filmstrip = self.content.filmstrip.clone();
+
+    self.content.filmstrip.connect_activate(move |path| {
+      match open_path(&headerbar, &image_container, &frame_bar, &current_file, &full_depth, &animation, path) {
+        Err(error) => println!("{:?}", error),
+        Ok(()) => {
+          markers.write().unwrap().clear();
+          follow_current_image(&image_container, &current_file, &annotations);
+          if let Err(error) = filmstrip.show(path) {
+            println!("{:?}", error);
+          }
+        }
+      }
+    });
+
+    let filmstrip = self.content.filmstrip.clone();
+    self.window.connect_key_press_event(move |window, event| {
+      let step = match event.get_keyval() {
+        key::Left => -1,
+        key::Right => 1,
+        _ => return Inhibit(false)
+      };
+      // the arrow keys stay with the widgets that use them
+      let focus_uses_arrows = window.get_focus()
+        .map_or(false, |focus| focus.is::<Entry>() || focus.is::<Range>() || focus.is::<TextView>());
+      Inhibit(!focus_uses_arrows && filmstrip.step(step))
+    });
+  }
+
+  fn frame_events(&self,
+                  current_file: Arc<RwLock<Option<Image>>>,
+                  recipe: Arc<RwLock<Pipeline>>,
+                  markers: Arc<RwLock<Markers>>,
+                  animation: Arc<RwLock<Option<Animation>>>,
+                  ) {
+
+    let frame_bar = self.content.frame_bar.clone();
+    let image_container = self.content.image_container.clone();
+
+    {
+      let frame_bar = frame_bar.clone();
+      let image_container = image_container.clone();
+      let current_file = current_file.clone();
+      let animation = animation.clone();
+      self.content.frame_bar.scale.connect_value_changed(move |_| {
+        match show_frame(&frame_bar, &image_container, &current_file, &animation, frame_bar.frame()) {
+          Err(error) => println!("{:?}", error),
+          // the markers belonged to the previous frame
+          Ok(()) => markers.write().unwrap().clear()
+        }
+      });
+    }
+
+    {
+      let current_file = current_file.clone();
+      let animation = animation.clone();
+      self.content.frame_bar.apply.connect_clicked(move |ab| {
+        ab.set_sensitive(false);
+        match apply_recipe_to_frames(&frame_bar, &image_container, &current_file, &recipe, &animation) {
+          Err(error) => println!("{:?}", error),
+          Ok(()) => ()
+        }
+        ab.set_sensitive(true);
+      });
+    }
+
+    self.content.frame_bar.save.connect_clicked(move |sb| {
+      sb.set_sensitive(false);
+      match save_animation(&current_file, &animation) {
+        Err(error) => println!("{:?}", error),
+        Ok(()) => ()
+      }
+      sb.set_sensitive(true);
+    });
+  }
+
   fn save_event( &self,
                 button: &Button,
                 current_file: Arc<RwLock<Option<Image>>>,
+                full_depth: Arc<RwLock<Option<FullDepth>>>,
+                annotations: Arc<RwLock<AnnotationLayer>>,
                 save_as: bool,
                 ) {
     let headerbar = self.header.container.clone();
+    let flatten = self.content.side_menu.annotation_controls.flatten.clone();
 
     button.connect_clicked( move |sb| {
       sb.set_sensitive(false);
-      match save(&headerbar, &current_file, save_as) {
+      match save(&headerbar, &current_file, &full_depth, save_as, &annotations, flatten.get_active()) {
         Err(error) => println!("{:?}", error),
         Ok(()) => ()
       }
@@ -0,0 +1,99 @@
+use failure::{format_err, Error};
+use std::error::Error as OtherError;
+use std::sync::RwLock;
+use gtk::*;
+
+use ui::image_container::{ImageContainer, render_image};
+use image::{FullDepth, Image as MyImage};
+use pipeline::{Operation, Pipeline, Step};
+use super::selection_tool::SelectionState;
+
+pub fn apply_operation(image_container: &ImageContainer,
+                       current_file: &RwLock<Option<MyImage>>,
+                       recipe: &RwLock<Pipeline>,
+                       selection: &RwLock<SelectionState>,
+                       operation: Operation,
+                       ) -> Result<(), Error> {
+
+	// operations are restricted to the current selection, if there is one
+	let step = match selection.try_read() {
+		Ok(guard) => Step { operation, selection: guard.selection.clone() },
+		Err(error) => return Err(format_err!("{}", error.description()))
+	};
+
+	let new_dynamic_image =
+	match current_file.try_read() {
+		Ok(guard) => match *guard {
+			Some(ref image) => Some(step.apply(image)?),
+			None => None
+		},
+		Err(error) => return Err(format_err!("{}", error.description()))
+	};
+
+	// if there's no file open, then the new image will be None
+	if let Some(new_dynamic_image) = new_dynamic_image {
+		match current_file.try_write() {
+			Ok(mut guard) => if let Some(ref mut image) = *guard {
+				image.set_dynamic_image(new_dynamic_image);
+				render_image(&image_container, image);
+			},
+			Err(error) => return Err(format_err!("{}", error.description()))
+		}
+
+		// every operation applied in the session ends up in the recipe
+		match recipe.try_write() {
+			Ok(mut guard) => guard.steps.push(step),
+			Err(error) => return Err(format_err!("{}", error.description()))
+		}
+	}
+	Ok(())
+}
+
+// Applies the operation to the samples of an image opened at full depth too, as
+// long as they stand for the open image, and shows the result at 8 bits. The
+// operations that only work at 8 bits, or on a selection, leave the samples
+// behind and the image is saved at 8 bits from then on.
+pub fn apply_operation_at_full_depth(image_container: &ImageContainer,
+                                     current_file: &RwLock<Option<MyImage>>,
+                                     full_depth: &RwLock<Option<FullDepth>>,
+                                     recipe: &RwLock<Pipeline>,
+                                     selection: &RwLock<SelectionState>,
+                                     operation: Operation,
+                                     ) -> Result<(), Error> {
+
+	let selected = match selection.try_read() {
+		Ok(guard) => guard.selection.is_some(),
+		Err(error) => return Err(format_err!("{}", error.description()))
+	};
+
+	let applied = match (current_file.try_read(), full_depth.try_read()) {
+		(Ok(image), Ok(samples)) => match (&*image, &*samples) {
+			(&Some(ref image), &Some(ref samples)) if !selected && samples.stands_for(image) =>
+				operation.apply_precise(samples.image()).ok()
+				.map(|applied| FullDepth::new(&image.get_image_path(), applied)),
+			_ => None
+		},
+		(Err(error), _) => return Err(format_err!("{}", error.description())),
+		(_, Err(error)) => return Err(format_err!("{}", error.description()))
+	};
+
+	let applied = match applied {
+		Some(applied) => applied,
+		None => return apply_operation(image_container, current_file, recipe, selection, operation)
+	};
+
+	render_image(&image_container, applied.shown());
+	match current_file.try_write() {
+		Ok(mut guard) => *guard = Some(applied.shown().clone()),
+		Err(error) => return Err(format_err!("{}", error.description()))
+	}
+	match recipe.try_write() {
+		Ok(mut guard) => guard.steps.push(Step { operation, selection: None }),
+		Err(error) => return Err(format_err!("{}", error.description()))
+	}
+	match full_depth.try_write() {
+		Ok(mut guard) => *guard = Some(applied),
+		Err(error) => return Err(format_err!("{}", error.description()))
+	}
+	Ok(())
+}
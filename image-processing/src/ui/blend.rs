@@ -0,0 +1,35 @@
+use failure::{format_err, Error};
+use std::error::Error as OtherError;
+use std::sync::RwLock;
+
+use image::Image as MyImage;
+use pipeline::{Operation, Pipeline};
+use super::ImageContainer;
+use super::apply_operation::apply_operation;
+use super::selection_tool::SelectionState;
+use super::dialogs::OpenDialog;
+
+// asks for an image of the same size, then blends its selected part in, or all
+// of it when nothing is selected
+pub fn blend_image(image_container: &ImageContainer,
+                   current_file: &RwLock<Option<MyImage>>,
+                   recipe: &RwLock<Pipeline>,
+                   selection: &RwLock<SelectionState>,
+                   levels: u32,
+                   ) -> Result<(), Error> {
+
+	let open_dialog = OpenDialog::new({
+		match current_file.try_read() {
+			Ok(guard) => match *guard {
+				Some(ref image) => image.get_dir(),
+				None => return Ok(())
+			},
+			Err(error) => return Err(format_err!("{}", error.description()))
+		}
+	});
+
+	if let Some(source) = open_dialog.run() {
+		apply_operation(image_container, current_file, recipe, selection, Operation::BlendImage { source, levels })?;
+	}
+	Ok(())
+}
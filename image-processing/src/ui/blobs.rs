@@ -0,0 +1,83 @@
+use failure::{err_msg, format_err, Error};
+use std::error::Error as OtherError;
+use std::fs::File;
+use std::sync::RwLock;
+use gdk_pixbuf::{Pixbuf, Colorspace};
+
+use image::Image as MyImage;
+use blobs::{self, Blob, Connectivity};
+use super::markers::{Marker, Markers, PIXEL_CENTER};
+use super::dialogs::SaveDialog;
+
+// Labels the blobs of the open image and marks them over it. Returns how many
+// were found.
+pub fn label_blobs(current_file: &RwLock<Option<MyImage>>,
+                   markers: &RwLock<Markers>,
+                   blobs: &RwLock<Vec<Blob>>,
+                   connectivity: Connectivity,
+                   ) -> Result<usize, Error> {
+
+	let labels = match current_file.try_read() {
+		Ok(guard) => match *guard {
+			Some(ref image) => image.label_components(connectivity),
+			None => return Ok(0)
+		},
+		Err(error) => return Err(format_err!("{}", error.description()))
+	};
+
+	let colors = labels.colorize();
+	let (width, height) = colors.dimensions();
+	let pixbuf = Pixbuf::new_from_vec(
+	                                  colors.into_raw(),
+	                                  Colorspace::Rgb,
+	                                  true,
+	                                  8,
+	                                  width as i32,
+	                                  height as i32,
+	                                  4*width as i32);
+
+	let found = labels.blobs();
+
+	let mut markers = match markers.try_write() {
+		Ok(markers) => markers,
+		Err(error) => return Err(format_err!("{}", error.description()))
+	};
+	markers.clear();
+	markers.labels = Some(pixbuf);
+	for blob in found.iter() {
+		let (x, y, width, height) = blob.bounding_box;
+		markers.shapes.push(Marker::Rectangle { x: x as f64, y: y as f64, width: width as f64, height: height as f64 });
+		markers.shapes.push(Marker::Cross { x: blob.centroid.0 + PIXEL_CENTER, y: blob.centroid.1 + PIXEL_CENTER });
+	}
+
+	let count = found.len();
+	match blobs.try_write() {
+		Ok(mut blobs) => *blobs = found,
+		Err(error) => return Err(format_err!("{}", error.description()))
+	}
+	Ok(count)
+}
+
+// writes the table of the last labeled blobs
+pub fn export_blobs(current_file: &RwLock<Option<MyImage>>, blobs: &RwLock<Vec<Blob>>) -> Result<(), Error> {
+
+	let save_dialog = SaveDialog::new({
+		match current_file.try_read() {
+			Ok(guard) => match *guard {
+				Some(ref image) => image.get_dir(),
+				None => return Ok(())
+			},
+			Err(error) => return Err(format_err!("{}", error.description()))
+		}
+	});
+
+	if let Some(path) = save_dialog.run() {
+		let blobs = match blobs.try_read() {
+			Ok(blobs) => blobs,
+			Err(error) => return Err(format_err!("{}", error.description()))
+		};
+		let file = File::create(&path).map_err(err_msg)?;
+		blobs::write_csv(&blobs, file).map_err(err_msg)?;
+	}
+	Ok(())
+}
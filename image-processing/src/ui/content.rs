@@ -1,9 +1,13 @@
 use gtk::*;
 use super::{SideMenu, ImageContainer};
+use super::frames::FrameBar;
+use super::filmstrip::Filmstrip;
 
 pub struct Content {
 	pub container: Box,
 	pub image_container: ImageContainer,
+	pub frame_bar: FrameBar,
+	pub filmstrip: Filmstrip,
 	pub side_menu: SideMenu
 }
 
@@ -16,13 +20,24 @@ impl Content {
 
 		let side_menu = SideMenu::new();
 		let image_container = ImageContainer::new();
+		let frame_bar = FrameBar::new();
+		let filmstrip = Filmstrip::new();
 
-		container.pack_start(&image_container.image_widget, false, false, padding_between_children as u32);
+		// the frames of an animation are scrubbed through below the image, above
+		// the other images in its folder
+		let image_area = Box::new(Orientation::Vertical, padding_between_children);
+		image_area.pack_start(&image_container.container, true, true, padding_between_children as u32);
+		image_area.pack_end(&filmstrip.container, false, false, padding_between_children as u32);
+		image_area.pack_end(&frame_bar.container, false, false, padding_between_children as u32);
+
+		container.pack_start(&image_area, true, true, padding_between_children as u32);
 		container.pack_end(&side_menu.container, false, false, padding_between_children as u32);
 
 		Self {
 			container,
 			image_container,
+			frame_bar,
+			filmstrip,
 			side_menu
 		}
 	}
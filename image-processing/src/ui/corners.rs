@@ -0,0 +1,29 @@
+use failure::Error;
+use std::sync::RwLock;
+use gtk::*;
+
+use image::Image as MyImage;
+use super::markers::{Marker, Markers, PIXEL_CENTER, detect};
+use super::sidemenu::{CornerControls, HARRIS_DETECTOR, SHI_TOMASI_DETECTOR};
+
+pub fn detect_corners(current_file: &RwLock<Option<MyImage>>,
+                      markers: &RwLock<Markers>,
+                      controls: &CornerControls,
+                      ) -> Result<usize, Error> {
+
+	let quality = controls.quality.get_value();
+	let radius = controls.radius.get_value_as_int() as u32;
+	let detector = controls.detector.get_active_id();
+
+	detect(current_file, markers, |image| {
+		let keypoints = match detector.as_ref().map(|id| id.as_str()) {
+			Some(HARRIS_DETECTOR) => image.harris_corners(quality, radius),
+			Some(SHI_TOMASI_DETECTOR) => image.shi_tomasi_corners(quality, radius),
+			_ => image.fast_corners(controls.fast_threshold.get_value_as_int() as u8, radius)
+		};
+
+		keypoints.iter()
+		.map(|keypoint| Marker::Cross { x: keypoint.x as f64 + PIXEL_CENTER, y: keypoint.y as f64 + PIXEL_CENTER })
+		.collect()
+	})
+}
@@ -0,0 +1,65 @@
+use gtk::*;
+use std::path::PathBuf;
+use failure::Error;
+
+use pipeline::Batch;
+
+// the height of the list of failures, which scrolls past it
+const FAILURES_HEIGHT: i32 = 160;
+
+pub struct BatchDialog{
+  pub batch_dialog: Dialog
+}
+
+impl BatchDialog {
+    pub fn new(output_dir: &PathBuf, batch: &Result<Batch, Error>) -> Self {
+        let batch_dialog = Dialog::new();
+        batch_dialog.set_title("Recipe applied to a folder");
+        batch_dialog.add_button("Close", ResponseType::Close.into());
+
+        let content_area = batch_dialog.get_content_area();
+        content_area.set_spacing(8);
+
+        match *batch {
+            Ok(ref batch) => {
+                let summary = format!("{} saved to {}, {} failed",
+                                      batch.saved.len(), output_dir.display(), batch.failed.len());
+                let summary = Label::new(Some(summary.as_str()));
+                summary.set_halign(Align::Start);
+                content_area.pack_start(&summary, false, false, 0);
+
+                if !batch.failed.is_empty() {
+                    let failures: Vec<String> = batch.failed.iter()
+                        .map(|&(ref path, ref error)| format!("{}: {}", path.display(), error))
+                        .collect();
+                    let failures = Label::new(Some(failures.join("\n").as_str()));
+                    failures.set_halign(Align::Start);
+                    failures.set_selectable(true);
+
+                    let scrolled = ScrolledWindow::new(None::<&Adjustment>, None::<&Adjustment>);
+                    scrolled.set_min_content_height(FAILURES_HEIGHT);
+                    scrolled.add(&failures);
+                    content_area.pack_start(&scrolled, true, true, 0);
+                }
+            },
+            Err(ref error) => {
+                let message = Label::new(Some(format!("the recipe couldn't be applied: {}", error).as_str()));
+                message.set_halign(Align::Start);
+                content_area.pack_start(&message, false, false, 0);
+            }
+        }
+        batch_dialog.show_all();
+
+        Self {
+          batch_dialog: batch_dialog
+        }
+    }
+
+    pub fn run(&self) {
+        self.batch_dialog.run();
+    }
+}
+
+impl Drop for BatchDialog {
+    fn drop(&mut self) { self.batch_dialog.destroy(); }
+}
@@ -0,0 +1,121 @@
+use gtk::*;
+use std::cell::RefCell;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use hdr::ToneMapping;
+
+pub struct ExposureDialog{
+  pub exposure_dialog: Dialog,
+  // the exposure time of every image, in seconds or as a fraction like 1/60
+  pub exposures: Vec<Entry>,
+  pub tone_mapping: ComboBoxText,
+  // the parameters of the chosen tone mapping, by name
+  pub parameters: Rc<RefCell<Vec<(String, Entry)>>>
+}
+
+impl ExposureDialog {
+    pub fn new(paths: &[PathBuf]) -> Self {
+        let exposure_dialog = Dialog::new();
+        exposure_dialog.set_title("Exposure times");
+        exposure_dialog.add_button("Cancel", ResponseType::Cancel.into());
+        exposure_dialog.add_button("Merge", ResponseType::Ok.into());
+
+        let grid = Grid::new();
+        grid.set_column_spacing(12);
+        grid.set_row_spacing(4);
+
+        let mut exposures = Vec::with_capacity(paths.len());
+        for (row, path) in paths.iter().enumerate() {
+            let name = path.file_name().and_then(|name| name.to_str()).unwrap_or("");
+            let label = Label::new(Some(name));
+            label.set_halign(Align::Start);
+            let entry = Entry::new();
+            entry.set_placeholder_text(Some("seconds, like 1/60"));
+            grid.attach(&label, 0, row as i32, 1, 1);
+            grid.attach(&entry, 1, row as i32, 1, 1);
+            exposures.push(entry);
+        }
+
+        let tone_mapping = ComboBoxText::new();
+        for name in ToneMapping::names() {
+            tone_mapping.append(Some(*name), name);
+        }
+        tone_mapping.set_active_id(Some("reinhard"));
+        grid.attach(&Label::new(Some("tone mapping")), 0, paths.len() as i32, 1, 1);
+        grid.attach(&tone_mapping, 1, paths.len() as i32, 1, 1);
+
+        // the parameters change along with the tone mapping
+        let parameter_grid = Grid::new();
+        parameter_grid.set_column_spacing(12);
+        parameter_grid.set_row_spacing(4);
+        let parameters = Rc::new(RefCell::new(Vec::new()));
+        show_parameters(&parameter_grid, &parameters, "reinhard");
+        {
+            let parameter_grid = parameter_grid.clone();
+            let parameters = parameters.clone();
+            tone_mapping.connect_changed(move |tone_mapping| {
+                if let Some(name) = tone_mapping.get_active_id() {
+                    show_parameters(&parameter_grid, &parameters, &name);
+                }
+            });
+        }
+
+        let content_area = exposure_dialog.get_content_area();
+        content_area.set_spacing(4);
+        content_area.pack_start(&grid, false, false, 0);
+        content_area.pack_start(&parameter_grid, false, false, 0);
+        exposure_dialog.show_all();
+
+        Self {
+          exposure_dialog: exposure_dialog,
+          exposures: exposures,
+          tone_mapping: tone_mapping,
+          parameters: parameters
+        }
+    }
+
+    // the exposure times as typed, the name of the tone mapping and the values
+    // typed for its parameters, None when cancelled
+    pub fn run(&self) -> Option<(Vec<String>, String, Vec<(String, String)>)> {
+        if self.exposure_dialog.run() == ResponseType::Ok.into() {
+            let exposures = self.exposures.iter()
+                .map(|entry| entry.get_text().unwrap_or_default())
+                .collect();
+            let tone_mapping = self.tone_mapping.get_active_id().unwrap_or_else(|| "reinhard".to_string());
+            let parameters = self.parameters.borrow().iter()
+                .map(|&(ref name, ref entry)| (name.clone(), entry.get_text().unwrap_or_default()))
+                .collect();
+            Some((exposures, tone_mapping, parameters))
+        } else {
+            None
+        }
+    }
+}
+
+// an entry for every parameter of the tone mapping, holding its default
+fn show_parameters(grid: &Grid, parameters: &RefCell<Vec<(String, Entry)>>, name: &str) {
+    for child in grid.get_children() {
+        grid.remove(&child);
+    }
+    let mut parameters = parameters.borrow_mut();
+    parameters.clear();
+
+    if let Ok(tone_mapping) = ToneMapping::new(name) {
+        for (row, (parameter, value)) in tone_mapping.parameters().into_iter().enumerate() {
+            let entry = Entry::new();
+            match value {
+                Some(value) => entry.set_text(&value.to_string()),
+                None => entry.set_placeholder_text(Some("from the image"))
+            }
+            grid.attach(&Label::new(Some(parameter)), 0, row as i32, 1, 1);
+            grid.attach(&entry, 1, row as i32, 1, 1);
+            parameters.push((parameter.to_string(), entry));
+        }
+    }
+    grid.show_all();
+}
+
+impl Drop for ExposureDialog {
+    fn drop(&mut self) { self.exposure_dialog.destroy(); }
+}
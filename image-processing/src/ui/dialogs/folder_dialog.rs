@@ -0,0 +1,37 @@
+use gtk::*;
+use std::path::PathBuf;
+
+pub struct FolderDialog{
+  pub folder_dialog: FileChooserDialog
+}
+
+impl FolderDialog {
+    pub fn new(title: &str, path: Option<PathBuf>) -> Self {
+        let folder_dialog = FileChooserDialog::new(
+                                                   Some(title),
+                                                   Some(&Window::new(WindowType::Popup)),
+                                                   FileChooserAction::SelectFolder,
+                                                   );
+
+        folder_dialog.add_button("Cancel", ResponseType::Cancel.into());
+        folder_dialog.add_button("Select", ResponseType::Ok.into());
+
+        path.map(|p| folder_dialog.set_current_folder(p));
+
+        Self {
+          folder_dialog: folder_dialog
+        }
+    }
+
+    pub fn run(&self) -> Option<PathBuf> {
+        if self.folder_dialog.run() == ResponseType::Ok.into() {
+            self.folder_dialog.get_filename()
+        } else {
+            None
+        }
+    }
+}
+
+impl Drop for FolderDialog {
+    fn drop(&mut self) { self.folder_dialog.destroy(); }
+}
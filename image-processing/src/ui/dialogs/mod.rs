@@ -1,5 +1,15 @@
 pub mod open_dialog;
 pub mod save_dialog;
+pub mod folder_dialog;
+pub mod statistics_dialog;
+pub mod spectrum_dialog;
+pub mod exposure_dialog;
+pub mod batch_dialog;
 
 pub use self::open_dialog::OpenDialog;
-pub use self::save_dialog::SaveDialog;
\ No newline at end of file
+pub use self::save_dialog::SaveDialog;
+pub use self::folder_dialog::FolderDialog;
+pub use self::statistics_dialog::StatisticsDialog;
+pub use self::spectrum_dialog::SpectrumDialog;
+pub use self::exposure_dialog::ExposureDialog;
+pub use self::batch_dialog::BatchDialog;
\ No newline at end of file
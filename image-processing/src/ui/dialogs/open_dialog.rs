@@ -23,6 +23,14 @@ impl OpenDialog {
         }
     }
 
+    // only lists the files matching `pattern`, like *.json
+    pub fn add_filter(&self, name: &str, pattern: &str) {
+        let filter = FileFilter::new();
+        filter.set_name(Some(name));
+        filter.add_pattern(pattern);
+        self.open_dialog.add_filter(&filter);
+    }
+
     pub fn run(&self) -> Option<PathBuf> {
         if self.open_dialog.run() == ResponseType::Ok.into() {
             self.open_dialog.get_filename()
@@ -30,6 +38,18 @@ impl OpenDialog {
             None
         }
     }
+
+    // every selected file, in the order of their names
+    pub fn run_multiple(&self) -> Vec<PathBuf> {
+        self.open_dialog.set_select_multiple(true);
+        if self.open_dialog.run() == ResponseType::Ok.into() {
+            let mut paths = self.open_dialog.get_filenames();
+            paths.sort();
+            paths
+        } else {
+            Vec::new()
+        }
+    }
 }
 
 impl Drop for OpenDialog {
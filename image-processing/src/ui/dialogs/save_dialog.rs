@@ -23,6 +23,14 @@ impl SaveDialog {
         }
     }
 
+    // only lists the files matching `pattern`, like *.json
+    pub fn add_filter(&self, name: &str, pattern: &str) {
+        let filter = FileFilter::new();
+        filter.set_name(Some(name));
+        filter.add_pattern(pattern);
+        self.save_dialog.add_filter(&filter);
+    }
+
     pub fn run(&self) -> Option<PathBuf> {
         if self.save_dialog.run() == ResponseType::Ok.into() {
             self.save_dialog.get_filename()
@@ -0,0 +1,55 @@
+use gtk::*;
+use gdk_pixbuf::{Pixbuf, Colorspace};
+
+use image::image::GrayImage;
+
+pub struct SpectrumDialog{
+  pub spectrum_dialog: Dialog,
+  // receives the clicks on the spectrum, in spectrum pixel coordinates
+  pub event_box: EventBox
+}
+
+impl SpectrumDialog {
+    pub fn new(log_magnitude: &GrayImage) -> Self {
+        let spectrum_dialog = Dialog::new();
+        spectrum_dialog.set_title("Spectrum - click a peak to notch it out");
+        spectrum_dialog.set_default_size(640, 640);
+        spectrum_dialog.add_button("Close", ResponseType::Close.into());
+
+        let (width, height) = log_magnitude.dimensions();
+        let pixels: Vec<u8> = log_magnitude.iter().flat_map(|&v| vec![v, v, v]).collect();
+        let pixbuf = Pixbuf::new_from_vec(
+                                          pixels,
+                                          Colorspace::Rgb,
+                                          false,
+                                          8,
+                                          width as i32,
+                                          height as i32,
+                                          3*width as i32);
+
+        let spectrum = Image::new_from_pixbuf(&pixbuf);
+        let event_box = EventBox::new();
+        event_box.add(&spectrum);
+        event_box.set_halign(Align::Start);
+        event_box.set_valign(Align::Start);
+
+        let scrolled_window = ScrolledWindow::new(None::<&Adjustment>, None::<&Adjustment>);
+        scrolled_window.add(&event_box);
+
+        spectrum_dialog.get_content_area().pack_start(&scrolled_window, true, true, 0);
+        spectrum_dialog.show_all();
+
+        Self {
+          spectrum_dialog: spectrum_dialog,
+          event_box: event_box
+        }
+    }
+
+    pub fn run(&self) {
+        self.spectrum_dialog.run();
+    }
+}
+
+impl Drop for SpectrumDialog {
+    fn drop(&mut self) { self.spectrum_dialog.destroy(); }
+}
@@ -0,0 +1,66 @@
+use gtk::*;
+
+use statistics::ChannelStatistics;
+
+pub struct StatisticsDialog{
+  pub statistics_dialog: Dialog,
+  pub compare: Button,
+  pub comparison: Label
+}
+
+impl StatisticsDialog {
+    pub fn new(statistics: &[ChannelStatistics]) -> Self {
+        let statistics_dialog = Dialog::new();
+        statistics_dialog.set_title("Statistics");
+        statistics_dialog.add_button("Close", ResponseType::Close.into());
+
+        let grid = Grid::new();
+        grid.set_column_spacing(12);
+        grid.set_row_spacing(4);
+
+        let headers = ["channel", "mean", "std dev", "min", "max", "entropy"];
+        for (column, header) in headers.iter().enumerate() {
+            grid.attach(&Label::new(Some(*header)), column as i32, 0, 1, 1);
+        }
+
+        for (row, channel) in statistics.iter().enumerate() {
+            let cells = [
+                channel.channel.to_string(),
+                format!("{:.2}", channel.mean),
+                format!("{:.2}", channel.std_dev),
+                channel.min.to_string(),
+                channel.max.to_string(),
+                format!("{:.3} bits", channel.entropy)
+            ];
+            for (column, cell) in cells.iter().enumerate() {
+                grid.attach(&Label::new(Some(cell.as_str())), column as i32, row as i32 + 1, 1, 1);
+            }
+        }
+
+        let compare = Button::new_with_label("compare with...");
+        compare.set_halign(Align::Start);
+        let comparison = Label::new(None);
+        comparison.set_halign(Align::Start);
+
+        let content_area = statistics_dialog.get_content_area();
+        content_area.set_spacing(8);
+        content_area.pack_start(&grid, false, false, 0);
+        content_area.pack_start(&compare, false, false, 0);
+        content_area.pack_start(&comparison, false, false, 0);
+        statistics_dialog.show_all();
+
+        Self {
+          statistics_dialog: statistics_dialog,
+          compare: compare,
+          comparison: comparison
+        }
+    }
+
+    pub fn run(&self) {
+        self.statistics_dialog.run();
+    }
+}
+
+impl Drop for StatisticsDialog {
+    fn drop(&mut self) { self.statistics_dialog.destroy(); }
+}
@@ -0,0 +1,180 @@
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, TryRecvError};
+use std::thread;
+use failure::Error;
+use gtk::*;
+use gdk_pixbuf::Pixbuf;
+
+use thumbnails::{sibling_images, ThumbnailCache, THUMBNAIL_SIZE};
+
+// how often the thumbnails made in the background are picked up, in ms
+const POLL_INTERVAL: u32 = 50;
+
+struct Strip {
+	paths: Vec<PathBuf>,
+	buttons: Vec<Button>,
+	current: Option<usize>,
+	activate: Option<Rc<Fn(&PathBuf)>>
+}
+
+// The images in the folder of the open one, as thumbnails made in the
+// background and cached on disk, below the image.
+#[derive(Clone)]
+pub struct Filmstrip {
+	pub container: ScrolledWindow,
+	strip: Box,
+	state: Arc<RwLock<Strip>>,
+	// bumped for every folder shown, so that the thumbnails of the one before
+	// stop being made
+	generation: Arc<AtomicUsize>
+}
+
+impl Filmstrip {
+	pub fn new() -> Self {
+		let container = ScrolledWindow::new(None::<&Adjustment>, None::<&Adjustment>);
+		container.set_policy(PolicyType::Automatic, PolicyType::Never);
+		container.set_size_request(-1, THUMBNAIL_SIZE as i32 + 48);
+		let strip = Box::new(Orientation::Horizontal, 4);
+		container.add(&strip);
+		container.set_no_show_all(true);
+
+		Self {
+			container,
+			strip,
+			state: Arc::new(RwLock::new(Strip { paths: Vec::new(), buttons: Vec::new(), current: None, activate: None })),
+			generation: Arc::new(AtomicUsize::new(0))
+		}
+	}
+
+	// what picking an image, by a click or the arrow keys, does with it
+	pub fn connect_activate<F: Fn(&PathBuf) + 'static>(&self, activate: F) {
+		self.state.write().unwrap().activate = Some(Rc::new(activate));
+	}
+
+	// Shows the images next to `path`, listing the folder again only when it's
+	// another one or `path` is new to it.
+	pub fn show(&self, path: &PathBuf) -> Result<(), Error> {
+		let listed = self.state.read().unwrap().paths.iter().position(|listed| listed == path);
+		if let Some(index) = listed {
+			self.select(index);
+			return Ok(());
+		}
+
+		let paths = sibling_images(path)?;
+		for button in self.state.write().unwrap().buttons.drain(..) {
+			self.strip.remove(&button);
+		}
+
+		let mut buttons = Vec::with_capacity(paths.len());
+		for (index, sibling) in paths.iter().enumerate() {
+			let name = sibling.file_name().and_then(|name| name.to_str()).unwrap_or("");
+			let button = Button::new_with_label(name);
+			button.set_image_position(PositionType::Top);
+			button.set_always_show_image(true);
+			button.set_relief(ReliefStyle::None);
+			button.set_tooltip_text(sibling.to_str());
+
+			let filmstrip = self.clone();
+			button.connect_clicked(move |_| filmstrip.activate(index));
+			self.strip.pack_start(&button, false, false, 0);
+			buttons.push(button);
+		}
+
+		{
+			let mut state = self.state.write().unwrap();
+			state.paths = paths.clone();
+			state.buttons = buttons;
+			state.current = None;
+		}
+		self.container.show_all();
+		if let Some(index) = paths.iter().position(|sibling| sibling == path) {
+			self.select(index);
+		}
+		self.make_thumbnails(paths);
+		Ok(())
+	}
+
+	fn make_thumbnails(&self, paths: Vec<PathBuf>) {
+		let generation = self.generation.fetch_add(1, Ordering::SeqCst) + 1;
+		let (sender, receiver) = channel();
+
+		let latest = self.generation.clone();
+		thread::spawn(move || {
+			let cache = ThumbnailCache::in_user_cache();
+			for (index, path) in paths.iter().enumerate() {
+				if latest.load(Ordering::SeqCst) != generation || sender.send((index, cache.thumbnail(path))).is_err() {
+					return;
+				}
+			}
+		});
+
+		let filmstrip = self.clone();
+		timeout_add(POLL_INTERVAL, move || {
+			if filmstrip.generation.load(Ordering::SeqCst) != generation {
+				return Continue(false);
+			}
+			loop {
+				match receiver.try_recv() {
+					Ok((index, Ok(thumbnail_path))) => filmstrip.set_thumbnail(index, &thumbnail_path),
+					Ok((_, Err(error))) => println!("{:?}", error),
+					Err(TryRecvError::Empty) => return Continue(true),
+					Err(TryRecvError::Disconnected) => return Continue(false)
+				}
+			}
+		});
+	}
+
+	fn set_thumbnail(&self, index: usize, thumbnail_path: &PathBuf) {
+		match Pixbuf::new_from_file(thumbnail_path) {
+			Ok(pixbuf) => if let Some(button) = self.state.read().unwrap().buttons.get(index) {
+				button.set_image(&Image::new_from_pixbuf(&pixbuf));
+			},
+			Err(error) => println!("{:?}", error)
+		}
+	}
+
+	// marks the image shown and scrolls to it
+	fn select(&self, index: usize) {
+		let mut state = self.state.write().unwrap();
+		if let Some(button) = state.current.and_then(|current| state.buttons.get(current)) {
+			button.set_relief(ReliefStyle::None);
+		}
+		if let Some(button) = state.buttons.get(index) {
+			button.set_relief(ReliefStyle::Normal);
+			let allocation = button.get_allocation();
+			if let Some(adjustment) = self.container.get_hadjustment() {
+				adjustment.clamp_page(allocation.x as f64, (allocation.x + allocation.width) as f64);
+			}
+		}
+		state.current = Some(index);
+	}
+
+	fn activate(&self, index: usize) {
+		let (activate, path) = {
+			let state = self.state.read().unwrap();
+			(state.activate.clone(), state.paths.get(index).cloned())
+		};
+		if let (Some(activate), Some(path)) = (activate, path) {
+			activate(&path);
+		}
+	}
+
+	// Opens the image `step` places from the one shown, if there is one, and
+	// tells whether there was.
+	pub fn step(&self, step: isize) -> bool {
+		let (current, count) = {
+			let state = self.state.read().unwrap();
+			(state.current, state.paths.len())
+		};
+		match current.map(|current| current as isize + step) {
+			Some(index) if index >= 0 && (index as usize) < count => {
+				self.activate(index as usize);
+				true
+			},
+			_ => false
+		}
+	}
+}
@@ -0,0 +1,161 @@
+use failure::{err_msg, format_err, Error};
+use std::error::Error as OtherError;
+use std::sync::RwLock;
+use gtk::*;
+
+use ui::image_container::{ImageContainer, render_image};
+use image::Image as MyImage;
+use animation::{parse_frames, Animation};
+use pipeline::Pipeline;
+use super::dialogs::SaveDialog;
+
+// Scrubs through the frames of an animation or the pages of a TIFF file, shown
+// below the image only while one is open.
+#[derive(Clone)]
+pub struct FrameBar {
+	pub container: Box,
+	pub scale: Scale,
+	pub info: Label,
+	// which frames the recipe goes to, like 1-3,5 or all
+	pub frames: Entry,
+	pub apply: Button,
+	pub save: Button
+}
+
+impl FrameBar {
+	pub fn new() -> Self {
+		let container = Box::new(Orientation::Horizontal, 6);
+		let scale = Scale::new_with_range(Orientation::Horizontal, 1.0, 2.0, 1.0);
+		scale.set_digits(0);
+		let info = Label::new(None);
+		let frames = Entry::new();
+		frames.set_text("all");
+		frames.set_width_chars(8);
+		let apply = Button::new_with_label("Apply recipe to frames");
+		let save = Button::new_with_label("Save frames as...");
+
+		container.pack_start(&scale, true, true, 0);
+		container.pack_start(&info, false, false, 0);
+		container.pack_start(&frames, false, false, 0);
+		container.pack_start(&apply, false, false, 0);
+		container.pack_start(&save, false, false, 0);
+		container.set_no_show_all(true);
+
+		Self { container, scale, info, frames, apply, save }
+	}
+
+	pub fn show_animation(&self, animation: &Animation) {
+		self.scale.set_range(1.0, animation.frames.len().max(2) as f64);
+		self.scale.set_value(1.0);
+		self.frames.set_text("all");
+		self.container.show_all();
+	}
+
+	// the frame the scale is on, counting from 0
+	pub fn frame(&self) -> usize {
+		(self.scale.get_value().round() as usize).max(1) - 1
+	}
+}
+
+pub fn forget_animation(frame_bar: &FrameBar, animation: &RwLock<Option<Animation>>) {
+	*animation.write().unwrap() = None;
+	frame_bar.container.hide();
+}
+
+// once an image from elsewhere, like a panorama, takes the frame's place
+pub fn forget_replaced_animation(frame_bar: &FrameBar,
+                                 current_file: &RwLock<Option<MyImage>>,
+                                 animation: &RwLock<Option<Animation>>) {
+	let replaced = match (&*current_file.read().unwrap(), &*animation.read().unwrap()) {
+		(&Some(ref image), &Some(ref animation)) => image.get_image_path() != animation.path,
+		_ => false
+	};
+	if replaced {
+		forget_animation(frame_bar, animation);
+	}
+}
+
+// Shows a frame in place of the image, which operations then apply to as usual.
+pub fn show_frame(frame_bar: &FrameBar,
+                  image_container: &ImageContainer,
+                  current_file: &RwLock<Option<MyImage>>,
+                  animation: &RwLock<Option<Animation>>,
+                  index: usize,
+                  ) -> Result<(), Error> {
+
+	let (image, delay, count) = match animation.try_read() {
+		Ok(guard) => match *guard {
+			Some(ref animation) => match animation.frame(index) {
+				Some(image) => (image, animation.frames[index].delay, animation.frames.len()),
+				None => return Err(format_err!("there is no frame {}", index + 1))
+			},
+			None => return Ok(())
+		},
+		Err(error) => return Err(format_err!("{}", error.description()))
+	};
+
+	frame_bar.info.set_text(&format!("frame {} of {}, {} ms", index + 1, count, delay));
+	render_image(image_container, &image);
+	*current_file.write().unwrap() = Some(image);
+	Ok(())
+}
+
+// Replays the recipe on the chosen frames of the animation, from the frames as
+// they were opened rather than as they are shown.
+pub fn apply_recipe_to_frames(frame_bar: &FrameBar,
+                              image_container: &ImageContainer,
+                              current_file: &RwLock<Option<MyImage>>,
+                              recipe: &RwLock<Pipeline>,
+                              animation: &RwLock<Option<Animation>>,
+                              ) -> Result<(), Error> {
+
+	let pipeline = match recipe.try_read() {
+		Ok(guard) => guard.clone(),
+		Err(error) => return Err(format_err!("{}", error.description()))
+	};
+	if pipeline.is_empty() {
+		return Ok(());
+	}
+
+	{
+		let mut guard = match animation.try_write() {
+			Ok(guard) => guard,
+			Err(error) => return Err(format_err!("{}", error.description()))
+		};
+		let applied = match *guard {
+			Some(ref animation) => {
+				let frames = parse_frames(&frame_bar.frames.get_text().unwrap_or_default(), animation.frames.len())?;
+				animation.apply(&pipeline, &frames)?
+			},
+			None => return Err(err_msg("no animation is open"))
+		};
+		*guard = Some(applied);
+	}
+	show_frame(frame_bar, image_container, current_file, animation, frame_bar.frame())
+}
+
+pub fn save_animation(current_file: &RwLock<Option<MyImage>>,
+                      animation: &RwLock<Option<Animation>>,
+                      ) -> Result<(), Error> {
+
+	let save_dialog = SaveDialog::new({
+		match current_file.try_read() {
+			Ok(guard) => match *guard {
+				Some(ref image) => image.get_dir(),
+				None => None
+			},
+			Err(error) => return Err(format_err!("{}", error.description()))
+		}
+	});
+
+	if let Some(path) = save_dialog.run() {
+		match animation.try_read() {
+			Ok(guard) => match *guard {
+				Some(ref animation) => { animation.save(&path)?; },
+				None => return Err(err_msg("no animation is open"))
+			},
+			Err(error) => return Err(format_err!("{}", error.description()))
+		}
+	}
+	Ok(())
+}
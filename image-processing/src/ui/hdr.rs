@@ -0,0 +1,63 @@
+use failure::{err_msg, format_err, Error};
+use std::error::Error as OtherError;
+use std::sync::RwLock;
+use gtk::*;
+
+use ui::image_container::{ImageContainer, render_image};
+use image::Image as MyImage;
+use hdr::{merge_bracket, parse_exposure, ToneMapping};
+use super::dialogs::{ExposureDialog, OpenDialog};
+
+// Asks for the exposures of a scene and how long each was taken for, then opens
+// their tone mapped merge, which is saved next to the first of them.
+pub fn merge_exposures(headerbar: &HeaderBar,
+                       image_container: &ImageContainer,
+                       current_file: &RwLock<Option<MyImage>>,
+                       ) -> Result<(), Error> {
+
+	let open_dialog = OpenDialog::new({
+		match current_file.try_read() {
+			Ok(guard) => match *guard {
+				Some(ref image) => image.get_dir(),
+				None => None
+			},
+			Err(error) => return Err(format_err!("{}", error.description()))
+		}
+	});
+
+	let paths = open_dialog.run_multiple();
+	if paths.len() < 2 {
+		return Ok(());
+	}
+
+	let (exposures, tone_mapping, parameters) = match ExposureDialog::new(&paths).run() {
+		Some(answers) => answers,
+		None => return Ok(())
+	};
+	let exposures = exposures.iter().map(|exposure| parse_exposure(exposure)).collect::<Result<Vec<f64>, Error>>()?;
+	let mut tone_mapping = ToneMapping::new(&tone_mapping)?;
+	// the parameters left empty keep their defaults
+	for &(ref parameter, ref value) in parameters.iter().filter(|&&(_, ref value)| !value.trim().is_empty()) {
+		let value = value.trim().parse::<f64>().map_err(|_| format_err!("{} isn't a number for {}", value, parameter))?;
+		tone_mapping.set(parameter, value)?;
+	}
+
+	let mut images = Vec::with_capacity(paths.len());
+	for path in paths.iter() {
+		match MyImage::open(path) {
+			Ok(image) => images.push(image),
+			Err(error) => return Err(err_msg(error))
+		}
+	}
+
+	let first = &paths[0];
+	let stem = first.file_stem().and_then(|stem| stem.to_str()).unwrap_or("merged");
+	let path = first.with_file_name(format!("{}-hdr.png", stem));
+
+	let radiance = merge_bracket(&images, &exposures)?;
+	let image = MyImage::new(&path, tone_mapping.apply(&radiance)?);
+	headerbar.set_title(path.to_str());
+	render_image(image_container, &image);
+	*current_file.write().unwrap() = Some(image);
+	Ok(())
+}
@@ -3,8 +3,13 @@ use gtk::*;
 pub struct Header {
 	pub container: HeaderBar,
 	pub open: Button,
+	pub stitch: Button,
+	pub hdr: Button,
 	pub save: Button,
 	pub save_as: Button,
+	pub zoom_in: Button,
+	pub zoom_out: Button,
+	pub zoom_reset: Button,
 
 }
 
@@ -17,17 +22,32 @@ impl Header {
 
 
 		let open = Button::new_with_mnemonic("_Open");
+		let stitch = Button::new_with_mnemonic("S_titch...");
+		let hdr = Button::new_with_mnemonic("_HDR...");
 		let save = Button::new_with_mnemonic("_Save");
 		let save_as = Button::new_with_mnemonic("Save _As");
+		let zoom_out = Button::new_with_mnemonic("Zoom _Out");
+		let zoom_reset = Button::new_with_mnemonic("_100%");
+		let zoom_in = Button::new_with_mnemonic("Zoom _In");
 		container.pack_start(&open);
+		container.pack_start(&stitch);
+		container.pack_start(&hdr);
+		container.pack_start(&zoom_out);
+		container.pack_start(&zoom_reset);
+		container.pack_start(&zoom_in);
 		container.pack_end(&save_as);
 		container.pack_end(&save);
 
 		Self {
 			container,
 			open,
+			stitch,
+			hdr,
 			save,
-			save_as
+			save_as,
+			zoom_in,
+			zoom_out,
+			zoom_reset
 		}
 	}
 }
\ No newline at end of file
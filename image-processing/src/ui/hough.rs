@@ -0,0 +1,54 @@
+use failure::Error;
+use std::sync::RwLock;
+use gtk::*;
+
+use image::Image as MyImage;
+use image::image::GenericImageView;
+use super::markers::{Marker, Markers, PIXEL_CENTER, detect, to_pixel_center};
+use super::sidemenu::HoughControls;
+
+pub fn detect_lines(current_file: &RwLock<Option<MyImage>>,
+                    markers: &RwLock<Markers>,
+                    controls: &HoughControls,
+                    ) -> Result<usize, Error> {
+
+	detect(current_file, markers, |image| {
+		let (width, height) = image.as_dynamic_image().dimensions();
+		image.hough_lines(controls.votes.get_value_as_int() as u32).iter()
+		.filter_map(|line| line.clip(width, height))
+		.map(|(from, to)| Marker::Line { from: to_pixel_center(from), to: to_pixel_center(to) })
+		.collect()
+	})
+}
+
+pub fn detect_segments(current_file: &RwLock<Option<MyImage>>,
+                       markers: &RwLock<Markers>,
+                       controls: &HoughControls,
+                       ) -> Result<usize, Error> {
+
+	detect(current_file, markers, |image| {
+		image.hough_segments(controls.votes.get_value_as_int() as u32,
+		                     controls.min_length.get_value(),
+		                     controls.max_gap.get_value_as_int() as u32).iter()
+		.map(|segment| Marker::Line { from: to_pixel_center(segment.start), to: to_pixel_center(segment.end) })
+		.collect()
+	})
+}
+
+pub fn detect_circles(current_file: &RwLock<Option<MyImage>>,
+                      markers: &RwLock<Markers>,
+                      controls: &HoughControls,
+                      ) -> Result<usize, Error> {
+
+	detect(current_file, markers, |image| {
+		image.hough_circles(controls.min_radius.get_value_as_int() as u32,
+		                    controls.max_radius.get_value_as_int() as u32,
+		                    controls.coverage.get_value()).iter()
+		.map(|circle| Marker::Circle {
+			x: circle.center_x + PIXEL_CENTER,
+			y: circle.center_y + PIXEL_CENTER,
+			radius: circle.radius
+		})
+		.collect()
+	})
+}
@@ -1,38 +1,130 @@
+use std::sync::{Arc, RwLock};
 use gtk::*;
 use gtk::ImageExt;
-use gdk_pixbuf::Pixbuf;
-use gdk_pixbuf::Colorspace;
+use gdk::EventMask;
+use gdk_pixbuf::{Pixbuf, Colorspace, InterpType};
 
 use image::Image as MyImage;
-use image::image::{GenericImageView};
+use image::image::{DynamicImage, GenericImageView};
 
+const MIN_ZOOM: f64 = 0.125;
+const MAX_ZOOM: f64 = 32.0;
+
+struct View {
+	zoom: f64,
+	// the rendered image at its actual size, scaled on every zoom change
+	pixbuf: Option<Pixbuf>,
+	// how much larger the image is than the pixbuf, for previews computed on a
+	// smaller copy
+	scale: f64
+}
+
+#[derive(Clone)]
 pub struct ImageContainer {
-	pub image_widget: Image
+	pub container: ScrolledWindow,
+	pub overlay: Overlay,
+	pub image_widget: Image,
+	pub drawing_area: DrawingArea,
+	view: Arc<RwLock<View>>
 }
 
 impl ImageContainer {
 	pub fn new() -> Self {
+		let container = ScrolledWindow::new(None::<&Adjustment>, None::<&Adjustment>);
+		let overlay = Overlay::new();
 		let image_widget = Image::new();
+		image_widget.set_halign(Align::Start);
+		image_widget.set_valign(Align::Start);
+
+		// sits on top of the image, so that tools can draw over it and receive
+		// pointer events
+		let drawing_area = DrawingArea::new();
+		drawing_area.add_events((EventMask::BUTTON_PRESS_MASK |
+		                         EventMask::BUTTON_RELEASE_MASK |
+		                         EventMask::POINTER_MOTION_MASK |
+		                         EventMask::LEAVE_NOTIFY_MASK).bits() as i32);
+
+		overlay.add(&image_widget);
+		overlay.add_overlay(&drawing_area);
+		overlay.set_halign(Align::Start);
+		overlay.set_valign(Align::Start);
+		container.add(&overlay);
+
 		Self {
-			image_widget
+			container,
+			overlay,
+			image_widget,
+			drawing_area,
+			view: Arc::new(RwLock::new(View { zoom: 1.0, pixbuf: None, scale: 1.0 }))
+		}
+	}
+
+	pub fn zoom(&self) -> f64 {
+		self.view.read().unwrap().zoom
+	}
+
+	pub fn set_zoom(&self, zoom: f64) {
+		self.view.write().unwrap().zoom = zoom.max(MIN_ZOOM).min(MAX_ZOOM);
+		self.refresh();
+	}
+
+	// converts a point on the drawing area into image pixel space
+	pub fn to_image_coordinates(&self, point: (f64, f64)) -> (f64, f64) {
+		let zoom = self.zoom();
+		(point.0 / zoom, point.1 / zoom)
+	}
+
+	fn refresh(&self) {
+		let view = self.view.read().unwrap();
+
+		if let Some(ref pixbuf) = view.pixbuf {
+			let zoom = view.zoom * view.scale;
+			let width = ((pixbuf.get_width() as f64) * zoom).round().max(1.0) as i32;
+			let height = ((pixbuf.get_height() as f64) * zoom).round().max(1.0) as i32;
+
+			// keep the pixels sharp when zooming in, so they can be inspected
+			let interpolation = if zoom >= 1.0 && view.scale == 1.0 { InterpType::Nearest } else { InterpType::Bilinear };
+			match pixbuf.scale_simple(width, height, interpolation) {
+				Some(scaled) => self.image_widget.set_from_pixbuf(&scaled),
+				None => self.image_widget.set_from_pixbuf(pixbuf)
+			}
 		}
+		self.drawing_area.queue_draw();
+	}
+}
+
+pub fn render_image(image_container: &ImageContainer, image: &MyImage) {
+	render_scaled_image(image_container, image, 1.0);
+}
+
+// shows an image `scale` times larger than it is, in place of the open one
+pub fn render_scaled_image(image_container: &ImageContainer, image: &MyImage, scale: f64) {
+	{
+		let mut view = image_container.view.write().unwrap();
+		view.pixbuf = Some(to_pixbuf(image));
+		view.scale = scale;
 	}
+	image_container.refresh();
 }
 
-pub fn render_image(image_widget: &Image, image: &MyImage) {
+pub fn to_pixbuf(image: &MyImage) -> Pixbuf {
 
-	let dynamic_image = image.get_dynamic_image();
-	let pixels = dynamic_image.raw_pixels();
+	// the pixbuf needs pixels of its own, but RGB ones can be copied as they are
+	let dynamic_image = image.as_dynamic_image();
+	let (pixels, has_alpha) = match *dynamic_image {
+		DynamicImage::ImageRgb8(ref buffer) => (buffer.to_vec(), false),
+		DynamicImage::ImageRgba8(ref buffer) => (buffer.to_vec(), true),
+		_ => (dynamic_image.to_rgba().into_raw(), true)
+	};
 	let (width, height) = dynamic_image.dimensions();
+	let channels = if has_alpha { 4 } else { 3 };
 
-	let pixbuf = Pixbuf::new_from_vec(
-	                                  pixels,
-	                                  Colorspace::Rgb,
-	                                  false,
-	                                  8,
-	                                  width as i32,
-	                                  height as i32,
-	                                  3*width as i32);	
-	image_widget.set_halign(Align::Start);
-	image_widget.set_from_pixbuf(&pixbuf);
+	Pixbuf::new_from_vec(
+	                     pixels,
+	                     Colorspace::Rgb,
+	                     has_alpha,
+	                     8,
+	                     width as i32,
+	                     height as i32,
+	                     channels * width as i32)
 }
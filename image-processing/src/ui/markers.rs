@@ -0,0 +1,126 @@
+use failure::{format_err, Error};
+use std::error::Error as OtherError;
+use std::f64::consts::PI;
+use std::sync::{Arc, RwLock};
+use gtk::*;
+use gdk::ContextExt;
+use gdk_pixbuf::Pixbuf;
+use cairo::Context;
+
+use image::Image as MyImage;
+use super::ImageContainer;
+
+// how opaque the label layer is drawn over the image
+const LABELS_ALPHA: f64 = 0.5;
+// detections are drawn through the centers of the pixels they were found at
+pub const PIXEL_CENTER: f64 = 0.5;
+
+// a mark in image coordinates
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Marker {
+	Rectangle { x: f64, y: f64, width: f64, height: f64 },
+	Cross { x: f64, y: f64 },
+	Line { from: (f64, f64), to: (f64, f64) },
+	Circle { x: f64, y: f64, radius: f64 }
+}
+
+// Analysis results drawn over the image, e.g. labeled blobs.
+pub struct Markers {
+	// image sized, transparent where there's nothing to show
+	pub labels: Option<Pixbuf>,
+	pub shapes: Vec<Marker>
+}
+
+impl Markers {
+	pub fn new() -> Self {
+		Self {
+			labels: None,
+			shapes: Vec::new()
+		}
+	}
+
+	pub fn clear(&mut self) {
+		self.labels = None;
+		self.shapes.clear();
+	}
+}
+
+pub fn connect_markers(image_container: &ImageContainer, markers: &Arc<RwLock<Markers>>) {
+
+	let image_container_clone = image_container.clone();
+	let markers = markers.clone();
+	image_container.drawing_area.connect_draw(move |_, cr| {
+		draw_markers(cr, &markers.read().unwrap(), image_container_clone.zoom());
+		Inhibit(false)
+	});
+}
+
+// replaces the markers with the shapes found in the open image, which is left
+// untouched, and returns how many there are
+pub fn detect<F>(current_file: &RwLock<Option<MyImage>>, markers: &RwLock<Markers>, find: F) -> Result<usize, Error>
+	where F: Fn(&MyImage) -> Vec<Marker> {
+
+	let shapes = match current_file.try_read() {
+		Ok(guard) => match *guard {
+			Some(ref image) => find(image),
+			None => return Ok(0)
+		},
+		Err(error) => return Err(format_err!("{}", error.description()))
+	};
+
+	let count = shapes.len();
+	match markers.try_write() {
+		Ok(mut markers) => {
+			markers.clear();
+			markers.shapes = shapes;
+		},
+		Err(error) => return Err(format_err!("{}", error.description()))
+	}
+	Ok(count)
+}
+
+pub fn to_pixel_center(point: (f64, f64)) -> (f64, f64) {
+	(point.0 + PIXEL_CENTER, point.1 + PIXEL_CENTER)
+}
+
+fn draw_markers(cr: &Context, markers: &Markers, zoom: f64) {
+	cr.save();
+	cr.scale(zoom, zoom);
+
+	if let Some(ref labels) = markers.labels {
+		cr.set_source_pixbuf(labels, 0.0, 0.0);
+		cr.paint_with_alpha(LABELS_ALPHA);
+	}
+
+	// marks keep their on-screen size whatever the zoom
+	let cross_size = 4.0 / zoom;
+	for marker in markers.shapes.iter() {
+		match *marker {
+			Marker::Rectangle { x, y, width, height } => cr.rectangle(x, y, width, height),
+			Marker::Cross { x, y } => {
+				cr.move_to(x - cross_size, y);
+				cr.line_to(x + cross_size, y);
+				cr.move_to(x, y - cross_size);
+				cr.line_to(x, y + cross_size);
+			},
+			Marker::Line { from, to } => {
+				cr.move_to(from.0, from.1);
+				cr.line_to(to.0, to.1);
+			},
+			Marker::Circle { x, y, radius } => {
+				cr.new_sub_path();
+				cr.arc(x, y, radius, 0.0, 2.0 * PI);
+			}
+		}
+	}
+
+	// a dark outline under a bright stroke stays visible on any background
+	cr.set_line_width(3.0 / zoom);
+	cr.set_source_rgb(0.0, 0.0, 0.0);
+	cr.stroke_preserve();
+	cr.set_line_width(1.0 / zoom);
+	cr.set_source_rgb(1.0, 0.85, 0.0);
+	cr.stroke();
+
+	cr.restore();
+}
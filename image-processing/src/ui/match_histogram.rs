@@ -0,0 +1,33 @@
+use failure::{format_err, Error};
+use std::error::Error as OtherError;
+use std::sync::RwLock;
+
+use image::Image as MyImage;
+use pipeline::{Operation, Pipeline};
+use super::ImageContainer;
+use super::apply_operation::apply_operation;
+use super::selection_tool::SelectionState;
+use super::dialogs::OpenDialog;
+
+// asks for the reference image, then matches the open image's histogram to it
+pub fn match_histogram(image_container: &ImageContainer,
+                       current_file: &RwLock<Option<MyImage>>,
+                       recipe: &RwLock<Pipeline>,
+                       selection: &RwLock<SelectionState>,
+                       ) -> Result<(), Error> {
+
+	let open_dialog = OpenDialog::new({
+		match current_file.try_read() {
+			Ok(guard) => match *guard {
+				Some(ref image) => image.get_dir(),
+				None => return Ok(())
+			},
+			Err(error) => return Err(format_err!("{}", error.description()))
+		}
+	});
+
+	if let Some(reference) = open_dialog.run() {
+		apply_operation(image_container, current_file, recipe, selection, Operation::MatchHistogram { reference })?;
+	}
+	Ok(())
+}
@@ -3,12 +3,32 @@ mod connected_app;
 mod header;
 mod sidemenu;
 mod content;
+mod status_bar;
 mod dialogs;
 
 mod open;
 mod save;
 mod image_container;
-mod equalize_histogram;
+mod apply_operation;
+mod preview;
+mod recipe;
+mod selection_tool;
+mod pixel_inspector;
+mod statistics;
+mod spectrum;
+mod match_histogram;
+mod blend;
+mod markers;
+mod blobs;
+mod hough;
+mod corners;
+mod templates;
+mod stitch;
+mod hdr;
+mod frames;
+mod filmstrip;
+mod annotations;
+mod perspective;
 
 pub use self::app::App;
 pub use self::connected_app::ConnectedApp;
@@ -16,6 +36,7 @@ pub use self::content::Content;
 pub use self::sidemenu::SideMenu;
 pub use self::image_container::ImageContainer;
 pub use self::header::Header;
+pub use self::status_bar::StatusBar;
 pub use self::save::save;
 pub use self::open::open;
-pub use self::equalize_histogram::equalize_histogram;
\ No newline at end of file
+pub use self::apply_operation::apply_operation;
\ No newline at end of file
@@ -1,16 +1,24 @@
 use failure::{err_msg, format_err, Error};
 use std::error::Error as OtherError;
+use std::path::PathBuf;
 use std::sync::RwLock;
 use gtk::*;
 
-use ui::image_container::render_image;
-use image::Image as MyImage;
+use ui::image_container::{ImageContainer, render_image};
+use image::{FullDepth, Image as MyImage};
+use tiles::{overview_path, TiledImage, OVERVIEW_SIZE};
+use animation::{is_animated, Animation};
+use depth::is_high_depth;
+use ui::frames::{forget_animation, show_frame, FrameBar};
 use super::dialogs::open_dialog::OpenDialog;
 
 pub fn open (headerbar: &HeaderBar,
-             image_container: &Image,
+             image_container: &ImageContainer,
+             frame_bar: &FrameBar,
              current_file: &RwLock<Option<MyImage>>,
-             ) -> Result<(), Error> {
+             full_depth: &RwLock<Option<FullDepth>>,
+             animation: &RwLock<Option<Animation>>,
+             ) -> Result<Option<PathBuf>, Error> {
 
 
 	let open_dialog = OpenDialog::new({
@@ -24,15 +32,65 @@ pub fn open (headerbar: &HeaderBar,
 
 	});
 
-	if let Some(file_path) = open_dialog.run() {
-		match MyImage::open(&file_path) {
-			Ok(mut image) => {
-				headerbar.set_title(file_path.to_str());
-				render_image(&image_container, &image);
-				*current_file.write().unwrap() = Some(image);
-			},
-			Err(error) => return Err(err_msg(error))
-		}
+	match open_dialog.run() {
+		Some(file_path) => {
+			open_path(headerbar, image_container, frame_bar, current_file, full_depth, animation, &file_path)?;
+			Ok(Some(file_path))
+		},
+		None => Ok(None)
+	}
+}
+
+// Opens an image without asking for it, as the filmstrip does.
+pub fn open_path (headerbar: &HeaderBar,
+                  image_container: &ImageContainer,
+                  frame_bar: &FrameBar,
+                  current_file: &RwLock<Option<MyImage>>,
+                  full_depth: &RwLock<Option<FullDepth>>,
+                  animation: &RwLock<Option<Animation>>,
+                  file_path: &PathBuf,
+                  ) -> Result<(), Error> {
+
+	*full_depth.write().unwrap() = None;
+
+	// animations show their first frame, the frame bar the others
+	if is_animated(file_path) {
+		let opened = Animation::open(file_path)?;
+		frame_bar.show_animation(&opened);
+		*animation.write().unwrap() = Some(opened);
+		headerbar.set_title(file_path.to_str());
+		return show_frame(frame_bar, image_container, current_file, animation, 0);
+	}
+	forget_animation(frame_bar, animation);
+
+	// large images are shown as an overview, saved apart from the image
+	if TiledImage::should_tile(file_path) {
+		let overview = TiledImage::open(file_path)?.overview(OVERVIEW_SIZE)?;
+		let image = MyImage::new(&overview_path(file_path), overview);
+		headerbar.set_title(Some(format!("{} (overview)", file_path.display()).as_str()));
+		render_image(&image_container, &image);
+		*current_file.write().unwrap() = Some(image);
+		return Ok(());
+	}
+
+	// 16 bit and floating point images are shown at 8 bits, their samples kept
+	// for saving
+	if is_high_depth(file_path) {
+		let opened = FullDepth::open(file_path)?;
+		headerbar.set_title(file_path.to_str());
+		render_image(&image_container, opened.shown());
+		*current_file.write().unwrap() = Some(opened.shown().clone());
+		*full_depth.write().unwrap() = Some(opened);
+		return Ok(());
+	}
+
+	match MyImage::open(file_path) {
+		Ok(image) => {
+			headerbar.set_title(file_path.to_str());
+			render_image(&image_container, &image);
+			*current_file.write().unwrap() = Some(image);
+			Ok(())
+		},
+		Err(error) => Err(err_msg(error))
 	}
-	Ok(())
-}
\ No newline at end of file
+}
@@ -0,0 +1,48 @@
+use failure::{err_msg, format_err, Error};
+use std::error::Error as OtherError;
+use std::sync::RwLock;
+use gtk::*;
+
+use image::Image as MyImage;
+use pipeline::{Operation, Pipeline};
+use perspective::{order_corners, rectified_size};
+use super::ImageContainer;
+use super::apply_operation::apply_operation;
+use super::selection_tool::SelectionState;
+use super::sidemenu::PerspectiveControls;
+
+// Warps the region between the four clicked corners onto a rectangle. Width
+// and height left at 0 keep the size of the region.
+pub fn correct_perspective(image_container: &ImageContainer,
+                           current_file: &RwLock<Option<MyImage>>,
+                           recipe: &RwLock<Pipeline>,
+                           selection: &RwLock<SelectionState>,
+                           controls: &PerspectiveControls,
+                           ) -> Result<(), Error> {
+
+	let corners = match selection.try_write() {
+		Ok(mut state) => {
+			if state.corners.len() != 4 {
+				return Err(err_msg("click the four corners of the region first"));
+			}
+			let corners = order_corners([state.corners[0], state.corners[1], state.corners[2], state.corners[3]]);
+			// neither the corners nor the selection make sense on the warped image
+			state.clear();
+			corners
+		},
+		Err(error) => return Err(format_err!("{}", error.description()))
+	};
+
+	let (region_width, region_height) = rectified_size(&corners);
+	let width = match controls.width.get_value_as_int() {
+		0 => region_width,
+		width => width as u32
+	};
+	let height = match controls.height.get_value_as_int() {
+		0 => region_height,
+		height => height as u32
+	};
+
+	let operation = Operation::WarpPerspective { corners, width, height, interpolation: controls.interpolation() };
+	apply_operation(image_container, current_file, recipe, selection, operation)
+}
@@ -0,0 +1,139 @@
+use std::sync::{Arc, RwLock};
+use gtk::*;
+use cairo::Context;
+
+use image::{FullDepth, Image as MyImage};
+use image::image::{ColorType, Pixel, Rgba};
+use super::ImageContainer;
+use super::status_bar::{StatusBar, LOUPE_PIXELS, LOUPE_MAGNIFICATION};
+
+// Reports the pixel under the cursor in the status bar and magnifies its
+// surroundings in the loupe.
+pub fn connect_pixel_inspector(image_container: &ImageContainer,
+                               status_bar: &StatusBar,
+                               current_file: Arc<RwLock<Option<MyImage>>>,
+                               full_depth: Arc<RwLock<Option<FullDepth>>>) {
+
+	// image coordinates of the inspected pixel
+	let cursor: Arc<RwLock<Option<(u32, u32)>>> = Arc::new(RwLock::new(None));
+
+	{
+		let view = image_container.clone();
+		let status_bar = status_bar.clone();
+		let current_file = current_file.clone();
+		let cursor = cursor.clone();
+
+		image_container.drawing_area.connect_motion_notify_event(move |_, event| {
+			let (x, y) = view.to_image_coordinates(event.get_position());
+			let (x, y) = (x.floor() as i64, y.floor() as i64);
+
+			let pixel = match current_file.try_read() {
+				Ok(guard) => match *guard {
+					Some(ref image) if x >= 0 && y >= 0 =>
+						image.get_pixel(x as u32, y as u32).map(|rgba| {
+							// the samples the image was made from tell more than its 8 bits
+							let described = describe_full_depth(&full_depth, image, x as u32, y as u32)
+								.unwrap_or_else(|| (format!("{:?}", image.get_color_type()), describe_pixel(&rgba, image.get_color_type())));
+							(rgba, described)
+						}),
+					_ => None
+				},
+				Err(_) => return Inhibit(false)
+			};
+
+			match pixel {
+				Some((rgba, (pixel_format, value))) => {
+					status_bar.position.set_text(&format!("x: {}  y: {}", x, y));
+					status_bar.pixel_format.set_text(&pixel_format);
+					status_bar.value.set_text(&value);
+					status_bar.luminance.set_text(&format!("L {}", rgba.to_luma()[0]));
+					*cursor.write().unwrap() = Some((x as u32, y as u32));
+				},
+				None => {
+					status_bar.clear();
+					*cursor.write().unwrap() = None;
+				}
+			}
+			status_bar.loupe.queue_draw();
+			Inhibit(false)
+		});
+	}
+
+	{
+		let status_bar = status_bar.clone();
+		let cursor = cursor.clone();
+
+		image_container.drawing_area.connect_leave_notify_event(move |_, _| {
+			status_bar.clear();
+			*cursor.write().unwrap() = None;
+			status_bar.loupe.queue_draw();
+			Inhibit(false)
+		});
+	}
+
+	status_bar.loupe.connect_draw(move |_, cr| {
+		if let Some(center) = *cursor.read().unwrap() {
+			if let Ok(guard) = current_file.try_read() {
+				if let Some(ref image) = *guard {
+					draw_loupe(cr, image, center);
+				}
+			}
+		}
+		Inhibit(false)
+	});
+}
+
+// the format and the samples of the pixel at full depth, as long as the image
+// still stands for them
+fn describe_full_depth(full_depth: &RwLock<Option<FullDepth>>, image: &MyImage, x: u32, y: u32) -> Option<(String, String)> {
+	match full_depth.try_read() {
+		Ok(guard) => match *guard {
+			Some(ref full_depth) if full_depth.stands_for(image) => {
+				let precise = full_depth.image();
+				precise.describe_pixel(x, y)
+					.map(|value| (format!("{:?}, {} channels", precise.format(), precise.channels()), value))
+			},
+			_ => None
+		},
+		Err(_) => None
+	}
+}
+
+// only lists the channels the image actually has
+fn describe_pixel(rgba: &Rgba<u8>, color_type: ColorType) -> String {
+	match color_type {
+		ColorType::Gray(_) => format!("gray {}", rgba[0]),
+		ColorType::GrayA(_) => format!("gray {}  A {}", rgba[0], rgba[3]),
+		ColorType::RGB(_) | ColorType::BGR(_) =>
+			format!("R {}  G {}  B {}", rgba[0], rgba[1], rgba[2]),
+		_ => format!("R {}  G {}  B {}  A {}", rgba[0], rgba[1], rgba[2], rgba[3])
+	}
+}
+
+fn draw_loupe(cr: &Context, image: &MyImage, center: (u32, u32)) {
+	let radius = (LOUPE_PIXELS / 2) as i64;
+	let size = LOUPE_MAGNIFICATION as f64;
+
+	for dy in -radius..radius + 1 {
+		for dx in -radius..radius + 1 {
+			let x = center.0 as i64 + dx;
+			let y = center.1 as i64 + dy;
+
+			let pixel = if x >= 0 && y >= 0 { image.get_pixel(x as u32, y as u32) } else { None };
+			match pixel {
+				Some(rgba) => cr.set_source_rgb(rgba[0] as f64 / 255.0,
+				                                rgba[1] as f64 / 255.0,
+				                                rgba[2] as f64 / 255.0),
+				None => cr.set_source_rgb(0.2, 0.2, 0.2)
+			}
+			cr.rectangle(((dx + radius) as f64) * size, ((dy + radius) as f64) * size, size, size);
+			cr.fill();
+		}
+	}
+
+	// outline the inspected pixel
+	cr.set_line_width(1.0);
+	cr.set_source_rgb(1.0, 0.0, 0.0);
+	cr.rectangle((radius as f64) * size + 0.5, (radius as f64) * size + 0.5, size - 1.0, size - 1.0);
+	cr.stroke();
+}
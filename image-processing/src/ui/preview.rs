@@ -0,0 +1,51 @@
+use failure::{format_err, Error};
+use std::error::Error as OtherError;
+use std::sync::RwLock;
+
+use ui::image_container::{ImageContainer, render_image, render_scaled_image};
+use image::Image as MyImage;
+use image::image::GenericImageView;
+use pipeline::{proxy_scale, Operation, Step};
+use super::selection_tool::SelectionState;
+
+// the largest side of the copy previews are computed on
+pub const PROXY_SIZE: u32 = 512;
+
+// Shows what the operation would do to the open image, which is left as it is.
+pub fn preview_operation(image_container: &ImageContainer,
+                         current_file: &RwLock<Option<MyImage>>,
+                         selection: &RwLock<SelectionState>,
+                         operation: Operation,
+                         ) -> Result<(), Error> {
+
+	let step = match selection.try_read() {
+		Ok(guard) => Step { operation, selection: guard.selection.clone() },
+		Err(error) => return Err(format_err!("{}", error.description()))
+	};
+
+	// the preview is computed on a smaller copy, enlarged again when shown
+	let (preview, scale) = match current_file.try_read() {
+		Ok(guard) => match *guard {
+			Some(ref image) => (MyImage::new(&image.get_image_path(), step.preview(image, PROXY_SIZE)?),
+			                    proxy_scale(image.as_dynamic_image().dimensions(), PROXY_SIZE)),
+			None => return Ok(())
+		},
+		Err(error) => return Err(format_err!("{}", error.description()))
+	};
+
+	render_scaled_image(image_container, &preview, scale);
+	Ok(())
+}
+
+// shows the open image again
+pub fn end_preview(image_container: &ImageContainer, current_file: &RwLock<Option<MyImage>>) -> Result<(), Error> {
+	match current_file.try_read() {
+		Ok(guard) => {
+			if let Some(ref image) = *guard {
+				render_image(image_container, image);
+			}
+			Ok(())
+		},
+		Err(error) => Err(format_err!("{}", error.description()))
+	}
+}
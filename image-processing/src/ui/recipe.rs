@@ -0,0 +1,138 @@
+use failure::{err_msg, format_err, Error};
+use std::error::Error as OtherError;
+use std::sync::RwLock;
+use std::sync::mpsc::{channel, TryRecvError};
+use std::path::PathBuf;
+use std::thread;
+use gtk::*;
+
+use ui::image_container::{ImageContainer, render_image};
+use image::Image as MyImage;
+use pipeline::Pipeline;
+use super::dialogs::{OpenDialog, SaveDialog, FolderDialog, BatchDialog};
+
+const RECIPE_PATTERN: &str = "*.json";
+// how often a running batch is checked on, in milliseconds
+const POLL_INTERVAL: u32 = 100;
+
+fn current_dir(current_file: &RwLock<Option<MyImage>>) -> Result<Option<PathBuf>, Error> {
+	match current_file.try_read() {
+		Ok(guard) => match *guard {
+			Some(ref image) => Ok(image.get_dir()),
+			None => Ok(None)
+		},
+		Err(error) => Err(format_err!("{}", error.description()))
+	}
+}
+
+pub fn save_recipe(current_file: &RwLock<Option<MyImage>>,
+                   recipe: &RwLock<Pipeline>,
+                   ) -> Result<(), Error> {
+
+	let save_dialog = SaveDialog::new(current_dir(current_file)?);
+	save_dialog.add_filter("Recipes", RECIPE_PATTERN);
+
+	if let Some(mut recipe_path) = save_dialog.run() {
+		if recipe_path.extension().is_none() {
+			recipe_path.set_extension("json");
+		}
+		match recipe.try_read() {
+			Ok(guard) => { guard.save(&recipe_path)?; },
+			Err(error) => return Err(format_err!("{}", error.description()))
+		}
+	}
+	Ok(())
+}
+
+// Replays a recipe file on the open image (if any) and appends its operations
+// to the session recipe, so it can be saved or applied to a folder afterwards.
+pub fn load_recipe(image_container: &ImageContainer,
+                   current_file: &RwLock<Option<MyImage>>,
+                   recipe: &RwLock<Pipeline>,
+                   ) -> Result<(), Error> {
+
+	let open_dialog = OpenDialog::new(current_dir(current_file)?);
+	open_dialog.add_filter("Recipes", RECIPE_PATTERN);
+
+	let loaded = match open_dialog.run() {
+		Some(recipe_path) => Pipeline::open(&recipe_path)?,
+		None => return Ok(())
+	};
+
+	let new_image =
+	match current_file.try_read() {
+		Ok(guard) => match *guard {
+			Some(ref image) => Some(loaded.apply(image)?),
+			None => None
+		},
+		Err(error) => return Err(format_err!("{}", error.description()))
+	};
+
+	if let Some(new_image) = new_image {
+		render_image(&image_container, &new_image);
+		*current_file.write().unwrap() = Some(new_image);
+	}
+
+	match recipe.try_write() {
+		Ok(mut guard) => guard.steps.extend(loaded.steps),
+		Err(error) => return Err(format_err!("{}", error.description()))
+	}
+	Ok(())
+}
+
+// Replays the recipe on every image of a folder on a worker thread, so that the
+// window keeps responding, and reports the results once it's done. The button
+// stays insensitive while the batch runs.
+pub fn apply_recipe_to_folder(button: &Button,
+                              current_file: &RwLock<Option<MyImage>>,
+                              recipe: &RwLock<Pipeline>,
+                              ) -> Result<(), Error> {
+
+	let pipeline = match recipe.try_read() {
+		Ok(guard) => guard.clone(),
+		Err(error) => return Err(format_err!("{}", error.description()))
+	};
+
+	if pipeline.is_empty() {
+		return Ok(());
+	}
+
+	let input_dir = match FolderDialog::new("Images to process", current_dir(current_file)?).run() {
+		Some(input_dir) => input_dir,
+		None => return Ok(())
+	};
+	let output_dir = match FolderDialog::new("Save results to", Some(input_dir.clone())).run() {
+		Some(output_dir) => output_dir,
+		None => return Ok(())
+	};
+
+	let (sender, receiver) = channel();
+	{
+		let output_dir = output_dir.clone();
+		thread::spawn(move || {
+			let _ = sender.send(pipeline.apply_to_dir(&input_dir, &output_dir));
+		});
+	}
+
+	button.set_sensitive(false);
+	let button = button.clone();
+	timeout_add(POLL_INTERVAL, move || {
+		let batch = match receiver.try_recv() {
+			Ok(batch) => batch,
+			Err(TryRecvError::Empty) => return Continue(true),
+			Err(TryRecvError::Disconnected) => Err(err_msg("the batch stopped before it was done"))
+		};
+		BatchDialog::new(&output_dir, &batch).run();
+		button.set_sensitive(true);
+		Continue(false)
+	});
+	Ok(())
+}
+
+pub fn clear_recipe(recipe: &RwLock<Pipeline>) -> Result<(), Error> {
+	match recipe.try_write() {
+		Ok(mut guard) => guard.clear(),
+		Err(error) => return Err(format_err!("{}", error.description()))
+	}
+	Ok(())
+}
@@ -2,40 +2,60 @@ use failure::{err_msg, format_err, Error};
 use std::error::Error as OtherError;
 use std::sync::RwLock;
 use gtk::*;
-use image::image::DynamicImage;
-use image::Image;
+use std::path::PathBuf;
+use image::{FullDepth, Image};
+use depth::is_high_depth;
 use super::dialogs::save_dialog::SaveDialog;
+use super::annotations::{AnnotationLayer, flatten};
 
 pub enum SaveAction {
 	New(Image),
+	// a copy with the annotations drawn in, which stays apart from the open image
+	Exported,
 	Saved,
 	Canceled
 }
 
-fn save_image(image: Option<& Image>, dynamic_image: Option<DynamicImage>) -> Result<SaveAction, Error> {
+// at full depth while the samples the image was opened with stand for it
+fn write_image(image: &Image, full_depth: Option<&FullDepth>, path: &PathBuf) -> Result<PathBuf, Error> {
+	match full_depth {
+		Some(samples) if samples.stands_for(image) => samples.image().save(path),
+		_ => image.save_image(Some(path))
+	}
+}
 
-	// if there's no file open, then there wont be a dynamic_image
-	if let Some(dynamic_image) = dynamic_image {
+fn save_image(image: &Image, full_depth: Option<&FullDepth>, save_as: bool, annotations: &RwLock<AnnotationLayer>, flatten_annotations: bool) -> Result<SaveAction, Error> {
 
-		// user clicked the 'Save' button
-		if let Some(image) = image {
-			if let Err(error) = image.save_image(None){
-				return Err(err_msg(error));
-			}
-			return Ok(SaveAction::Saved);
+	// user clicked the 'Save' button
+	if !save_as {
+		if let Err(error) = write_image(image, full_depth, &image.get_image_path()) {
+			return Err(err_msg(error));
 		}
+		return Ok(SaveAction::Saved);
+	}
 
-		// user clicked the 'Save As' button
-		let save_dialog = SaveDialog::new(None);
-		if let Some(new_path) = save_dialog.run() {
-
-			let mut new_image = Image::new(&new_path, &dynamic_image);
+	// user clicked the 'Save As' button
+	let save_dialog = SaveDialog::new(None);
+	if let Some(new_path) = save_dialog.run() {
 
-			if let Err(error) = new_image.save_image(None) {
-				return Err(err_msg(error));
+		if flatten_annotations {
+			let annotations = annotations.read().unwrap().annotations.clone();
+			if !annotations.annotations.is_empty() {
+				let flattened = flatten(image, &annotations)?.with_path(&new_path);
+				if let Err(error) = flattened.save_image(None) {
+					return Err(err_msg(error));
+				}
+				return Ok(SaveAction::Exported);
 			}
-			return Ok(SaveAction::New(new_image));
 		}
+
+		// shares the pixels of the open image
+		let new_image = image.with_path(&new_path);
+
+		if let Err(error) = write_image(image, full_depth, &new_path) {
+			return Err(err_msg(error));
+		}
+		return Ok(SaveAction::New(new_image));
 	}
 	// user canceled the dialog
 	Ok(SaveAction::Canceled)
@@ -43,31 +63,29 @@ fn save_image(image: Option<& Image>, dynamic_image: Option<DynamicImage>) -> Re
 
 pub fn save(headerbar: &HeaderBar,
             current_file: &RwLock<Option<Image>>,
+            full_depth: &RwLock<Option<FullDepth>>,
             save_as: bool,
+            annotations: &RwLock<AnnotationLayer>,
+            flatten_annotations: bool,
             ) -> Result<(), Error> {
 
-	let dynamic_image = match current_file.try_read() {
-		Ok(guard) => match *guard {
-			Some(ref image) => Some(image.get_dynamic_image()),
-			None => None
-		},
+	// a copy sharing the pixels, so that the file isn't locked while the dialog
+	// is open
+	let image = match current_file.try_read() {
+		Ok(guard) => guard.clone(),
 		Err(error) => return Err(format_err!("{}", error.description()))
 	};
 
-	// user has clicked the  'Save As' button
-	let result =
-		if save_as {
-			save_image(None, dynamic_image)
-		}
-		else {
-			match current_file.try_read() {
-				Ok(guard) => match *guard {
-					Some(ref image) => save_image(Some(image), dynamic_image),
-					None => Ok(SaveAction::Canceled)
-				},
-				Err(error) => return Err(format_err!("{}", error.description()))
-			}
-		};
+	let samples = match full_depth.try_read() {
+		Ok(guard) => guard.clone(),
+		Err(error) => return Err(format_err!("{}", error.description()))
+	};
+
+	// if there's no file open, then there's nothing to save
+	let result = match image {
+		Some(ref image) => save_image(image, samples.as_ref(), save_as, annotations, flatten_annotations),
+		None => Ok(SaveAction::Canceled)
+	};
 
 	match result {
 		Ok(SaveAction::Saved) => {
@@ -79,11 +97,20 @@ pub fn save(headerbar: &HeaderBar,
 
 			headerbar.set_title(image_path.to_str());
 
-			match Image::open(&image_path){
-				Ok(mut image) => *current_file.write().unwrap() = Some(image),
-				Err(error) => return Err(err_msg(error))
+			if is_high_depth(&image_path) {
+				let opened = FullDepth::open(&image_path)?;
+				*current_file.write().unwrap() = Some(opened.shown().clone());
+				*full_depth.write().unwrap() = Some(opened);
 			}
-			Ok(())
+			else {
+				match Image::open(&image_path){
+					Ok(mut image) => *current_file.write().unwrap() = Some(image),
+					Err(error) => return Err(err_msg(error))
+				}
+				*full_depth.write().unwrap() = None;
+			}
+			// the annotations go along with the image
+			annotations.write().unwrap().move_to(&image_path)
 		},
 		Err(error) => return Err(err_msg(error)),
 		_ => Ok(())
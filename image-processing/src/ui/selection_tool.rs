@@ -0,0 +1,211 @@
+use std::f64::consts::PI;
+use std::sync::{Arc, RwLock};
+use gtk::*;
+use cairo::Context;
+
+use selection::Selection;
+use super::ImageContainer;
+
+pub const RECTANGLE_TOOL: &str = "rectangle";
+pub const ELLIPSE_TOOL: &str = "ellipse";
+pub const FREEHAND_TOOL: &str = "freehand";
+// clicks the four corners of a region, e.g. for perspective correction
+pub const CORNERS_TOOL: &str = "corners";
+const CORNER_SIZE: f64 = 6.0;
+
+pub struct SelectionState {
+	pub selection: Option<Selection>,
+	pub corners: Vec<(f64, f64)>,
+	// where the pointer was pressed, while a selection is being drawn
+	anchor: Option<(f64, f64)>
+}
+
+impl SelectionState {
+	pub fn new() -> Self {
+		Self {
+			selection: None,
+			corners: Vec::new(),
+			anchor: None
+		}
+	}
+
+	pub fn clear(&mut self) {
+		self.selection = None;
+		self.corners.clear();
+		self.anchor = None;
+	}
+
+	fn begin(&mut self, tool: &str, point: (f64, f64)) -> bool {
+		if tool == CORNERS_TOOL {
+			// a fifth click starts over
+			if self.corners.len() == 4 {
+				self.corners.clear();
+			}
+			self.corners.push(point);
+			return true;
+		}
+
+		self.selection = match tool {
+			RECTANGLE_TOOL => Some(Selection::rectangle(point, point)),
+			ELLIPSE_TOOL => Some(Selection::ellipse(point, point)),
+			FREEHAND_TOOL => Some(Selection::Freehand { points: vec![point] }),
+			_ => return false
+		};
+		self.anchor = Some(point);
+		true
+	}
+
+	fn extend(&mut self, point: (f64, f64)) -> bool {
+		let anchor = match self.anchor {
+			Some(anchor) => anchor,
+			None => return false
+		};
+
+		self.selection = match self.selection.take() {
+			Some(Selection::Rectangle { .. }) => Some(Selection::rectangle(anchor, point)),
+			Some(Selection::Ellipse { .. }) => Some(Selection::ellipse(anchor, point)),
+			Some(Selection::Freehand { mut points }) => {
+				points.push(point);
+				Some(Selection::Freehand { points })
+			},
+			None => None
+		};
+		true
+	}
+
+	fn finish(&mut self) -> bool {
+		if self.anchor.take().is_none() {
+			return false;
+		}
+
+		// a click without a drag drops the selection instead of selecting nothing
+		let degenerate = match self.selection {
+			Some(Selection::Rectangle { width, height, .. }) => width < 1.0 || height < 1.0,
+			Some(Selection::Ellipse { radius_x, radius_y, .. }) => radius_x < 0.5 || radius_y < 0.5,
+			Some(Selection::Freehand { ref points }) => points.len() < 3,
+			None => false
+		};
+		if degenerate {
+			self.selection = None;
+		}
+		true
+	}
+}
+
+pub fn connect_selection_events(image_container: &ImageContainer,
+                                tool: &ComboBoxText,
+                                state: &Arc<RwLock<SelectionState>>) {
+
+	let drawing_area = &image_container.drawing_area;
+	{
+		let image_container = image_container.clone();
+		let tool = tool.clone();
+		let state = state.clone();
+		drawing_area.connect_button_press_event(move |da, event| {
+			if event.get_button() == 1 {
+				if let Some(tool) = tool.get_active_id() {
+					let point = image_container.to_image_coordinates(event.get_position());
+					if state.write().unwrap().begin(&tool, point) {
+						da.queue_draw();
+					}
+				}
+			}
+			Inhibit(false)
+		});
+	}
+
+	{
+		let image_container = image_container.clone();
+		let state = state.clone();
+		drawing_area.connect_motion_notify_event(move |da, event| {
+			let point = image_container.to_image_coordinates(event.get_position());
+			if state.write().unwrap().extend(point) {
+				da.queue_draw();
+			}
+			Inhibit(false)
+		});
+	}
+
+	{
+		let state = state.clone();
+		drawing_area.connect_button_release_event(move |da, event| {
+			if event.get_button() == 1 && state.write().unwrap().finish() {
+				da.queue_draw();
+			}
+			Inhibit(false)
+		});
+	}
+
+	let image_container = image_container.clone();
+	let state = state.clone();
+	drawing_area.connect_draw(move |_, cr| {
+		let state = state.read().unwrap();
+		if let Some(ref selection) = state.selection {
+			draw_selection(cr, selection, image_container.zoom());
+		}
+		draw_corners(cr, &state.corners, image_container.zoom());
+		Inhibit(false)
+	});
+}
+
+fn draw_selection(cr: &Context, selection: &Selection, zoom: f64) {
+	cr.save();
+	// the path is built in image coordinates, the outline keeps its on-screen width
+	cr.scale(zoom, zoom);
+	cr.set_line_width(1.0 / zoom);
+
+	match *selection {
+		Selection::Rectangle { x, y, width, height } => cr.rectangle(x, y, width, height),
+		Selection::Ellipse { center_x, center_y, radius_x, radius_y } => {
+			if radius_x > 0.0 && radius_y > 0.0 {
+				cr.save();
+				cr.translate(center_x, center_y);
+				cr.scale(radius_x, radius_y);
+				cr.arc(0.0, 0.0, 1.0, 0.0, 2.0 * PI);
+				cr.restore();
+			}
+		},
+		Selection::Freehand { ref points } => {
+			for (i, &(x, y)) in points.iter().enumerate() {
+				if i == 0 { cr.move_to(x, y); } else { cr.line_to(x, y); }
+			}
+			cr.close_path();
+		}
+	}
+
+	// black and white dashes stay visible on any background
+	cr.set_source_rgb(0.0, 0.0, 0.0);
+	cr.stroke_preserve();
+	cr.set_source_rgb(1.0, 1.0, 1.0);
+	cr.set_dash(&[4.0 / zoom, 4.0 / zoom], 0.0);
+	cr.stroke();
+
+	cr.restore();
+}
+
+fn draw_corners(cr: &Context, corners: &[(f64, f64)], zoom: f64) {
+	if corners.is_empty() {
+		return;
+	}
+
+	cr.save();
+	// drawn in screen coordinates, so the handles keep their size
+	for (i, &(x, y)) in corners.iter().enumerate() {
+		if i == 0 { cr.move_to(x * zoom, y * zoom); } else { cr.line_to(x * zoom, y * zoom); }
+	}
+	if corners.len() == 4 {
+		cr.close_path();
+	}
+	for &(x, y) in corners.iter() {
+		cr.rectangle(x * zoom - CORNER_SIZE / 2.0, y * zoom - CORNER_SIZE / 2.0, CORNER_SIZE, CORNER_SIZE);
+	}
+
+	cr.set_line_width(1.0);
+	cr.set_source_rgb(0.0, 0.0, 0.0);
+	cr.stroke_preserve();
+	cr.set_source_rgb(1.0, 1.0, 1.0);
+	cr.set_dash(&[4.0, 4.0], 0.0);
+	cr.stroke();
+
+	cr.restore();
+}
@@ -1,9 +1,270 @@
+use std::rc::Rc;
 use gtk::*;
+use gdk::RGBA;
+use super::selection_tool::{RECTANGLE_TOOL, ELLIPSE_TOOL, FREEHAND_TOOL, CORNERS_TOOL};
+use super::annotations::{
+	ARROW_ANNOTATION, RECTANGLE_ANNOTATION, ELLIPSE_ANNOTATION, FREEHAND_ANNOTATION, TEXT_ANNOTATION, EDIT_ANNOTATIONS
+};
+use fft::{FrequencyFilter, FilterBand, FilterShape};
+use blobs::Connectivity;
+use perspective::Interpolation;
+use operations::{registry, ImageOperation, Arguments, ParameterKind, Value};
+use annotations::{Annotation, Shape};
+
+pub const NOTCH_BAND: &str = "notch";
+pub const HARRIS_DETECTOR: &str = "harris";
+pub const SHI_TOMASI_DETECTOR: &str = "shi_tomasi";
+pub const FAST_DETECTOR: &str = "fast";
+pub const SQUARED_DIFFERENCES: &str = "ssd";
+pub const CROSS_CORRELATION: &str = "ncc";
+pub const ZERO_MEAN_CROSS_CORRELATION: &str = "zncc";
+
+// the finest step of the sliders of number parameters
+const MIN_SCALE_STEP: f64 = 0.01;
+
+// the widgets describing a frequency domain filter
+#[derive(Clone)]
+pub struct FrequencyControls {
+	pub band: ComboBoxText,
+	pub shape: ComboBoxText,
+	pub cutoff: SpinButton,
+	pub upper_cutoff: SpinButton,
+	pub order: SpinButton,
+	pub notch_u: SpinButton,
+	pub notch_v: SpinButton
+}
+
+impl FrequencyControls {
+	pub fn filter(&self) -> FrequencyFilter {
+		let cutoff = self.cutoff.get_value();
+
+		let band = match self.band.get_active_id().as_ref().map(|id| id.as_str()) {
+			Some("high_pass") => FilterBand::HighPass { cutoff },
+			Some("band_pass") => FilterBand::BandPass { low: cutoff, high: self.upper_cutoff.get_value() },
+			Some(NOTCH_BAND) => FilterBand::Notch {
+				u: self.notch_u.get_value(),
+				v: self.notch_v.get_value(),
+				radius: cutoff
+			},
+			_ => FilterBand::LowPass { cutoff }
+		};
+
+		let shape = match self.shape.get_active_id().as_ref().map(|id| id.as_str()) {
+			Some("gaussian") => FilterShape::Gaussian,
+			Some("butterworth") => FilterShape::Butterworth { order: self.order.get_value_as_int() as u32 },
+			_ => FilterShape::Ideal
+		};
+
+		FrequencyFilter { band, shape }
+	}
+}
+
+// the widget holding the argument of a parameter
+#[derive(Clone)]
+pub enum ParameterWidget {
+	Integer(Scale),
+	Number(Scale),
+	Choice(ComboBoxText),
+	Boolean(CheckButton)
+}
+
+impl ParameterWidget {
+	pub fn value(&self) -> Option<Value> {
+		match *self {
+			ParameterWidget::Integer(ref scale) => Some(Value::Integer(scale.get_value().round() as i64)),
+			ParameterWidget::Number(ref scale) => Some(Value::Number(scale.get_value())),
+			ParameterWidget::Choice(ref combo) => combo.get_active_id().map(Value::Text),
+			ParameterWidget::Boolean(ref check_button) => Some(Value::Boolean(check_button.get_active()))
+		}
+	}
+
+	pub fn connect_changed(&self, changed: Rc<dyn Fn()>) {
+		match *self {
+			ParameterWidget::Integer(ref scale) | ParameterWidget::Number(ref scale) => {
+				scale.connect_value_changed(move |_| changed());
+			},
+			ParameterWidget::Choice(ref combo) => {
+				combo.connect_changed(move |_| changed());
+			},
+			ParameterWidget::Boolean(ref check_button) => {
+				check_button.connect_toggled(move |_| changed());
+			}
+		}
+	}
+}
+
+// The panel of an operation of the registry. Operations without parameters
+// are a single button, the others an expander holding their parameters, to be
+// previewed while it's open, and an apply button.
+#[derive(Clone)]
+pub struct OperationControls {
+	pub name: String,
+	pub panel: Option<Expander>,
+	pub apply: Button,
+	pub parameters: Vec<(String, ParameterWidget)>
+}
+
+impl OperationControls {
+	pub fn arguments(&self) -> Arguments {
+		self.parameters.iter()
+		.filter_map(|&(ref name, ref widget)| widget.value().map(|value| (name.clone(), value)))
+		.collect()
+	}
+
+	// called whenever any of the parameters changes
+	pub fn connect_changed(&self, changed: Rc<dyn Fn()>) {
+		for &(_, ref widget) in self.parameters.iter() {
+			widget.connect_changed(changed.clone());
+		}
+	}
+}
+
+// the widgets of the blob analysis
+#[derive(Clone)]
+pub struct BlobControls {
+	pub connectivity: ComboBoxText,
+	pub count: Label
+}
+
+impl BlobControls {
+	pub fn connectivity(&self) -> Connectivity {
+		match self.connectivity.get_active_id().as_ref().map(|id| id.as_str()) {
+			Some("eight") => Connectivity::Eight,
+			_ => Connectivity::Four
+		}
+	}
+}
+
+// the widgets of the Hough transforms
+#[derive(Clone)]
+pub struct HoughControls {
+	pub votes: SpinButton,
+	pub min_length: SpinButton,
+	pub max_gap: SpinButton,
+	pub min_radius: SpinButton,
+	pub max_radius: SpinButton,
+	pub coverage: SpinButton,
+	pub count: Label
+}
+
+// the widgets of the corner detectors
+#[derive(Clone)]
+pub struct CornerControls {
+	pub detector: ComboBoxText,
+	// relative to the strongest corner, for Harris and Shi-Tomasi
+	pub quality: SpinButton,
+	pub fast_threshold: SpinButton,
+	// of the non-maximum suppression
+	pub radius: SpinButton,
+	pub count: Label
+}
+
+// the widgets of the template matching
+#[derive(Clone)]
+pub struct TemplateControls {
+	pub method: ComboBoxText,
+	// how many matches to mark at most
+	pub matches: SpinButton,
+	pub count: Label
+}
+
+// the widgets of the perspective correction
+#[derive(Clone)]
+pub struct PerspectiveControls {
+	// 0 keeps the size of the clicked region
+	pub width: SpinButton,
+	pub height: SpinButton,
+	pub interpolation: ComboBoxText
+}
+
+impl PerspectiveControls {
+	pub fn interpolation(&self) -> Interpolation {
+		match self.interpolation.get_active_id().as_ref().map(|id| id.as_str()) {
+			Some("nearest") => Interpolation::Nearest,
+			Some("bicubic") => Interpolation::Bicubic,
+			_ => Interpolation::Bilinear
+		}
+	}
+}
+
+// the widgets styling the annotations, new ones and the one being edited
+#[derive(Clone)]
+pub struct AnnotationControls {
+	pub text: Entry,
+	pub color: ColorButton,
+	pub line_width: SpinButton,
+	pub text_size: SpinButton,
+	// whether 'save as' writes a copy with the annotations drawn into it
+	pub flatten: CheckButton
+}
+
+impl AnnotationControls {
+	pub fn color(&self) -> (f64, f64, f64) {
+		let rgba = self.color.get_rgba();
+		(rgba.red, rgba.green, rgba.blue)
+	}
+
+	pub fn text(&self) -> String {
+		self.text.get_text().unwrap_or_default()
+	}
+
+	// an annotation of the shape in the style of the controls
+	pub fn annotate(&self, shape: Shape) -> Annotation {
+		let size = match shape {
+			Shape::Text { .. } => self.text_size.get_value(),
+			_ => self.line_width.get_value()
+		};
+		Annotation { shape, color: self.color(), size }
+	}
+
+	// takes on the style, and the text, of the annotation being edited
+	pub fn show(&self, annotation: &Annotation) {
+		let (red, green, blue) = annotation.color;
+		self.color.set_rgba(&RGBA { red, green, blue, alpha: 1.0 });
+		match annotation.shape {
+			Shape::Text { ref text, .. } => {
+				self.text.set_text(text);
+				self.text_size.set_value(annotation.size);
+			},
+			_ => self.line_width.set_value(annotation.size)
+		}
+	}
+}
 
 pub struct SideMenu {
 	pub container: Box,
-	pub equalize_histogram: Button,
-	pub edge_detection: Button
+	pub tool: ComboBoxText,
+	pub clear_selection: Button,
+	pub clear_markers: Button,
+	pub operations: Vec<OperationControls>,
+	pub match_histogram: Button,
+	pub blend_image: Button,
+	pub blend_levels: SpinButton,
+	pub statistics: Button,
+	pub frequency_controls: FrequencyControls,
+	pub filter_frequencies: Button,
+	pub show_spectrum: Button,
+	pub blob_controls: BlobControls,
+	pub label_blobs: Button,
+	pub export_blobs: Button,
+	pub hough_controls: HoughControls,
+	pub detect_lines: Button,
+	pub detect_segments: Button,
+	pub detect_circles: Button,
+	pub corner_controls: CornerControls,
+	pub detect_corners: Button,
+	pub template_controls: TemplateControls,
+	pub crop_template: Button,
+	pub find_template: Button,
+	pub perspective_controls: PerspectiveControls,
+	pub correct_perspective: Button,
+	pub annotation_controls: AnnotationControls,
+	pub delete_annotation: Button,
+	pub clear_annotations: Button,
+	pub save_recipe: Button,
+	pub load_recipe: Button,
+	pub apply_recipe_to_folder: Button,
+	pub clear_recipe: Button
 }
 
 impl SideMenu {
@@ -11,34 +272,444 @@ impl SideMenu {
 		let padding_between_children = 2;
 		let container = Box::new(Orientation::Vertical, padding_between_children);
 
-		let equalize_histogram = SideMenu::initialize_equalize_histogram_button(&container);
-		let edge_detection = SideMenu::initialize_edge_detection_button(&container);
+		let tool = SideMenu::initialize_tool_combo(&container);
+		let clear_selection = SideMenu::initialize_button(&container, "clear selection");
+		let clear_markers = SideMenu::initialize_button(&container, "clear markers");
+		container.pack_start(&Separator::new(Orientation::Horizontal), false, false, 0);
+
+		// built in operations and the plugins' ones alike
+		let operations: Vec<OperationControls> = registry().read().unwrap().operations().iter()
+		.map(|operation| SideMenu::initialize_operation(&container, operation.as_ref()))
+		.collect();
+		let match_histogram = SideMenu::initialize_button(&container, "match histogram...");
+		let (blend_image, blend_levels) = SideMenu::initialize_parameterized_button(&container, "blend image...", 1.0, 10.0, 5.0);
+		let statistics = SideMenu::initialize_button(&container, "statistics");
+
+		container.pack_start(&Separator::new(Orientation::Horizontal), false, false, 0);
+		container.pack_start(&Label::new(Some("frequency domain")), false, false, 0);
+
+		let frequency_controls = SideMenu::initialize_frequency_controls(&container);
+		let filter_frequencies = SideMenu::initialize_button(&container, "filter frequencies");
+		let show_spectrum = SideMenu::initialize_button(&container, "show spectrum");
+
+		container.pack_start(&Separator::new(Orientation::Horizontal), false, false, 0);
+		container.pack_start(&Label::new(Some("blobs")), false, false, 0);
+
+		let blob_controls = SideMenu::initialize_blob_controls(&container);
+		let label_blobs = SideMenu::initialize_button(&container, "label blobs");
+		let export_blobs = SideMenu::initialize_button(&container, "export blobs...");
+		container.pack_start(&blob_controls.count, false, false, 0);
+
+		container.pack_start(&Separator::new(Orientation::Horizontal), false, false, 0);
+		container.pack_start(&Label::new(Some("lines and circles")), false, false, 0);
+
+		let hough_controls = SideMenu::initialize_hough_controls(&container);
+		let detect_lines = SideMenu::initialize_button(&container, "detect lines");
+		let detect_segments = SideMenu::initialize_button(&container, "detect segments");
+		let detect_circles = SideMenu::initialize_button(&container, "detect circles");
+		container.pack_start(&hough_controls.count, false, false, 0);
+
+		container.pack_start(&Separator::new(Orientation::Horizontal), false, false, 0);
+		container.pack_start(&Label::new(Some("corners")), false, false, 0);
+
+		let corner_controls = SideMenu::initialize_corner_controls(&container);
+		let detect_corners = SideMenu::initialize_button(&container, "detect corners");
+		container.pack_start(&corner_controls.count, false, false, 0);
+
+		container.pack_start(&Separator::new(Orientation::Horizontal), false, false, 0);
+		container.pack_start(&Label::new(Some("template matching")), false, false, 0);
+
+		let template_controls = SideMenu::initialize_template_controls(&container);
+		let crop_template = SideMenu::initialize_button(&container, "crop template");
+		let find_template = SideMenu::initialize_button(&container, "find template");
+		container.pack_start(&template_controls.count, false, false, 0);
+
+		container.pack_start(&Separator::new(Orientation::Horizontal), false, false, 0);
+		container.pack_start(&Label::new(Some("perspective")), false, false, 0);
+
+		let perspective_controls = SideMenu::initialize_perspective_controls(&container);
+		let correct_perspective = SideMenu::initialize_button(&container, "correct perspective");
+
+		container.pack_start(&Separator::new(Orientation::Horizontal), false, false, 0);
+		container.pack_start(&Label::new(Some("annotations")), false, false, 0);
 
+		let annotation_controls = SideMenu::initialize_annotation_controls(&container);
+		let delete_annotation = SideMenu::initialize_button(&container, "delete annotation");
+		let clear_annotations = SideMenu::initialize_button(&container, "clear annotations");
+		container.pack_start(&annotation_controls.flatten, false, false, 0);
+
+		container.pack_start(&Separator::new(Orientation::Horizontal), false, false, 0);
+		container.pack_start(&Label::new(Some("recipe")), false, false, 0);
+
+		let save_recipe = SideMenu::initialize_button(&container, "save recipe");
+		let load_recipe = SideMenu::initialize_button(&container, "load recipe");
+		let apply_recipe_to_folder = SideMenu::initialize_button(&container, "apply recipe to folder");
+		let clear_recipe = SideMenu::initialize_button(&container, "clear recipe");
 
 		Self {
 			container,
-			equalize_histogram,
-			edge_detection
+			tool,
+			clear_selection,
+			clear_markers,
+			operations,
+			match_histogram,
+			blend_image,
+			blend_levels,
+			statistics,
+			frequency_controls,
+			filter_frequencies,
+			show_spectrum,
+			blob_controls,
+			label_blobs,
+			export_blobs,
+			hough_controls,
+			detect_lines,
+			detect_segments,
+			detect_circles,
+			corner_controls,
+			detect_corners,
+			template_controls,
+			crop_template,
+			find_template,
+			perspective_controls,
+			correct_perspective,
+			annotation_controls,
+			delete_annotation,
+			clear_annotations,
+			save_recipe,
+			load_recipe,
+			apply_recipe_to_folder,
+			clear_recipe
 		}
 	}
 
-	fn initialize_equalize_histogram_button(container: &Box) -> Button {
+	fn initialize_tool_combo(container: &Box) -> ComboBoxText {
 		let padding_between_children = 0;
-		let equalize_histogram_button = Button::new_with_label("equalize histogram");
+		let tool_combo = ComboBoxText::new();
+
+		tool_combo.append(Some(RECTANGLE_TOOL), "rectangle selection");
+		tool_combo.append(Some(ELLIPSE_TOOL), "ellipse selection");
+		tool_combo.append(Some(FREEHAND_TOOL), "freehand selection");
+		tool_combo.append(Some(CORNERS_TOOL), "four corners");
+		tool_combo.append(Some(ARROW_ANNOTATION), "arrow annotation");
+		tool_combo.append(Some(RECTANGLE_ANNOTATION), "rectangle annotation");
+		tool_combo.append(Some(ELLIPSE_ANNOTATION), "ellipse annotation");
+		tool_combo.append(Some(FREEHAND_ANNOTATION), "freehand annotation");
+		tool_combo.append(Some(TEXT_ANNOTATION), "text annotation");
+		tool_combo.append(Some(EDIT_ANNOTATIONS), "edit annotations");
+		tool_combo.set_active_id(Some(RECTANGLE_TOOL));
+		tool_combo.set_halign(Align::Center);
+
+		container.pack_start(&tool_combo, false, false, padding_between_children);
+		tool_combo
+	}
+
+	fn initialize_operation(container: &Box, operation: &dyn ImageOperation) -> OperationControls {
+		let padding_between_children = 0;
+		let parameters = operation.parameters();
+
+		if parameters.is_empty() {
+			return OperationControls {
+				name: operation.name().to_string(),
+				panel: None,
+				apply: SideMenu::initialize_button(container, operation.label()),
+				parameters: Vec::new()
+			};
+		}
+
+		let panel = Expander::new(Some(operation.label()));
+		let panel_container = Box::new(Orientation::Vertical, padding_between_children);
+
+		let parameters = parameters.into_iter().map(|parameter| {
+			let widget = match parameter.kind {
+				ParameterKind::Integer { min, max, default } => {
+					let scale = SideMenu::initialize_scale(min as f64, max as f64, 1.0, default as f64);
+					scale.set_digits(0);
+					ParameterWidget::Integer(scale)
+				},
+				ParameterKind::Number { min, max, default } => {
+					let scale = SideMenu::initialize_scale(min, max, (max - min) / 100.0, default);
+					scale.set_digits(2);
+					ParameterWidget::Number(scale)
+				},
+				ParameterKind::Choice { ref options, ref default } => {
+					let combo = ComboBoxText::new();
+					for &(ref value, ref label) in options.iter() {
+						combo.append(Some(value.as_str()), label);
+					}
+					combo.set_active_id(Some(default.as_str()));
+					ParameterWidget::Choice(combo)
+				},
+				ParameterKind::Boolean { default } => {
+					let check_button = CheckButton::new_with_label(&parameter.label);
+					check_button.set_active(default);
+					ParameterWidget::Boolean(check_button)
+				}
+			};
+
+			match widget {
+				ParameterWidget::Integer(ref scale) | ParameterWidget::Number(ref scale) =>
+					SideMenu::initialize_labeled(&panel_container, &parameter.label, scale),
+				ParameterWidget::Choice(ref combo) =>
+					SideMenu::initialize_labeled(&panel_container, &parameter.label, combo),
+				ParameterWidget::Boolean(ref check_button) =>
+					panel_container.pack_start(check_button, false, false, padding_between_children as u32)
+			}
+			(parameter.name, widget)
+		}).collect();
+
+		let apply = SideMenu::initialize_button(&panel_container, "apply");
+		panel.add(&panel_container);
+		container.pack_start(&panel, false, false, padding_between_children as u32);
+
+		OperationControls {
+			name: operation.name().to_string(),
+			panel: Some(panel),
+			apply,
+			parameters
+		}
+	}
+
+	fn initialize_scale(min: f64, max: f64, step: f64, default: f64) -> Scale {
+		// a parameter with a single value still gets a range to slide over
+		let step = step.max(MIN_SCALE_STEP);
+		let scale = Scale::new_with_range(Orientation::Horizontal, min, max.max(min + step), step);
+		scale.set_value(default);
+		scale.set_size_request(120, -1);
+		scale
+	}
+
+	fn initialize_button(container: &Box, label: &str) -> Button {
+		let padding_between_children = 0;
+		let button = Button::new_with_label(label);
+
+		button.set_halign(Align::Center);
+
+		container.pack_start(&button, false, false, padding_between_children);
+		button
+	}
+
+	fn initialize_frequency_controls(container: &Box) -> FrequencyControls {
+		let band = ComboBoxText::new();
+		band.append(Some("low_pass"), "low-pass");
+		band.append(Some("high_pass"), "high-pass");
+		band.append(Some("band_pass"), "band-pass");
+		band.append(Some(NOTCH_BAND), "notch");
+		band.set_active_id(Some("low_pass"));
+
+		let shape = ComboBoxText::new();
+		shape.append(Some("ideal"), "ideal");
+		shape.append(Some("gaussian"), "gaussian");
+		shape.append(Some("butterworth"), "butterworth");
+		shape.set_active_id(Some("gaussian"));
+
+		// frequencies are in cycles per pixel
+		let frequency = |default: f64, min: f64| {
+			let spin_button = SpinButton::new_with_range(min, 0.5, 0.005);
+			spin_button.set_digits(3);
+			spin_button.set_value(default);
+			spin_button
+		};
+		let cutoff = frequency(0.1, 0.0);
+		let upper_cutoff = frequency(0.25, 0.0);
+		let notch_u = frequency(0.0, -0.5);
+		let notch_v = frequency(0.0, -0.5);
+
+		let order = SpinButton::new_with_range(1.0, 10.0, 1.0);
+		order.set_value(2.0);
+
+		SideMenu::initialize_labeled(container, "band", &band);
+		SideMenu::initialize_labeled(container, "shape", &shape);
+		SideMenu::initialize_labeled(container, "cutoff / radius", &cutoff);
+		SideMenu::initialize_labeled(container, "upper cutoff", &upper_cutoff);
+		SideMenu::initialize_labeled(container, "order", &order);
+		SideMenu::initialize_labeled(container, "notch u", &notch_u);
+		SideMenu::initialize_labeled(container, "notch v", &notch_v);
+
+		FrequencyControls {
+			band,
+			shape,
+			cutoff,
+			upper_cutoff,
+			order,
+			notch_u,
+			notch_v
+		}
+	}
+
+	fn initialize_blob_controls(container: &Box) -> BlobControls {
+		let connectivity = ComboBoxText::new();
+		connectivity.append(Some("four"), "4-connected");
+		connectivity.append(Some("eight"), "8-connected");
+		connectivity.set_active_id(Some("eight"));
+
+		SideMenu::initialize_labeled(container, "connectivity", &connectivity);
+
+		BlobControls {
+			connectivity,
+			count: Label::new(None)
+		}
+	}
+
+	fn initialize_hough_controls(container: &Box) -> HoughControls {
+		let spin_button = |min: f64, max: f64, default: f64| {
+			let spin_button = SpinButton::new_with_range(min, max, 1.0);
+			spin_button.set_value(default);
+			spin_button
+		};
+		let votes = spin_button(1.0, 10000.0, 50.0);
+		let min_length = spin_button(1.0, 10000.0, 20.0);
+		let max_gap = spin_button(0.0, 100.0, 3.0);
+		let min_radius = spin_button(1.0, 1000.0, 5.0);
+		let max_radius = spin_button(1.0, 1000.0, 30.0);
+
+		let coverage = SpinButton::new_with_range(0.05, 1.0, 0.05);
+		coverage.set_digits(2);
+		coverage.set_value(0.6);
+
+		SideMenu::initialize_labeled(container, "line votes", &votes);
+		SideMenu::initialize_labeled(container, "min segment length", &min_length);
+		SideMenu::initialize_labeled(container, "max segment gap", &max_gap);
+		SideMenu::initialize_labeled(container, "min radius", &min_radius);
+		SideMenu::initialize_labeled(container, "max radius", &max_radius);
+		SideMenu::initialize_labeled(container, "circle coverage", &coverage);
+
+		HoughControls {
+			votes,
+			min_length,
+			max_gap,
+			min_radius,
+			max_radius,
+			coverage,
+			count: Label::new(None)
+		}
+	}
+
+	fn initialize_corner_controls(container: &Box) -> CornerControls {
+		let detector = ComboBoxText::new();
+		detector.append(Some(HARRIS_DETECTOR), "Harris");
+		detector.append(Some(SHI_TOMASI_DETECTOR), "Shi-Tomasi");
+		detector.append(Some(FAST_DETECTOR), "FAST");
+		detector.set_active_id(Some(HARRIS_DETECTOR));
+
+		let quality = SpinButton::new_with_range(0.001, 1.0, 0.01);
+		quality.set_digits(3);
+		quality.set_value(0.05);
 
-		equalize_histogram_button.set_halign(Align::Center);
+		let fast_threshold = SpinButton::new_with_range(1.0, 255.0, 1.0);
+		fast_threshold.set_value(20.0);
 
-		container.pack_start(&equalize_histogram_button, false, false, padding_between_children);
-		equalize_histogram_button
+		let radius = SpinButton::new_with_range(0.0, 50.0, 1.0);
+		radius.set_value(3.0);
+
+		SideMenu::initialize_labeled(container, "detector", &detector);
+		SideMenu::initialize_labeled(container, "quality", &quality);
+		SideMenu::initialize_labeled(container, "FAST threshold", &fast_threshold);
+		SideMenu::initialize_labeled(container, "suppression radius", &radius);
+
+		CornerControls {
+			detector,
+			quality,
+			fast_threshold,
+			radius,
+			count: Label::new(None)
+		}
+	}
+
+	fn initialize_template_controls(container: &Box) -> TemplateControls {
+		let method = ComboBoxText::new();
+		method.append(Some(SQUARED_DIFFERENCES), "squared differences");
+		method.append(Some(CROSS_CORRELATION), "cross-correlation");
+		method.append(Some(ZERO_MEAN_CROSS_CORRELATION), "zero-mean cross-correlation");
+		method.set_active_id(Some(ZERO_MEAN_CROSS_CORRELATION));
+
+		let matches = SpinButton::new_with_range(1.0, 100.0, 1.0);
+		matches.set_value(1.0);
+
+		SideMenu::initialize_labeled(container, "method", &method);
+		SideMenu::initialize_labeled(container, "matches", &matches);
+
+		TemplateControls {
+			method,
+			matches,
+			count: Label::new(None)
+		}
 	}
 
-	fn initialize_edge_detection_button(container: &Box) -> Button {
+	fn initialize_perspective_controls(container: &Box) -> PerspectiveControls {
+		let width = SpinButton::new_with_range(0.0, 10000.0, 1.0);
+		let height = SpinButton::new_with_range(0.0, 10000.0, 1.0);
+
+		let interpolation = ComboBoxText::new();
+		interpolation.append(Some("nearest"), "nearest");
+		interpolation.append(Some("bilinear"), "bilinear");
+		interpolation.append(Some("bicubic"), "bicubic");
+		interpolation.set_active_id(Some("bilinear"));
+
+		SideMenu::initialize_labeled(container, "output width", &width);
+		SideMenu::initialize_labeled(container, "output height", &height);
+		SideMenu::initialize_labeled(container, "interpolation", &interpolation);
+
+		PerspectiveControls {
+			width,
+			height,
+			interpolation
+		}
+	}
+
+	fn initialize_annotation_controls(container: &Box) -> AnnotationControls {
+		let text = Entry::new();
+		text.set_placeholder_text(Some("text to place"));
+		let color = ColorButton::new_with_rgba(&RGBA { red: 1.0, green: 0.0, blue: 0.0, alpha: 1.0 });
+		let line_width = SpinButton::new_with_range(1.0, 50.0, 1.0);
+		line_width.set_value(3.0);
+		let text_size = SpinButton::new_with_range(6.0, 400.0, 1.0);
+		text_size.set_value(24.0);
+
+		SideMenu::initialize_labeled(container, "text", &text);
+		SideMenu::initialize_labeled(container, "color", &color);
+		SideMenu::initialize_labeled(container, "line width", &line_width);
+		SideMenu::initialize_labeled(container, "text size", &text_size);
+
+		AnnotationControls {
+			text,
+			color,
+			line_width,
+			text_size,
+			flatten: CheckButton::new_with_label("flatten into 'save as' copies")
+		}
+	}
+
+	// a label next to the widget it describes
+	fn initialize_labeled<W: IsA<Widget>>(container: &Box, label: &str, widget: &W) {
+		let padding_between_children = 4;
+		let row = Box::new(Orientation::Horizontal, padding_between_children);
+
+		row.pack_start(&Label::new(Some(label)), false, false, 0);
+		row.pack_end(widget, false, false, 0);
+
+		container.pack_start(&row, false, false, 0);
+	}
+
+	// a button next to a spin button holding the operation's parameter
+	fn initialize_parameterized_button(container: &Box,
+	                                   label: &str,
+	                                   min: f64,
+	                                   max: f64,
+	                                   default: f64,
+	                                   ) -> (Button, SpinButton) {
 		let padding_between_children = 0;
-		let edge_detection_button = Button::new_with_label("edge detection");
+		let row = Box::new(Orientation::Horizontal, padding_between_children);
 
-		edge_detection_button.set_halign(Align::Center);
+		let button = Button::new_with_label(label);
+		let parameter = SpinButton::new_with_range(min, max, 1.0);
+		parameter.set_value(default);
 
-		container.pack_start(&edge_detection_button, false, false, padding_between_children);
-		edge_detection_button
+		row.pack_start(&button, true, true, padding_between_children as u32);
+		row.pack_end(&parameter, false, false, padding_between_children as u32);
+		row.set_halign(Align::Center);
+
+		container.pack_start(&row, false, false, padding_between_children as u32);
+		(button, parameter)
 	}
-}
\ No newline at end of file
+}
@@ -0,0 +1,41 @@
+use failure::{format_err, Error};
+use std::error::Error as OtherError;
+use std::sync::RwLock;
+use gtk::*;
+
+use image::Image as MyImage;
+use super::dialogs::SpectrumDialog;
+use super::sidemenu::{FrequencyControls, NOTCH_BAND};
+
+// Shows the log-magnitude spectrum of the open image. Clicking on it sets up
+// a notch filter at the clicked frequency.
+pub fn show_spectrum(current_file: &RwLock<Option<MyImage>>,
+                     frequency_controls: &FrequencyControls,
+                     ) -> Result<(), Error> {
+
+	let spectrum = match current_file.try_read() {
+		Ok(guard) => match *guard {
+			Some(ref image) => Some(image.spectrum()),
+			None => None
+		},
+		Err(error) => return Err(format_err!("{}", error.description()))
+	};
+
+	if let Some(spectrum) = spectrum {
+		let spectrum_dialog = SpectrumDialog::new(&spectrum.log_magnitude());
+
+		let frequency_controls = frequency_controls.clone();
+		spectrum_dialog.event_box.connect_button_press_event(move |_, event| {
+			let (x, y) = event.get_position();
+			let (u, v) = spectrum.display_frequency(x as u32, y as u32);
+
+			frequency_controls.notch_u.set_value(u);
+			frequency_controls.notch_v.set_value(v);
+			frequency_controls.band.set_active_id(Some(NOTCH_BAND));
+			Inhibit(false)
+		});
+
+		spectrum_dialog.run();
+	}
+	Ok(())
+}
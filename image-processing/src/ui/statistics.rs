@@ -0,0 +1,51 @@
+use failure::{err_msg, format_err, Error};
+use std::error::Error as OtherError;
+use std::sync::RwLock;
+use gtk::*;
+
+use image::Image as MyImage;
+use super::dialogs::{OpenDialog, StatisticsDialog};
+
+pub fn show_statistics(current_file: &RwLock<Option<MyImage>>) -> Result<(), Error> {
+
+	let image = match current_file.try_read() {
+		Ok(guard) => guard.clone(),
+		Err(error) => return Err(format_err!("{}", error.description()))
+	};
+
+	// if there's no file open, there's nothing to show
+	if let Some(image) = image {
+		let statistics_dialog = StatisticsDialog::new(&image.channel_statistics());
+
+		let comparison = statistics_dialog.comparison.clone();
+		statistics_dialog.compare.connect_clicked(move |cb| {
+			cb.set_sensitive(false);
+			match compare(&image) {
+				Ok(Some(text)) => comparison.set_text(&text),
+				Ok(None) => (),
+				Err(error) => comparison.set_text(&format!("{}", error))
+			}
+			cb.set_sensitive(true);
+		});
+
+		statistics_dialog.run();
+	}
+	Ok(())
+}
+
+fn compare(image: &MyImage) -> Result<Option<String>, Error> {
+	let open_dialog = OpenDialog::new(image.get_dir());
+
+	let reference = match open_dialog.run() {
+		Some(reference_path) => match MyImage::open(&reference_path) {
+			Ok(reference) => reference,
+			Err(error) => return Err(err_msg(error))
+		},
+		None => return Ok(None)
+	};
+
+	Ok(Some(format!("MSE {:.3}   PSNR {:.2} dB   SSIM {:.4}",
+	                image.mean_squared_error(&reference)?,
+	                image.peak_signal_to_noise_ratio(&reference)?,
+	                image.structural_similarity(&reference)?)))
+}
@@ -0,0 +1,57 @@
+use gtk::*;
+
+// the loupe shows LOUPE_PIXELS x LOUPE_PIXELS image pixels around the cursor
+pub const LOUPE_PIXELS: i32 = 11;
+pub const LOUPE_MAGNIFICATION: i32 = 9;
+
+#[derive(Clone)]
+pub struct StatusBar {
+	pub container: Box,
+	pub position: Label,
+	pub pixel_format: Label,
+	pub value: Label,
+	pub luminance: Label,
+	pub zoom: Label,
+	pub loupe: DrawingArea
+}
+
+impl StatusBar {
+	pub fn new() -> Self {
+		let padding_between_children = 12;
+		let container = Box::new(Orientation::Horizontal, padding_between_children);
+
+		let position = Label::new(None);
+		let pixel_format = Label::new(None);
+		let value = Label::new(None);
+		let luminance = Label::new(None);
+		let zoom = Label::new(Some("100%"));
+
+		let loupe = DrawingArea::new();
+		let loupe_size = LOUPE_PIXELS * LOUPE_MAGNIFICATION;
+		loupe.set_size_request(loupe_size, loupe_size);
+
+		container.pack_start(&position, false, false, 0);
+		container.pack_start(&pixel_format, false, false, 0);
+		container.pack_start(&value, false, false, 0);
+		container.pack_start(&luminance, false, false, 0);
+		container.pack_end(&loupe, false, false, 0);
+		container.pack_end(&zoom, false, false, 0);
+
+		Self {
+			container,
+			position,
+			pixel_format,
+			value,
+			luminance,
+			zoom,
+			loupe
+		}
+	}
+
+	pub fn clear(&self) {
+		self.position.set_text("");
+		self.pixel_format.set_text("");
+		self.value.set_text("");
+		self.luminance.set_text("");
+	}
+}
@@ -0,0 +1,50 @@
+use failure::{err_msg, format_err, Error};
+use std::error::Error as OtherError;
+use std::sync::RwLock;
+use gtk::*;
+
+use ui::image_container::{ImageContainer, render_image};
+use image::Image as MyImage;
+use stitching::stitch;
+use super::dialogs::OpenDialog;
+
+// Asks for the overlapping images and opens their panorama, which is saved next
+// to the first of them.
+pub fn stitch_images(headerbar: &HeaderBar,
+                     image_container: &ImageContainer,
+                     current_file: &RwLock<Option<MyImage>>,
+                     ) -> Result<(), Error> {
+
+	let open_dialog = OpenDialog::new({
+		match current_file.try_read() {
+			Ok(guard) => match *guard {
+				Some(ref image) => image.get_dir(),
+				None => None
+			},
+			Err(error) => return Err(format_err!("{}", error.description()))
+		}
+	});
+
+	let paths = open_dialog.run_multiple();
+	if paths.len() < 2 {
+		return Ok(());
+	}
+
+	let mut images = Vec::with_capacity(paths.len());
+	for path in paths.iter() {
+		match MyImage::open(path) {
+			Ok(image) => images.push(image),
+			Err(error) => return Err(err_msg(error))
+		}
+	}
+
+	let first = &paths[0];
+	let stem = first.file_stem().and_then(|stem| stem.to_str()).unwrap_or("panorama");
+	let path = first.with_file_name(format!("{}-stitched.png", stem));
+
+	let image = MyImage::new(&path, stitch(&images)?);
+	headerbar.set_title(path.to_str());
+	render_image(image_container, &image);
+	*current_file.write().unwrap() = Some(image);
+	Ok(())
+}
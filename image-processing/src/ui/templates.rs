@@ -0,0 +1,101 @@
+use failure::{err_msg, format_err, Error};
+use std::error::Error as OtherError;
+use std::sync::RwLock;
+use gtk::*;
+
+use image::Image as MyImage;
+use image::image::GenericImageView;
+use templates::MatchMethod;
+use super::markers::{Marker, Markers};
+use super::selection_tool::SelectionState;
+use super::sidemenu::{TemplateControls, SQUARED_DIFFERENCES, CROSS_CORRELATION};
+
+// Crops the bounding box of the selection out of the open image, to look for it
+// later. Returns the template's size.
+pub fn crop_template(current_file: &RwLock<Option<MyImage>>,
+                     selection: &RwLock<SelectionState>,
+                     template: &RwLock<Option<MyImage>>,
+                     ) -> Result<(u32, u32), Error> {
+
+	let bounds = match selection.try_read() {
+		Ok(state) => match state.selection {
+			Some(ref selection) => selection.bounds(),
+			None => return Err(err_msg("select the template first"))
+		},
+		Err(error) => return Err(format_err!("{}", error.description()))
+	};
+
+	// whole pixels, at least one of them
+	let (x, y) = (bounds.0.max(0.0).floor() as u32, bounds.1.max(0.0).floor() as u32);
+	let width = ((bounds.0 + bounds.2).ceil() as u32).saturating_sub(x).max(1);
+	let height = ((bounds.1 + bounds.3).ceil() as u32).saturating_sub(y).max(1);
+
+	let cropped = match current_file.try_read() {
+		Ok(guard) => match *guard {
+			Some(ref image) => MyImage::new(&image.get_image_path(), image.crop(x, y, width, height)),
+			None => return Ok((0, 0))
+		},
+		Err(error) => return Err(format_err!("{}", error.description()))
+	};
+
+	// a selection outside of the image leaves nothing to look for
+	let size = cropped.as_dynamic_image().dimensions();
+	if size.0 == 0 || size.1 == 0 {
+		return Err(err_msg("the selection is outside of the image"));
+	}
+	match template.try_write() {
+		Ok(mut template) => *template = Some(cropped),
+		Err(error) => return Err(format_err!("{}", error.description()))
+	}
+	Ok(size)
+}
+
+// Looks for the template in the open image and marks the best matches. Returns
+// how many were found.
+pub fn find_template(current_file: &RwLock<Option<MyImage>>,
+                     markers: &RwLock<Markers>,
+                     template: &RwLock<Option<MyImage>>,
+                     controls: &TemplateControls,
+                     ) -> Result<usize, Error> {
+
+	let method = match controls.method.get_active_id().as_ref().map(|id| id.as_str()) {
+		Some(SQUARED_DIFFERENCES) => MatchMethod::SquaredDifferences,
+		Some(CROSS_CORRELATION) => MatchMethod::CrossCorrelation,
+		_ => MatchMethod::ZeroMeanCrossCorrelation
+	};
+	let count = controls.matches.get_value_as_int() as usize;
+
+	let scores = {
+		let template = match template.try_read() {
+			Ok(template) => template,
+			Err(error) => return Err(format_err!("{}", error.description()))
+		};
+		let template = match *template {
+			Some(ref template) => template,
+			None => return Err(err_msg("crop a template first"))
+		};
+
+		match current_file.try_read() {
+			Ok(guard) => match *guard {
+				Some(ref image) => image.match_template(template, method)?,
+				None => return Ok(0)
+			},
+			Err(error) => return Err(format_err!("{}", error.description()))
+		}
+	};
+
+	let found = scores.best_matches(count);
+	match markers.try_write() {
+		Ok(mut markers) => {
+			markers.clear();
+			markers.shapes = found.iter().map(|found| Marker::Rectangle {
+				x: found.x as f64,
+				y: found.y as f64,
+				width: scores.template_width as f64,
+				height: scores.template_height as f64
+			}).collect();
+		},
+		Err(error) => return Err(format_err!("{}", error.description()))
+	}
+	Ok(found.len())
+}